@@ -0,0 +1,50 @@
+use bytes::BytesMut;
+use codecrafters_redis::parser::RespParser;
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use tokio_util::codec::Decoder;
+
+const VALUE_LEN: usize = 10 * 1024 * 1024;
+const CHUNK_LEN: usize = 4 * 1024;
+
+/// A SET command carrying a 10MB value, as it would arrive on the wire
+fn big_set_command() -> Vec<u8> {
+    let mut frame = format!("*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n${}\r\n", VALUE_LEN).into_bytes();
+    frame.extend(std::iter::repeat_n(b'x', VALUE_LEN));
+    frame.extend_from_slice(b"\r\n");
+    frame
+}
+
+/// Feed the frame a chunk at a time, the way tokio fills the read buffer,
+/// attempting a decode after each chunk. With `restart` every attempt gets a
+/// fresh parser, so nothing is remembered between chunks.
+fn feed_in_chunks(frame: &[u8], restart: bool) {
+    let mut buf = BytesMut::new();
+    let mut parser = RespParser::default();
+    for chunk in frame.chunks(CHUNK_LEN) {
+        buf.extend_from_slice(chunk);
+        if restart {
+            parser = RespParser::default();
+        }
+        if let Some(value) = parser.decode(&mut buf).unwrap() {
+            black_box(value);
+            return;
+        }
+    }
+    panic!("frame never completed");
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let frame = big_set_command();
+
+    let mut group = c.benchmark_group("decode_10mb_in_4kb_chunks");
+    group.sample_size(10);
+    // One parser for the whole connection, so it resumes from what it has seen
+    group.bench_function("resume", |b| b.iter(|| feed_in_chunks(&frame, false)));
+    // A fresh parser per chunk re-parses the frame from scratch every time
+    group.bench_function("restart", |b| b.iter(|| feed_in_chunks(&frame, true)));
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,189 @@
+/// Append-only file persistence. With `--appendonly yes` every write that
+/// would be sent to replicas is also appended to the AOF in its RESP form,
+/// and on startup the file is replayed to rebuild the dataset.
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+use bytes::BytesMut;
+use tokio_util::codec::Decoder;
+
+use crate::{
+    Db, handle_command,
+    interpreter::RedisCommand,
+    parser::{RedisValueRef, RespParser, write_redis_value},
+};
+
+pub const DEFAULT_AOF_FILE: &str = "appendonly.aof";
+
+/// Open (creating if needed) the AOF that writes will be appended to from now on
+pub fn enable(db: &Db, path: &Path) -> std::io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    *db.aof.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+pub fn is_enabled(db: &Db) -> bool {
+    db.aof.lock().unwrap().is_some()
+}
+
+/// Flush the AOF to disk. Returns whether everything appended so far is
+/// known to be durable, which can't be true while appendonly is off.
+pub fn fsync(db: &Db) -> bool {
+    match db.aof.lock().unwrap().as_ref() {
+        Some(file) => file.sync_data().is_ok(),
+        None => false,
+    }
+}
+
+/// Log a command that has just been applied. Only replicable writes are
+/// logged, in the same form replicas receive them. PUBLISH is sent to
+/// replicas but isn't part of the dataset, so like Redis it's left out.
+pub fn append(db: &Db, command: &RedisCommand) {
+    if !command.can_replicate() || matches!(command, RedisCommand::Publish(..)) {
+        return;
+    }
+    let mut aof = db.aof.lock().unwrap();
+    let Some(file) = aof.as_mut() else {
+        return;
+    };
+    let value: RedisValueRef = match command.clone().try_into() {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("Error converting command for the AOF: {}", e);
+            return;
+        }
+    };
+    let mut buf = BytesMut::new();
+    write_redis_value(value, &mut buf);
+    if let Err(e) = file.write_all(&buf) {
+        eprintln!("Error writing to the AOF: {}", e);
+    }
+}
+
+/// Replay an AOF into the database, returning how many commands were applied.
+/// A command cut short at the end of the file (from a crash mid-write) is
+/// ignored, as Redis does with `aof-load-truncated yes`.
+pub async fn load(db: &Db, path: &Path) -> Result<usize, Box<dyn Error>> {
+    let mut buffer = Vec::new();
+    File::open(path)?.read_to_end(&mut buffer)?;
+    let mut buf = BytesMut::from(&buffer[..]);
+    let mut parser = RespParser::default();
+    let mut count = 0;
+    while let Some(value) = parser.decode(&mut buf)? {
+        let command: RedisCommand = value.try_into()?;
+        handle_command(db, command).await;
+        count += 1;
+    }
+    if !buf.is_empty() {
+        eprintln!("AOF ends with a truncated command, ignoring it");
+    }
+    // Replaying doesn't count as new changes, the data is already on disk
+    db.dirty_at_last_save
+        .store(db.dirty.load(Ordering::Relaxed), Ordering::Relaxed);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        RedisDb, get,
+        lists::lrange,
+        parser::{RArray, RNull, RString},
+    };
+    use std::sync::Arc;
+
+    fn setup() -> Arc<RedisDb> {
+        Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
+    }
+
+    #[tokio::test]
+    async fn test_append_and_load() {
+        std::fs::create_dir_all("/tmp/redis-files").unwrap();
+        let path = Path::new("/tmp/redis-files").join(format!("{}.aof", uuid::Uuid::new_v4()));
+        let db = setup();
+        enable(&db, &path).unwrap();
+        let commands = [
+            RedisCommand::Set("key".to_string(), "value".to_string()),
+            RedisCommand::Get("key".to_string()),
+            RedisCommand::Rpush("list".to_string(), vec!["a".to_string(), "b".to_string()]),
+            RedisCommand::Incr("counter".to_string()),
+            RedisCommand::Incr("counter".to_string()),
+        ];
+        for command in commands {
+            handle_command(&db, command.clone()).await;
+            append(&db, &command);
+        }
+
+        let fresh = setup();
+        // The GET isn't a write so it was never logged
+        assert_eq!(load(&fresh, &path).await.unwrap(), 4);
+        assert_eq!(get(&fresh, "key".to_string()).await, RString("value"));
+        assert_eq!(get(&fresh, "counter".to_string()).await, RString("2"));
+        assert_eq!(
+            lrange(&fresh, "list".to_string(), 0, -1).await,
+            RArray(vec![RString("a"), RString("b")])
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expiry_survives_reload() {
+        std::fs::create_dir_all("/tmp/redis-files").unwrap();
+        let path = Path::new("/tmp/redis-files").join(format!("{}.aof", uuid::Uuid::new_v4()));
+        let db = setup();
+        enable(&db, &path).unwrap();
+        let commands = [
+            RedisCommand::SetEx("short".to_string(), "v".to_string(), 50),
+            RedisCommand::Set("expiring".to_string(), "v".to_string()),
+            RedisCommand::Expire("expiring".to_string(), 100),
+            RedisCommand::SetEx("long".to_string(), "v".to_string(), 100_000),
+        ];
+        for command in commands {
+            let result = handle_command(&db, command.clone()).await;
+            append(&db, &command.for_replication(&result));
+        }
+        let expiry = |db: &Db, key: &str| *db.ttl.get(key).unwrap();
+        let (long, expiring) = (expiry(&db, "long"), expiry(&db, "expiring"));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        // Replayed relative TTLs would start over and bring "short" back
+        let fresh = setup();
+        assert_eq!(load(&fresh, &path).await.unwrap(), 4);
+        assert_eq!(get(&fresh, "short".to_string()).await, RNull());
+        assert_eq!(get(&fresh, "long".to_string()).await, RString("v"));
+        // The logged times are taken just after the command ran
+        assert!(expiry(&fresh, "long").abs_diff(long) <= 5);
+        assert!(expiry(&fresh, "expiring").abs_diff(expiring) <= 5);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_fsync() {
+        let db = setup();
+        assert!(!fsync(&db));
+        let path = Path::new("/tmp/redis-files").join(format!("{}.aof", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all("/tmp/redis-files").unwrap();
+        enable(&db, &path).unwrap();
+        assert!(fsync(&db));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_load_truncated() {
+        std::fs::create_dir_all("/tmp/redis-files").unwrap();
+        let path = Path::new("/tmp/redis-files").join(format!("{}.aof", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n*3\r\n$3\r\nSET\r\n$1\r\nb",
+        )
+        .unwrap();
+        let db = setup();
+        assert_eq!(load(&db, &path).await.unwrap(), 1);
+        assert_eq!(get(&db, "a".to_string()).await, RString("1"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -73,13 +73,22 @@ pub fn auth(db: &Db, username: String, password: String) -> RedisValueRef {
     }
 }
 
+/// Username and password carried by a command that can authenticate
+pub fn credentials(command: &RedisCommand) -> Option<(&String, &String)> {
+    match command {
+        RedisCommand::Auth(username, password) => Some((username, password)),
+        RedisCommand::Hello(_, Some((username, password)), _) => Some((username, password)),
+        _ => None,
+    }
+}
+
 pub fn check_auth(db: &Db, command: &RedisCommand) -> bool {
     // Need to handle case of default user with no password set
-    match command {
-        RedisCommand::Auth(username, password) => {
+    match credentials(command) {
+        Some((username, password)) => {
             let result = auth(db, username.clone(), password.clone());
             result == RSimpleString("OK")
         }
-        _ => db.users.lock().unwrap().is_empty(),
+        None => db.users.lock().unwrap().is_empty(),
     }
 }
@@ -0,0 +1,685 @@
+use bytes::Bytes;
+
+use crate::{
+    Db, RedisValue,
+    parser::{RArray, RError, RInt, RNull, RedisValueRef},
+    string_value, take_keys,
+};
+
+/// Offsets are limited to the 512MB maximum string size
+pub const MAX_BIT_OFFSET: u64 = 512 * 1024 * 1024 * 8 - 1;
+
+/// Whether a range in BITCOUNT/BITPOS is given in bytes or in bits
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BitUnit {
+    #[default]
+    Byte,
+    Bit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+/// A BITFIELD integer: signed or not, and its width. Signed fields can be up
+/// to 64 bits wide, unsigned ones up to 63 so every value fits in an i64.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitFieldType {
+    pub signed: bool,
+    pub bits: u8,
+}
+
+impl BitFieldType {
+    fn range(&self) -> (i128, i128) {
+        if self.signed {
+            (-(1 << (self.bits - 1)), (1 << (self.bits - 1)) - 1)
+        } else {
+            (0, (1 << self.bits) - 1)
+        }
+    }
+}
+
+impl std::str::FromStr for BitFieldType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let (signed, bits) = match s.split_at_checked(1) {
+            Some(("i" | "I", bits)) => (true, bits),
+            Some(("u" | "U", bits)) => (false, bits),
+            _ => return Err(()),
+        };
+        let bits: u8 = bits.parse().map_err(|_| ())?;
+        let max = if signed { 64 } else { 63 };
+        if (1..=max).contains(&bits) {
+            Ok(BitFieldType { signed, bits })
+        } else {
+            Err(())
+        }
+    }
+}
+
+impl std::fmt::Display for BitFieldType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", if self.signed { 'i' } else { 'u' }, self.bits)
+    }
+}
+
+/// What BITFIELD does when a SET or INCRBY doesn't fit the field
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Overflow {
+    #[default]
+    Wrap,
+    Sat,
+    Fail,
+}
+
+/// One BITFIELD operation. Offsets are in bits.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BitFieldOp {
+    Get(BitFieldType, u64),
+    Set(BitFieldType, u64, i64),
+    IncrBy(BitFieldType, u64, i64),
+    Overflow(Overflow),
+}
+
+/// Bit 0 is the most significant bit of the first byte
+fn bit_at(bytes: &[u8], offset: usize) -> u8 {
+    match bytes.get(offset >> 3) {
+        Some(byte) => (byte >> (7 - (offset & 7))) & 1,
+        None => 0,
+    }
+}
+
+/// Resolve an inclusive, possibly negative, range against `len` units.
+/// Returns None when the range selects nothing.
+fn normalize_bit_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let end = if end < 0 { (len + end).max(0) } else { end };
+    let end = end.min(len - 1);
+    if len == 0 || start > end {
+        None
+    } else {
+        Some((start as usize, end as usize))
+    }
+}
+
+pub fn setbit(db: &Db, key: String, offset: u64, value: u8) -> RedisValueRef {
+    if offset > MAX_BIT_OFFSET {
+        return RError("ERR bit offset is not an integer or out of range");
+    }
+    let offset = offset as usize;
+    let byte_index = offset >> 3;
+    let mask = 1u8 << (7 - (offset & 7));
+    let mut bytes = match string_value(db, &key) {
+        Ok(value) => value.unwrap_or_default().to_vec(),
+        Err(e) => return e,
+    };
+    if bytes.len() <= byte_index {
+        bytes.resize(byte_index + 1, 0);
+    }
+    let old = (bytes[byte_index] & mask != 0) as i64;
+    if value == 1 {
+        bytes[byte_index] |= mask;
+    } else {
+        bytes[byte_index] &= !mask;
+    }
+    db.dict.insert(key, RedisValue::String(Bytes::from(bytes)));
+    db.add_dirty(1);
+    RInt(old)
+}
+
+pub fn getbit(db: &Db, key: String, offset: u64) -> RedisValueRef {
+    match string_value(db, &key) {
+        Ok(Some(s)) => RInt(bit_at(&s, offset as usize) as i64),
+        Ok(None) => RInt(0),
+        Err(e) => e,
+    }
+}
+
+pub fn bitcount(db: &Db, key: String, range: Option<(i64, i64)>, unit: BitUnit) -> RedisValueRef {
+    let bytes = match string_value(db, &key) {
+        Ok(Some(s)) => s,
+        Ok(None) => return RInt(0),
+        Err(e) => return e,
+    };
+    let count = match (range, unit) {
+        (None, _) => bytes.iter().map(|b| b.count_ones() as usize).sum(),
+        (Some((start, end)), BitUnit::Byte) => match normalize_bit_range(start, end, bytes.len()) {
+            Some((start, end)) => bytes[start..=end]
+                .iter()
+                .map(|b| b.count_ones() as usize)
+                .sum(),
+            None => 0,
+        },
+        (Some((start, end)), BitUnit::Bit) => {
+            match normalize_bit_range(start, end, bytes.len() * 8) {
+                Some((start, end)) => (start..=end).filter(|&i| bit_at(&bytes, i) == 1).count(),
+                None => 0,
+            }
+        }
+    };
+    RInt(count as i64)
+}
+
+/// Position of the first `bit` in the range. When searching for a clear bit
+/// without an explicit end, the string is treated as padded with zeros, so
+/// the answer can be the first bit past the end.
+pub fn bitpos(
+    db: &Db,
+    key: String,
+    bit: u8,
+    start: Option<i64>,
+    end: Option<i64>,
+    unit: BitUnit,
+) -> RedisValueRef {
+    let bytes = match string_value(db, &key) {
+        Ok(value) => value.unwrap_or_default(),
+        Err(e) => return e,
+    };
+    if bytes.is_empty() {
+        return RInt(if bit == 1 { -1 } else { 0 });
+    }
+    let len = match unit {
+        BitUnit::Byte => bytes.len(),
+        BitUnit::Bit => bytes.len() * 8,
+    };
+    let range = normalize_bit_range(start.unwrap_or(0), end.unwrap_or(-1), len);
+    let (first, last) = match (range, unit) {
+        (None, _) => return RInt(-1),
+        (Some((start, end)), BitUnit::Byte) => (start * 8, end * 8 + 7),
+        (Some((start, end)), BitUnit::Bit) => (start, end),
+    };
+    match (first..=last).find(|&i| bit_at(&bytes, i) == bit) {
+        Some(pos) => RInt(pos as i64),
+        None if bit == 0 && end.is_none() => RInt(last as i64 + 1),
+        None => RInt(-1),
+    }
+}
+
+fn read_field(bytes: &[u8], ty: BitFieldType, offset: usize) -> i64 {
+    let raw =
+        (offset..offset + ty.bits as usize).fold(0u64, |acc, i| acc << 1 | bit_at(bytes, i) as u64);
+    if ty.signed && ty.bits < 64 && raw >> (ty.bits - 1) == 1 {
+        (raw as i64) - (1 << ty.bits)
+    } else {
+        raw as i64
+    }
+}
+
+fn write_field(bytes: &mut Vec<u8>, ty: BitFieldType, offset: usize, value: i64) {
+    let end = offset + ty.bits as usize;
+    if bytes.len() < end.div_ceil(8) {
+        bytes.resize(end.div_ceil(8), 0);
+    }
+    for (n, i) in (offset..end).enumerate() {
+        let bit = (value as u64 >> (ty.bits as usize - 1 - n)) & 1;
+        let mask = 1u8 << (7 - (i & 7));
+        if bit == 1 {
+            bytes[i >> 3] |= mask;
+        } else {
+            bytes[i >> 3] &= !mask;
+        }
+    }
+}
+
+/// Bring `value` into the field's range, or None if overflow is FAIL and it
+/// doesn't fit
+fn fit(value: i128, ty: BitFieldType, overflow: Overflow) -> Option<i64> {
+    let (min, max) = ty.range();
+    if (min..=max).contains(&value) {
+        return Some(value as i64);
+    }
+    match overflow {
+        Overflow::Wrap => {
+            let modulus = 1i128 << ty.bits;
+            let wrapped = value.rem_euclid(modulus);
+            Some(if wrapped > max {
+                wrapped - modulus
+            } else {
+                wrapped
+            } as i64)
+        }
+        Overflow::Sat => Some(if value > max { max } else { min } as i64),
+        Overflow::Fail => None,
+    }
+}
+
+/// BITFIELD: run the operations in order against the string at `key`,
+/// replying with one result per GET, SET or INCRBY. SET replies with the old
+/// value, INCRBY with the new one, and either replies null when it overflows
+/// under OVERFLOW FAIL.
+pub fn bitfield(db: &Db, key: String, ops: Vec<BitFieldOp>) -> RedisValueRef {
+    let mut bytes = match string_value(db, &key) {
+        Ok(value) => value.unwrap_or_default().to_vec(),
+        Err(e) => return e,
+    };
+    let mut overflow = Overflow::default();
+    let mut changes = 0;
+    let mut results = Vec::new();
+    for op in ops {
+        let result = match op {
+            BitFieldOp::Get(ty, offset) => RInt(read_field(&bytes, ty, offset as usize)),
+            BitFieldOp::Set(ty, offset, value) => {
+                let old = read_field(&bytes, ty, offset as usize);
+                match fit(value as i128, ty, overflow) {
+                    Some(value) => {
+                        write_field(&mut bytes, ty, offset as usize, value);
+                        changes += 1;
+                        RInt(old)
+                    }
+                    None => RNull(),
+                }
+            }
+            BitFieldOp::IncrBy(ty, offset, increment) => {
+                let old = read_field(&bytes, ty, offset as usize);
+                match fit(old as i128 + increment as i128, ty, overflow) {
+                    Some(value) => {
+                        write_field(&mut bytes, ty, offset as usize, value);
+                        changes += 1;
+                        RInt(value)
+                    }
+                    None => RNull(),
+                }
+            }
+            BitFieldOp::Overflow(mode) => {
+                overflow = mode;
+                continue;
+            }
+        };
+        results.push(result);
+    }
+    if changes > 0 {
+        db.dict.insert(key, RedisValue::String(Bytes::from(bytes)));
+        db.add_dirty(changes);
+    }
+    RArray(results)
+}
+
+/// Combine the source strings into `dest`, zero padding shorter operands.
+/// Returns the length of the resulting string.
+pub fn bitop(db: &Db, op: BitOperation, dest: String, keys: Vec<String>) -> RedisValueRef {
+    let mut sources = Vec::with_capacity(keys.len());
+    for key in &keys {
+        match string_value(db, key) {
+            Ok(value) => sources.push(value.unwrap_or_default()),
+            Err(e) => return e,
+        }
+    }
+    let len = sources.iter().map(|s| s.len()).max().unwrap_or(0);
+    let result: Vec<u8> = (0..len)
+        .map(|i| {
+            let mut operands = sources.iter().map(|s| s.get(i).copied().unwrap_or(0));
+            let first = operands.next().unwrap_or(0);
+            match op {
+                BitOperation::And => operands.fold(first, |acc, b| acc & b),
+                BitOperation::Or => operands.fold(first, |acc, b| acc | b),
+                BitOperation::Xor => operands.fold(first, |acc, b| acc ^ b),
+                BitOperation::Not => !first,
+            }
+        })
+        .collect();
+
+    // Like SET, the destination is overwritten whatever its type
+    take_keys(db, std::slice::from_ref(&dest));
+    if !result.is_empty() {
+        db.dict
+            .insert(dest, RedisValue::String(Bytes::from(result)));
+    }
+    db.add_dirty(1);
+    RInt(len as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::parser::RString;
+    use crate::{RedisDb, get, set};
+
+    fn setup() -> Arc<RedisDb> {
+        Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
+    }
+
+    fn u8_field() -> BitFieldType {
+        BitFieldType {
+            signed: false,
+            bits: 8,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bitfield_set_get() {
+        let db = setup();
+        let key = "bf".to_string();
+        let ops = vec![
+            BitFieldOp::Set(u8_field(), 0, 200),
+            BitFieldOp::Get(u8_field(), 0),
+        ];
+        assert_eq!(
+            bitfield(&db, key.clone(), ops),
+            RArray(vec![RInt(0), RInt(200)])
+        );
+        // 200 is 11001000
+        assert_eq!(bitcount(&db, key.clone(), None, BitUnit::Byte), RInt(3));
+        assert_eq!(getbit(&db, key.clone(), 1), RInt(1));
+
+        // Fields needn't be byte aligned, and signed ones read the top bit as
+        // the sign
+        let i4 = BitFieldType {
+            signed: true,
+            bits: 4,
+        };
+        let ops = vec![BitFieldOp::Get(i4, 2), BitFieldOp::Set(i4, 2, -3)];
+        // Bits 2..6 are 0010
+        assert_eq!(
+            bitfield(&db, key.clone(), ops),
+            RArray(vec![RInt(2), RInt(2)])
+        );
+        assert_eq!(
+            bitfield(&db, key, vec![BitFieldOp::Get(i4, 2)]),
+            RArray(vec![RInt(-3)])
+        );
+    }
+
+    #[test]
+    fn test_bitfield_overflow() {
+        let db = setup();
+        let key = "bf".to_string();
+        bitfield(&db, key.clone(), vec![BitFieldOp::Set(u8_field(), 0, 250)]);
+
+        let incr = |overflow| {
+            vec![
+                BitFieldOp::Overflow(overflow),
+                BitFieldOp::IncrBy(u8_field(), 0, 10),
+            ]
+        };
+        assert_eq!(
+            bitfield(&db, key.clone(), incr(Overflow::Sat)),
+            RArray(vec![RInt(255)])
+        );
+        assert_eq!(
+            bitfield(&db, key.clone(), incr(Overflow::Fail)),
+            RArray(vec![RNull()])
+        );
+        assert_eq!(
+            bitfield(&db, key.clone(), incr(Overflow::Wrap)),
+            RArray(vec![RInt(9)])
+        );
+
+        let i8 = BitFieldType {
+            signed: true,
+            bits: 8,
+        };
+        let ops = vec![
+            BitFieldOp::Overflow(Overflow::Sat),
+            BitFieldOp::IncrBy(i8, 8, -200),
+            BitFieldOp::Overflow(Overflow::Wrap),
+            BitFieldOp::IncrBy(i8, 16, 130),
+        ];
+        assert_eq!(
+            bitfield(&db, key, ops),
+            RArray(vec![RInt(-128), RInt(-126)])
+        );
+    }
+
+    #[test]
+    fn test_bitfield_type() {
+        assert_eq!(
+            "i64".parse(),
+            Ok(BitFieldType {
+                signed: true,
+                bits: 64
+            })
+        );
+        assert_eq!(
+            "u63"
+                .to_string()
+                .parse::<BitFieldType>()
+                .unwrap()
+                .to_string(),
+            "u63"
+        );
+        assert!("u64".parse::<BitFieldType>().is_err());
+        assert!("i0".parse::<BitFieldType>().is_err());
+        assert!("x8".parse::<BitFieldType>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_setbit_getbit() {
+        let db = setup();
+        let key = "bits".to_string();
+
+        assert_eq!(setbit(&db, key.clone(), 7, 1), RInt(0));
+        assert_eq!(setbit(&db, key.clone(), 7, 1), RInt(1));
+        assert_eq!(getbit(&db, key.clone(), 7), RInt(1));
+        assert_eq!(getbit(&db, key.clone(), 6), RInt(0));
+        // Past the end of the string
+        assert_eq!(getbit(&db, key.clone(), 100), RInt(0));
+        assert_eq!(get(&db, key).await, RString("\u{1}"));
+    }
+
+    #[tokio::test]
+    async fn test_setbit_extends_string() {
+        let db = setup();
+        let key = "bits".to_string();
+        set(&db, key.clone(), "a".to_string()).await;
+
+        // 'a' is 0b01100001, setting bit 22 grows the string to 3 bytes
+        setbit(&db, key.clone(), 22, 1);
+        match db.get_if_valid(&key).unwrap().clone() {
+            RedisValue::String(s) => assert_eq!(&s[..], &[b'a', 0, 0b10]),
+            _ => panic!("Expected string"),
+        }
+
+        setbit(&db, key.clone(), 22, 0);
+        match db.get_if_valid(&key).unwrap().clone() {
+            RedisValue::String(s) => assert_eq!(&s[..], &[b'a', 0, 0]),
+            _ => panic!("Expected string"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bitcount() {
+        let db = setup();
+        let key = "mykey".to_string();
+        set(&db, key.clone(), "foobar".to_string()).await;
+
+        assert_eq!(bitcount(&db, key.clone(), None, BitUnit::Byte), RInt(26));
+        assert_eq!(
+            bitcount(&db, key.clone(), Some((0, 0)), BitUnit::Byte),
+            RInt(4)
+        );
+        assert_eq!(
+            bitcount(&db, key.clone(), Some((1, 1)), BitUnit::Byte),
+            RInt(6)
+        );
+        assert_eq!(
+            bitcount(&db, key.clone(), Some((1, -2)), BitUnit::Byte),
+            RInt(18)
+        );
+        assert_eq!(
+            bitcount(&db, key.clone(), Some((5, 30)), BitUnit::Bit),
+            RInt(17)
+        );
+        assert_eq!(
+            bitcount(&db, key.clone(), Some((3, 1)), BitUnit::Byte),
+            RInt(0)
+        );
+        assert_eq!(
+            bitcount(&db, "missing".to_string(), None, BitUnit::Byte),
+            RInt(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bitop_and() {
+        let db = setup();
+        set(&db, "key1".to_string(), "foobar".to_string()).await;
+        set(&db, "key2".to_string(), "abcdef".to_string()).await;
+
+        let result = bitop(
+            &db,
+            BitOperation::And,
+            "dest".to_string(),
+            vec!["key1".to_string(), "key2".to_string()],
+        );
+        assert_eq!(result, RInt(6));
+        assert_eq!(get(&db, "dest".to_string()).await, RString("`bc`ab"));
+    }
+
+    #[tokio::test]
+    async fn test_bitop_pads_shorter_operands() {
+        let db = setup();
+        db.dict.insert(
+            "a".to_string(),
+            RedisValue::String(Bytes::from_static(b"\x0f\x0f")),
+        );
+        db.dict.insert(
+            "b".to_string(),
+            RedisValue::String(Bytes::from_static(b"\xf0")),
+        );
+
+        let result = bitop(
+            &db,
+            BitOperation::Or,
+            "dest".to_string(),
+            vec!["a".to_string(), "b".to_string(), "missing".to_string()],
+        );
+        assert_eq!(result, RInt(2));
+        match db.get_if_valid("dest").unwrap().clone() {
+            RedisValue::String(s) => assert_eq!(&s[..], &[0xff, 0x0f]),
+            _ => panic!("Expected string"),
+        }
+
+        bitop(
+            &db,
+            BitOperation::Not,
+            "dest".to_string(),
+            vec!["b".to_string()],
+        );
+        match db.get_if_valid("dest").unwrap().clone() {
+            RedisValue::String(s) => assert_eq!(&s[..], &[0x0f]),
+            _ => panic!("Expected string"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bitpos() {
+        let db = setup();
+        let key = "mykey".to_string();
+        db.dict.insert(
+            key.clone(),
+            RedisValue::String(Bytes::from_static(b"\xff\xf0\x00")),
+        );
+        assert_eq!(
+            bitpos(&db, key.clone(), 0, None, None, BitUnit::Byte),
+            RInt(12)
+        );
+
+        db.dict.insert(
+            key.clone(),
+            RedisValue::String(Bytes::from_static(b"\x00\xff\xf0")),
+        );
+        assert_eq!(
+            bitpos(&db, key.clone(), 1, Some(0), None, BitUnit::Byte),
+            RInt(8)
+        );
+        assert_eq!(
+            bitpos(&db, key.clone(), 1, Some(2), None, BitUnit::Byte),
+            RInt(16)
+        );
+        assert_eq!(
+            bitpos(&db, key.clone(), 1, Some(2), Some(-1), BitUnit::Byte),
+            RInt(16)
+        );
+        assert_eq!(
+            bitpos(&db, key.clone(), 1, Some(7), Some(15), BitUnit::Bit),
+            RInt(8)
+        );
+
+        db.dict.insert(
+            key.clone(),
+            RedisValue::String(Bytes::from_static(b"\xff\xff")),
+        );
+        // No clear bit, but the string is implicitly padded with zeros
+        assert_eq!(
+            bitpos(&db, key.clone(), 0, None, None, BitUnit::Byte),
+            RInt(16)
+        );
+        // ...unless the end of the range was given explicitly
+        assert_eq!(
+            bitpos(&db, key.clone(), 0, Some(0), Some(-1), BitUnit::Byte),
+            RInt(-1)
+        );
+
+        assert_eq!(
+            bitpos(&db, "missing".to_string(), 1, None, None, BitUnit::Byte),
+            RInt(-1)
+        );
+        assert_eq!(
+            bitpos(&db, "missing".to_string(), 0, None, None, BitUnit::Byte),
+            RInt(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bits_wrong_type() {
+        let db = setup();
+        crate::lists::rpush(&db, "list".to_string(), vec!["a".to_string()]).await;
+        crate::zset::zadd(&db, "zset".to_string(), 1.0, "m".to_string());
+
+        for key in ["list", "zset"] {
+            let key = key.to_string();
+            assert!(matches!(
+                setbit(&db, key.clone(), 1, 1),
+                RedisValueRef::Error(_)
+            ));
+            assert!(matches!(
+                getbit(&db, key.clone(), 1),
+                RedisValueRef::Error(_)
+            ));
+            assert!(matches!(
+                bitcount(&db, key.clone(), None, BitUnit::Byte),
+                RedisValueRef::Error(_)
+            ));
+            assert!(matches!(
+                bitpos(&db, key.clone(), 1, None, None, BitUnit::Byte),
+                RedisValueRef::Error(_)
+            ));
+            let ops = vec![BitFieldOp::Get("u8".parse().unwrap(), 0)];
+            assert!(matches!(
+                bitfield(&db, key.clone(), ops),
+                RedisValueRef::Error(_)
+            ));
+            assert!(matches!(
+                bitop(&db, BitOperation::Not, "dest".to_string(), vec![key]),
+                RedisValueRef::Error(_)
+            ));
+        }
+        assert_eq!(crate::type_of(&db, "zset"), "zset");
+    }
+
+    #[tokio::test]
+    async fn test_bitop_overwrites_zset_dest() {
+        let db = setup();
+        set(&db, "src".to_string(), "a".to_string()).await;
+        crate::zset::zadd(&db, "dest".to_string(), 1.0, "m".to_string());
+        bitop(
+            &db,
+            BitOperation::Not,
+            "dest".to_string(),
+            vec!["src".to_string()],
+        );
+        assert_eq!(crate::type_of(&db, "dest"), "string");
+    }
+}
@@ -0,0 +1,78 @@
+/// Single-node answers to the CLUSTER subcommands that cluster-aware clients
+/// send while connecting. Cluster mode itself isn't supported.
+use crate::{
+    Db,
+    parser::{RArray, RString, RedisValueRef},
+};
+
+/// CLUSTER INFO: the state of a node that isn't part of a cluster
+pub fn info() -> RedisValueRef {
+    let fields = [
+        ("cluster_enabled", "0"),
+        ("cluster_state", "ok"),
+        ("cluster_slots_assigned", "0"),
+        ("cluster_slots_ok", "0"),
+        ("cluster_slots_pfail", "0"),
+        ("cluster_slots_fail", "0"),
+        ("cluster_known_nodes", "1"),
+        ("cluster_size", "0"),
+        ("cluster_current_epoch", "0"),
+        ("cluster_my_epoch", "0"),
+    ];
+    let info: String = fields
+        .iter()
+        .map(|(name, value)| format!("{}:{}\r\n", name, value))
+        .collect();
+    RString(info)
+}
+
+/// CLUSTER MYID: this node's 40 character ID, which is its run ID
+pub fn myid(db: &Db) -> RedisValueRef {
+    RString(db.run_id.clone())
+}
+
+/// CLUSTER SLOTS: no slots are served, since there is no cluster
+pub fn slots() -> RedisValueRef {
+    RArray(vec![])
+}
+
+/// CLUSTER SHARDS: no shards, for the same reason
+pub fn shards() -> RedisValueRef {
+    RArray(vec![])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::RedisDb;
+
+    fn setup() -> Arc<RedisDb> {
+        Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
+    }
+
+    #[test]
+    fn test_cluster_info() {
+        let RedisValueRef::String(info) = info() else {
+            panic!("CLUSTER INFO should return a bulk string");
+        };
+        let info = String::from_utf8_lossy(&info).to_string();
+        let lines: Vec<&str> = info.split_terminator("\r\n").collect();
+        assert_eq!(lines[0], "cluster_enabled:0");
+        assert!(lines.contains(&"cluster_state:ok"));
+        assert!(lines.contains(&"cluster_known_nodes:1"));
+        assert!(lines.iter().all(|line| line.contains(':')));
+    }
+
+    #[test]
+    fn test_cluster_myid() {
+        let db = setup();
+        let RedisValueRef::String(id) = myid(&db) else {
+            panic!("CLUSTER MYID should return a bulk string");
+        };
+        assert_eq!(id, db.run_id.as_bytes());
+        assert_eq!(slots(), RArray(vec![]));
+        assert_eq!(shards(), RArray(vec![]));
+    }
+}
@@ -0,0 +1,501 @@
+/// Static command table, used to answer COMMAND introspection queries.
+/// Arity, flags and key positions follow the Redis command docs.
+use crate::{
+    glob::glob_match,
+    interpreter::COMMANDS,
+    parser::{RArray, RInt, RNull, RSimpleString, RString, RedisValueRef},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// Positive for a fixed number of arguments (including the name), negative for a minimum
+    pub arity: i64,
+    pub flags: &'static [&'static str],
+    pub first_key: i64,
+    pub last_key: i64,
+    pub step: i64,
+}
+
+const fn spec(
+    name: &'static str,
+    arity: i64,
+    flags: &'static [&'static str],
+    keys: (i64, i64, i64),
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        arity,
+        flags,
+        first_key: keys.0,
+        last_key: keys.1,
+        step: keys.2,
+    }
+}
+
+const NO_KEYS: (i64, i64, i64) = (0, 0, 0);
+const ONE_KEY: (i64, i64, i64) = (1, 1, 1);
+
+pub const COMMAND_TABLE: &[CommandSpec] = &[
+    spec("acl", -2, &[], NO_KEYS),
+    spec(
+        "auth",
+        -2,
+        &[
+            "noscript",
+            "loading",
+            "stale",
+            "fast",
+            "no_auth",
+            "allow_busy",
+        ],
+        NO_KEYS,
+    ),
+    spec(
+        "bgsave",
+        -1,
+        &["admin", "noscript", "no_async_loading"],
+        NO_KEYS,
+    ),
+    spec("bitcount", -2, &["readonly"], ONE_KEY),
+    spec("bitfield", -2, &["write", "denyoom"], ONE_KEY),
+    spec("bitop", -4, &["write", "denyoom"], (2, -1, 1)),
+    spec("bitpos", -3, &["readonly"], ONE_KEY),
+    spec("blpop", -3, &["write", "noscript", "blocking"], (1, -2, 1)),
+    spec("client", -2, &[], NO_KEYS),
+    spec("cluster", -2, &[], NO_KEYS),
+    spec("command", -1, &["loading", "stale"], NO_KEYS),
+    spec("config", -2, &[], NO_KEYS),
+    spec("del", -2, &["write"], (1, -1, 1)),
+    spec(
+        "discard",
+        1,
+        &["noscript", "loading", "stale", "fast", "allow_busy"],
+        NO_KEYS,
+    ),
+    spec("echo", 2, &["fast"], NO_KEYS),
+    spec(
+        "exec",
+        1,
+        &["noscript", "loading", "stale", "skip_slowlog"],
+        NO_KEYS,
+    ),
+    spec("expire", -3, &["write", "fast"], ONE_KEY),
+    spec("expireat", 3, &["write", "fast"], ONE_KEY),
+    spec("fcall", -3, &["noscript", "stale", "movablekeys"], NO_KEYS),
+    spec("flushall", -1, &["write"], NO_KEYS),
+    spec("flushdb", -1, &["write"], NO_KEYS),
+    spec("function", -2, &[], NO_KEYS),
+    spec("geoadd", -5, &["write", "denyoom"], ONE_KEY),
+    spec("geodist", -4, &["readonly"], ONE_KEY),
+    spec("geopos", -2, &["readonly"], ONE_KEY),
+    spec("georadius", -6, &["readonly"], ONE_KEY),
+    spec("georadiusbymember", -5, &["readonly"], ONE_KEY),
+    spec("geosearch", -7, &["readonly"], ONE_KEY),
+    spec("get", 2, &["readonly", "fast"], ONE_KEY),
+    spec("getbit", 3, &["readonly", "fast"], ONE_KEY),
+    spec("getex", -2, &["write", "fast"], ONE_KEY),
+    spec("getrange", 4, &["readonly"], ONE_KEY),
+    spec("hdel", -3, &["write", "fast"], ONE_KEY),
+    spec(
+        "hello",
+        -1,
+        &[
+            "noscript",
+            "loading",
+            "stale",
+            "fast",
+            "no_auth",
+            "allow_busy",
+        ],
+        NO_KEYS,
+    ),
+    spec("hexpire", -6, &["write", "denyoom", "fast"], ONE_KEY),
+    spec("hget", 3, &["readonly", "fast"], ONE_KEY),
+    spec("hgetall", 2, &["readonly"], ONE_KEY),
+    spec("hgetdel", -5, &["write", "fast"], ONE_KEY),
+    spec("hgetex", -5, &["write", "fast"], ONE_KEY),
+    spec("hlen", 2, &["readonly", "fast"], ONE_KEY),
+    spec("hpersist", -5, &["write", "fast"], ONE_KEY),
+    spec("hpexpireat", -6, &["write", "denyoom", "fast"], ONE_KEY),
+    spec("hset", -4, &["write", "denyoom", "fast"], ONE_KEY),
+    spec("hsetex", -6, &["write", "denyoom", "fast"], ONE_KEY),
+    spec("httl", -5, &["readonly", "fast"], ONE_KEY),
+    spec("incr", 2, &["write", "denyoom", "fast"], ONE_KEY),
+    spec("info", -1, &["loading", "stale"], NO_KEYS),
+    spec("keys", 2, &["readonly"], NO_KEYS),
+    spec("lastsave", 1, &["loading", "stale", "fast"], NO_KEYS),
+    spec("lcs", -3, &["readonly"], (1, 2, 1)),
+    spec("llen", 2, &["readonly", "fast"], ONE_KEY),
+    spec("lolwut", -1, &["readonly", "fast"], NO_KEYS),
+    spec("lpop", -2, &["write", "fast"], ONE_KEY),
+    spec("lpush", -3, &["write", "denyoom", "fast"], ONE_KEY),
+    spec("lpushx", -3, &["write", "denyoom", "fast"], ONE_KEY),
+    spec("lrange", 4, &["readonly"], ONE_KEY),
+    spec("memory", -2, &[], NO_KEYS),
+    spec(
+        "multi",
+        1,
+        &["noscript", "loading", "stale", "fast", "allow_busy"],
+        NO_KEYS,
+    ),
+    spec("object", -2, &[], NO_KEYS),
+    spec("pfadd", -2, &["write", "denyoom", "fast"], ONE_KEY),
+    spec("pfcount", -2, &["readonly"], (1, -1, 1)),
+    spec("pfmerge", -2, &["write", "denyoom"], (1, -1, 1)),
+    spec("pexpireat", 3, &["write", "fast"], ONE_KEY),
+    spec("ping", -1, &["fast"], NO_KEYS),
+    spec("psetex", 4, &["write", "denyoom"], ONE_KEY),
+    spec(
+        "psubscribe",
+        -2,
+        &["pubsub", "noscript", "loading", "stale", "no_multi"],
+        NO_KEYS,
+    ),
+    spec(
+        "psync",
+        -3,
+        &["admin", "noscript", "no_async_loading", "no_multi"],
+        NO_KEYS,
+    ),
+    spec(
+        "publish",
+        3,
+        &["pubsub", "loading", "stale", "fast"],
+        NO_KEYS,
+    ),
+    spec("pubsub", -2, &["pubsub", "loading", "stale"], NO_KEYS),
+    spec(
+        "punsubscribe",
+        -1,
+        &["pubsub", "noscript", "loading", "stale"],
+        NO_KEYS,
+    ),
+    spec(
+        "quit",
+        -1,
+        &[
+            "noscript",
+            "loading",
+            "stale",
+            "fast",
+            "no_auth",
+            "allow_busy",
+        ],
+        NO_KEYS,
+    ),
+    spec(
+        "replconf",
+        -1,
+        &["admin", "noscript", "loading", "stale", "allow_busy"],
+        NO_KEYS,
+    ),
+    spec(
+        "reset",
+        1,
+        &[
+            "noscript",
+            "loading",
+            "stale",
+            "fast",
+            "no_auth",
+            "allow_busy",
+        ],
+        NO_KEYS,
+    ),
+    spec(
+        "role",
+        1,
+        &["noscript", "loading", "stale", "fast"],
+        NO_KEYS,
+    ),
+    spec("rpush", -3, &["write", "denyoom", "fast"], ONE_KEY),
+    spec("rpushx", -3, &["write", "denyoom", "fast"], ONE_KEY),
+    spec("sadd", -3, &["write", "denyoom", "fast"], ONE_KEY),
+    spec(
+        "save",
+        1,
+        &["admin", "noscript", "no_async_loading", "no_multi"],
+        NO_KEYS,
+    ),
+    spec("scan", -2, &["readonly"], NO_KEYS),
+    spec("scard", 2, &["readonly", "fast"], ONE_KEY),
+    spec("sdiff", -2, &["readonly"], (1, -1, 1)),
+    spec("sdiffstore", -3, &["write", "denyoom"], (1, -1, 1)),
+    spec("set", -3, &["write", "denyoom"], ONE_KEY),
+    spec("setex", 4, &["write", "denyoom"], ONE_KEY),
+    spec("setbit", 4, &["write", "denyoom"], ONE_KEY),
+    spec("setrange", 4, &["write", "denyoom"], ONE_KEY),
+    spec(
+        "shutdown",
+        -1,
+        &["admin", "noscript", "loading", "stale", "no_multi"],
+        NO_KEYS,
+    ),
+    spec("sinter", -2, &["readonly"], (1, -1, 1)),
+    spec("sinterstore", -3, &["write", "denyoom"], (1, -1, 1)),
+    spec("sismember", 3, &["readonly", "fast"], ONE_KEY),
+    spec("slowlog", -2, &[], NO_KEYS),
+    spec("smembers", 2, &["readonly"], ONE_KEY),
+    spec("sort", -2, &["write", "denyoom", "movablekeys"], ONE_KEY),
+    spec("sort_ro", -2, &["readonly", "movablekeys"], ONE_KEY),
+    spec("srem", -3, &["write", "fast"], ONE_KEY),
+    spec("strlen", 2, &["readonly", "fast"], ONE_KEY),
+    spec(
+        "subscribe",
+        -2,
+        &["pubsub", "noscript", "loading", "stale", "no_multi"],
+        NO_KEYS,
+    ),
+    spec("sunion", -2, &["readonly"], (1, -1, 1)),
+    spec("sunionstore", -3, &["write", "denyoom"], (1, -1, 1)),
+    spec("touch", -2, &["readonly", "fast"], (1, -1, 1)),
+    spec("type", 2, &["readonly", "fast"], ONE_KEY),
+    spec("unlink", -2, &["write", "fast"], (1, -1, 1)),
+    spec(
+        "unsubscribe",
+        -1,
+        &["pubsub", "noscript", "loading", "stale"],
+        NO_KEYS,
+    ),
+    spec("wait", 3, &["noscript"], NO_KEYS),
+    spec("waitaof", 4, &["noscript"], NO_KEYS),
+    spec("xadd", -5, &["write", "denyoom", "fast"], ONE_KEY),
+    spec("xinfo", -2, &[], NO_KEYS),
+    spec("xrange", -4, &["readonly"], ONE_KEY),
+    spec(
+        "xread",
+        -4,
+        &["readonly", "blocking", "movablekeys"],
+        NO_KEYS,
+    ),
+    spec("xsetid", -3, &["write", "denyoom", "fast"], ONE_KEY),
+    spec("zadd", -4, &["write", "denyoom", "fast"], ONE_KEY),
+    spec("zcard", 2, &["readonly", "fast"], ONE_KEY),
+    spec("zdiff", -3, &["readonly", "movablekeys"], NO_KEYS),
+    spec(
+        "zdiffstore",
+        -4,
+        &["write", "denyoom", "movablekeys"],
+        ONE_KEY,
+    ),
+    spec("zrange", -4, &["readonly"], ONE_KEY),
+    spec("zrank", -3, &["readonly", "fast"], ONE_KEY),
+    spec("zrem", -3, &["write", "fast"], ONE_KEY),
+    spec("zremrangebylex", 4, &["write"], ONE_KEY),
+    spec("zremrangebyrank", 4, &["write"], ONE_KEY),
+    spec("zremrangebyscore", 4, &["write"], ONE_KEY),
+    spec("zscore", 3, &["readonly", "fast"], ONE_KEY),
+];
+
+/// Case-insensitive lookup in the command table
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    COMMAND_TABLE
+        .iter()
+        .find(|spec| spec.name.eq_ignore_ascii_case(name))
+}
+
+/// Whether MULTI refuses to queue the command (the `no_multi` flag).
+/// Subcommands are looked up by their container, as in `config|resetstat`.
+pub fn is_no_multi(name: &str) -> bool {
+    let container = name.split('|').next().unwrap_or(name);
+    lookup(container).is_some_and(|spec| spec.flags.contains(&"no_multi"))
+}
+
+/// Whether the command is refused while used memory is over maxmemory (the
+/// `denyoom` flag)
+pub fn is_deny_oom(name: &str) -> bool {
+    let container = name.split('|').next().unwrap_or(name);
+    lookup(container).is_some_and(|spec| spec.flags.contains(&"denyoom"))
+}
+
+fn spec_reply(spec: &CommandSpec) -> RedisValueRef {
+    RArray(vec![
+        RString(spec.name),
+        RInt(spec.arity),
+        RArray(spec.flags.iter().map(|flag| RSimpleString(*flag)).collect()),
+        RInt(spec.first_key),
+        RInt(spec.last_key),
+        RInt(spec.step),
+    ])
+}
+
+/// COMMAND INFO: one entry per requested name, null for unknown commands.
+/// With no names every command is described.
+pub fn command_info(names: Vec<String>) -> RedisValueRef {
+    if names.is_empty() {
+        return RArray(COMMAND_TABLE.iter().map(spec_reply).collect());
+    }
+    RArray(
+        names
+            .iter()
+            .map(|name| lookup(name).map(spec_reply).unwrap_or_else(RNull))
+            .collect(),
+    )
+}
+
+/// The kind of data a command works on, as an ACL category
+fn data_category(name: &str) -> Option<&'static str> {
+    let category = match name {
+        "get" | "getex" | "getrange" | "incr" | "lcs" | "psetex" | "set" | "setex" | "setrange"
+        | "strlen" => "string",
+        "bitcount" | "bitfield" | "bitop" | "bitpos" | "getbit" | "setbit" => "bitmap",
+        "blpop" | "llen" | "lpop" | "lpush" | "lpushx" | "lrange" | "rpush" | "rpushx" => "list",
+        "hdel" | "hexpire" | "hget" | "hgetall" | "hgetdel" | "hgetex" | "hlen" | "hpersist"
+        | "hpexpireat" | "hset" | "hsetex" | "httl" => "hash",
+        "sadd" | "scard" | "sdiff" | "sdiffstore" | "sinter" | "sinterstore" | "sismember"
+        | "smembers" | "srem" | "sunion" | "sunionstore" => "set",
+        "geoadd" | "geodist" | "geopos" | "georadius" | "georadiusbymember" | "geosearch" => "geo",
+        "pfadd" | "pfcount" | "pfmerge" => "hyperloglog",
+        "xadd" | "xinfo" | "xrange" | "xread" | "xsetid" => "stream",
+        "psubscribe" | "publish" | "pubsub" | "punsubscribe" | "subscribe" | "unsubscribe" => {
+            "pubsub"
+        }
+        "del" | "expire" | "expireat" | "flushall" | "flushdb" | "keys" | "object"
+        | "pexpireat" | "scan" | "sort" | "sort_ro" | "touch" | "type" | "unlink" => "keyspace",
+        "auth" | "client" | "echo" | "hello" | "ping" | "quit" | "reset" => "connection",
+        "discard" | "exec" | "multi" => "transaction",
+        "fcall" | "function" => "scripting",
+        name if name.starts_with('z') => "sortedset",
+        _ => return None,
+    };
+    Some(category)
+}
+
+/// ACL categories of a command: those implied by its flags plus the kind of
+/// data it touches
+pub fn acl_categories(spec: &CommandSpec) -> Vec<&'static str> {
+    let has = |flag| spec.flags.contains(&flag);
+    let mut categories = Vec::new();
+    if has("write") {
+        categories.push("write");
+    }
+    if has("readonly") {
+        categories.push("read");
+    }
+    categories.push(if has("fast") { "fast" } else { "slow" });
+    if has("admin") {
+        categories.extend(["admin", "dangerous"]);
+    }
+    if has("blocking") {
+        categories.push("blocking");
+    }
+    categories.extend(data_category(spec.name));
+    categories
+}
+
+/// How COMMAND LIST narrows down its answer
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandFilter {
+    Module(String),
+    AclCat(String),
+    Pattern(String),
+}
+
+/// COMMAND LIST: the names of every command, or those matching the filter.
+/// There are no modules, so filtering by one finds nothing.
+pub fn command_list(filter: Option<CommandFilter>) -> RedisValueRef {
+    let names = COMMANDS.iter().map(|(name, _)| name.to_lowercase());
+    let names: Vec<String> = match filter {
+        None => names.collect(),
+        Some(CommandFilter::Module(_)) => Vec::new(),
+        Some(CommandFilter::AclCat(category)) => names
+            .filter(|name| {
+                lookup(name).is_some_and(|spec| {
+                    acl_categories(spec)
+                        .iter()
+                        .any(|c| c.eq_ignore_ascii_case(&category))
+                })
+            })
+            .collect(),
+        Some(CommandFilter::Pattern(pattern)) => names
+            .filter(|name| glob_match(pattern.as_bytes(), name.as_bytes(), true))
+            .collect(),
+    };
+    RArray(names.into_iter().map(RString).collect())
+}
+
+/// COMMAND COUNT: how many commands the interpreter recognizes
+pub fn command_count() -> RedisValueRef {
+    RInt(COMMANDS.len() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_count() {
+        assert_eq!(command_count(), RInt(COMMANDS.len() as i64));
+        // Every described command is one the interpreter actually accepts
+        for spec in COMMAND_TABLE {
+            assert!(
+                COMMANDS
+                    .iter()
+                    .any(|(name, _)| name.eq_ignore_ascii_case(spec.name)),
+                "{} has a spec but isn't dispatched",
+                spec.name
+            );
+        }
+    }
+
+    fn list_names(filter: CommandFilter) -> Vec<String> {
+        match command_list(Some(filter)) {
+            RedisValueRef::Array(names) => names.into_iter().map(|n| n.to_string()).collect(),
+            other => panic!("COMMAND LIST should return an array, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_command_list() {
+        let RedisValueRef::Array(all) = command_list(None) else {
+            panic!("COMMAND LIST should return an array");
+        };
+        assert_eq!(all.len(), COMMANDS.len());
+
+        let zset = list_names(CommandFilter::Pattern("z*".to_string()));
+        assert!(zset.contains(&"zadd".to_string()));
+        assert!(zset.contains(&"zremrangebyscore".to_string()));
+        assert!(zset.iter().all(|name| name.starts_with('z')), "{:?}", zset);
+
+        let hyperloglog = list_names(CommandFilter::AclCat("HyperLogLog".to_string()));
+        assert_eq!(hyperloglog, vec!["pfadd", "pfcount", "pfmerge"]);
+        let admin = list_names(CommandFilter::AclCat("admin".to_string()));
+        assert!(admin.contains(&"bgsave".to_string()));
+        assert!(!admin.contains(&"get".to_string()));
+
+        assert!(list_names(CommandFilter::Module("json".to_string())).is_empty());
+    }
+
+    #[test]
+    fn test_command_info() {
+        let result = command_info(vec!["GET".to_string(), "set".to_string()]);
+        assert_eq!(
+            result,
+            RArray(vec![
+                RArray(vec![
+                    RString("get"),
+                    RInt(2),
+                    RArray(vec![RSimpleString("readonly"), RSimpleString("fast")]),
+                    RInt(1),
+                    RInt(1),
+                    RInt(1),
+                ]),
+                RArray(vec![
+                    RString("set"),
+                    RInt(-3),
+                    RArray(vec![RSimpleString("write"), RSimpleString("denyoom")]),
+                    RInt(1),
+                    RInt(1),
+                    RInt(1),
+                ]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_command_info_unknown() {
+        let result = command_info(vec!["nosuchcommand".to_string()]);
+        assert_eq!(result, RArray(vec![RNull()]));
+    }
+}
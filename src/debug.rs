@@ -0,0 +1,273 @@
+/// DEBUG subcommands. These exist mostly so tests can reach into server
+/// internals, the same way the Redis test suite does.
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::{
+    Db, RedisValue, encoding_of,
+    glob::glob_match,
+    parser::{RError, RInt, RSimpleString, RedisValueRef},
+    random_hex_id,
+    rdb::{serialized_length, write_rdb},
+    zset::ZSet,
+};
+
+/// DEBUG SET-ACTIVE-EXPIRE 0|1: switch the background expiry cycle off or on.
+/// While it is off keys only expire lazily, when they are next accessed.
+pub fn set_active_expire(db: &Db, enabled: bool) -> RedisValueRef {
+    db.active_expire.store(enabled, Ordering::Relaxed);
+    RSimpleString("OK")
+}
+
+/// DEBUG SLEEP <seconds>: hold up this connection without blocking others
+pub async fn sleep(seconds: f64) -> RedisValueRef {
+    tokio::time::sleep(Duration::from_secs_f64(seconds)).await;
+    RSimpleString("OK")
+}
+
+/// DEBUG CHANGE-REPL-ID: start a new replication history under a fresh ID
+pub fn change_repl_id(db: &Db) -> RedisValueRef {
+    *db.replication_id.lock().unwrap() = random_hex_id();
+    RSimpleString("OK")
+}
+
+/// DEBUG QUICKLIST-PACKED-THRESHOLD <bytes>: elements at least this large
+/// are stored as plain quicklist nodes, so a list holding one is never a
+/// listpack.
+pub fn quicklist_packed_threshold(db: &Db, threshold: usize) -> RedisValueRef {
+    db.quicklist_packed_threshold
+        .store(threshold, Ordering::Relaxed);
+    RSimpleString("OK")
+}
+
+/// DEBUG RELOAD: write the dataset out as an RDB dump, empty the keyspace and
+/// load the dump back in. Streams can't be dumped yet, so they stay in place.
+pub fn reload(db: &Db) -> RedisValueRef {
+    let dump = write_rdb(&db.snapshot());
+    db.dict
+        .retain(|_, value| matches!(value, RedisValue::Stream(_)));
+    db.ttl.retain(|key, _| db.dict.contains_key(key));
+    db.zsets.lock().unwrap().clear();
+    match db.load_rdb(&dump) {
+        Ok(_) => RSimpleString("OK"),
+        Err(e) => RError(format!("ERR Error trying to load the RDB dump: {}", e)),
+    }
+}
+
+/// DEBUG OBJECT <key>: low level details of the value at `key`. Quicklists
+/// also report how many nodes they span. serializedlength is the size of the
+/// value in a dump, so 0 for streams since those aren't saved.
+pub fn object(db: &Db, key: String) -> RedisValueRef {
+    let Some(encoding) = encoding_of(db, &key) else {
+        return RError("ERR no such key");
+    };
+    let (value, ql_nodes) = match db.get_if_valid(&key).as_deref() {
+        Some(value @ RedisValue::List(list)) => {
+            let nodes = list.quicklist_nodes(
+                db.list_max_listpack_entries.load(Ordering::Relaxed),
+                db.quicklist_packed_threshold.load(Ordering::Relaxed),
+            );
+            (value.to_rdb(), (encoding == "quicklist").then_some(nodes))
+        }
+        Some(value) => (value.to_rdb(), None),
+        None => (db.zsets.lock().unwrap().get(&key).map(ZSet::to_rdb), None),
+    };
+    let length = value.as_ref().map_or(0, serialized_length);
+    let mut reply = format!(
+        "Value at:0x0 refcount:1 encoding:{} serializedlength:{} lru:0 lru_seconds_idle:0",
+        encoding, length
+    );
+    if let Some(nodes) = ql_nodes {
+        reply.push_str(&format!(" ql_nodes:{}", nodes));
+    }
+    RSimpleString(reply)
+}
+
+/// DEBUG STRINGMATCH-LEN <pattern> <string>: run the glob matcher directly
+pub fn stringmatch_len(pattern: String, string: String) -> RedisValueRef {
+    RInt(glob_match(pattern.as_bytes(), string.as_bytes(), false) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        RedisDb, get,
+        hashes::{hexpire, hset, httl},
+        lists::{lrange, rpush},
+        object_encoding,
+        parser::{RArray, RInt, RString},
+        set_ex,
+        zset::{zadd, zrange, zscore},
+    };
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn setup() -> Arc<RedisDb> {
+        Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let db = setup();
+        crate::set(&db, "str".to_string(), "value".to_string()).await;
+        set_ex(&db, "temp".to_string(), "soon".to_string(), 100_000).await;
+        rpush(
+            &db,
+            "list".to_string(),
+            vec!["a".to_string(), "b".to_string()],
+        )
+        .await;
+        zadd(&db, "zset".to_string(), 2.5, "y".to_string());
+        zadd(&db, "zset".to_string(), -1.0, "x".to_string());
+        let pairs = vec![
+            ("f".to_string(), "1".to_string()),
+            ("g".to_string(), "2".to_string()),
+        ];
+        hset(&db, "hash".to_string(), pairs);
+        hexpire(&db, "hash".to_string(), 100, vec!["f".to_string()]);
+
+        assert_eq!(reload(&db), RSimpleString("OK"));
+        assert_eq!(get(&db, "str".to_string()).await, RString("value"));
+        assert_eq!(get(&db, "temp".to_string()).await, RString("soon"));
+        assert!(db.ttl.contains_key("temp"));
+        assert_eq!(
+            lrange(&db, "list".to_string(), 0, -1).await,
+            RArray(vec![RString("a"), RString("b")])
+        );
+        assert_eq!(
+            zrange(&db, "zset".to_string(), 0, -1),
+            RArray(vec![RString("x"), RString("y")])
+        );
+        assert_eq!(
+            zscore(&db, "zset".to_string(), "y".to_string()),
+            RString("2.5")
+        );
+        assert_eq!(
+            httl(
+                &db,
+                "hash".to_string(),
+                vec!["f".to_string(), "g".to_string()]
+            ),
+            RArray(vec![RInt(100), RInt(-1)])
+        );
+    }
+
+    #[test]
+    fn test_change_repl_id() {
+        let db = setup();
+        let is_hex_id = |id: &str| id.len() == 40 && id.chars().all(|c| c.is_ascii_hexdigit());
+        assert!(is_hex_id(&db.run_id));
+
+        let before = db.replication_id.lock().unwrap().clone();
+        assert!(is_hex_id(&before));
+        assert_eq!(change_repl_id(&db), RSimpleString("OK"));
+        let after = db.replication_id.lock().unwrap().clone();
+        assert!(is_hex_id(&after));
+        assert_ne!(before, after);
+        assert_ne!(after, db.run_id);
+    }
+
+    #[tokio::test]
+    async fn test_set_active_expire() {
+        let db = setup();
+        set_active_expire(&db, false);
+        set_ex(&db, "key".to_string(), "value".to_string(), 1).await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        assert_eq!(db.active_expire_cycle(), 0);
+        assert!(db.dict.contains_key("key"));
+        // Lazy expiry still applies on access
+        assert_eq!(get(&db, "key".to_string()).await, crate::parser::RNull());
+        assert!(!db.dict.contains_key("key"));
+
+        set_active_expire(&db, true);
+        set_ex(&db, "key".to_string(), "value".to_string(), 1).await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(db.active_expire_cycle(), 1);
+        assert!(!db.dict.contains_key("key"));
+    }
+
+    #[tokio::test]
+    async fn test_quicklist_packed_threshold() {
+        let db = setup();
+        quicklist_packed_threshold(&db, 4);
+        rpush(&db, "small".to_string(), vec!["abcd".to_string()]).await;
+        assert_eq!(
+            object_encoding(&db, "small".to_string()),
+            RString("listpack")
+        );
+        rpush(&db, "big".to_string(), vec!["abcde".to_string()]).await;
+        assert_eq!(
+            object_encoding(&db, "big".to_string()),
+            RString("quicklist")
+        );
+    }
+
+    /// The ql_nodes field of a DEBUG OBJECT reply
+    fn object_field(db: &Db, key: &str, name: &str) -> Option<String> {
+        let reply = object(db, key.to_string()).to_string();
+        let prefix = format!("{}:", name);
+        reply
+            .split(' ')
+            .find_map(|field| field.strip_prefix(prefix.as_str()))
+            .map(String::from)
+    }
+
+    fn ql_nodes(db: &Db, key: &str) -> Option<String> {
+        object_field(db, key, "ql_nodes")
+    }
+
+    #[tokio::test]
+    async fn test_object_ql_nodes() {
+        let db = setup();
+        assert_eq!(
+            object(&db, "missing".to_string()),
+            RError("ERR no such key")
+        );
+
+        rpush(&db, "small".to_string(), vec!["a".to_string(); 10]).await;
+        assert_eq!(ql_nodes(&db, "small"), None);
+
+        // 128 entries per node by default
+        rpush(&db, "long".to_string(), vec!["a".to_string(); 300]).await;
+        assert_eq!(ql_nodes(&db, "long"), Some("3".to_string()));
+
+        // A large element splits the node it lands in and gets its own
+        quicklist_packed_threshold(&db, 8);
+        let items = ["a", "b", "large element", "c"].map(String::from).to_vec();
+        rpush(&db, "plain".to_string(), items).await;
+        assert_eq!(ql_nodes(&db, "plain"), Some("3".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_object_serializedlength() {
+        let db = setup();
+        let serialized = |key| object_field(&db, key, "serializedlength").unwrap();
+
+        // A length byte and the contents
+        crate::set(&db, "short".to_string(), "abc".to_string()).await;
+        assert_eq!(serialized("short"), "4");
+
+        // Repetitive strings are LZF compressed
+        crate::set(&db, "long".to_string(), "a".repeat(1000)).await;
+        let length: usize = serialized("long").parse().unwrap();
+        assert!(length < 1000, "serializedlength {}", length);
+
+        zadd(&db, "zset".to_string(), 1.0, "m".to_string());
+        // Count, member, 8 byte score
+        assert_eq!(serialized("zset"), "11");
+    }
+
+    #[test]
+    fn test_stringmatch_len() {
+        assert_eq!(
+            stringmatch_len("h?llo*".to_string(), "hello world".to_string()),
+            RInt(1)
+        );
+        assert_eq!(
+            stringmatch_len("h[^e]llo".to_string(), "hello".to_string()),
+            RInt(0)
+        );
+    }
+}
@@ -0,0 +1,149 @@
+/// Key eviction under maxmemory. Used memory is the sum of what MEMORY USAGE
+/// reports for every key, worked out afresh before each client command
+/// while a limit is set. Rather than sampling like Redis, the policy picks
+/// its victim from every candidate key.
+use crate::{Db, lfu, memory::key_usages, remove_keys};
+
+/// What making room for a command did
+#[derive(Debug, Default, PartialEq)]
+pub struct Evictions {
+    pub keys: Vec<String>,
+    /// Still over the limit, because the policy ran out of keys it may evict
+    pub out_of_memory: bool,
+}
+
+/// The index of the key the policy would evict next, if any. Volatile
+/// policies only consider keys with a TTL.
+fn victim(db: &Db, usages: &[(String, usize)]) -> Option<usize> {
+    let (scope, algorithm) = db.maxmemory_policy.split_once('-')?;
+    let candidates: Vec<usize> = (0..usages.len())
+        .filter(|&i| scope == "allkeys" || db.ttl.contains_key(&usages[i].0))
+        .collect();
+    let lowest = |rank: &dyn Fn(&str) -> u64| {
+        candidates
+            .iter()
+            .copied()
+            .min_by_key(|&i| rank(&usages[i].0))
+    };
+    match algorithm {
+        "lru" => lowest(&|key| db.last_access.get(key).map_or(0, |at| *at)),
+        "lfu" => lowest(&|key| lfu::frequency(db, key) as u64),
+        "ttl" => lowest(&|key| db.ttl.get(key).map_or(u64::MAX, |at| *at)),
+        "random" if !candidates.is_empty() => {
+            let pick = (lfu::random_roll() * candidates.len() as f64) as usize;
+            Some(candidates[pick])
+        }
+        _ => None,
+    }
+}
+
+/// Evict keys, as the maxmemory policy chooses them, until used memory is
+/// back within maxmemory
+pub fn perform_evictions(db: &Db) -> Evictions {
+    let mut evictions = Evictions::default();
+    if db.maxmemory == 0 {
+        return evictions;
+    }
+    let mut usages = key_usages(db);
+    let mut used: usize = usages.iter().map(|(_, size)| size).sum();
+    while used as u64 > db.maxmemory {
+        let Some(i) = victim(db, &usages) else {
+            evictions.out_of_memory = true;
+            break;
+        };
+        let (key, size) = usages.swap_remove(i);
+        remove_keys(db, std::slice::from_ref(&key));
+        used -= size;
+        evictions.keys.push(key);
+    }
+    evictions
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        ClientFlags, RedisDb,
+        interpreter::RedisCommand,
+        parser::{RNull, RString},
+        run_client_command,
+    };
+
+    fn setup(policy: &str) -> RedisDb {
+        let mut db = RedisDb::new(None, "/tmp/redis-files", "dump.rdb");
+        db.maxmemory_policy = policy.to_string();
+        db
+    }
+
+    async fn run(db: &Db, command: RedisCommand) -> crate::parser::RedisValueRef {
+        run_client_command(db, command, ClientFlags::default(), None).await
+    }
+
+    fn set(key: &str) -> RedisCommand {
+        RedisCommand::Set(key.to_string(), "v".to_string())
+    }
+
+    fn get(key: &str) -> RedisCommand {
+        RedisCommand::Get(key.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_lfu_evicts_least_frequent() {
+        let mut db = setup("allkeys-lfu");
+        // Room for one of the two keys
+        db.maxmemory = 60;
+        let db = Arc::new(db);
+        for key in ["hot", "cold"] {
+            crate::set(&db, key.to_string(), "v".to_string()).await;
+            lfu::record(&db, key);
+        }
+        // The first access always bumps a new key's counter
+        lfu::record(&db, "hot");
+
+        assert_eq!(run(&db, get("hot")).await, RString("v"));
+        assert_eq!(run(&db, get("cold")).await, RNull());
+        assert!(!db.lfu.contains_key("cold"));
+    }
+
+    #[tokio::test]
+    async fn test_volatile_ttl_evicts_soonest_expiry() {
+        let mut db = setup("volatile-ttl");
+        db.maxmemory = 100;
+        let db = Arc::new(db);
+        crate::set(&db, "keep".to_string(), "v".to_string()).await;
+        for (key, ttl) in [("later", 200_000), ("sooner", 100_000)] {
+            crate::set_ex(&db, key.to_string(), "v".to_string(), ttl).await;
+        }
+
+        let evictions = perform_evictions(&db);
+        assert_eq!(evictions.keys, vec!["sooner"]);
+        assert!(!evictions.out_of_memory);
+        assert!(db.dict.contains_key("later"));
+    }
+
+    #[tokio::test]
+    async fn test_noeviction_refuses_writes() {
+        let mut db = setup("noeviction");
+        db.maxmemory = 1;
+        let db = Arc::new(db);
+        crate::set(&db, "key".to_string(), "v".to_string()).await;
+
+        assert_eq!(
+            run(&db, set("other")).await,
+            crate::ref_error("OOM command not allowed when used memory > 'maxmemory'.")
+        );
+        assert!(!db.dict.contains_key("other"));
+        // Reads still go through
+        assert_eq!(run(&db, get("key")).await, RString("v"));
+
+        // A volatile policy with no keys to pick from is just as stuck
+        let mut db = setup("volatile-lru");
+        db.maxmemory = 1;
+        let db = Arc::new(db);
+        crate::set(&db, "key".to_string(), "v".to_string()).await;
+        assert!(perform_evictions(&db).out_of_memory);
+        assert!(db.dict.contains_key("key"));
+    }
+}
@@ -0,0 +1,47 @@
+/// Redis Functions aren't supported: there is no scripting engine. FUNCTION
+/// LIST and STATS still answer as an empty registry would, since client
+/// libraries send them while detecting capabilities.
+use crate::parser::{RArray, RError, RNull, RString, RedisValueRef};
+
+/// FUNCTION LIST: no libraries are loaded
+pub fn list() -> RedisValueRef {
+    RArray(vec![])
+}
+
+/// FUNCTION STATS: no script is running and no engines are available. The
+/// reply is the RESP2 form of the map Redis sends.
+pub fn stats() -> RedisValueRef {
+    RArray(vec![
+        RString("running_script"),
+        RNull(),
+        RString("engines"),
+        RArray(vec![]),
+    ])
+}
+
+/// FUNCTION LOAD and FCALL
+pub fn unsupported() -> RedisValueRef {
+    RError("ERR Functions are not supported")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_function_list() {
+        assert_eq!(list(), RArray(vec![]));
+    }
+
+    #[test]
+    fn test_function_stats() {
+        let RedisValueRef::Array(fields) = stats() else {
+            panic!("FUNCTION STATS should return an array");
+        };
+        assert_eq!(fields.len(), 4);
+        assert_eq!(fields[0], RString("running_script"));
+        assert_eq!(fields[1], RNull());
+        assert_eq!(fields[2], RString("engines"));
+        assert_eq!(fields[3], RArray(vec![]));
+    }
+}
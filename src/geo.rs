@@ -14,7 +14,7 @@ const MAX_LONGITUDE: f64 = 180.0;
 const LATITUDE_RANGE: f64 = MAX_LATITUDE - MIN_LATITUDE;
 const LONGITUDE_RANGE: f64 = MAX_LONGITUDE - MIN_LONGITUDE;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 struct Point {
     lat: f64,
     lng: f64,
@@ -152,15 +152,25 @@ pub fn geodist(db: &Db, set: String, member1: String, member2: String) -> RedisV
     }
 }
 
-pub fn geosearch(
-    db: &Db,
-    key: String,
-    lng: f64,
-    lat: f64,
-    radius: f64,
-    _unit: String,
-) -> RedisValueRef {
-    let origin = Point { lat, lng };
+/// Meters in one of the distance units GEO commands take
+fn unit_to_meters(unit: &str) -> Option<f64> {
+    match unit.to_lowercase().as_str() {
+        "m" => Some(1.0),
+        "km" => Some(1000.0),
+        "ft" => Some(0.3048),
+        "mi" => Some(1609.34),
+        _ => None,
+    }
+}
+
+fn unsupported_unit() -> RedisValueRef {
+    RError("ERR unsupported unit provided. please use M, KM, FT, MI")
+}
+
+/// Members of `key` within `radius` meters of `origin`, with their position
+/// and distance
+fn members_within(db: &Db, key: &str, origin: &Point, radius: f64) -> Vec<(String, Point, f64)> {
+    let Point { lat, lng } = *origin;
 
     // https://stackoverflow.com/questions/238260/how-to-calculate-the-bounding-box-for-a-given-lat-lng-location
     // http://janmatuschek.de/LatitudeLongitudeBoundingCoordinates#LongitudeIncorrect
@@ -200,24 +210,24 @@ pub fn geosearch(
     let max_score = encode_point(max_point);
 
     // This gives us rough set of candidates that can be filtered down futher by distance calculation
-    let candidates = zrangebyscore(db, key.clone(), min_score, max_score);
+    let candidates = zrangebyscore(db, key.to_string(), min_score, max_score);
     // TODO the command returns RedisValueRef, so we have to convert back to native values,
     // the logic should be factored out
 
-    let filtered_candidates: Vec<String> = match candidates {
+    match candidates {
         RedisValueRef::Array(members) => members
             .into_iter()
             .filter_map(|member| {
                 if let RedisValueRef::String(member_bytes) = member {
                     let member_name = String::from_utf8_lossy(&member_bytes).to_string();
                     if let RedisValueRef::String(score_bytes) =
-                        zscore(db, key.clone(), member_name.clone())
+                        zscore(db, key.to_string(), member_name.clone())
                         && let Ok(score) = String::from_utf8_lossy(&score_bytes).parse::<f64>()
                     {
                         let point = decode_geocode(score);
-                        let distance = haversine_distance(origin.clone(), point);
+                        let distance = haversine_distance(*origin, point);
                         if distance <= radius {
-                            return Some(member_name);
+                            return Some((member_name, point, distance));
                         }
                     }
                 }
@@ -225,9 +235,124 @@ pub fn geosearch(
             })
             .collect(),
         _ => Vec::new(),
+    }
+}
+
+pub fn geosearch(
+    db: &Db,
+    key: String,
+    lng: f64,
+    lat: f64,
+    radius: f64,
+    unit: String,
+) -> RedisValueRef {
+    let Some(meters) = unit_to_meters(&unit) else {
+        return unsupported_unit();
     };
+    let origin = Point { lat, lng };
+    let found = members_within(db, &key, &origin, radius * meters);
+    RArray(
+        found
+            .into_iter()
+            .map(|(member, _, _)| RString(member))
+            .collect(),
+    )
+}
 
-    RArray(filtered_candidates.into_iter().map(RString).collect())
+/// GEORADIUS options. Results are only sorted by distance when asked to, or
+/// nearest first when COUNT is given, as in Redis.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GeoRadiusOptions {
+    pub withcoord: bool,
+    pub withdist: bool,
+    pub count: Option<usize>,
+    /// Some(true) for DESC, Some(false) for ASC
+    pub desc: Option<bool>,
+}
+
+fn radius_reply(
+    mut found: Vec<(String, Point, f64)>,
+    meters: f64,
+    opts: &GeoRadiusOptions,
+) -> RedisValueRef {
+    let desc = match opts.desc {
+        None if opts.count.is_some() => Some(false),
+        desc => desc,
+    };
+    if let Some(desc) = desc {
+        found.sort_by(|a, b| a.2.total_cmp(&b.2));
+        if desc {
+            found.reverse();
+        }
+    }
+    if let Some(count) = opts.count {
+        found.truncate(count);
+    }
+    let results = found
+        .into_iter()
+        .map(|(member, point, distance)| {
+            if !opts.withdist && !opts.withcoord {
+                return RString(member);
+            }
+            let mut item = vec![RString(member)];
+            if opts.withdist {
+                item.push(RString(format!("{:.4}", distance / meters)));
+            }
+            if opts.withcoord {
+                item.push(RArray(vec![
+                    RString(point.lng.to_string()),
+                    RString(point.lat.to_string()),
+                ]));
+            }
+            RArray(item)
+        })
+        .collect();
+    RArray(results)
+}
+
+/// GEORADIUS: members within `radius` of a longitude/latitude
+pub fn georadius(
+    db: &Db,
+    key: String,
+    lng: f64,
+    lat: f64,
+    radius: f64,
+    unit: String,
+    opts: &GeoRadiusOptions,
+) -> RedisValueRef {
+    let Some(meters) = unit_to_meters(&unit) else {
+        return unsupported_unit();
+    };
+    let origin = Point { lat, lng };
+    if let Err(err) = validate_point(&origin) {
+        return RError(format!("ERR {}", err));
+    }
+    let found = members_within(db, &key, &origin, radius * meters);
+    radius_reply(found, meters, opts)
+}
+
+/// GEORADIUSBYMEMBER: members within `radius` of another member, itself
+/// included
+pub fn georadiusbymember(
+    db: &Db,
+    key: String,
+    member: String,
+    radius: f64,
+    unit: String,
+    opts: &GeoRadiusOptions,
+) -> RedisValueRef {
+    let Some(meters) = unit_to_meters(&unit) else {
+        return unsupported_unit();
+    };
+    let origin = match zscore(db, key.clone(), member) {
+        RedisValueRef::String(score) => match String::from_utf8_lossy(&score).parse::<f64>() {
+            Ok(score) => decode_geocode(score),
+            Err(_) => return RError("ERR could not decode requested zset member"),
+        },
+        _ => return RError("ERR could not decode requested zset member"),
+    };
+    let found = members_within(db, &key, &origin, radius * meters);
+    radius_reply(found, meters, opts)
 }
 
 #[cfg(test)]
@@ -329,6 +454,91 @@ mod tests {
         assert_eq!(result, RArray(vec![RString("Munich".to_string()),]));
     }
 
+    fn places() -> Arc<RedisDb> {
+        let db = setup();
+        geoadd(&db, s("places"), 11.5030378, 48.164271, s("Munich"));
+        geoadd(&db, s("places"), 2.2944692, 48.8584625, s("Paris"));
+        geoadd(&db, s("places"), -0.0884948, 51.506479, s("London"));
+        db
+    }
+
+    fn s(value: &str) -> String {
+        value.to_string()
+    }
+
+    #[test]
+    fn test_georadius() {
+        let db = places();
+        let opts = GeoRadiusOptions {
+            desc: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(
+            georadius(&db, s("places"), 2.0, 48.0, 500.0, s("km"), &opts),
+            RArray(vec![RString("Paris"), RString("London")])
+        );
+
+        let opts = GeoRadiusOptions {
+            withdist: true,
+            count: Some(1),
+            desc: Some(true),
+            ..Default::default()
+        };
+        let result = georadius(&db, s("places"), 2.0, 48.0, 1000.0, s("km"), &opts);
+        let RedisValueRef::Array(items) = result else {
+            panic!("expected an array, got {:?}", result);
+        };
+        let [RedisValueRef::Array(item)] = items.as_slice() else {
+            panic!("expected one item, got {:?}", items);
+        };
+        assert_eq!(item[0], RString("Munich"));
+        let RedisValueRef::String(distance) = &item[1] else {
+            panic!("expected a distance, got {:?}", item[1]);
+        };
+        let distance: f64 = String::from_utf8_lossy(distance).parse().unwrap();
+        assert!((690.0..720.0).contains(&distance), "distance {}", distance);
+
+        assert_eq!(
+            georadius(&db, s("places"), 2.0, 48.0, 500.0, s("yards"), &opts),
+            unsupported_unit()
+        );
+    }
+
+    #[test]
+    fn test_georadiusbymember() {
+        let db = places();
+        let opts = GeoRadiusOptions {
+            withcoord: true,
+            desc: Some(false),
+            ..Default::default()
+        };
+        let result = georadiusbymember(&db, s("places"), s("Paris"), 400.0, s("km"), &opts);
+        let RedisValueRef::Array(items) = result else {
+            panic!("expected an array, got {:?}", result);
+        };
+        let names: Vec<_> = items
+            .iter()
+            .map(|item| match item {
+                RedisValueRef::Array(item) => item[0].clone(),
+                other => panic!("expected an array item, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(names, vec![RString("Paris"), RString("London")]);
+
+        let count = GeoRadiusOptions {
+            count: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            georadiusbymember(&db, s("places"), s("Munich"), 2000.0, s("km"), &count),
+            RArray(vec![RString("Munich"), RString("Paris")])
+        );
+        assert_eq!(
+            georadiusbymember(&db, s("places"), s("Rome"), 100.0, s("km"), &count),
+            RError("ERR could not decode requested zset member")
+        );
+    }
+
     #[test]
     fn test_geosearch_large_radius() {
         let db = setup();
@@ -0,0 +1,125 @@
+/// Glob-style pattern matching as used by KEYS, SCAN MATCH, PSUBSCRIBE and
+/// CONFIG GET. Supports `*`, `?`, `[...]` classes (with `^` negation and
+/// `a-z` ranges) and `\` to escape the next character.
+pub fn glob_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let eq = |a: u8, b: u8| {
+        if nocase {
+            a.eq_ignore_ascii_case(&b)
+        } else {
+            a == b
+        }
+    };
+    let mut p = 0;
+    let mut s = 0;
+    // Where to resume after the most recent `*` if the rest fails to match
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while s < string.len() {
+        let consumed = match pattern.get(p) {
+            Some(b'*') => {
+                // Collapse runs of stars
+                while pattern.get(p + 1) == Some(&b'*') {
+                    p += 1;
+                }
+                backtrack = Some((p, s));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => match_class(pattern, p, string[s], nocase),
+            Some(b'\\') if p + 1 < pattern.len() => eq(pattern[p + 1], string[s]).then_some(p + 2),
+            Some(&c) => eq(c, string[s]).then_some(p + 1),
+            None => None,
+        };
+        match (consumed, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                s += 1;
+            }
+            (None, Some((star, matched))) => {
+                // Let the star swallow one more character and try again
+                p = star + 1;
+                s = matched + 1;
+                backtrack = Some((star, matched + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p.min(pattern.len())..].iter().all(|&c| c == b'*')
+}
+
+/// Match `c` against the class starting at `pattern[start] == b'['`. Returns
+/// the pattern index just past the class on success.
+fn match_class(pattern: &[u8], start: usize, c: u8, nocase: bool) -> Option<usize> {
+    let fold = |b: u8| if nocase { b.to_ascii_lowercase() } else { b };
+    let c = fold(c);
+    let mut p = start + 1;
+    let negate = pattern.get(p) == Some(&b'^');
+    if negate {
+        p += 1;
+    }
+    let mut matched = false;
+    // An unterminated class runs to the end of the pattern, as in Redis
+    while p < pattern.len() && pattern[p] != b']' {
+        if pattern[p] == b'\\' && p + 1 < pattern.len() {
+            p += 1;
+            matched |= fold(pattern[p]) == c;
+        } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+            let (mut lo, mut hi) = (fold(pattern[p]), fold(pattern[p + 2]));
+            if lo > hi {
+                std::mem::swap(&mut lo, &mut hi);
+            }
+            matched |= (lo..=hi).contains(&c);
+            p += 2;
+        } else {
+            matched |= fold(pattern[p]) == c;
+        }
+        p += 1;
+    }
+    (matched != negate).then_some(p + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn m(pattern: &str, string: &str) -> bool {
+        glob_match(pattern.as_bytes(), string.as_bytes(), false)
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert!(m("*", ""));
+        assert!(m("*", "anything"));
+        assert!(m("h?llo", "hello"));
+        assert!(!m("h?llo", "hllo"));
+        assert!(m("h*llo", "heeeello"));
+        assert!(m("h*llo", "hllo"));
+        assert!(m("a*b*c", "axxbyyc"));
+        assert!(!m("a*b*c", "axxbyy"));
+        assert!(m("**x", "abcx"));
+        assert!(!m("abc", "abcd"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(m("h[ae]llo", "hello"));
+        assert!(m("h[ae]llo", "hallo"));
+        assert!(!m("h[ae]llo", "hillo"));
+        assert!(m("h[^e]llo", "hallo"));
+        assert!(!m("h[^e]llo", "hello"));
+        assert!(m("h[a-b]llo", "hbllo"));
+        assert!(m("h[b-a]llo", "hallo"));
+        assert!(!m("h[a-b]llo", "hcllo"));
+        assert!(m("[\\]]", "]"));
+    }
+
+    #[test]
+    fn test_escape_and_nocase() {
+        assert!(m("h\\*llo", "h*llo"));
+        assert!(!m("h\\*llo", "hello"));
+        assert!(glob_match(b"HeLLo", b"hello", true));
+        assert!(glob_match(b"[A-C]x", b"bx", true));
+        assert!(!glob_match(b"HeLLo", b"hello", false));
+    }
+}
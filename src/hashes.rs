@@ -0,0 +1,635 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use crate::{
+    Db, GetExOption, RedisValue, expect_no_zset, now_ms,
+    parser::{RArray, RError, RInt, RNull, RedisValueRef},
+};
+
+/// Hashes default to the compact listpack encoding up to this many fields...
+pub const HASH_MAX_LISTPACK_ENTRIES: usize = 128;
+/// ...as long as every field and value is at most this many bytes
+pub const HASH_MAX_LISTPACK_VALUE: usize = 64;
+
+/// Hash storage. Fields can carry their own expiry (absolute ms), which is
+/// checked lazily whenever the hash is accessed. Field names are kept as
+/// bytes, so ones loaded from a dump needn't be UTF-8.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Hash {
+    fields: HashMap<Bytes, Bytes>,
+    expires: HashMap<Bytes, u64>,
+}
+
+impl Hash {
+    /// Drop every field whose TTL has passed, returning how many were removed
+    fn remove_expired(&mut self, now: u64) -> usize {
+        let expired: Vec<Bytes> = self
+            .expires
+            .iter()
+            .filter(|(_, expiry)| **expiry <= now)
+            .map(|(field, _)| field.clone())
+            .collect();
+        for field in &expired {
+            self.fields.remove(field);
+            self.expires.remove(field);
+        }
+        expired.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Field/value pairs, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, &Bytes)> {
+        self.fields.iter()
+    }
+
+    /// Fields with their value and absolute expiry, in no particular order
+    pub fn iter_with_expiry(&self) -> impl Iterator<Item = (&Bytes, &Bytes, Option<u64>)> {
+        self.fields
+            .iter()
+            .map(|(field, value)| (field, value, self.expires.get(field).copied()))
+    }
+
+    pub fn has_field_expiries(&self) -> bool {
+        !self.expires.is_empty()
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        let small = self.fields.len() <= HASH_MAX_LISTPACK_ENTRIES
+            && self.fields.iter().all(|(field, value)| {
+                field.len() <= HASH_MAX_LISTPACK_VALUE && value.len() <= HASH_MAX_LISTPACK_VALUE
+            });
+        match (small, self.expires.is_empty()) {
+            (true, true) => "listpack",
+            (true, false) => "listpackex",
+            (false, _) => "hashtable",
+        }
+    }
+}
+
+/// Build a hash from fields and their optional expiry, as when loading a dump
+impl FromIterator<(Bytes, Bytes, Option<u64>)> for Hash {
+    fn from_iter<I: IntoIterator<Item = (Bytes, Bytes, Option<u64>)>>(iter: I) -> Self {
+        let mut hash = Hash::default();
+        for (field, value, expire) in iter {
+            if let Some(expire) = expire {
+                hash.expires.insert(field.clone(), expire);
+            }
+            hash.fields.insert(field, value);
+        }
+        hash
+    }
+}
+
+fn wrong_type() -> RedisValueRef {
+    RError("WRONGTYPE Operation against a key holding the wrong kind of value")
+}
+
+/// Run `f` on the hash at `key` once its expired fields are gone. Ok(None)
+/// means there is no such key. The key is deleted if the hash ends up empty.
+fn with_hash<T>(
+    db: &Db,
+    key: &str,
+    f: impl FnOnce(&mut Hash) -> T,
+) -> Result<Option<T>, RedisValueRef> {
+    let (result, is_now_empty) = {
+        let Some(mut entry) = db.get_mut_if_valid(key) else {
+            return expect_no_zset(db, key).map(|()| None);
+        };
+        let RedisValue::Hash(hash) = &mut *entry else {
+            return Err(wrong_type());
+        };
+        db.add_dirty(hash.remove_expired(now_ms()) as u64);
+        let result = f(hash);
+        (result, hash.is_empty())
+    }; // Dict get_mut guard dropped
+
+    if is_now_empty {
+        db.dict.remove(key);
+        db.ttl.remove(key);
+    }
+    Ok(Some(result))
+}
+
+/// Make sure a hash exists at `key` for a write to fill in. An empty one is
+/// removed again by with_hash if nothing ends up in it.
+fn create_hash(db: &Db, key: &str) -> Result<(), RedisValueRef> {
+    expect_no_zset(db, key)?;
+    db.remove_if_expired(key);
+    db.dict
+        .entry(key.to_string())
+        .or_insert_with(|| RedisValue::Hash(Hash::default()));
+    Ok(())
+}
+
+/// HSET: set each field, replying with how many were new. Overwriting a
+/// field clears its TTL.
+pub fn hset(db: &Db, key: String, pairs: Vec<(String, String)>) -> RedisValueRef {
+    let set_fields = |hash: &mut Hash| {
+        db.add_dirty(pairs.len() as u64);
+        pairs
+            .into_iter()
+            .filter(|(field, value)| {
+                hash.expires.remove(field.as_bytes());
+                hash.fields
+                    .insert(Bytes::from(field.clone()), Bytes::from(value.clone()))
+                    .is_none()
+            })
+            .count()
+    };
+    if let Err(err) = create_hash(db, &key) {
+        return err;
+    }
+    match with_hash(db, &key, set_fields) {
+        Ok(added) => RInt(added.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+pub fn hget(db: &Db, key: String, field: String) -> RedisValueRef {
+    match with_hash(db, &key, |hash| hash.fields.get(field.as_bytes()).cloned()) {
+        Ok(Some(Some(value))) => RedisValueRef::String(value),
+        Ok(_) => RNull(),
+        Err(err) => err,
+    }
+}
+
+pub fn hgetall(db: &Db, key: String) -> RedisValueRef {
+    let pairs = |hash: &mut Hash| {
+        hash.iter()
+            .flat_map(|(field, value)| {
+                [
+                    RedisValueRef::String(field.clone()),
+                    RedisValueRef::String(value.clone()),
+                ]
+            })
+            .collect()
+    };
+    match with_hash(db, &key, pairs) {
+        Ok(Some(pairs)) => RArray(pairs),
+        Ok(None) => RArray(vec![]),
+        Err(err) => err,
+    }
+}
+
+pub fn hlen(db: &Db, key: String) -> RedisValueRef {
+    match with_hash(db, &key, |hash| hash.len()) {
+        Ok(len) => RInt(len.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+pub fn hdel(db: &Db, key: String, fields: Vec<String>) -> RedisValueRef {
+    let delete = |hash: &mut Hash| {
+        let removed = fields
+            .iter()
+            .filter(|field| {
+                hash.expires.remove(field.as_bytes());
+                hash.fields.remove(field.as_bytes()).is_some()
+            })
+            .count();
+        db.add_dirty(removed as u64);
+        removed
+    };
+    match with_hash(db, &key, delete) {
+        Ok(removed) => RInt(removed.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+/// Per-field replies for the HEXPIRE family: -2 for a missing field (or
+/// key), otherwise whatever `f` decides for the field
+fn per_field(
+    db: &Db,
+    key: &str,
+    fields: Vec<String>,
+    mut f: impl FnMut(&mut Hash, Bytes) -> i64,
+) -> RedisValueRef {
+    let replies = with_hash(db, key, |hash| {
+        fields
+            .iter()
+            .map(|field| match hash.fields.contains_key(field.as_bytes()) {
+                true => f(hash, Bytes::from(field.clone())),
+                false => -2,
+            })
+            .collect::<Vec<i64>>()
+    });
+    match replies {
+        Ok(Some(replies)) => RArray(replies.into_iter().map(RInt).collect()),
+        Ok(None) => RArray(fields.iter().map(|_| RInt(-2)).collect()),
+        Err(err) => err,
+    }
+}
+
+/// HEXPIRE: give each field a TTL. Replies 1 per field set, or 2 if a zero
+/// TTL deleted the field straight away.
+pub fn hexpire(db: &Db, key: String, seconds: u64, fields: Vec<String>) -> RedisValueRef {
+    let expiry = now_ms().saturating_add(seconds.saturating_mul(1000));
+    hpexpireat(db, key, expiry, fields)
+}
+
+/// HPEXPIREAT: like HEXPIRE with a unix time in milliseconds. Fields whose
+/// time has already come are deleted, replying 2 for them.
+pub fn hpexpireat(db: &Db, key: String, expiry: u64, fields: Vec<String>) -> RedisValueRef {
+    let now = now_ms();
+    per_field(db, &key, fields, |hash, field| {
+        db.add_dirty(1);
+        if expiry <= now {
+            hash.fields.remove(&field);
+            hash.expires.remove(&field);
+            2
+        } else {
+            hash.expires.insert(field, expiry);
+            1
+        }
+    })
+}
+
+/// HTTL: seconds left on each field, or -1 if it has no TTL
+pub fn httl(db: &Db, key: String, fields: Vec<String>) -> RedisValueRef {
+    let now = now_ms();
+    per_field(db, &key, fields, |hash, field| {
+        match hash.expires.get(&field) {
+            Some(expiry) => (expiry.saturating_sub(now).saturating_add(500) / 1000) as i64,
+            None => -1,
+        }
+    })
+}
+
+/// HPERSIST: remove each field's TTL. Replies 1 if one was removed, -1 if
+/// the field had none.
+pub fn hpersist(db: &Db, key: String, fields: Vec<String>) -> RedisValueRef {
+    per_field(db, &key, fields, |hash, field| {
+        match hash.expires.remove(&field) {
+            Some(_) => {
+                db.add_dirty(1);
+                1
+            }
+            None => -1,
+        }
+    })
+}
+
+/// Replies for commands that return a value (or null) per requested field
+fn field_values(values: Vec<Option<Bytes>>) -> RedisValueRef {
+    RArray(
+        values
+            .into_iter()
+            .map(|value| value.map_or_else(RNull, RedisValueRef::String))
+            .collect(),
+    )
+}
+
+/// HGETDEL: reply with each field's value and delete the fields
+pub fn hgetdel(db: &Db, key: String, fields: Vec<String>) -> RedisValueRef {
+    let take = |hash: &mut Hash| {
+        let values: Vec<Option<Bytes>> = fields
+            .iter()
+            .map(|field| {
+                hash.expires.remove(field.as_bytes());
+                hash.fields.remove(field.as_bytes())
+            })
+            .collect();
+        db.add_dirty(values.iter().flatten().count() as u64);
+        values
+    };
+    match with_hash(db, &key, take) {
+        Ok(Some(values)) => field_values(values),
+        Ok(None) => field_values(fields.iter().map(|_| None).collect()),
+        Err(err) => err,
+    }
+}
+
+/// HSETEX condition: FNX only sets the fields if none of them exist, FXX
+/// only if all of them do
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldCondition {
+    Fnx,
+    Fxx,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HSetExOptions {
+    pub condition: Option<FieldCondition>,
+    /// TTL for the fields, relative (EX/PX) or absolute (EXAT/PXAT)
+    pub expiry: Option<GetExOption>,
+    /// KEEPTTL: fields that already have a TTL keep it
+    pub keep_ttl: bool,
+}
+
+/// HSETEX: set the fields and their TTL in one go. Replies 1 if the fields
+/// were set, or 0 if FNX/FXX stopped them. With no TTL option, and no
+/// KEEPTTL, any TTL the fields had is cleared as with HSET. An absolute time
+/// in the past deletes the fields.
+pub fn hsetex(
+    db: &Db,
+    key: String,
+    opts: HSetExOptions,
+    pairs: Vec<(String, String)>,
+) -> RedisValueRef {
+    let now = now_ms();
+    let expiry = match opts.expiry {
+        Some(GetExOption::Expire(ttl)) => Some(now.saturating_add(ttl)),
+        Some(GetExOption::ExpireAt(at)) => Some(at),
+        Some(GetExOption::Persist) | None => None,
+    };
+    let set_fields = |hash: &mut Hash| {
+        let allowed = match opts.condition {
+            Some(FieldCondition::Fnx) => pairs
+                .iter()
+                .all(|(f, _)| !hash.fields.contains_key(f.as_bytes())),
+            Some(FieldCondition::Fxx) => pairs
+                .iter()
+                .all(|(f, _)| hash.fields.contains_key(f.as_bytes())),
+            None => true,
+        };
+        if !allowed {
+            return 0;
+        }
+        db.add_dirty(pairs.len() as u64);
+        for (field, value) in pairs {
+            let field = Bytes::from(field);
+            match expiry {
+                Some(at) if at <= now => {
+                    hash.fields.remove(&field);
+                    hash.expires.remove(&field);
+                }
+                Some(at) => {
+                    hash.fields.insert(field.clone(), Bytes::from(value));
+                    hash.expires.insert(field, at);
+                }
+                None => {
+                    if !opts.keep_ttl {
+                        hash.expires.remove(&field);
+                    }
+                    hash.fields.insert(field, Bytes::from(value));
+                }
+            }
+        }
+        1
+    };
+    // FXX can't be met without the hash, so don't create one for it
+    if opts.condition != Some(FieldCondition::Fxx)
+        && let Err(err) = create_hash(db, &key)
+    {
+        return err;
+    }
+    match with_hash(db, &key, set_fields) {
+        Ok(set) => RInt(set.unwrap_or(0)),
+        Err(err) => err,
+    }
+}
+
+/// HGETEX: reply with each field's value, then change the TTL of the fields
+/// that exist. An absolute time in the past deletes the field.
+pub fn hgetex(
+    db: &Db,
+    key: String,
+    option: Option<GetExOption>,
+    fields: Vec<String>,
+) -> RedisValueRef {
+    let now = now_ms();
+    let get_and_expire = |hash: &mut Hash| {
+        let values: Vec<Option<Bytes>> = fields
+            .iter()
+            .map(|field| hash.fields.get(field.as_bytes()).cloned())
+            .collect();
+        for (field, _) in fields
+            .iter()
+            .zip(&values)
+            .filter(|(_, value)| value.is_some())
+        {
+            let field = Bytes::from(field.clone());
+            match &option {
+                Some(GetExOption::Expire(ttl)) => {
+                    hash.expires.insert(field, now.saturating_add(*ttl));
+                }
+                Some(GetExOption::ExpireAt(at)) if *at <= now => {
+                    hash.fields.remove(&field);
+                    hash.expires.remove(&field);
+                }
+                Some(GetExOption::ExpireAt(at)) => {
+                    hash.expires.insert(field, *at);
+                }
+                Some(GetExOption::Persist) => {
+                    hash.expires.remove(&field);
+                }
+                None => continue,
+            }
+            db.add_dirty(1);
+        }
+        values
+    };
+    match with_hash(db, &key, get_and_expire) {
+        Ok(Some(values)) => field_values(values),
+        Ok(None) => field_values(fields.iter().map(|_| None).collect()),
+        Err(err) => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{RedisDb, parser::RString};
+
+    fn setup() -> Arc<RedisDb> {
+        Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
+    }
+
+    fn s(value: &str) -> String {
+        value.to_string()
+    }
+
+    /// HGETALL reply as sorted field/value pairs
+    fn sorted_pairs(reply: RedisValueRef) -> Vec<(String, String)> {
+        let RedisValueRef::Array(items) = reply else {
+            panic!("HGETALL should return an array");
+        };
+        let mut pairs: Vec<(String, String)> = items
+            .chunks_exact(2)
+            .map(|pair| (pair[0].to_string(), pair[1].to_string()))
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
+    #[test]
+    fn test_hset_hget() {
+        let db = setup();
+        let result = hset(&db, s("h"), vec![(s("a"), s("1")), (s("b"), s("2"))]);
+        assert_eq!(result, RInt(2));
+        let result = hset(&db, s("h"), vec![(s("a"), s("3")), (s("c"), s("4"))]);
+        assert_eq!(result, RInt(1));
+        assert_eq!(hget(&db, s("h"), s("a")), RString("3"));
+        assert_eq!(hget(&db, s("h"), s("z")), RNull());
+        assert_eq!(hlen(&db, s("h")), RInt(3));
+
+        assert_eq!(hdel(&db, s("h"), vec![s("a"), s("b"), s("z")]), RInt(2));
+        assert_eq!(hdel(&db, s("h"), vec![s("c")]), RInt(1));
+        assert!(db.dict.get("h").is_none());
+    }
+
+    #[test]
+    fn test_hash_wrong_type() {
+        let db = setup();
+        db.dict
+            .insert(s("str"), RedisValue::String(Bytes::from("v")));
+        assert_eq!(hget(&db, s("str"), s("a")), wrong_type());
+        assert_eq!(hset(&db, s("str"), vec![(s("a"), s("1"))]), wrong_type());
+    }
+
+    #[test]
+    fn test_expired_field_removed() {
+        let db = setup();
+        hset(&db, s("h"), vec![(s("a"), s("1")), (s("b"), s("2"))]);
+        assert_eq!(
+            hexpire(&db, s("h"), 100, vec![s("a"), s("b"), s("z")]),
+            RArray(vec![RInt(1), RInt(1), RInt(-2)])
+        );
+        assert_eq!(
+            hpersist(&db, s("h"), vec![s("b"), s("b")]),
+            RArray(vec![RInt(1), RInt(-1)])
+        );
+        assert_eq!(
+            httl(&db, s("h"), vec![s("a"), s("b")]),
+            RArray(vec![RInt(100), RInt(-1)])
+        );
+
+        // Push a's expiry into the past
+        if let Some(mut entry) = db.dict.get_mut("h")
+            && let RedisValue::Hash(hash) = &mut *entry
+        {
+            hash.expires.insert(Bytes::from("a"), now_ms() - 1);
+        }
+        assert_eq!(sorted_pairs(hgetall(&db, s("h"))), vec![(s("b"), s("2"))]);
+        assert_eq!(hget(&db, s("h"), s("a")), RNull());
+        assert_eq!(hlen(&db, s("h")), RInt(1));
+    }
+
+    #[test]
+    fn test_hexpire_zero_deletes() {
+        let db = setup();
+        hset(&db, s("h"), vec![(s("a"), s("1"))]);
+        assert_eq!(hexpire(&db, s("h"), 0, vec![s("a")]), RArray(vec![RInt(2)]));
+        assert!(db.dict.get("h").is_none());
+        assert_eq!(httl(&db, s("h"), vec![s("a")]), RArray(vec![RInt(-2)]));
+    }
+
+    #[test]
+    fn test_hgetdel() {
+        let db = setup();
+        hset(&db, s("h"), vec![(s("a"), s("1")), (s("b"), s("2"))]);
+        assert_eq!(
+            hgetdel(&db, s("h"), vec![s("a"), s("z")]),
+            RArray(vec![RString("1"), RNull()])
+        );
+        assert_eq!(sorted_pairs(hgetall(&db, s("h"))), vec![(s("b"), s("2"))]);
+
+        // Deleting the last field deletes the key
+        assert_eq!(
+            hgetdel(&db, s("h"), vec![s("b")]),
+            RArray(vec![RString("2")])
+        );
+        assert!(db.dict.get("h").is_none());
+        assert_eq!(hgetdel(&db, s("h"), vec![s("b")]), RArray(vec![RNull()]));
+    }
+
+    #[test]
+    fn test_hgetex() {
+        let db = setup();
+        hset(&db, s("h"), vec![(s("a"), s("1")), (s("b"), s("2"))]);
+        let ex = Some(GetExOption::Expire(100_000));
+        assert_eq!(
+            hgetex(&db, s("h"), ex, vec![s("a"), s("b"), s("z")]),
+            RArray(vec![RString("1"), RString("2"), RNull()])
+        );
+        assert_eq!(
+            httl(&db, s("h"), vec![s("a"), s("b")]),
+            RArray(vec![RInt(100), RInt(100)])
+        );
+
+        let persist = Some(GetExOption::Persist);
+        assert_eq!(
+            hgetex(&db, s("h"), persist, vec![s("a")]),
+            RArray(vec![RString("1")])
+        );
+        assert_eq!(
+            httl(&db, s("h"), vec![s("a"), s("b")]),
+            RArray(vec![RInt(-1), RInt(100)])
+        );
+
+        // A time in the past removes the field after replying with it
+        let past = Some(GetExOption::ExpireAt(1));
+        assert_eq!(
+            hgetex(&db, s("h"), past, vec![s("b")]),
+            RArray(vec![RString("2")])
+        );
+        assert_eq!(hget(&db, s("h"), s("b")), RNull());
+    }
+
+    #[test]
+    fn test_hsetex() {
+        let db = setup();
+        let px = |ms| HSetExOptions {
+            expiry: Some(GetExOption::Expire(ms)),
+            ..Default::default()
+        };
+        let pairs = vec![(s("a"), s("1")), (s("b"), s("2"))];
+        assert_eq!(hsetex(&db, s("h"), px(50), pairs), RInt(1));
+        assert_eq!(hget(&db, s("h"), s("a")), RString("1"));
+        assert_eq!(hlen(&db, s("h")), RInt(2));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(hget(&db, s("h"), s("a")), RNull());
+        assert!(db.dict.get("h").is_none());
+
+        let fxx = HSetExOptions {
+            condition: Some(FieldCondition::Fxx),
+            ..px(100_000)
+        };
+        assert_eq!(
+            hsetex(&db, s("h"), fxx.clone(), vec![(s("a"), s("1"))]),
+            RInt(0)
+        );
+        assert!(db.dict.get("h").is_none());
+
+        hset(&db, s("h"), vec![(s("a"), s("1"))]);
+        let fnx = HSetExOptions {
+            condition: Some(FieldCondition::Fnx),
+            ..px(100_000)
+        };
+        let pairs = vec![(s("a"), s("2")), (s("b"), s("2"))];
+        assert_eq!(hsetex(&db, s("h"), fnx, pairs.clone()), RInt(0));
+        assert_eq!(hlen(&db, s("h")), RInt(1));
+        assert_eq!(hsetex(&db, s("h"), fxx, vec![(s("a"), s("3"))]), RInt(1));
+        assert_eq!(httl(&db, s("h"), vec![s("a")]), RArray(vec![RInt(100)]));
+
+        // KEEPTTL leaves a's TTL alone, and b is new so has none
+        let keep_ttl = HSetExOptions {
+            keep_ttl: true,
+            ..Default::default()
+        };
+        assert_eq!(hsetex(&db, s("h"), keep_ttl, pairs), RInt(1));
+        assert_eq!(
+            httl(&db, s("h"), vec![s("a"), s("b")]),
+            RArray(vec![RInt(100), RInt(-1)])
+        );
+    }
+
+    #[test]
+    fn test_hset_clears_ttl() {
+        let db = setup();
+        hset(&db, s("h"), vec![(s("a"), s("1"))]);
+        hexpire(&db, s("h"), 100, vec![s("a")]);
+        hset(&db, s("h"), vec![(s("a"), s("2"))]);
+        assert_eq!(httl(&db, s("h"), vec![s("a")]), RArray(vec![RInt(-1)]));
+    }
+}
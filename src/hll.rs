@@ -0,0 +1,231 @@
+/// HyperLogLog cardinality estimation (PFADD/PFCOUNT/PFMERGE). A HyperLogLog
+/// is kept as an ordinary string value: a magic header followed by one byte
+/// per register, so it can be read, copied and persisted like any string.
+use bytes::Bytes;
+
+use crate::{
+    Db, RedisValue, expect_no_zset,
+    parser::{RError, RInt, RSimpleString, RedisValueRef},
+};
+
+/// Bits of the hash used to pick a register
+const HLL_P: u32 = 14;
+const HLL_REGISTERS: usize = 1 << HLL_P;
+const HLL_MAGIC: &[u8] = b"HYLL";
+
+type Registers = Vec<u8>;
+
+fn wrong_type() -> RedisValueRef {
+    RError("WRONGTYPE Operation against a key holding the wrong kind of value")
+}
+
+fn invalid_hll() -> RedisValueRef {
+    RError("WRONGTYPE Key is not a valid HyperLogLog string value.")
+}
+
+/// MurmurHash64A, the hash Redis feeds its HyperLogLogs with
+fn murmur_hash64a(data: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (data.len() as u64).wrapping_mul(M);
+
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+/// Register index for `element`, and the length of the run of zero bits
+/// (plus one) in the rest of its hash
+fn register_and_count(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, 0xadc83b19);
+    let index = (hash as usize) & (HLL_REGISTERS - 1);
+    // Guard bit so the count is at most 64 - HLL_P + 1
+    let rest = (hash >> HLL_P) | (1 << (64 - HLL_P));
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+/// Cardinality estimate from the registers, using linear counting while
+/// many registers are still empty since raw HyperLogLog overestimates there
+fn estimate(registers: &[u8]) -> u64 {
+    let m = HLL_REGISTERS as f64;
+    let alpha = 0.7213 / (1.0 + 1.079 / m);
+    let sum: f64 = registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+    let raw = alpha * m * m / sum;
+
+    let zeros = registers.iter().filter(|r| **r == 0).count();
+    if raw <= 2.5 * m && zeros > 0 {
+        (m * (m / zeros as f64).ln()).round() as u64
+    } else {
+        raw.round() as u64
+    }
+}
+
+/// The registers stored at `key`. Ok(None) means there is no such key.
+fn load(db: &Db, key: &str) -> Result<Option<Registers>, RedisValueRef> {
+    let Some(entry) = db.get_if_valid(key) else {
+        return expect_no_zset(db, key).map(|()| None);
+    };
+    match &*entry {
+        RedisValue::String(value)
+            if value.len() == HLL_MAGIC.len() + HLL_REGISTERS && value.starts_with(HLL_MAGIC) =>
+        {
+            Ok(Some(value[HLL_MAGIC.len()..].to_vec()))
+        }
+        RedisValue::String(_) => Err(invalid_hll()),
+        _ => Err(wrong_type()),
+    }
+}
+
+fn store(db: &Db, key: String, registers: &[u8]) {
+    let mut value = HLL_MAGIC.to_vec();
+    value.extend_from_slice(registers);
+    db.dict.insert(key, RedisValue::String(Bytes::from(value)));
+    db.add_dirty(1);
+}
+
+/// PFADD: replies 1 if any register changed (or the key was created), so the
+/// estimate has likely changed
+pub fn pfadd(db: &Db, key: String, elements: Vec<String>) -> RedisValueRef {
+    let (mut registers, mut changed) = match load(db, &key) {
+        Ok(Some(registers)) => (registers, false),
+        Ok(None) => (vec![0; HLL_REGISTERS], true),
+        Err(err) => return err,
+    };
+    for element in &elements {
+        let (index, count) = register_and_count(element.as_bytes());
+        if count > registers[index] {
+            registers[index] = count;
+            changed = true;
+        }
+    }
+    if changed {
+        store(db, key, &registers);
+    }
+    RInt(changed as i64)
+}
+
+/// Register-wise maximum of the given keys, ignoring missing ones
+fn merged(db: &Db, keys: &[String]) -> Result<Registers, RedisValueRef> {
+    let mut registers = vec![0; HLL_REGISTERS];
+    for key in keys {
+        if let Some(other) = load(db, key)? {
+            for (register, value) in registers.iter_mut().zip(other) {
+                *register = (*register).max(value);
+            }
+        }
+    }
+    Ok(registers)
+}
+
+/// PFCOUNT: estimated cardinality of the union of the given HyperLogLogs
+pub fn pfcount(db: &Db, keys: Vec<String>) -> RedisValueRef {
+    match merged(db, &keys) {
+        Ok(registers) => RInt(estimate(&registers) as i64),
+        Err(err) => err,
+    }
+}
+
+/// PFMERGE: store the union of `dest` and the sources in `dest`
+pub fn pfmerge(db: &Db, dest: String, sources: Vec<String>) -> RedisValueRef {
+    let mut keys = vec![dest.clone()];
+    keys.extend(sources);
+    match merged(db, &keys) {
+        Ok(registers) => {
+            store(db, dest, &registers);
+            RSimpleString("OK")
+        }
+        Err(err) => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::RedisDb;
+
+    fn setup() -> Arc<RedisDb> {
+        Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
+    }
+
+    fn s(value: &str) -> String {
+        value.to_string()
+    }
+
+    fn elements(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|i| format!("element:{}", i)).collect()
+    }
+
+    fn count(db: &Db, keys: &[&str]) -> i64 {
+        match pfcount(db, keys.iter().map(|k| s(k)).collect()) {
+            RedisValueRef::Int(n) => n,
+            other => panic!("PFCOUNT should return an integer, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pfadd_pfcount() {
+        let db = setup();
+        assert_eq!(pfadd(&db, s("hll"), elements(0..1000)), RInt(1));
+        let estimate = count(&db, &["hll"]);
+        assert!((970..=1030).contains(&estimate), "estimate {}", estimate);
+
+        // Re-adding known elements leaves every register alone
+        assert_eq!(pfadd(&db, s("hll"), elements(0..10)), RInt(0));
+        assert_eq!(count(&db, &["missing"]), 0);
+    }
+
+    #[test]
+    fn test_pfadd_creates_key() {
+        let db = setup();
+        assert_eq!(pfadd(&db, s("hll"), vec![]), RInt(1));
+        assert_eq!(pfadd(&db, s("hll"), vec![]), RInt(0));
+        assert_eq!(count(&db, &["hll"]), 0);
+    }
+
+    #[test]
+    fn test_pfmerge() {
+        let db = setup();
+        pfadd(&db, s("a"), elements(0..600));
+        pfadd(&db, s("b"), elements(400..1000));
+        let union = count(&db, &["a", "b"]);
+        assert!((970..=1030).contains(&union), "estimate {}", union);
+
+        assert_eq!(
+            pfmerge(&db, s("dest"), vec![s("a"), s("b")]),
+            RSimpleString("OK")
+        );
+        assert_eq!(count(&db, &["dest"]), union);
+    }
+
+    #[test]
+    fn test_hll_wrong_type() {
+        let db = setup();
+        db.dict
+            .insert(s("str"), RedisValue::String(Bytes::from("not an hll")));
+        assert_eq!(pfadd(&db, s("str"), vec![s("a")]), invalid_hll());
+        assert_eq!(pfcount(&db, vec![s("str")]), invalid_hll());
+
+        db.dict.insert(s("list"), RedisValue::List(vec![].into()));
+        assert_eq!(pfmerge(&db, s("dest"), vec![s("list")]), wrong_type());
+    }
+}
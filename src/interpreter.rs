@@ -4,26 +4,65 @@ use bytes::Bytes;
 use thiserror::Error;
 
 use crate::{
+    GetExOption, LcsOptions, ScanOptions, SetExpiry,
+    bits::{BitFieldOp, BitFieldType, BitOperation, BitUnit, MAX_BIT_OFFSET, Overflow},
+    commands::CommandFilter,
+    geo::GeoRadiusOptions,
+    hashes::{FieldCondition, HSetExOptions},
+    now_ms, parse_memory_size,
     parser::{RArray, RString, RedisValueRef},
-    streams::StreamIdIn,
+    sets::SetOperation,
+    sort::SortOptions,
+    streams::{StreamBoundIn, StreamIdIn, XINFO_FULL_COUNT},
+    zset::{LexBound, ScoreBound, ZAddOptions},
 };
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum RedisCommand {
-    Ping,
-    Echo(String),
+    Ping(Option<String>),
+    Lolwut(Option<u64>),
+    Echo(Bytes),
     Set(String, String),
     SetEx(String, String, u64),
+    SetPxAt(String, String, u64), // expires at unix ms
+    SetGet(String, String, Option<SetExpiry>),
     Get(String),
     Rpush(String, Vec<String>),
     Lpush(String, Vec<String>),
+    Rpushx(String, Vec<String>),
+    Lpushx(String, Vec<String>),
     Lrange(String, i64, i64),
     LLen(String),
     LPop(String, Option<u64>),
     BLPop(String, Option<f64>),
     Type(String),
-    XAdd(String, StreamIdIn, Vec<(String, String)>),
-    XRange(String, StreamIdIn, StreamIdIn),
+    /// Key, ID, field/value pairs and whether NOMKSTREAM was given
+    XAdd(String, StreamIdIn, Vec<(String, String)>, bool),
+    XRange(String, StreamBoundIn, StreamBoundIn),
+    XSetId(String, u64, u64),
+    XInfoStream(String, Option<usize>),
+    HSet(String, Vec<(String, String)>),
+    HGet(String, String),
+    HGetAll(String),
+    HLen(String),
+    HDel(String, Vec<String>),
+    HExpire(String, u64, Vec<String>),
+    HPExpireAt(String, u64, Vec<String>),
+    HTtl(String, Vec<String>),
+    HPersist(String, Vec<String>),
+    HGetDel(String, Vec<String>),
+    HGetEx(String, Option<GetExOption>, Vec<String>),
+    HSetEx(String, HSetExOptions, Vec<(String, String)>),
+    SAdd(String, Vec<String>),
+    SRem(String, Vec<String>),
+    SMembers(String),
+    SetOp(SetOperation, Vec<String>),
+    SetOpStore(SetOperation, String, Vec<String>),
+    SIsMember(String, String),
+    SCard(String),
+    PfAdd(String, Vec<String>),
+    PfCount(Vec<String>),
+    PfMerge(String, Vec<String>),
     XRead(Vec<(String, StreamIdIn)>, Option<u64>),
     Incr(String),
     Multi,
@@ -34,27 +73,99 @@ pub enum RedisCommand {
     Psync(String, i64),
     RdbPayload(Bytes),
     Wait(u64, u64),
+    WaitAof(u64, u64, u64), // numlocal, numreplicas, timeout
     Config(String, String),
     Keys(String),
-    Subscribe(String),
+    Del(Vec<String>),
+    Unlink(Vec<String>),
+    Scan(u64, ScanOptions),
+    Subscribe(Vec<String>),
     Unsubscribe(String),
     PSubscribe(String),
     PUnsubscribe(String),
     Publish(String, String),
-    ZAdd(String, f64, String),
+    PubSubChannels(Option<String>),
+    ZAdd(String, Vec<(f64, String)>, ZAddOptions),
     ZRank(String, String),
     ZRange(String, i64, i64),
     ZCard(String),
     ZScore(String, String),
     ZRem(String, String),
+    ZRemRangeByRank(String, i64, i64),
+    ZRemRangeByScore(String, ScoreBound, ScoreBound),
+    ZRemRangeByLex(String, LexBound, LexBound),
+    ZDiff(Vec<String>, bool),
+    ZDiffStore(String, Vec<String>),
     GeoAdd(String, f64, f64, String),
     GeoPos(String, Vec<String>),
     GeoDist(String, String, String),
     GeoSearch(String, f64, f64, f64, String), // key, lng, lat, radius, unit
+    GeoRadius(String, f64, f64, f64, String, GeoRadiusOptions),
+    GeoRadiusByMember(String, String, f64, String, GeoRadiusOptions),
     AclWhoami(),
     AclGetUser(String),
     AclSetUser(String, String),
     Auth(String, String),
+    Hello(Option<u64>, Option<(String, String)>, Option<String>), // protover, auth, name
+    Reset,
+    Quit,
+    FlushDb(bool), // async
+    FlushAll(bool),
+    Lcs(String, String, LcsOptions),
+    SetBit(String, u64, u8),
+    GetBit(String, u64),
+    BitCount(String, Option<(i64, i64)>, BitUnit),
+    BitPos(String, u8, Option<i64>, Option<i64>, BitUnit),
+    BitOp(BitOperation, String, Vec<String>),
+    BitField(String, Vec<BitFieldOp>),
+    ClientSetName(String),
+    ClientGetName(),
+    ClientTracking(bool),
+    ClientNoEvict(bool),
+    ClientNoTouch(bool),
+    CommandInfo(Vec<String>),
+    CommandCount,
+    CommandList(Option<CommandFilter>),
+    DebugSetActiveExpire(bool),
+    DebugQuicklistPackedThreshold(usize),
+    DebugStringMatchLen(String, String),
+    GetEx(String, Option<GetExOption>),
+    Append(String, String),
+    StrLen(String),
+    SetRange(String, usize, String),
+    GetRange(String, i64, i64),
+    Expire(String, i64),
+    PExpireAt(String, i64), // unix ms, also what EXPIREAT parses to
+    Sort(String, SortOptions),
+    ObjectEncoding(String),
+    ObjectIdleTime(String),
+    ObjectFreq(String),
+    Touch(Vec<String>),
+    BgSave,
+    Save,
+    LastSave,
+    Role,
+    /// Whether to save first: Some(true) for SAVE, Some(false) for NOSAVE,
+    /// None to follow the configuration
+    Shutdown(Option<bool>),
+    ConfigResetStat,
+    SlowlogGet(Option<i64>),
+    SlowlogLen,
+    SlowlogReset,
+    MemoryUsage(String, Option<usize>),
+    MemoryDoctor,
+    ClusterInfo,
+    ClusterMyId,
+    ClusterSlots,
+    ClusterShards,
+    FunctionList,
+    FunctionStats,
+    FunctionLoad(String),
+    Fcall(String, Vec<String>, Vec<String>),
+    DebugSleep(f64),
+    DebugChangeReplId,
+    DebugObject(String),
+    DebugReload,
 }
 
 impl RedisCommand {
@@ -63,25 +174,378 @@ impl RedisCommand {
             self,
             RedisCommand::Set(_, _)
                 | RedisCommand::SetEx(_, _, _)
+                | RedisCommand::SetPxAt(_, _, _)
+                | RedisCommand::SetGet(_, _, _)
                 | RedisCommand::Rpush(_, _)
                 | RedisCommand::Lpush(_, _)
+                | RedisCommand::Rpushx(_, _)
+                | RedisCommand::Lpushx(_, _)
                 | RedisCommand::LPop(_, _)
-                | RedisCommand::XAdd(_, _, _)
+                | RedisCommand::XAdd(_, _, _, _)
+                | RedisCommand::FlushDb(_)
+                | RedisCommand::FlushAll(_)
+                | RedisCommand::XSetId(_, _, _)
+                | RedisCommand::HSet(_, _)
+                | RedisCommand::HDel(_, _)
+                | RedisCommand::HExpire(_, _, _)
+                | RedisCommand::HPExpireAt(_, _, _)
+                | RedisCommand::HPersist(_, _)
+                | RedisCommand::HGetDel(_, _)
+                | RedisCommand::HGetEx(_, Some(_), _)
+                | RedisCommand::HSetEx(_, _, _)
+                | RedisCommand::SAdd(_, _)
+                | RedisCommand::SRem(_, _)
+                | RedisCommand::SetOpStore(_, _, _)
+                | RedisCommand::PfAdd(_, _)
+                | RedisCommand::PfMerge(_, _)
                 | RedisCommand::Incr(_)
+                | RedisCommand::SetBit(_, _, _)
+                | RedisCommand::BitOp(_, _, _)
+                | RedisCommand::BitField(_, _)
+                | RedisCommand::ZAdd(_, _, _)
+                | RedisCommand::ZRem(_, _)
+                | RedisCommand::ZRemRangeByRank(..)
+                | RedisCommand::ZRemRangeByScore(..)
+                | RedisCommand::ZRemRangeByLex(..)
+                | RedisCommand::ZDiffStore(_, _)
+                | RedisCommand::GeoAdd(_, _, _, _)
+                | RedisCommand::GetEx(_, Some(_))
+                | RedisCommand::Append(_, _)
+                | RedisCommand::SetRange(_, _, _)
+                | RedisCommand::Expire(_, _)
+                | RedisCommand::PExpireAt(_, _)
+                | RedisCommand::Del(_)
+                | RedisCommand::Unlink(_)
+                | RedisCommand::Publish(_, _)
+        ) || matches!(self, RedisCommand::Sort(_, opts) if opts.store.is_some())
+    }
+
+    /// The command name as reported by INFO commandstats. Subcommands of
+    /// container commands are named `container|subcommand`, like Redis does.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RedisCommand::Ping(..) => "ping",
+            RedisCommand::Lolwut(..) => "lolwut",
+            RedisCommand::Echo(..) => "echo",
+            RedisCommand::Set(..) => "set",
+            RedisCommand::SetEx(..) => "set",
+            RedisCommand::SetPxAt(..) => "set",
+            RedisCommand::SetGet(..) => "set",
+            RedisCommand::Get(..) => "get",
+            RedisCommand::Rpush(..) => "rpush",
+            RedisCommand::Lpush(..) => "lpush",
+            RedisCommand::Rpushx(..) => "rpushx",
+            RedisCommand::Lpushx(..) => "lpushx",
+            RedisCommand::Lrange(..) => "lrange",
+            RedisCommand::LLen(..) => "llen",
+            RedisCommand::LPop(..) => "lpop",
+            RedisCommand::BLPop(..) => "blpop",
+            RedisCommand::Type(..) => "type",
+            RedisCommand::XAdd(..) => "xadd",
+            RedisCommand::XRange(..) => "xrange",
+            RedisCommand::XSetId(..) => "xsetid",
+            RedisCommand::XInfoStream(..) => "xinfo|stream",
+            RedisCommand::HSet(..) => "hset",
+            RedisCommand::HGet(..) => "hget",
+            RedisCommand::HGetAll(..) => "hgetall",
+            RedisCommand::HLen(..) => "hlen",
+            RedisCommand::HDel(..) => "hdel",
+            RedisCommand::HExpire(..) => "hexpire",
+            RedisCommand::HPExpireAt(..) => "hpexpireat",
+            RedisCommand::HTtl(..) => "httl",
+            RedisCommand::HPersist(..) => "hpersist",
+            RedisCommand::HGetDel(..) => "hgetdel",
+            RedisCommand::HGetEx(..) => "hgetex",
+            RedisCommand::HSetEx(..) => "hsetex",
+            RedisCommand::SAdd(..) => "sadd",
+            RedisCommand::SRem(..) => "srem",
+            RedisCommand::SMembers(..) => "smembers",
+            RedisCommand::SetOp(op, _) => op.name(),
+            RedisCommand::SetOpStore(op, _, _) => op.store_name(),
+            RedisCommand::SIsMember(..) => "sismember",
+            RedisCommand::SCard(..) => "scard",
+            RedisCommand::PfAdd(..) => "pfadd",
+            RedisCommand::PfCount(..) => "pfcount",
+            RedisCommand::PfMerge(..) => "pfmerge",
+            RedisCommand::XRead(..) => "xread",
+            RedisCommand::Incr(..) => "incr",
+            RedisCommand::Multi => "multi",
+            RedisCommand::Exec => "exec",
+            RedisCommand::Discard => "discard",
+            RedisCommand::Info(..) => "info",
+            RedisCommand::ReplConf(..) => "replconf",
+            RedisCommand::Psync(..) => "psync",
+            RedisCommand::RdbPayload(..) => "psync",
+            RedisCommand::Wait(..) => "wait",
+            RedisCommand::WaitAof(..) => "waitaof",
+            RedisCommand::Config(..) => "config|get",
+            RedisCommand::Keys(..) => "keys",
+            RedisCommand::Del(..) => "del",
+            RedisCommand::Unlink(..) => "unlink",
+            RedisCommand::Subscribe(..) => "subscribe",
+            RedisCommand::Unsubscribe(..) => "unsubscribe",
+            RedisCommand::PSubscribe(..) => "psubscribe",
+            RedisCommand::PUnsubscribe(..) => "punsubscribe",
+            RedisCommand::Publish(..) => "publish",
+            RedisCommand::PubSubChannels(_) => "pubsub|channels",
+            RedisCommand::ZAdd(..) => "zadd",
+            RedisCommand::ZRank(..) => "zrank",
+            RedisCommand::ZRange(..) => "zrange",
+            RedisCommand::ZCard(..) => "zcard",
+            RedisCommand::ZScore(..) => "zscore",
+            RedisCommand::ZRem(..) => "zrem",
+            RedisCommand::ZRemRangeByRank(..) => "zremrangebyrank",
+            RedisCommand::ZRemRangeByScore(..) => "zremrangebyscore",
+            RedisCommand::ZRemRangeByLex(..) => "zremrangebylex",
+            RedisCommand::ZDiff(..) => "zdiff",
+            RedisCommand::ZDiffStore(..) => "zdiffstore",
+            RedisCommand::GeoAdd(..) => "geoadd",
+            RedisCommand::GeoPos(..) => "geopos",
+            RedisCommand::GeoDist(..) => "geodist",
+            RedisCommand::GeoSearch(..) => "geosearch",
+            RedisCommand::GeoRadius(..) => "georadius",
+            RedisCommand::GeoRadiusByMember(..) => "georadiusbymember",
+            RedisCommand::AclWhoami(..) => "acl|whoami",
+            RedisCommand::AclGetUser(..) => "acl|getuser",
+            RedisCommand::AclSetUser(..) => "acl|setuser",
+            RedisCommand::Auth(..) => "auth",
+            RedisCommand::Hello(..) => "hello",
+            RedisCommand::Reset => "reset",
+            RedisCommand::Quit => "quit",
+            RedisCommand::FlushDb(..) => "flushdb",
+            RedisCommand::FlushAll(..) => "flushall",
+            RedisCommand::Lcs(..) => "lcs",
+            RedisCommand::Scan(..) => "scan",
+            RedisCommand::SetBit(..) => "setbit",
+            RedisCommand::GetBit(..) => "getbit",
+            RedisCommand::BitCount(..) => "bitcount",
+            RedisCommand::BitPos(..) => "bitpos",
+            RedisCommand::BitOp(..) => "bitop",
+            RedisCommand::BitField(..) => "bitfield",
+            RedisCommand::ClientSetName(..) => "client|setname",
+            RedisCommand::ClientGetName(..) => "client|getname",
+            RedisCommand::ClientTracking(..) => "client|tracking",
+            RedisCommand::ClientNoEvict(..) => "client|no-evict",
+            RedisCommand::ClientNoTouch(..) => "client|no-touch",
+            RedisCommand::CommandInfo(..) => "command|info",
+            RedisCommand::CommandCount => "command|count",
+            RedisCommand::CommandList(..) => "command|list",
+            RedisCommand::DebugSetActiveExpire(..) => "debug",
+            RedisCommand::DebugQuicklistPackedThreshold(..) => "debug",
+            RedisCommand::DebugStringMatchLen(..) => "debug",
+            RedisCommand::GetEx(..) => "getex",
+            RedisCommand::Append(..) => "append",
+            RedisCommand::StrLen(..) => "strlen",
+            RedisCommand::SetRange(..) => "setrange",
+            RedisCommand::GetRange(..) => "getrange",
+            RedisCommand::Expire(..) => "expire",
+            RedisCommand::PExpireAt(..) => "pexpireat",
+            RedisCommand::Sort(..) => "sort",
+            RedisCommand::ObjectEncoding(..) => "object|encoding",
+            RedisCommand::ObjectIdleTime(..) => "object|idletime",
+            RedisCommand::ObjectFreq(..) => "object|freq",
+            RedisCommand::Touch(..) => "touch",
+            RedisCommand::BgSave => "bgsave",
+            RedisCommand::Save => "save",
+            RedisCommand::LastSave => "lastsave",
+            RedisCommand::Role => "role",
+            RedisCommand::Shutdown(..) => "shutdown",
+            RedisCommand::ConfigResetStat => "config|resetstat",
+            RedisCommand::SlowlogGet(..) => "slowlog|get",
+            RedisCommand::SlowlogLen => "slowlog|len",
+            RedisCommand::SlowlogReset => "slowlog|reset",
+            RedisCommand::MemoryUsage(..) => "memory|usage",
+            RedisCommand::MemoryDoctor => "memory|doctor",
+            RedisCommand::ClusterInfo => "cluster|info",
+            RedisCommand::ClusterMyId => "cluster|myid",
+            RedisCommand::ClusterSlots => "cluster|slots",
+            RedisCommand::ClusterShards => "cluster|shards",
+            RedisCommand::FunctionList => "function|list",
+            RedisCommand::FunctionStats => "function|stats",
+            RedisCommand::FunctionLoad(_) => "function|load",
+            RedisCommand::Fcall(..) => "fcall",
+            RedisCommand::DebugSleep(..) => "debug",
+            RedisCommand::DebugChangeReplId => "debug",
+            RedisCommand::DebugObject(..) => "debug",
+            RedisCommand::DebugReload => "debug",
+        }
+    }
+
+    /// The keys a command reads or writes, for client-side caching. Commands
+    /// that don't touch the keyspace return nothing.
+    pub fn keys(&self) -> Vec<&String> {
+        match self {
+            RedisCommand::Set(key, _)
+            | RedisCommand::SetEx(key, _, _)
+            | RedisCommand::SetPxAt(key, _, _)
+            | RedisCommand::SetGet(key, _, _)
+            | RedisCommand::Get(key)
+            | RedisCommand::Rpush(key, _)
+            | RedisCommand::Lpush(key, _)
+            | RedisCommand::Rpushx(key, _)
+            | RedisCommand::Lpushx(key, _)
+            | RedisCommand::Lrange(key, _, _)
+            | RedisCommand::LLen(key)
+            | RedisCommand::LPop(key, _)
+            | RedisCommand::BLPop(key, _)
+            | RedisCommand::Type(key)
+            | RedisCommand::XAdd(key, _, _, _)
+            | RedisCommand::XRange(key, _, _)
+            | RedisCommand::XSetId(key, _, _)
+            | RedisCommand::XInfoStream(key, _)
+            | RedisCommand::HSet(key, _)
+            | RedisCommand::HGet(key, _)
+            | RedisCommand::HGetAll(key)
+            | RedisCommand::HLen(key)
+            | RedisCommand::HDel(key, _)
+            | RedisCommand::HExpire(key, _, _)
+            | RedisCommand::HPExpireAt(key, _, _)
+            | RedisCommand::HTtl(key, _)
+            | RedisCommand::HPersist(key, _)
+            | RedisCommand::HGetDel(key, _)
+            | RedisCommand::HGetEx(key, _, _)
+            | RedisCommand::HSetEx(key, _, _)
+            | RedisCommand::SAdd(key, _)
+            | RedisCommand::SRem(key, _)
+            | RedisCommand::SMembers(key)
+            | RedisCommand::SIsMember(key, _)
+            | RedisCommand::SCard(key)
+            | RedisCommand::PfAdd(key, _)
+            | RedisCommand::Incr(key)
+            | RedisCommand::ZAdd(key, _, _)
+            | RedisCommand::ZRank(key, _)
+            | RedisCommand::ZRange(key, _, _)
+            | RedisCommand::ZCard(key)
+            | RedisCommand::ZScore(key, _)
+            | RedisCommand::ZRem(key, _)
+            | RedisCommand::ZRemRangeByRank(key, _, _)
+            | RedisCommand::ZRemRangeByScore(key, _, _)
+            | RedisCommand::ZRemRangeByLex(key, _, _)
+            | RedisCommand::GeoAdd(key, _, _, _)
+            | RedisCommand::GeoPos(key, _)
+            | RedisCommand::GeoDist(key, _, _)
+            | RedisCommand::GeoSearch(key, _, _, _, _)
+            | RedisCommand::GeoRadius(key, _, _, _, _, _)
+            | RedisCommand::GeoRadiusByMember(key, _, _, _, _)
+            | RedisCommand::SetBit(key, _, _)
+            | RedisCommand::GetBit(key, _)
+            | RedisCommand::BitField(key, _)
+            | RedisCommand::BitCount(key, _, _)
+            | RedisCommand::BitPos(key, _, _, _, _)
+            | RedisCommand::GetEx(key, _)
+            | RedisCommand::Append(key, _)
+            | RedisCommand::StrLen(key)
+            | RedisCommand::SetRange(key, _, _)
+            | RedisCommand::GetRange(key, _, _)
+            | RedisCommand::Expire(key, _)
+            | RedisCommand::PExpireAt(key, _)
+            | RedisCommand::ObjectEncoding(key)
+            | RedisCommand::ObjectIdleTime(key)
+            | RedisCommand::ObjectFreq(key)
+            | RedisCommand::DebugObject(key)
+            | RedisCommand::MemoryUsage(key, _) => vec![key],
+            RedisCommand::XRead(streams, _) => streams.iter().map(|(key, _)| key).collect(),
+            RedisCommand::Lcs(key1, key2, _) => vec![key1, key2],
+            RedisCommand::BitOp(_, dest, keys)
+            | RedisCommand::SetOpStore(_, dest, keys)
+            | RedisCommand::ZDiffStore(dest, keys) => std::iter::once(dest).chain(keys).collect(),
+            RedisCommand::PfCount(keys)
+            | RedisCommand::Touch(keys)
+            | RedisCommand::Del(keys)
+            | RedisCommand::Unlink(keys)
+            | RedisCommand::SetOp(_, keys)
+            | RedisCommand::ZDiff(keys, _)
+            | RedisCommand::Fcall(_, keys, _) => keys.iter().collect(),
+            RedisCommand::PfMerge(dest, keys) => std::iter::once(dest).chain(keys).collect(),
+            RedisCommand::Sort(key, opts) => std::iter::once(key).chain(&opts.store).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Whether running the command counts as an access to its keys for
+    /// OBJECT IDLETIME. Introspection commands look at keys without touching.
+    pub fn touches_keys(&self) -> bool {
+        !matches!(
+            self,
+            RedisCommand::Type(_)
+                | RedisCommand::ObjectEncoding(_)
+                | RedisCommand::ObjectIdleTime(_)
+                | RedisCommand::ObjectFreq(_)
+                | RedisCommand::DebugObject(_)
+                | RedisCommand::MemoryUsage(_, _)
         )
     }
+
+    /// Rewrite a command that has just run into the form replicas should apply,
+    /// so they end up with the same state rather than re-deciding it themselves.
+    /// Currently this pins auto-generated stream IDs to the ID the master chose.
+    pub fn for_replication(self, result: &RedisValueRef) -> RedisCommand {
+        let now = now_ms();
+        // Relative expiry times become absolute, so a command replayed later
+        // from the AOF or a replica's backlog doesn't restart the clock
+        let absolute = |option: Option<GetExOption>| match option {
+            Some(GetExOption::Expire(ttl)) => Some(GetExOption::ExpireAt(now.saturating_add(ttl))),
+            other => other,
+        };
+        match (self, result) {
+            (RedisCommand::SetEx(key, value, ttl), _)
+            | (RedisCommand::SetGet(key, value, Some(SetExpiry::In(ttl))), _) => {
+                RedisCommand::SetPxAt(key, value, now.saturating_add(ttl))
+            }
+            (RedisCommand::SetGet(key, value, Some(SetExpiry::At(at))), _) => {
+                RedisCommand::SetPxAt(key, value, at)
+            }
+            (RedisCommand::Expire(key, seconds), _) => RedisCommand::PExpireAt(
+                key,
+                (now as i64).saturating_add(seconds.saturating_mul(1000)),
+            ),
+            (RedisCommand::GetEx(key, option), _) => RedisCommand::GetEx(key, absolute(option)),
+            (RedisCommand::HExpire(key, seconds, fields), _) => RedisCommand::HPExpireAt(
+                key,
+                now.saturating_add(seconds.saturating_mul(1000)),
+                fields,
+            ),
+            (RedisCommand::HGetEx(key, option, fields), _) => {
+                RedisCommand::HGetEx(key, absolute(option), fields)
+            }
+            (RedisCommand::HSetEx(key, opts, pairs), _) => RedisCommand::HSetEx(
+                key,
+                HSetExOptions {
+                    expiry: absolute(opts.expiry),
+                    ..opts
+                },
+                pairs,
+            ),
+            (RedisCommand::XAdd(key, _id, fields, nomkstream), RedisValueRef::String(id)) => {
+                match parse_stream_id(&String::from_utf8_lossy(id)) {
+                    Ok(id) => RedisCommand::XAdd(key, id, fields, nomkstream),
+                    Err(_) => RedisCommand::XAdd(key, (None, None), fields, nomkstream),
+                }
+            }
+            (command, _) => command,
+        }
+    }
 }
 
 impl Display for RedisCommand {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RedisCommand::Ping => write!(f, "'Ping'"),
-            RedisCommand::Echo(msg) => write!(f, "'ECHO' {}", msg),
+            RedisCommand::Ping(message) => write!(f, "'Ping' {:?}", message),
+            RedisCommand::Lolwut(version) => write!(f, "'LOLWUT' {:?}", version),
+            RedisCommand::Echo(msg) => write!(f, "'ECHO' {}", String::from_utf8_lossy(msg)),
             RedisCommand::Set(key, value) => write!(f, "'SET' {} {}", key, value),
             RedisCommand::SetEx(key, value, ttl) => write!(f, "'SETEX' {} {} {}", key, value, ttl),
+            RedisCommand::SetPxAt(key, value, at) => {
+                write!(f, "'SET' {} {} PXAT {}", key, value, at)
+            }
+            RedisCommand::SetGet(key, value, ttl) => {
+                write!(f, "'SET' {} {} {:?} GET", key, value, ttl)
+            }
             RedisCommand::Get(key) => write!(f, "'GET' {}", key),
             RedisCommand::Rpush(key, values) => write!(f, "'RPUSH' {} {:?}", key, values),
             RedisCommand::Lpush(key, values) => write!(f, "'LPUSH' {} {:?}", key, values),
+            RedisCommand::Rpushx(key, values) => write!(f, "'RPUSHX' {} {:?}", key, values),
+            RedisCommand::Lpushx(key, values) => write!(f, "'LPUSHX' {} {:?}", key, values),
             RedisCommand::Lrange(key, start, stop) => {
                 write!(f, "'LRANGE' {} {} {}", key, start, stop)
             }
@@ -89,12 +553,47 @@ impl Display for RedisCommand {
             RedisCommand::LPop(key, count) => write!(f, "'LPOP' {} {:?}", key, count),
             RedisCommand::BLPop(key, timeout) => write!(f, "'BLPOP' {} {:?}", key, timeout),
             RedisCommand::Type(key) => write!(f, "'TYPE' {}", key),
-            RedisCommand::XAdd(key, id, fields) => {
-                write!(f, "'XADD' {} {:?} {:?}", key, id, fields)
+            RedisCommand::XAdd(key, id, fields, nomkstream) => {
+                write!(f, "'XADD' {} {:?} {:?} {}", key, id, fields, nomkstream)
             }
             RedisCommand::XRange(key, start, end) => {
                 write!(f, "'XRANGE' {} {:?} {:?}", key, start, end)
             }
+            RedisCommand::HSet(key, pairs) => write!(f, "'HSET' {} {:?}", key, pairs),
+            RedisCommand::HGet(key, field) => write!(f, "'HGET' {} {}", key, field),
+            RedisCommand::HGetAll(key) => write!(f, "'HGETALL' {}", key),
+            RedisCommand::HLen(key) => write!(f, "'HLEN' {}", key),
+            RedisCommand::HDel(key, fields) => write!(f, "'HDEL' {} {:?}", key, fields),
+            RedisCommand::HExpire(key, seconds, fields) => {
+                write!(f, "'HEXPIRE' {} {} {:?}", key, seconds, fields)
+            }
+            RedisCommand::HPExpireAt(key, at, fields) => {
+                write!(f, "'HPEXPIREAT' {} {} {:?}", key, at, fields)
+            }
+            RedisCommand::HTtl(key, fields) => write!(f, "'HTTL' {} {:?}", key, fields),
+            RedisCommand::HPersist(key, fields) => write!(f, "'HPERSIST' {} {:?}", key, fields),
+            RedisCommand::HGetDel(key, fields) => write!(f, "'HGETDEL' {} {:?}", key, fields),
+            RedisCommand::HGetEx(key, option, fields) => {
+                write!(f, "'HGETEX' {} {:?} {:?}", key, option, fields)
+            }
+            RedisCommand::HSetEx(key, opts, pairs) => {
+                write!(f, "'HSETEX' {} {:?} {:?}", key, opts, pairs)
+            }
+            RedisCommand::SAdd(key, members) => write!(f, "'SADD' {} {:?}", key, members),
+            RedisCommand::SRem(key, members) => write!(f, "'SREM' {} {:?}", key, members),
+            RedisCommand::SMembers(key) => write!(f, "'SMEMBERS' {}", key),
+            RedisCommand::SetOp(op, keys) => write!(f, "'{}' {:?}", op.name().to_uppercase(), keys),
+            RedisCommand::SetOpStore(op, dest, keys) => {
+                let name = op.store_name().to_uppercase();
+                write!(f, "'{}' {} {:?}", name, dest, keys)
+            }
+            RedisCommand::SIsMember(key, member) => write!(f, "'SISMEMBER' {} {}", key, member),
+            RedisCommand::SCard(key) => write!(f, "'SCARD' {}", key),
+            RedisCommand::PfAdd(key, elements) => write!(f, "'PFADD' {} {:?}", key, elements),
+            RedisCommand::PfCount(keys) => write!(f, "'PFCOUNT' {:?}", keys),
+            RedisCommand::PfMerge(dest, sources) => write!(f, "'PFMERGE' {} {:?}", dest, sources),
+            RedisCommand::XSetId(key, ms, seq) => write!(f, "'XSETID' {} {}-{}", key, ms, seq),
+            RedisCommand::XInfoStream(key, full) => write!(f, "'XINFO' STREAM {} {:?}", key, full),
             RedisCommand::XRead(streams, block) => write!(f, "'XREAD' {:?} {:?}", streams, block),
             RedisCommand::Incr(key) => write!(f, "'INCR' {}", key),
             RedisCommand::Multi => write!(f, "'Multi'"),
@@ -105,17 +604,26 @@ impl Display for RedisCommand {
             RedisCommand::Psync(repl_id, offset) => write!(f, "'PSYNC' {} {}", repl_id, offset),
             RedisCommand::RdbPayload(bytes) => write!(f, "'RdbPayload' {:?}", bytes),
             RedisCommand::Wait(replicas, timeout) => write!(f, "'WAIT' {} {}", replicas, timeout),
+            RedisCommand::WaitAof(numlocal, replicas, timeout) => {
+                write!(f, "'WAITAOF' {} {} {}", numlocal, replicas, timeout)
+            }
             RedisCommand::Config(key, value) => write!(f, "'CONFIG' {} {}", key, value),
             RedisCommand::Keys(pattern) => write!(f, "'KEYS' {}", pattern),
-            RedisCommand::Subscribe(channel) => write!(f, "'SUBSCRIBE' {}", channel),
+            RedisCommand::Del(keys) => write!(f, "'DEL' {:?}", keys),
+            RedisCommand::Unlink(keys) => write!(f, "'UNLINK' {:?}", keys),
+            RedisCommand::Scan(cursor, opts) => write!(f, "'SCAN' {} {:?}", cursor, opts),
+            RedisCommand::Subscribe(channels) => write!(f, "'SUBSCRIBE' {:?}", channels),
             RedisCommand::Unsubscribe(channel) => write!(f, "'UNSUBSCRIBE' {}", channel),
             RedisCommand::PSubscribe(pattern) => write!(f, "'PSUBSCRIBE' {}", pattern),
             RedisCommand::PUnsubscribe(pattern) => write!(f, "'PUNSUBSCRIBE' {}", pattern),
             RedisCommand::Publish(channel, message) => {
                 write!(f, "'PUBLISH' {} {}", channel, message)
             }
-            RedisCommand::ZAdd(key, score, member) => {
-                write!(f, "'ZADD' {} {} {}", key, score, member)
+            RedisCommand::PubSubChannels(pattern) => {
+                write!(f, "'PUBSUB' CHANNELS {:?}", pattern)
+            }
+            RedisCommand::ZAdd(key, members, opts) => {
+                write!(f, "'ZADD' {} {:?} {:?}", key, members, opts)
             }
             RedisCommand::ZRank(key, member) => write!(f, "'ZRANK' {} {}", key, member),
             RedisCommand::ZRange(key, start, stop) => {
@@ -125,6 +633,19 @@ impl Display for RedisCommand {
             RedisCommand::ZCard(key) => write!(f, "'ZCARD' {}", key),
             RedisCommand::ZScore(key, member) => write!(f, "'ZSCORE' {} {}", key, member),
             RedisCommand::ZRem(key, member) => write!(f, "'ZREM' {} {}", key, member),
+            RedisCommand::ZRemRangeByRank(key, start, stop) => {
+                write!(f, "'ZREMRANGEBYRANK' {} {} {}", key, start, stop)
+            }
+            RedisCommand::ZRemRangeByScore(key, min, max) => {
+                write!(f, "'ZREMRANGEBYSCORE' {} {:?} {:?}", key, min, max)
+            }
+            RedisCommand::ZRemRangeByLex(key, min, max) => {
+                write!(f, "'ZREMRANGEBYLEX' {} {:?} {:?}", key, min, max)
+            }
+            RedisCommand::ZDiff(keys, withscores) => {
+                write!(f, "'ZDIFF' {:?} {}", keys, withscores)
+            }
+            RedisCommand::ZDiffStore(dest, keys) => write!(f, "'ZDIFFSTORE' {} {:?}", dest, keys),
             RedisCommand::GeoAdd(key, lng, lat, member) => {
                 write!(f, "'GEOADD' {} {} {} {}", key, lng, lat, member)
             }
@@ -137,10 +658,111 @@ impl Display for RedisCommand {
             RedisCommand::GeoSearch(key, lng, lat, radius, unit) => {
                 write!(f, "'GEOSEARCH' {} {} {} {} {}", key, lng, lat, radius, unit)
             }
+            RedisCommand::GeoRadius(key, lng, lat, radius, unit, opts) => write!(
+                f,
+                "'GEORADIUS' {} {} {} {} {} {:?}",
+                key, lng, lat, radius, unit, opts
+            ),
+            RedisCommand::GeoRadiusByMember(key, member, radius, unit, opts) => write!(
+                f,
+                "'GEORADIUSBYMEMBER' {} {} {} {} {:?}",
+                key, member, radius, unit, opts
+            ),
             RedisCommand::AclWhoami() => write!(f, "'ACL' WHOAMI"),
             RedisCommand::AclGetUser(user) => write!(f, "'ACL' GETUSER {}", user),
             RedisCommand::AclSetUser(user, _) => write!(f, "'ACL' SETUSER {} ***", user),
             RedisCommand::Auth(username, _) => write!(f, "'AUTH' {} ***", username),
+            RedisCommand::Hello(protover, auth, name) => {
+                let username = auth.as_ref().map(|(username, _)| username);
+                write!(
+                    f,
+                    "'HELLO' {:?} AUTH {:?} *** {:?}",
+                    protover, username, name
+                )
+            }
+            RedisCommand::Reset => write!(f, "'RESET'"),
+            RedisCommand::Quit => write!(f, "'QUIT'"),
+            RedisCommand::FlushDb(asynchronous) => write!(f, "'FLUSHDB' {}", asynchronous),
+            RedisCommand::FlushAll(asynchronous) => write!(f, "'FLUSHALL' {}", asynchronous),
+            RedisCommand::BgSave => write!(f, "'BGSAVE'"),
+            RedisCommand::Save => write!(f, "'SAVE'"),
+            RedisCommand::LastSave => write!(f, "'LASTSAVE'"),
+            RedisCommand::Role => write!(f, "'ROLE'"),
+            RedisCommand::Shutdown(save) => write!(f, "'SHUTDOWN' {:?}", save),
+            RedisCommand::ConfigResetStat => write!(f, "'CONFIG' RESETSTAT"),
+            RedisCommand::SlowlogGet(count) => write!(f, "'SLOWLOG' GET {:?}", count),
+            RedisCommand::SlowlogLen => write!(f, "'SLOWLOG' LEN"),
+            RedisCommand::SlowlogReset => write!(f, "'SLOWLOG' RESET"),
+            RedisCommand::MemoryUsage(key, samples) => {
+                write!(f, "'MEMORY' USAGE {} {:?}", key, samples)
+            }
+            RedisCommand::MemoryDoctor => write!(f, "'MEMORY' DOCTOR"),
+            RedisCommand::ClusterInfo => write!(f, "'CLUSTER' INFO"),
+            RedisCommand::ClusterMyId => write!(f, "'CLUSTER' MYID"),
+            RedisCommand::ClusterSlots => write!(f, "'CLUSTER' SLOTS"),
+            RedisCommand::ClusterShards => write!(f, "'CLUSTER' SHARDS"),
+            RedisCommand::FunctionList => write!(f, "'FUNCTION' LIST"),
+            RedisCommand::FunctionStats => write!(f, "'FUNCTION' STATS"),
+            RedisCommand::FunctionLoad(code) => write!(f, "'FUNCTION' LOAD {}", code),
+            RedisCommand::Fcall(function, keys, args) => {
+                write!(f, "'FCALL' {} {:?} {:?}", function, keys, args)
+            }
+            RedisCommand::DebugSleep(seconds) => write!(f, "'DEBUG' SLEEP {}", seconds),
+            RedisCommand::DebugChangeReplId => write!(f, "'DEBUG' CHANGE-REPL-ID"),
+            RedisCommand::DebugObject(key) => write!(f, "'DEBUG' OBJECT {}", key),
+            RedisCommand::DebugReload => write!(f, "'DEBUG' RELOAD"),
+            RedisCommand::Lcs(key1, key2, opts) => write!(f, "'LCS' {} {} {:?}", key1, key2, opts),
+            RedisCommand::SetBit(key, offset, value) => {
+                write!(f, "'SETBIT' {} {} {}", key, offset, value)
+            }
+            RedisCommand::GetBit(key, offset) => write!(f, "'GETBIT' {} {}", key, offset),
+            RedisCommand::BitCount(key, range, unit) => {
+                write!(f, "'BITCOUNT' {} {:?} {:?}", key, range, unit)
+            }
+            RedisCommand::BitPos(key, bit, start, end, unit) => {
+                write!(
+                    f,
+                    "'BITPOS' {} {} {:?} {:?} {:?}",
+                    key, bit, start, end, unit
+                )
+            }
+            RedisCommand::BitField(key, ops) => write!(f, "'BITFIELD' {} {:?}", key, ops),
+            RedisCommand::BitOp(op, dest, keys) => {
+                write!(f, "'BITOP' {:?} {} {:?}", op, dest, keys)
+            }
+            RedisCommand::ClientSetName(name) => write!(f, "'CLIENT' SETNAME {}", name),
+            RedisCommand::ClientGetName() => write!(f, "'CLIENT' GETNAME"),
+            RedisCommand::ClientTracking(on) => write!(f, "'CLIENT' TRACKING {}", on),
+            RedisCommand::ClientNoEvict(on) => write!(f, "'CLIENT' NO-EVICT {}", on),
+            RedisCommand::ClientNoTouch(on) => write!(f, "'CLIENT' NO-TOUCH {}", on),
+            RedisCommand::CommandInfo(names) => write!(f, "'COMMAND' INFO {:?}", names),
+            RedisCommand::CommandCount => write!(f, "'COMMAND' COUNT"),
+            RedisCommand::CommandList(filter) => write!(f, "'COMMAND' LIST {:?}", filter),
+            RedisCommand::DebugSetActiveExpire(enabled) => {
+                write!(f, "'DEBUG' SET-ACTIVE-EXPIRE {}", enabled)
+            }
+            RedisCommand::DebugQuicklistPackedThreshold(threshold) => {
+                write!(f, "'DEBUG' QUICKLIST-PACKED-THRESHOLD {}", threshold)
+            }
+            RedisCommand::DebugStringMatchLen(pattern, string) => {
+                write!(f, "'DEBUG' STRINGMATCH-LEN {} {}", pattern, string)
+            }
+            RedisCommand::GetEx(key, option) => write!(f, "'GETEX' {} {:?}", key, option),
+            RedisCommand::Append(key, value) => write!(f, "'APPEND' {} {}", key, value),
+            RedisCommand::StrLen(key) => write!(f, "'STRLEN' {}", key),
+            RedisCommand::Expire(key, seconds) => write!(f, "'EXPIRE' {} {}", key, seconds),
+            RedisCommand::PExpireAt(key, at) => write!(f, "'PEXPIREAT' {} {}", key, at),
+            RedisCommand::Sort(key, opts) => write!(f, "'SORT' {} {:?}", key, opts),
+            RedisCommand::ObjectEncoding(key) => write!(f, "'OBJECT' ENCODING {}", key),
+            RedisCommand::ObjectIdleTime(key) => write!(f, "'OBJECT' IDLETIME {}", key),
+            RedisCommand::ObjectFreq(key) => write!(f, "'OBJECT' FREQ {}", key),
+            RedisCommand::Touch(keys) => write!(f, "'TOUCH' {:?}", keys),
+            RedisCommand::GetRange(key, start, end) => {
+                write!(f, "'GETRANGE' {} {} {}", key, start, end)
+            }
+            RedisCommand::SetRange(key, offset, value) => {
+                write!(f, "'SETRANGE' {} {} {}", key, offset, value)
+            }
         }
     }
 }
@@ -161,6 +783,10 @@ pub enum CmdError {
     InvalidIntegerArg { field: String },
     #[error("could not parse {field}")]
     ParseError { field: String },
+    #[error("value is not a valid float")]
+    InvalidFloat,
+    #[error("invalid expire time in '{0}' command")]
+    InvalidExpireTime(String),
     #[error("conversion not implemented")]
     ConversionError,
 }
@@ -185,6 +811,140 @@ where
     })
 }
 
+type CommandParser = fn(&[RedisValueRef]) -> Result<RedisCommand, CmdError>;
+
+/// Every command the interpreter recognizes, with the function that parses
+/// its arguments. Names are uppercase.
+pub const COMMANDS: &[(&str, CommandParser)] = &[
+    ("PING", ping),
+    ("ECHO", echo),
+    ("LOLWUT", lolwut),
+    ("SET", set),
+    ("SETEX", |args| setex(args, true)),
+    ("PSETEX", |args| setex(args, false)),
+    ("GET", get),
+    ("GETEX", getex),
+    ("APPEND", append),
+    ("STRLEN", strlen),
+    ("SETRANGE", setrange),
+    ("GETRANGE", getrange),
+    ("EXPIRE", expire),
+    ("EXPIREAT", |args| expireat(args, true)),
+    ("PEXPIREAT", |args| expireat(args, false)),
+    ("SORT", |args| sort(args, false)),
+    ("SORT_RO", |args| sort(args, true)),
+    ("OBJECT", object),
+    ("TOUCH", touch),
+    ("RPUSH", rpush),
+    ("LPUSH", lpush),
+    ("RPUSHX", rpushx),
+    ("LPUSHX", lpushx),
+    ("LRANGE", lrange),
+    ("LLEN", llen),
+    ("LPOP", lpop),
+    ("BLPOP", blpop),
+    ("TYPE", _type),
+    ("XADD", xadd),
+    ("XRANGE", xrange),
+    ("XSETID", xsetid),
+    ("XINFO", xinfo),
+    ("HSET", hset),
+    ("HGET", hget),
+    ("HGETALL", hgetall),
+    ("HLEN", hlen),
+    ("HDEL", hdel),
+    ("HEXPIRE", hexpire),
+    ("HPEXPIREAT", hpexpireat),
+    ("HTTL", httl),
+    ("HPERSIST", hpersist),
+    ("HGETDEL", hgetdel),
+    ("HGETEX", hgetex),
+    ("HSETEX", hsetex),
+    ("SADD", sadd),
+    ("SREM", srem),
+    ("SMEMBERS", smembers),
+    ("SINTER", |args| set_op(args, SetOperation::Inter)),
+    ("SUNION", |args| set_op(args, SetOperation::Union)),
+    ("SDIFF", |args| set_op(args, SetOperation::Diff)),
+    ("SINTERSTORE", |args| {
+        set_op_store(args, SetOperation::Inter)
+    }),
+    ("SUNIONSTORE", |args| {
+        set_op_store(args, SetOperation::Union)
+    }),
+    ("SDIFFSTORE", |args| set_op_store(args, SetOperation::Diff)),
+    ("SISMEMBER", sismember),
+    ("SCARD", scard),
+    ("PFADD", pfadd),
+    ("PFCOUNT", pfcount),
+    ("PFMERGE", pfmerge),
+    ("XREAD", xread),
+    ("INCR", incr),
+    ("MULTI", |_| Ok(RedisCommand::Multi)),
+    ("EXEC", |_| Ok(RedisCommand::Exec)),
+    ("DISCARD", |_| Ok(RedisCommand::Discard)),
+    ("INFO", info),
+    ("REPLCONF", replconf),
+    ("PSYNC", psync),
+    ("WAIT", wait),
+    ("WAITAOF", waitaof),
+    ("CONFIG", config),
+    ("KEYS", keys),
+    ("DEL", del),
+    ("UNLINK", unlink),
+    ("SCAN", scan),
+    ("SUBSCRIBE", subscribe),
+    ("UNSUBSCRIBE", unsubscribe),
+    ("PSUBSCRIBE", psubscribe),
+    ("PUNSUBSCRIBE", punsubscribe),
+    ("PUBLISH", publish),
+    ("PUBSUB", pubsub),
+    ("ZADD", zadd),
+    ("ZRANK", zrank),
+    ("ZRANGE", zrange),
+    ("ZCARD", zcard),
+    ("ZSCORE", zscore),
+    ("ZREM", zrem),
+    ("ZREMRANGEBYRANK", zremrangebyrank),
+    ("ZREMRANGEBYSCORE", zremrangebyscore),
+    ("ZREMRANGEBYLEX", zremrangebylex),
+    ("ZDIFF", zdiff),
+    ("ZDIFFSTORE", zdiffstore),
+    ("GEOADD", geoadd),
+    ("GEOPOS", geopos),
+    ("GEODIST", geodist),
+    ("GEOSEARCH", geosearch),
+    ("GEORADIUS", georadius),
+    ("GEORADIUSBYMEMBER", georadiusbymember),
+    ("ACL", acl),
+    ("AUTH", auth),
+    ("HELLO", hello),
+    ("RESET", |_| Ok(RedisCommand::Reset)),
+    ("QUIT", |_| Ok(RedisCommand::Quit)),
+    ("FLUSHDB", flushdb),
+    ("FLUSHALL", flushall),
+    ("BGSAVE", |_| Ok(RedisCommand::BgSave)),
+    ("SAVE", |_| Ok(RedisCommand::Save)),
+    ("LASTSAVE", |_| Ok(RedisCommand::LastSave)),
+    ("ROLE", |_| Ok(RedisCommand::Role)),
+    ("SHUTDOWN", shutdown),
+    ("LCS", lcs),
+    ("SETBIT", setbit),
+    ("GETBIT", getbit),
+    ("BITCOUNT", bitcount),
+    ("BITPOS", bitpos),
+    ("BITOP", bitop),
+    ("BITFIELD", bitfield),
+    ("CLIENT", client),
+    ("COMMAND", _command),
+    ("DEBUG", debug),
+    ("SLOWLOG", slowlog),
+    ("MEMORY", memory),
+    ("CLUSTER", cluster),
+    ("FUNCTION", function),
+    ("FCALL", fcall),
+];
+
 impl TryFrom<RedisValueRef> for RedisCommand {
     type Error = CmdError;
 
@@ -204,49 +964,9 @@ impl TryFrom<RedisValueRef> for RedisCommand {
                     }
                 };
 
-                match command.as_str() {
-                    "PING" => Ok(RedisCommand::Ping),
-                    "ECHO" => echo(&args),
-                    "SET" => set(&args),
-                    "GET" => get(&args),
-                    "RPUSH" => rpush(&args),
-                    "LPUSH" => lpush(&args),
-                    "LRANGE" => lrange(&args),
-                    "LLEN" => llen(&args),
-                    "LPOP" => lpop(&args),
-                    "BLPOP" => blpop(&args),
-                    "TYPE" => _type(&args),
-                    "XADD" => xadd(&args),
-                    "XRANGE" => xrange(&args),
-                    "XREAD" => xread(&args),
-                    "INCR" => incr(&args),
-                    "MULTI" => Ok(RedisCommand::Multi),
-                    "EXEC" => Ok(RedisCommand::Exec),
-                    "DISCARD" => Ok(RedisCommand::Discard),
-                    "INFO" => info(&args),
-                    "REPLCONF" => replconf(&args),
-                    "PSYNC" => psync(&args),
-                    "WAIT" => wait(&args),
-                    "CONFIG" => config(&args),
-                    "KEYS" => keys(&args),
-                    "SUBSCRIBE" => subscribe(&args),
-                    "UNSUBSCRIBE" => unsubscribe(&args),
-                    "PSUBSCRIBE" => psubscribe(&args),
-                    "PUNSUBSCRIBE" => punsubscribe(&args),
-                    "PUBLISH" => publish(&args),
-                    "ZADD" => zadd(&args),
-                    "ZRANK" => zrank(&args),
-                    "ZRANGE" => zrange(&args),
-                    "ZCARD" => zcard(&args),
-                    "ZSCORE" => zscore(&args),
-                    "ZREM" => zrem(&args),
-                    "GEOADD" => geoadd(&args),
-                    "GEOPOS" => geopos(&args),
-                    "GEODIST" => geodist(&args),
-                    "GEOSEARCH" => geosearch(&args),
-                    "ACL" => acl(&args),
-                    "AUTH" => auth(&args),
-                    _ => Err(CmdError::InvalidCommand(command.to_string())),
+                match COMMANDS.iter().find(|(name, _)| *name == command.as_str()) {
+                    Some((_, parse)) => parse(&args),
+                    None => Err(CmdError::InvalidCommand(command.to_string())),
                 }
             }
             RedisValueRef::String(s) => {
@@ -261,42 +981,723 @@ impl TryFrom<RedisValueRef> for RedisCommand {
     }
 }
 
+/// Build the RESP array for a command from its name and arguments
+fn command_value(name: &str, args: Vec<String>) -> RedisValueRef {
+    RArray(
+        std::iter::once(RString(name))
+            .chain(args.into_iter().map(RString))
+            .collect(),
+    )
+}
+
+/// GETEX style expiry options, always written in milliseconds
+fn getex_option_args(option: Option<GetExOption>) -> Vec<String> {
+    match option {
+        Some(GetExOption::Expire(ttl)) => vec!["PX".to_string(), ttl.to_string()],
+        Some(GetExOption::ExpireAt(at)) => vec!["PXAT".to_string(), at.to_string()],
+        Some(GetExOption::Persist) => vec!["PERSIST".to_string()],
+        None => vec![],
+    }
+}
+
+fn bit_unit_arg(unit: BitUnit) -> String {
+    match unit {
+        BitUnit::Byte => "BYTE".to_string(),
+        BitUnit::Bit => "BIT".to_string(),
+    }
+}
+
+fn stream_bound_arg(bound: StreamBoundIn, open_end: &str) -> String {
+    let (id, exclusive) = bound;
+    let id = match id {
+        (None, None) => open_end.to_string(),
+        id => stream_id_arg(id),
+    };
+    if exclusive { format!("({}", id) } else { id }
+}
+
+fn score_bound_arg(bound: ScoreBound) -> String {
+    let (score, exclusive) = bound;
+    if exclusive {
+        format!("({}", score)
+    } else {
+        score.to_string()
+    }
+}
+
+fn lex_bound_arg(bound: LexBound) -> String {
+    match bound {
+        LexBound::Min => "-".to_string(),
+        LexBound::Max => "+".to_string(),
+        LexBound::Inclusive(member) => format!("[{}", member),
+        LexBound::Exclusive(member) => format!("({}", member),
+    }
+}
+
+fn georadius_option_args(opts: &GeoRadiusOptions) -> Vec<String> {
+    let mut args = Vec::new();
+    if opts.withcoord {
+        args.push("WITHCOORD".to_string());
+    }
+    if opts.withdist {
+        args.push("WITHDIST".to_string());
+    }
+    if let Some(count) = opts.count {
+        args.extend(["COUNT".to_string(), count.to_string()]);
+    }
+    match opts.desc {
+        Some(true) => args.push("DESC".to_string()),
+        Some(false) => args.push("ASC".to_string()),
+        None => {}
+    }
+    args
+}
+
 impl TryFrom<RedisCommand> for RedisValueRef {
     type Error = CmdError;
 
     fn try_from(cmd: RedisCommand) -> Result<Self, CmdError> {
         let value = match cmd {
-            RedisCommand::Ping => RArray(vec![RString("PING")]),
-            RedisCommand::Set(key, value) => {
-                RArray(vec![RString("SET"), RString(key), RString(value)])
-            }
-            RedisCommand::SetEx(key, value, expire) => RArray(vec![
-                RString("SET"),
-                RString(key),
-                RString(value),
-                RString(expire.to_string()),
-            ]),
-            RedisCommand::ReplConf(key, value) => {
-                RArray(vec![RString("REPLCONF"), RString(key), RString(value)])
-            }
-            RedisCommand::Psync(id, offset) => RArray(vec![
-                RString("PSYNC"),
-                RString(id),
-                RString(offset.to_string()),
-            ]),
-            _ => {
-                return Err(CmdError::ConversionError);
+            RedisCommand::Ping(message) => command_value("PING", message.into_iter().collect()),
+            RedisCommand::Lolwut(version) => command_value(
+                "LOLWUT",
+                version
+                    .into_iter()
+                    .flat_map(|version| ["VERSION".to_string(), version.to_string()])
+                    .collect(),
+            ),
+            RedisCommand::Echo(message) => {
+                RArray(vec![RString("ECHO"), RedisValueRef::String(message)])
+            }
+            RedisCommand::Set(key, value) => command_value("SET", vec![key, value]),
+            RedisCommand::SetEx(key, value, expire) => command_value(
+                "SET",
+                vec![key, value, "PX".to_string(), expire.to_string()],
+            ),
+            RedisCommand::SetPxAt(key, value, at) => {
+                command_value("SET", vec![key, value, "PXAT".to_string(), at.to_string()])
+            }
+            // Replicas only need the write, not the old value
+            RedisCommand::SetGet(key, value, Some(SetExpiry::In(expire))) => command_value(
+                "SET",
+                vec![key, value, "PX".to_string(), expire.to_string()],
+            ),
+            RedisCommand::SetGet(key, value, Some(SetExpiry::At(at))) => {
+                command_value("SET", vec![key, value, "PXAT".to_string(), at.to_string()])
+            }
+            RedisCommand::SetGet(key, value, None) => command_value("SET", vec![key, value]),
+            RedisCommand::Get(key) => command_value("GET", vec![key]),
+            RedisCommand::Rpush(key, values) => {
+                command_value("RPUSH", std::iter::once(key).chain(values).collect())
+            }
+            RedisCommand::Lpush(key, values) => {
+                command_value("LPUSH", std::iter::once(key).chain(values).collect())
+            }
+            RedisCommand::Rpushx(key, values) => {
+                command_value("RPUSHX", std::iter::once(key).chain(values).collect())
+            }
+            RedisCommand::Lpushx(key, values) => {
+                command_value("LPUSHX", std::iter::once(key).chain(values).collect())
+            }
+            RedisCommand::Lrange(key, start, stop) => {
+                command_value("LRANGE", vec![key, start.to_string(), stop.to_string()])
+            }
+            RedisCommand::LLen(key) => command_value("LLEN", vec![key]),
+            RedisCommand::LPop(key, count) => command_value(
+                "LPOP",
+                std::iter::once(key)
+                    .chain(count.map(|count| count.to_string()))
+                    .collect(),
+            ),
+            RedisCommand::BLPop(key, timeout) => command_value(
+                "BLPOP",
+                std::iter::once(key)
+                    .chain(timeout.map(|timeout| timeout.to_string()))
+                    .collect(),
+            ),
+            RedisCommand::Type(key) => command_value("TYPE", vec![key]),
+            RedisCommand::XAdd(key, id, fields, nomkstream) => command_value(
+                "XADD",
+                [key]
+                    .into_iter()
+                    .chain(nomkstream.then(|| "NOMKSTREAM".to_string()))
+                    .chain([stream_id_arg(id)])
+                    .chain(fields.into_iter().flat_map(|(field, value)| [field, value]))
+                    .collect(),
+            ),
+            RedisCommand::HSet(key, pairs) => command_value(
+                "HSET",
+                [key]
+                    .into_iter()
+                    .chain(pairs.into_iter().flat_map(|(field, value)| [field, value]))
+                    .collect(),
+            ),
+            RedisCommand::HGet(key, field) => command_value("HGET", vec![key, field]),
+            RedisCommand::HGetAll(key) => command_value("HGETALL", vec![key]),
+            RedisCommand::HLen(key) => command_value("HLEN", vec![key]),
+            RedisCommand::HDel(key, fields) => {
+                command_value("HDEL", [key].into_iter().chain(fields).collect())
+            }
+            RedisCommand::HExpire(key, seconds, fields) => command_value(
+                "HEXPIRE",
+                hash_fields_args(vec![key, seconds.to_string()], fields),
+            ),
+            RedisCommand::HPExpireAt(key, at, fields) => command_value(
+                "HPEXPIREAT",
+                hash_fields_args(vec![key, at.to_string()], fields),
+            ),
+            RedisCommand::HTtl(key, fields) => {
+                command_value("HTTL", hash_fields_args(vec![key], fields))
+            }
+            RedisCommand::HPersist(key, fields) => {
+                command_value("HPERSIST", hash_fields_args(vec![key], fields))
+            }
+            RedisCommand::HGetDel(key, fields) => {
+                command_value("HGETDEL", hash_fields_args(vec![key], fields))
+            }
+            RedisCommand::HGetEx(key, option, fields) => {
+                let mut args = vec![key];
+                args.extend(getex_option_args(option));
+                command_value("HGETEX", hash_fields_args(args, fields))
+            }
+            RedisCommand::HSetEx(key, opts, pairs) => {
+                let mut args = vec![key];
+                match opts.condition {
+                    Some(FieldCondition::Fnx) => args.push("FNX".to_string()),
+                    Some(FieldCondition::Fxx) => args.push("FXX".to_string()),
+                    None => {}
+                }
+                args.extend(getex_option_args(opts.expiry));
+                if opts.keep_ttl {
+                    args.push("KEEPTTL".to_string());
+                }
+                args.extend(["FIELDS".to_string(), pairs.len().to_string()]);
+                args.extend(pairs.into_iter().flat_map(|(field, value)| [field, value]));
+                command_value("HSETEX", args)
+            }
+            RedisCommand::SAdd(key, members) => {
+                command_value("SADD", [key].into_iter().chain(members).collect())
+            }
+            RedisCommand::SRem(key, members) => {
+                command_value("SREM", [key].into_iter().chain(members).collect())
+            }
+            RedisCommand::SMembers(key) => command_value("SMEMBERS", vec![key]),
+            RedisCommand::SetOp(op, keys) => command_value(&op.name().to_uppercase(), keys),
+            RedisCommand::SetOpStore(op, dest, keys) => command_value(
+                &op.store_name().to_uppercase(),
+                std::iter::once(dest).chain(keys).collect(),
+            ),
+            RedisCommand::SIsMember(key, member) => command_value("SISMEMBER", vec![key, member]),
+            RedisCommand::SCard(key) => command_value("SCARD", vec![key]),
+            RedisCommand::PfAdd(key, elements) => {
+                command_value("PFADD", [key].into_iter().chain(elements).collect())
+            }
+            RedisCommand::PfCount(keys) => command_value("PFCOUNT", keys),
+            RedisCommand::PfMerge(dest, sources) => {
+                command_value("PFMERGE", [dest].into_iter().chain(sources).collect())
+            }
+            RedisCommand::XSetId(key, ms, seq) => {
+                command_value("XSETID", vec![key, format!("{}-{}", ms, seq)])
+            }
+            RedisCommand::XInfoStream(key, full) => {
+                let mut args = vec!["STREAM".to_string(), key];
+                if let Some(count) = full {
+                    args.extend(["FULL".to_string(), "COUNT".to_string(), count.to_string()]);
+                }
+                command_value("XINFO", args)
+            }
+            RedisCommand::XRange(key, start, end) => command_value(
+                "XRANGE",
+                vec![
+                    key,
+                    stream_bound_arg(start, "-"),
+                    stream_bound_arg(end, "+"),
+                ],
+            ),
+            RedisCommand::XRead(streams, block) => {
+                let mut args = match block {
+                    Some(block) => vec!["BLOCK".to_string(), block.to_string()],
+                    None => vec![],
+                };
+                args.push("STREAMS".to_string());
+                let (keys, ids): (Vec<_>, Vec<_>) = streams.into_iter().unzip();
+                args.extend(keys);
+                args.extend(ids.into_iter().map(stream_id_arg));
+                command_value("XREAD", args)
+            }
+            RedisCommand::Incr(key) => command_value("INCR", vec![key]),
+            RedisCommand::Multi => command_value("MULTI", vec![]),
+            RedisCommand::Exec => command_value("EXEC", vec![]),
+            RedisCommand::Discard => command_value("DISCARD", vec![]),
+            RedisCommand::Info(section) => command_value("INFO", vec![section]),
+            RedisCommand::ReplConf(key, value) => command_value("REPLCONF", vec![key, value]),
+            RedisCommand::Psync(id, offset) => command_value("PSYNC", vec![id, offset.to_string()]),
+            // The payload is sent as-is and recognised by its REDIS header on the way back in
+            RedisCommand::RdbPayload(payload) => RedisValueRef::String(payload),
+            RedisCommand::Wait(replicas, timeout) => {
+                command_value("WAIT", vec![replicas.to_string(), timeout.to_string()])
+            }
+            RedisCommand::WaitAof(numlocal, replicas, timeout) => command_value(
+                "WAITAOF",
+                vec![
+                    numlocal.to_string(),
+                    replicas.to_string(),
+                    timeout.to_string(),
+                ],
+            ),
+            RedisCommand::Config(operation, key) => command_value("CONFIG", vec![operation, key]),
+            RedisCommand::Keys(pattern) => command_value("KEYS", vec![pattern]),
+            RedisCommand::Del(keys) => command_value("DEL", keys),
+            RedisCommand::Unlink(keys) => command_value("UNLINK", keys),
+            RedisCommand::Scan(cursor, opts) => {
+                let mut args = vec![cursor.to_string()];
+                if let Some(pattern) = opts.pattern {
+                    args.extend(["MATCH".to_string(), pattern]);
+                }
+                if let Some(count) = opts.count {
+                    args.extend(["COUNT".to_string(), count.to_string()]);
+                }
+                if let Some(key_type) = opts.key_type {
+                    args.extend(["TYPE".to_string(), key_type]);
+                }
+                command_value("SCAN", args)
+            }
+            RedisCommand::Subscribe(channels) => command_value("SUBSCRIBE", channels),
+            RedisCommand::Unsubscribe(channel) => command_value("UNSUBSCRIBE", vec![channel]),
+            RedisCommand::PSubscribe(pattern) => command_value("PSUBSCRIBE", vec![pattern]),
+            RedisCommand::PUnsubscribe(pattern) => command_value("PUNSUBSCRIBE", vec![pattern]),
+            RedisCommand::Publish(channel, message) => {
+                command_value("PUBLISH", vec![channel, message])
+            }
+            RedisCommand::PubSubChannels(pattern) => command_value(
+                "PUBSUB",
+                std::iter::once("CHANNELS".to_string())
+                    .chain(pattern)
+                    .collect(),
+            ),
+            RedisCommand::ZAdd(key, members, opts) => {
+                let flags = [
+                    (opts.nx, "NX"),
+                    (opts.xx, "XX"),
+                    (opts.gt, "GT"),
+                    (opts.lt, "LT"),
+                    (opts.ch, "CH"),
+                    (opts.incr, "INCR"),
+                ];
+                let mut args = vec![key];
+                args.extend(
+                    flags
+                        .into_iter()
+                        .filter(|(set, _)| *set)
+                        .map(|(_, flag)| flag.to_string()),
+                );
+                args.extend(
+                    members
+                        .into_iter()
+                        .flat_map(|(score, member)| [score.to_string(), member]),
+                );
+                command_value("ZADD", args)
+            }
+            RedisCommand::ZRank(key, member) => command_value("ZRANK", vec![key, member]),
+            RedisCommand::ZRange(key, start, stop) => {
+                command_value("ZRANGE", vec![key, start.to_string(), stop.to_string()])
+            }
+            RedisCommand::ZCard(key) => command_value("ZCARD", vec![key]),
+            RedisCommand::ZScore(key, member) => command_value("ZSCORE", vec![key, member]),
+            RedisCommand::ZDiff(keys, withscores) => {
+                let mut args = vec![keys.len().to_string()];
+                args.extend(keys);
+                if withscores {
+                    args.push("WITHSCORES".to_string());
+                }
+                command_value("ZDIFF", args)
+            }
+            RedisCommand::ZDiffStore(dest, keys) => {
+                let mut args = vec![dest, keys.len().to_string()];
+                args.extend(keys);
+                command_value("ZDIFFSTORE", args)
+            }
+            RedisCommand::ZRem(key, member) => command_value("ZREM", vec![key, member]),
+            RedisCommand::ZRemRangeByRank(key, start, stop) => command_value(
+                "ZREMRANGEBYRANK",
+                vec![key, start.to_string(), stop.to_string()],
+            ),
+            RedisCommand::ZRemRangeByScore(key, min, max) => command_value(
+                "ZREMRANGEBYSCORE",
+                vec![key, score_bound_arg(min), score_bound_arg(max)],
+            ),
+            RedisCommand::ZRemRangeByLex(key, min, max) => command_value(
+                "ZREMRANGEBYLEX",
+                vec![key, lex_bound_arg(min), lex_bound_arg(max)],
+            ),
+            RedisCommand::GeoAdd(key, lng, lat, member) => command_value(
+                "GEOADD",
+                vec![key, lng.to_string(), lat.to_string(), member],
+            ),
+            RedisCommand::GeoPos(key, members) => {
+                command_value("GEOPOS", std::iter::once(key).chain(members).collect())
+            }
+            RedisCommand::GeoDist(key, member1, member2) => {
+                command_value("GEODIST", vec![key, member1, member2])
+            }
+            RedisCommand::GeoRadius(key, lng, lat, radius, unit, opts) => {
+                let mut args = vec![
+                    key,
+                    lng.to_string(),
+                    lat.to_string(),
+                    radius.to_string(),
+                    unit,
+                ];
+                args.extend(georadius_option_args(&opts));
+                command_value("GEORADIUS", args)
+            }
+            RedisCommand::GeoRadiusByMember(key, member, radius, unit, opts) => {
+                let mut args = vec![key, member, radius.to_string(), unit];
+                args.extend(georadius_option_args(&opts));
+                command_value("GEORADIUSBYMEMBER", args)
+            }
+            RedisCommand::GeoSearch(key, lng, lat, radius, unit) => command_value(
+                "GEOSEARCH",
+                vec![
+                    key,
+                    "FROMLONLAT".to_string(),
+                    lng.to_string(),
+                    lat.to_string(),
+                    "BYRADIUS".to_string(),
+                    radius.to_string(),
+                    unit,
+                ],
+            ),
+            RedisCommand::AclWhoami() => command_value("ACL", vec!["WHOAMI".to_string()]),
+            RedisCommand::AclGetUser(user) => {
+                command_value("ACL", vec!["GETUSER".to_string(), user])
+            }
+            RedisCommand::AclSetUser(user, password) => command_value(
+                "ACL",
+                vec!["SETUSER".to_string(), user, format!(">{}", password)],
+            ),
+            RedisCommand::Auth(username, password) => {
+                command_value("AUTH", vec![username, password])
+            }
+            RedisCommand::Hello(protover, auth, name) => {
+                let mut args: Vec<String> = protover.map(|p| p.to_string()).into_iter().collect();
+                if args.is_empty() && (auth.is_some() || name.is_some()) {
+                    // Options can't be expressed without a protocol version
+                    return Err(CmdError::ConversionError);
+                }
+                if let Some((username, password)) = auth {
+                    args.extend(["AUTH".to_string(), username, password]);
+                }
+                if let Some(name) = name {
+                    args.extend(["SETNAME".to_string(), name]);
+                }
+                command_value("HELLO", args)
+            }
+            RedisCommand::Reset => command_value("RESET", vec![]),
+            RedisCommand::Quit => command_value("QUIT", vec![]),
+            RedisCommand::FlushDb(asynchronous) => {
+                command_value("FLUSHDB", vec![flush_mode_arg(asynchronous)])
+            }
+            RedisCommand::FlushAll(asynchronous) => {
+                command_value("FLUSHALL", vec![flush_mode_arg(asynchronous)])
+            }
+            RedisCommand::BgSave => command_value("BGSAVE", vec![]),
+            RedisCommand::Save => command_value("SAVE", vec![]),
+            RedisCommand::LastSave => command_value("LASTSAVE", vec![]),
+            RedisCommand::Role => command_value("ROLE", vec![]),
+            RedisCommand::Shutdown(save) => command_value(
+                "SHUTDOWN",
+                save.into_iter()
+                    .map(|save| if save { "SAVE" } else { "NOSAVE" }.to_string())
+                    .collect(),
+            ),
+            RedisCommand::ConfigResetStat => command_value("CONFIG", vec!["RESETSTAT".to_string()]),
+            RedisCommand::SlowlogGet(count) => command_value(
+                "SLOWLOG",
+                std::iter::once("GET".to_string())
+                    .chain(count.map(|c| c.to_string()))
+                    .collect(),
+            ),
+            RedisCommand::SlowlogLen => command_value("SLOWLOG", vec!["LEN".to_string()]),
+            RedisCommand::SlowlogReset => command_value("SLOWLOG", vec!["RESET".to_string()]),
+            RedisCommand::MemoryUsage(key, samples) => {
+                let mut args = vec!["USAGE".to_string(), key];
+                if let Some(samples) = samples {
+                    args.extend(["SAMPLES".to_string(), samples.to_string()]);
+                }
+                command_value("MEMORY", args)
+            }
+            RedisCommand::MemoryDoctor => command_value("MEMORY", vec!["DOCTOR".to_string()]),
+            RedisCommand::ClusterInfo => command_value("CLUSTER", vec!["INFO".to_string()]),
+            RedisCommand::ClusterMyId => command_value("CLUSTER", vec!["MYID".to_string()]),
+            RedisCommand::ClusterSlots => command_value("CLUSTER", vec!["SLOTS".to_string()]),
+            RedisCommand::ClusterShards => command_value("CLUSTER", vec!["SHARDS".to_string()]),
+            RedisCommand::FunctionList => command_value("FUNCTION", vec!["LIST".to_string()]),
+            RedisCommand::FunctionStats => command_value("FUNCTION", vec!["STATS".to_string()]),
+            RedisCommand::FunctionLoad(code) => {
+                command_value("FUNCTION", vec!["LOAD".to_string(), code])
+            }
+            RedisCommand::Fcall(function, keys, args) => {
+                let mut all = vec![function, keys.len().to_string()];
+                all.extend(keys);
+                all.extend(args);
+                command_value("FCALL", all)
+            }
+            RedisCommand::DebugSleep(seconds) => {
+                command_value("DEBUG", vec!["SLEEP".to_string(), seconds.to_string()])
+            }
+            RedisCommand::DebugChangeReplId => {
+                command_value("DEBUG", vec!["CHANGE-REPL-ID".to_string()])
+            }
+            RedisCommand::DebugObject(key) => {
+                command_value("DEBUG", vec!["OBJECT".to_string(), key])
+            }
+            RedisCommand::DebugReload => command_value("DEBUG", vec!["RELOAD".to_string()]),
+            RedisCommand::Lcs(key1, key2, opts) => {
+                let mut args = vec![key1, key2];
+                if opts.len {
+                    args.push("LEN".to_string());
+                }
+                if opts.idx {
+                    args.push("IDX".to_string());
+                }
+                if opts.min_match_len > 0 {
+                    args.extend(["MINMATCHLEN".to_string(), opts.min_match_len.to_string()]);
+                }
+                if opts.with_match_len {
+                    args.push("WITHMATCHLEN".to_string());
+                }
+                command_value("LCS", args)
+            }
+            RedisCommand::SetBit(key, offset, value) => {
+                command_value("SETBIT", vec![key, offset.to_string(), value.to_string()])
+            }
+            RedisCommand::GetBit(key, offset) => {
+                command_value("GETBIT", vec![key, offset.to_string()])
+            }
+            RedisCommand::BitCount(key, range, unit) => {
+                let mut args = vec![key];
+                if let Some((start, end)) = range {
+                    args.extend([start.to_string(), end.to_string(), bit_unit_arg(unit)]);
+                }
+                command_value("BITCOUNT", args)
+            }
+            RedisCommand::BitPos(key, bit, start, end, unit) => {
+                let mut args = vec![key, bit.to_string()];
+                args.extend(start.map(|start| start.to_string()));
+                if let Some(end) = end {
+                    args.extend([end.to_string(), bit_unit_arg(unit)]);
+                }
+                command_value("BITPOS", args)
+            }
+            RedisCommand::BitField(key, ops) => {
+                let mut args = vec![key];
+                for op in ops {
+                    match op {
+                        BitFieldOp::Get(ty, offset) => {
+                            args.extend(["GET".to_string(), ty.to_string(), offset.to_string()])
+                        }
+                        BitFieldOp::Set(ty, offset, value) => args.extend([
+                            "SET".to_string(),
+                            ty.to_string(),
+                            offset.to_string(),
+                            value.to_string(),
+                        ]),
+                        BitFieldOp::IncrBy(ty, offset, increment) => args.extend([
+                            "INCRBY".to_string(),
+                            ty.to_string(),
+                            offset.to_string(),
+                            increment.to_string(),
+                        ]),
+                        BitFieldOp::Overflow(overflow) => {
+                            let mode = match overflow {
+                                Overflow::Wrap => "WRAP",
+                                Overflow::Sat => "SAT",
+                                Overflow::Fail => "FAIL",
+                            };
+                            args.extend(["OVERFLOW".to_string(), mode.to_string()])
+                        }
+                    }
+                }
+                command_value("BITFIELD", args)
+            }
+            RedisCommand::BitOp(op, dest, keys) => {
+                let op = match op {
+                    BitOperation::And => "AND",
+                    BitOperation::Or => "OR",
+                    BitOperation::Xor => "XOR",
+                    BitOperation::Not => "NOT",
+                };
+                command_value(
+                    "BITOP",
+                    [op.to_string(), dest].into_iter().chain(keys).collect(),
+                )
+            }
+            RedisCommand::ClientSetName(name) => {
+                command_value("CLIENT", vec!["SETNAME".to_string(), name])
+            }
+            RedisCommand::ClientGetName() => command_value("CLIENT", vec!["GETNAME".to_string()]),
+            RedisCommand::ClientTracking(on) => command_value(
+                "CLIENT",
+                vec![
+                    "TRACKING".to_string(),
+                    if on { "ON" } else { "OFF" }.to_string(),
+                ],
+            ),
+            RedisCommand::ClientNoEvict(on) => command_value(
+                "CLIENT",
+                vec![
+                    "NO-EVICT".to_string(),
+                    if on { "ON" } else { "OFF" }.to_string(),
+                ],
+            ),
+            RedisCommand::ClientNoTouch(on) => command_value(
+                "CLIENT",
+                vec![
+                    "NO-TOUCH".to_string(),
+                    if on { "ON" } else { "OFF" }.to_string(),
+                ],
+            ),
+            RedisCommand::CommandInfo(names) => command_value(
+                "COMMAND",
+                std::iter::once("INFO".to_string()).chain(names).collect(),
+            ),
+            RedisCommand::CommandCount => command_value("COMMAND", vec!["COUNT".to_string()]),
+            RedisCommand::CommandList(filter) => {
+                let mut args = vec!["LIST".to_string()];
+                if let Some(filter) = filter {
+                    let (kind, value) = match filter {
+                        CommandFilter::Module(name) => ("MODULE", name),
+                        CommandFilter::AclCat(category) => ("ACLCAT", category),
+                        CommandFilter::Pattern(pattern) => ("PATTERN", pattern),
+                    };
+                    args.extend(["FILTERBY".to_string(), kind.to_string(), value]);
+                }
+                command_value("COMMAND", args)
+            }
+            RedisCommand::DebugSetActiveExpire(enabled) => command_value(
+                "DEBUG",
+                vec!["SET-ACTIVE-EXPIRE".to_string(), (enabled as u8).to_string()],
+            ),
+            RedisCommand::DebugQuicklistPackedThreshold(threshold) => command_value(
+                "DEBUG",
+                vec![
+                    "QUICKLIST-PACKED-THRESHOLD".to_string(),
+                    threshold.to_string(),
+                ],
+            ),
+            RedisCommand::DebugStringMatchLen(pattern, string) => command_value(
+                "DEBUG",
+                vec!["STRINGMATCH-LEN".to_string(), pattern, string],
+            ),
+            RedisCommand::GetEx(key, option) => {
+                let mut args = vec![key];
+                args.extend(getex_option_args(option));
+                command_value("GETEX", args)
+            }
+            RedisCommand::Append(key, value) => command_value("APPEND", vec![key, value]),
+            RedisCommand::StrLen(key) => command_value("STRLEN", vec![key]),
+            RedisCommand::Expire(key, seconds) => {
+                command_value("EXPIRE", vec![key, seconds.to_string()])
+            }
+            RedisCommand::PExpireAt(key, at) => {
+                command_value("PEXPIREAT", vec![key, at.to_string()])
+            }
+            RedisCommand::Sort(key, opts) => {
+                let mut args = vec![key];
+                if let Some(by) = opts.by {
+                    args.extend(["BY".to_string(), by]);
+                }
+                if let Some((offset, count)) = opts.limit {
+                    args.extend(["LIMIT".to_string(), offset.to_string(), count.to_string()]);
+                }
+                for pattern in opts.get {
+                    args.extend(["GET".to_string(), pattern]);
+                }
+                if opts.desc {
+                    args.push("DESC".to_string());
+                }
+                if opts.alpha {
+                    args.push("ALPHA".to_string());
+                }
+                if let Some(dest) = opts.store {
+                    args.extend(["STORE".to_string(), dest]);
+                }
+                command_value("SORT", args)
+            }
+            RedisCommand::ObjectEncoding(key) => {
+                command_value("OBJECT", vec!["ENCODING".to_string(), key])
+            }
+            RedisCommand::ObjectIdleTime(key) => {
+                command_value("OBJECT", vec!["IDLETIME".to_string(), key])
+            }
+            RedisCommand::ObjectFreq(key) => command_value("OBJECT", vec!["FREQ".to_string(), key]),
+            RedisCommand::Touch(keys) => command_value("TOUCH", keys),
+            RedisCommand::GetRange(key, start, end) => {
+                command_value("GETRANGE", vec![key, start.to_string(), end.to_string()])
+            }
+            RedisCommand::SetRange(key, offset, value) => {
+                command_value("SETRANGE", vec![key, offset.to_string(), value])
             }
         };
         Ok(value)
     }
 }
 
+fn ping(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    match args.len() {
+        1 => Ok(RedisCommand::Ping(None)),
+        2 => Ok(RedisCommand::Ping(Some(extract_string_arg(
+            &args[1], "message",
+        )?))),
+        _ => Err(CmdError::InvalidArgumentNum),
+    }
+}
+
+fn lolwut(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    match args {
+        [_] => Ok(RedisCommand::Lolwut(None)),
+        [_, option, version] => {
+            let option = extract_string_arg(option, "option")?;
+            if !option.eq_ignore_ascii_case("VERSION") {
+                return Err(CmdError::InvalidArgument(option));
+            }
+            Ok(RedisCommand::Lolwut(Some(extract_parse_arg(
+                version, "version",
+            )?)))
+        }
+        _ => Err(CmdError::InvalidArgumentNum),
+    }
+}
+
+/// SHUTDOWN [NOSAVE|SAVE]
+fn shutdown(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    match args {
+        [_] => Ok(RedisCommand::Shutdown(None)),
+        [_, option] => {
+            let option = extract_string_arg(option, "option")?;
+            match option.to_uppercase().as_str() {
+                "SAVE" => Ok(RedisCommand::Shutdown(Some(true))),
+                "NOSAVE" => Ok(RedisCommand::Shutdown(Some(false))),
+                _ => Err(CmdError::InvalidArgument(option)),
+            }
+        }
+        _ => Err(CmdError::InvalidArgumentNum),
+    }
+}
+
 fn echo(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
     if args.len() < 2 {
         Err(CmdError::InvalidArgumentNum)
     } else {
-        Ok(RedisCommand::Echo(args[1].to_string()))
+        // Kept as raw bytes so binary messages come back unchanged
+        let message = args[1]
+            .clone()
+            .as_string()
+            .map_err(|_| CmdError::InvalidStringArg {
+                field: "message".to_string(),
+            })?;
+        Ok(RedisCommand::Echo(message))
     }
 }
 
@@ -306,20 +1707,69 @@ fn set(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
     }
     let key = extract_string_arg(&args[1], "key")?;
     let value = extract_string_arg(&args[2], "value")?;
-    match args.len() {
-        3 => Ok(RedisCommand::Set(key, value)),
-        5 => {
-            let ttl_type = extract_string_arg(&args[3], "ttl type")?;
-            let ttl_arg: u64 = extract_parse_arg(&args[4], "ttl value")?;
-            let ttl_val = match ttl_type.as_str() {
-                "EX" => ttl_arg * 1000,
-                "PX" => ttl_arg,
-                _ => return Err(CmdError::InvalidArgument(ttl_type)),
-            };
-            Ok(RedisCommand::SetEx(key, value, ttl_val))
+    let mut ttl = None;
+    let mut at = None;
+    let mut get = false;
+    let mut i = 3;
+    while i < args.len() {
+        let option = extract_string_arg(&args[i], "option")?.to_uppercase();
+        match option.as_str() {
+            "EX" | "PX" => {
+                let ttl_arg = args.get(i + 1).ok_or(CmdError::InvalidArgumentNum)?;
+                ttl = Some(parse_expire_ms(ttl_arg, option == "EX", "set")?);
+                i += 2;
+            }
+            "EXAT" | "PXAT" => {
+                let at_arg = args.get(i + 1).ok_or(CmdError::InvalidArgumentNum)?;
+                at = Some(parse_expire_ms(at_arg, option == "EXAT", "set")?);
+                i += 2;
+            }
+            "GET" => {
+                get = true;
+                i += 1;
+            }
+            _ => return Err(CmdError::InvalidArgument(option)),
         }
-        _ => Err(CmdError::InvalidArgumentNum),
     }
+    if ttl.is_some() && at.is_some() {
+        return Err(CmdError::InvalidArgument("PXAT".to_string()));
+    }
+    match (ttl, at, get) {
+        (_, _, true) => {
+            let expiry = ttl.map(SetExpiry::In).or(at.map(SetExpiry::At));
+            Ok(RedisCommand::SetGet(key, value, expiry))
+        }
+        (_, Some(at), false) => Ok(RedisCommand::SetPxAt(key, value, at)),
+        (Some(ttl), None, false) => Ok(RedisCommand::SetEx(key, value, ttl)),
+        (None, None, false) => Ok(RedisCommand::Set(key, value)),
+    }
+}
+
+/// A relative expire time in seconds or milliseconds, as milliseconds.
+/// Anything that isn't positive, or overflows as milliseconds, is refused.
+fn parse_expire_ms(arg: &RedisValueRef, seconds: bool, command: &str) -> Result<u64, CmdError> {
+    let time: i64 = extract_parse_arg(arg, "expire time")?;
+    let ms = if seconds {
+        time.checked_mul(1000)
+    } else {
+        Some(time)
+    };
+    match ms {
+        Some(ms) if ms > 0 => Ok(ms as u64),
+        _ => Err(CmdError::InvalidExpireTime(command.to_string())),
+    }
+}
+
+/// SETEX key seconds value, and PSETEX with milliseconds
+fn setex(args: &[RedisValueRef], seconds: bool) -> Result<RedisCommand, CmdError> {
+    if args.len() != 4 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let command = if seconds { "setex" } else { "psetex" };
+    let ttl = parse_expire_ms(&args[2], seconds, command)?;
+    let value = extract_string_arg(&args[3], "value")?;
+    Ok(RedisCommand::SetEx(key, value, ttl))
 }
 
 fn get(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
@@ -331,64 +1781,246 @@ fn get(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
     }
 }
 
-fn rpush(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
-    if args.len() < 3 {
+fn getex(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let key = extract_string_arg(args.get(1).ok_or(CmdError::InvalidArgumentNum)?, "key")?;
+    if args.len() == 2 {
+        return Ok(RedisCommand::GetEx(key, None));
+    }
+    let (option, used) = parse_getex_option(&args[2..])?;
+    if used != args.len() - 2 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    Ok(RedisCommand::GetEx(key, Some(option)))
+}
+
+/// Parse a GETEX style EX/PX/EXAT/PXAT/PERSIST option from the start of
+/// `args`, returning it with the number of arguments it took up
+fn parse_getex_option(args: &[RedisValueRef]) -> Result<(GetExOption, usize), CmdError> {
+    let option = extract_string_arg(&args[0], "option")?.to_uppercase();
+    match option.as_str() {
+        "PERSIST" => Ok((GetExOption::Persist, 1)),
+        "EX" | "PX" | "EXAT" | "PXAT" => {
+            let time = args.get(1).ok_or(CmdError::InvalidArgumentNum)?;
+            let time: u64 = extract_parse_arg(time, "expire time")?;
+            if time == 0 {
+                return Err(CmdError::InvalidArgument("expire time".to_string()));
+            }
+            let option = match option.as_str() {
+                "EX" => GetExOption::Expire(time.saturating_mul(1000)),
+                "PX" => GetExOption::Expire(time),
+                "EXAT" => GetExOption::ExpireAt(time.saturating_mul(1000)),
+                _ => GetExOption::ExpireAt(time),
+            };
+            Ok((option, 2))
+        }
+        _ => Err(CmdError::InvalidArgument(option)),
+    }
+}
+
+fn append(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 3 {
         Err(CmdError::InvalidArgumentNum)
     } else {
         let key = extract_string_arg(&args[1], "key")?;
-        let values: Result<Vec<String>, CmdError> = args[2..]
-            .iter()
-            .enumerate()
-            .map(|(i, arg)| extract_string_arg(arg, &format!("value[{}]", i)))
-            .collect();
-        let values = values?;
-        Ok(RedisCommand::Rpush(key, values))
+        let value = extract_string_arg(&args[2], "value")?;
+        Ok(RedisCommand::Append(key, value))
     }
 }
 
-fn lpush(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
-    if args.len() < 3 {
+fn strlen(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 2 {
         Err(CmdError::InvalidArgumentNum)
     } else {
         let key = extract_string_arg(&args[1], "key")?;
-        let values: Result<Vec<String>, CmdError> = args[2..]
-            .iter()
-            .enumerate()
-            .map(|(i, arg)| extract_string_arg(arg, &format!("value[{}]", i)))
-            .collect();
-        let values = values?;
-        Ok(RedisCommand::Lpush(key, values))
+        Ok(RedisCommand::StrLen(key))
     }
 }
 
-fn lrange(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+fn setrange(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
     if args.len() != 4 {
         Err(CmdError::InvalidArgumentNum)
     } else {
         let key = extract_string_arg(&args[1], "key")?;
-        let start: i64 = extract_parse_arg(&args[2], "start")?;
-        let stop: i64 = extract_parse_arg(&args[3], "stop")?;
-        Ok(RedisCommand::Lrange(key, start, stop))
+        let offset: usize = extract_parse_arg(&args[2], "offset")?;
+        let value = extract_string_arg(&args[3], "value")?;
+        Ok(RedisCommand::SetRange(key, offset, value))
     }
 }
 
-fn llen(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
-    if args.len() != 2 {
+fn getrange(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 4 {
         Err(CmdError::InvalidArgumentNum)
     } else {
         let key = extract_string_arg(&args[1], "key")?;
-        Ok(RedisCommand::LLen(key))
+        let start: i64 = extract_parse_arg(&args[2], "start")?;
+        let end: i64 = extract_parse_arg(&args[3], "end")?;
+        Ok(RedisCommand::GetRange(key, start, end))
     }
 }
 
-fn lpop(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
-    if args.len() < 2 || args.len() > 3 {
+fn expire(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 3 {
         Err(CmdError::InvalidArgumentNum)
     } else {
         let key = extract_string_arg(&args[1], "key")?;
-        args.get(2)
-            .map(|num_elements| extract_parse_arg::<u64>(num_elements, "num_elements"))
-            .transpose()
+        let seconds: i64 = extract_parse_arg(&args[2], "seconds")?;
+        Ok(RedisCommand::Expire(key, seconds))
+    }
+}
+
+/// EXPIREAT with unix seconds, and PEXPIREAT with unix milliseconds
+fn expireat(args: &[RedisValueRef], seconds: bool) -> Result<RedisCommand, CmdError> {
+    if args.len() != 3 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let at: i64 = extract_parse_arg(&args[2], "timestamp")?;
+    let at = if seconds { at.saturating_mul(1000) } else { at };
+    Ok(RedisCommand::PExpireAt(key, at))
+}
+
+/// SORT and SORT_RO share a parser; the read-only form refuses STORE
+fn sort(args: &[RedisValueRef], read_only: bool) -> Result<RedisCommand, CmdError> {
+    let key = extract_string_arg(args.get(1).ok_or(CmdError::InvalidArgumentNum)?, "key")?;
+    let mut opts = SortOptions::default();
+    let mut i = 2;
+    while i < args.len() {
+        let option = extract_string_arg(&args[i], "option")?.to_uppercase();
+        match option.as_str() {
+            "ASC" => opts.desc = false,
+            "DESC" => opts.desc = true,
+            "ALPHA" => opts.alpha = true,
+            "LIMIT" if i + 2 < args.len() => {
+                let offset: i64 = extract_parse_arg(&args[i + 1], "offset")?;
+                let count: i64 = extract_parse_arg(&args[i + 2], "count")?;
+                opts.limit = Some((offset, count));
+                i += 2;
+            }
+            "BY" if i + 1 < args.len() => {
+                opts.by = Some(extract_string_arg(&args[i + 1], "pattern")?);
+                i += 1;
+            }
+            "GET" if i + 1 < args.len() => {
+                opts.get.push(extract_string_arg(&args[i + 1], "pattern")?);
+                i += 1;
+            }
+            "STORE" if !read_only && i + 1 < args.len() => {
+                opts.store = Some(extract_string_arg(&args[i + 1], "destination")?);
+                i += 1;
+            }
+            _ => return Err(CmdError::InvalidArgument(option)),
+        }
+        i += 1;
+    }
+    Ok(RedisCommand::Sort(key, opts))
+}
+
+fn object(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let subcommand = extract_string_arg(
+        args.get(1).ok_or(CmdError::InvalidArgumentNum)?,
+        "subcommand",
+    )?;
+    match subcommand.to_uppercase().as_str() {
+        "ENCODING" if args.len() == 3 => {
+            let key = extract_string_arg(&args[2], "key")?;
+            Ok(RedisCommand::ObjectEncoding(key))
+        }
+        "ENCODING" => Err(CmdError::InvalidArgumentNum),
+        "IDLETIME" if args.len() == 3 => {
+            let key = extract_string_arg(&args[2], "key")?;
+            Ok(RedisCommand::ObjectIdleTime(key))
+        }
+        "IDLETIME" => Err(CmdError::InvalidArgumentNum),
+        "FREQ" if args.len() == 3 => {
+            let key = extract_string_arg(&args[2], "key")?;
+            Ok(RedisCommand::ObjectFreq(key))
+        }
+        "FREQ" => Err(CmdError::InvalidArgumentNum),
+        _ => Err(CmdError::InvalidArgument(subcommand)),
+    }
+}
+
+/// Every argument after the command name, as keys
+fn key_args(args: &[RedisValueRef]) -> Result<Vec<String>, CmdError> {
+    if args.len() < 2 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    args[1..]
+        .iter()
+        .map(|arg| extract_string_arg(arg, "key"))
+        .collect()
+}
+
+fn del(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    Ok(RedisCommand::Del(key_args(args)?))
+}
+
+fn unlink(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    Ok(RedisCommand::Unlink(key_args(args)?))
+}
+
+fn touch(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    Ok(RedisCommand::Touch(key_args(args)?))
+}
+
+/// The key and values of RPUSH, LPUSH, RPUSHX and LPUSHX
+fn push_args(args: &[RedisValueRef]) -> Result<(String, Vec<String>), CmdError> {
+    if args.len() < 3 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let values = args[2..]
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| extract_string_arg(arg, &format!("value[{}]", i)))
+        .collect::<Result<Vec<String>, CmdError>>()?;
+    Ok((key, values))
+}
+
+fn rpush(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    push_args(args).map(|(key, values)| RedisCommand::Rpush(key, values))
+}
+
+fn lpush(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    push_args(args).map(|(key, values)| RedisCommand::Lpush(key, values))
+}
+
+fn rpushx(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    push_args(args).map(|(key, values)| RedisCommand::Rpushx(key, values))
+}
+
+fn lpushx(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    push_args(args).map(|(key, values)| RedisCommand::Lpushx(key, values))
+}
+
+fn lrange(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 4 {
+        Err(CmdError::InvalidArgumentNum)
+    } else {
+        let key = extract_string_arg(&args[1], "key")?;
+        let start: i64 = extract_parse_arg(&args[2], "start")?;
+        let stop: i64 = extract_parse_arg(&args[3], "stop")?;
+        Ok(RedisCommand::Lrange(key, start, stop))
+    }
+}
+
+fn llen(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 2 {
+        Err(CmdError::InvalidArgumentNum)
+    } else {
+        let key = extract_string_arg(&args[1], "key")?;
+        Ok(RedisCommand::LLen(key))
+    }
+}
+
+fn lpop(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 2 || args.len() > 3 {
+        Err(CmdError::InvalidArgumentNum)
+    } else {
+        let key = extract_string_arg(&args[1], "key")?;
+        args.get(2)
+            .map(|num_elements| extract_parse_arg::<u64>(num_elements, "num_elements"))
+            .transpose()
             .map(|num_elements| RedisCommand::LPop(key, num_elements))
     }
 }
@@ -414,6 +2046,15 @@ fn _type(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
     }
 }
 
+/// Inverse of `parse_stream_id` for IDs that are sent back out as arguments
+fn stream_id_arg(id: StreamIdIn) -> String {
+    match id {
+        (None, _) => "*".to_string(),
+        (Some(ms), None) => format!("{}-*", ms),
+        (Some(ms), Some(seq)) => format!("{}-{}", ms, seq),
+    }
+}
+
 fn parse_stream_id(id: &str) -> Result<StreamIdIn, CmdError> {
     if id == "*" || id == "-" || id == "+" {
         return Ok((None, None));
@@ -446,7 +2087,9 @@ fn xadd(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
         Err(CmdError::InvalidArgumentNum)
     } else {
         let key = extract_string_arg(&args[1], "key")?;
-        let id = extract_string_arg(&args[2], "id")?;
+        let nomkstream = extract_string_arg(&args[2], "id")?.eq_ignore_ascii_case("NOMKSTREAM");
+        let args = if nomkstream { &args[1..] } else { args };
+        let id = extract_string_arg(args.get(2).ok_or(CmdError::InvalidArgumentNum)?, "id")?;
         let id_tuple = parse_stream_id(&id)?;
         let fields = args[3..]
             .chunks_exact(2)
@@ -456,8 +2099,353 @@ fn xadd(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
                 Ok((field, value))
             })
             .collect::<Result<Vec<(String, String)>, CmdError>>()?;
-        Ok(RedisCommand::XAdd(key, id_tuple, fields))
+        if fields.is_empty() {
+            return Err(CmdError::InvalidArgumentNum);
+        }
+        Ok(RedisCommand::XAdd(key, id_tuple, fields, nomkstream))
+    }
+}
+
+/// XRANGE endpoints may be prefixed with `(` to exclude that exact ID
+fn parse_stream_bound(id: &str) -> Result<StreamBoundIn, CmdError> {
+    match id.strip_prefix('(') {
+        Some(id) if ["-", "+", "*", "$"].contains(&id) => {
+            Err(CmdError::InvalidArgument("id".to_string()))
+        }
+        Some(id) => Ok((parse_stream_id(id)?, true)),
+        None => Ok((parse_stream_id(id)?, false)),
+    }
+}
+
+fn hset(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 4 || !(args.len() - 2).is_multiple_of(2) {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let pairs = args[2..]
+        .chunks_exact(2)
+        .map(|pair| {
+            let field = extract_string_arg(&pair[0], "field")?;
+            let value = extract_string_arg(&pair[1], "value")?;
+            Ok((field, value))
+        })
+        .collect::<Result<Vec<(String, String)>, CmdError>>()?;
+    Ok(RedisCommand::HSet(key, pairs))
+}
+
+fn hget(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 3 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let field = extract_string_arg(&args[2], "field")?;
+    Ok(RedisCommand::HGet(key, field))
+}
+
+fn hgetall(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 2 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    Ok(RedisCommand::HGetAll(key))
+}
+
+fn hlen(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 2 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    Ok(RedisCommand::HLen(key))
+}
+
+fn hdel(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 3 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let fields = args[2..]
+        .iter()
+        .map(|arg| extract_string_arg(arg, "field"))
+        .collect::<Result<Vec<String>, CmdError>>()?;
+    Ok(RedisCommand::HDel(key, fields))
+}
+
+/// The `FIELDS numfields field...` tail shared by the HEXPIRE family
+fn parse_hash_fields(args: &[RedisValueRef]) -> Result<Vec<String>, CmdError> {
+    let keyword = extract_string_arg(args.first().ok_or(CmdError::InvalidArgumentNum)?, "FIELDS")?;
+    if !keyword.eq_ignore_ascii_case("FIELDS") {
+        return Err(CmdError::InvalidArgument(keyword));
+    }
+    let numfields: usize = extract_parse_arg(
+        args.get(1).ok_or(CmdError::InvalidArgumentNum)?,
+        "numfields",
+    )?;
+    if numfields == 0 || numfields != args.len() - 2 {
+        return Err(CmdError::InvalidArgument("numfields".to_string()));
+    }
+    args[2..]
+        .iter()
+        .map(|arg| extract_string_arg(arg, "field"))
+        .collect()
+}
+
+fn hash_fields_args(mut args: Vec<String>, fields: Vec<String>) -> Vec<String> {
+    args.extend(["FIELDS".to_string(), fields.len().to_string()]);
+    args.extend(fields);
+    args
+}
+
+fn hgetdel(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 5 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let fields = parse_hash_fields(&args[2..])?;
+    Ok(RedisCommand::HGetDel(key, fields))
+}
+
+fn hgetex(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 5 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let keyword = extract_string_arg(&args[2], "option")?;
+    let (option, used) = match keyword.eq_ignore_ascii_case("FIELDS") {
+        true => (None, 0),
+        false => {
+            let (option, used) = parse_getex_option(&args[2..])?;
+            (Some(option), used)
+        }
+    };
+    let fields = parse_hash_fields(&args[2 + used..])?;
+    Ok(RedisCommand::HGetEx(key, option, fields))
+}
+
+fn hsetex(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 6 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let mut opts = HSetExOptions::default();
+    let mut i = 2;
+    loop {
+        let option =
+            extract_string_arg(args.get(i).ok_or(CmdError::InvalidArgumentNum)?, "option")?;
+        match option.to_uppercase().as_str() {
+            "FIELDS" => break,
+            "FNX" | "FXX" if opts.condition.is_some() => {
+                return Err(CmdError::InvalidArgument(option));
+            }
+            "FNX" => opts.condition = Some(FieldCondition::Fnx),
+            "FXX" => opts.condition = Some(FieldCondition::Fxx),
+            _ if opts.expiry.is_some() || opts.keep_ttl => {
+                return Err(CmdError::InvalidArgument(option));
+            }
+            "KEEPTTL" => opts.keep_ttl = true,
+            "PERSIST" => return Err(CmdError::InvalidArgument(option)),
+            _ => {
+                let (expiry, used) = parse_getex_option(&args[i..])?;
+                opts.expiry = Some(expiry);
+                i += used - 1;
+            }
+        }
+        i += 1;
+    }
+    let numfields: usize = extract_parse_arg(
+        args.get(i + 1).ok_or(CmdError::InvalidArgumentNum)?,
+        "numfields",
+    )?;
+    let values = &args[i + 2..];
+    if numfields == 0 || numfields.checked_mul(2) != Some(values.len()) {
+        return Err(CmdError::InvalidArgument("numfields".to_string()));
+    }
+    let pairs = values
+        .chunks(2)
+        .map(|pair| {
+            let field = extract_string_arg(&pair[0], "field")?;
+            let value = extract_string_arg(&pair[1], "value")?;
+            Ok((field, value))
+        })
+        .collect::<Result<Vec<_>, CmdError>>()?;
+    Ok(RedisCommand::HSetEx(key, opts, pairs))
+}
+
+fn hexpire(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 6 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let seconds: u64 = extract_parse_arg(&args[2], "seconds")?;
+    let fields = parse_hash_fields(&args[3..])?;
+    Ok(RedisCommand::HExpire(key, seconds, fields))
+}
+
+fn hpexpireat(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 6 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let at: u64 = extract_parse_arg(&args[2], "unix-time-milliseconds")?;
+    let fields = parse_hash_fields(&args[3..])?;
+    Ok(RedisCommand::HPExpireAt(key, at, fields))
+}
+
+fn httl(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 5 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let fields = parse_hash_fields(&args[2..])?;
+    Ok(RedisCommand::HTtl(key, fields))
+}
+
+fn hpersist(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 5 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let fields = parse_hash_fields(&args[2..])?;
+    Ok(RedisCommand::HPersist(key, fields))
+}
+
+/// Parses `NAME key member [member ...]`
+fn key_and_members(args: &[RedisValueRef]) -> Result<(String, Vec<String>), CmdError> {
+    if args.len() < 3 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let members = args[2..]
+        .iter()
+        .map(|arg| extract_string_arg(arg, "member"))
+        .collect::<Result<Vec<String>, CmdError>>()?;
+    Ok((key, members))
+}
+
+fn sadd(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let (key, members) = key_and_members(args)?;
+    Ok(RedisCommand::SAdd(key, members))
+}
+
+fn srem(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let (key, members) = key_and_members(args)?;
+    Ok(RedisCommand::SRem(key, members))
+}
+
+fn smembers(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 2 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    Ok(RedisCommand::SMembers(key))
+}
+
+fn set_op(args: &[RedisValueRef], op: SetOperation) -> Result<RedisCommand, CmdError> {
+    Ok(RedisCommand::SetOp(op, key_args(args)?))
+}
+
+fn set_op_store(args: &[RedisValueRef], op: SetOperation) -> Result<RedisCommand, CmdError> {
+    if args.len() < 3 {
+        return Err(CmdError::InvalidArgumentNum);
     }
+    let dest = extract_string_arg(&args[1], "destination")?;
+    Ok(RedisCommand::SetOpStore(op, dest, key_args(&args[1..])?))
+}
+
+fn sismember(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 3 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let member = extract_string_arg(&args[2], "member")?;
+    Ok(RedisCommand::SIsMember(key, member))
+}
+
+fn scard(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 2 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    Ok(RedisCommand::SCard(key))
+}
+
+fn pfadd(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 2 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let elements = args[2..]
+        .iter()
+        .map(|arg| extract_string_arg(arg, "element"))
+        .collect::<Result<Vec<String>, CmdError>>()?;
+    Ok(RedisCommand::PfAdd(key, elements))
+}
+
+fn pfcount(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 2 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let keys = args[1..]
+        .iter()
+        .map(|arg| extract_string_arg(arg, "key"))
+        .collect::<Result<Vec<String>, CmdError>>()?;
+    Ok(RedisCommand::PfCount(keys))
+}
+
+fn pfmerge(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 2 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let dest = extract_string_arg(&args[1], "destkey")?;
+    let sources = args[2..]
+        .iter()
+        .map(|arg| extract_string_arg(arg, "sourcekey"))
+        .collect::<Result<Vec<String>, CmdError>>()?;
+    Ok(RedisCommand::PfMerge(dest, sources))
+}
+
+fn xsetid(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 3 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let id = extract_string_arg(&args[2], "id")?;
+    let err = || CmdError::InvalidArgument("id".to_string());
+    let (ms, seq) = id.split_once('-').unwrap_or((&id, "0"));
+    let ms = ms.parse().map_err(|_| err())?;
+    let seq = seq.parse().map_err(|_| err())?;
+    Ok(RedisCommand::XSetId(key, ms, seq))
+}
+
+fn xinfo(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let subcommand = extract_string_arg(
+        args.get(1).ok_or(CmdError::InvalidArgumentNum)?,
+        "subcommand",
+    )?;
+    if !subcommand.eq_ignore_ascii_case("STREAM") {
+        return Err(CmdError::InvalidArgument(subcommand));
+    }
+    let key = extract_string_arg(args.get(2).ok_or(CmdError::InvalidArgumentNum)?, "key")?;
+    let full = match args.get(3) {
+        Some(option) => {
+            let option = extract_string_arg(option, "option")?;
+            if !option.eq_ignore_ascii_case("FULL") {
+                return Err(CmdError::InvalidArgument(option));
+            }
+            match &args[4..] {
+                [] => Some(XINFO_FULL_COUNT),
+                [option, count] => {
+                    let option = extract_string_arg(option, "option")?;
+                    if !option.eq_ignore_ascii_case("COUNT") {
+                        return Err(CmdError::InvalidArgument(option));
+                    }
+                    Some(extract_parse_arg(count, "count")?)
+                }
+                _ => return Err(CmdError::InvalidArgumentNum),
+            }
+        }
+        None => None,
+    };
+    Ok(RedisCommand::XInfoStream(key, full))
 }
 
 fn xrange(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
@@ -467,8 +2455,8 @@ fn xrange(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
         let key = extract_string_arg(&args[1], "key")?;
         let start = extract_string_arg(&args[2], "start")?;
         let end = extract_string_arg(&args[3], "end")?;
-        let start_parsed = parse_stream_id(&start)?;
-        let end_parsed = parse_stream_id(&end)?;
+        let start_parsed = parse_stream_bound(&start)?;
+        let end_parsed = parse_stream_bound(&end)?;
         Ok(RedisCommand::XRange(key, start_parsed, end_parsed))
     }
 }
@@ -560,8 +2548,51 @@ fn wait(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
     }
 }
 
+fn waitaof(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 4 {
+        Err(CmdError::InvalidArgumentNum)
+    } else {
+        let numlocal: u64 = extract_parse_arg(&args[1], "numlocal")?;
+        let replicas: u64 = extract_parse_arg(&args[2], "numreplicas")?;
+        let timeout: u64 = extract_parse_arg(&args[3], "timeout")?;
+        Ok(RedisCommand::WaitAof(numlocal, replicas, timeout))
+    }
+}
+
+/// The optional ASYNC or SYNC of FLUSHDB and FLUSHALL, true for ASYNC
+fn flush_mode(args: &[RedisValueRef]) -> Result<bool, CmdError> {
+    match args {
+        [_] => Ok(false),
+        [_, mode] => {
+            let mode = extract_string_arg(mode, "mode")?;
+            match mode.to_uppercase().as_str() {
+                "ASYNC" => Ok(true),
+                "SYNC" => Ok(false),
+                _ => Err(CmdError::InvalidArgument(mode)),
+            }
+        }
+        _ => Err(CmdError::InvalidArgumentNum),
+    }
+}
+
+fn flush_mode_arg(asynchronous: bool) -> String {
+    if asynchronous { "ASYNC" } else { "SYNC" }.to_string()
+}
+
+fn flushdb(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    Ok(RedisCommand::FlushDb(flush_mode(args)?))
+}
+
+fn flushall(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    Ok(RedisCommand::FlushAll(flush_mode(args)?))
+}
+
 fn config(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
-    if args.len() != 3 {
+    if args.len() == 2
+        && extract_string_arg(&args[1], "operation")?.eq_ignore_ascii_case("RESETSTAT")
+    {
+        Ok(RedisCommand::ConfigResetStat)
+    } else if args.len() != 3 {
         Err(CmdError::InvalidArgumentNum)
     } else {
         let operation = extract_string_arg(&args[1], "operation")?;
@@ -579,12 +2610,40 @@ fn keys(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
     }
 }
 
-fn subscribe(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
-    if args.len() != 2 {
+fn scan(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let cursor: u64 =
+        extract_parse_arg(args.get(1).ok_or(CmdError::InvalidArgumentNum)?, "cursor")?;
+    let mut opts = ScanOptions::default();
+    let mut i = 2;
+    while i < args.len() {
+        let option = extract_string_arg(&args[i], "option")?.to_uppercase();
+        let value = args.get(i + 1).ok_or(CmdError::InvalidArgumentNum)?;
+        match option.as_str() {
+            "MATCH" => opts.pattern = Some(extract_string_arg(value, "pattern")?),
+            "COUNT" => {
+                let count: usize = extract_parse_arg(value, "count")?;
+                if count == 0 {
+                    return Err(CmdError::InvalidArgument("count".to_string()));
+                }
+                opts.count = Some(count);
+            }
+            "TYPE" => opts.key_type = Some(extract_string_arg(value, "type")?),
+            _ => return Err(CmdError::InvalidArgument(option)),
+        }
+        i += 2;
+    }
+    Ok(RedisCommand::Scan(cursor, opts))
+}
+
+fn subscribe(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 2 {
         Err(CmdError::InvalidArgumentNum)
     } else {
-        let channel = extract_string_arg(&args[1], "channel")?;
-        Ok(RedisCommand::Subscribe(channel))
+        let channels = args[1..]
+            .iter()
+            .map(|arg| extract_string_arg(arg, "channel"))
+            .collect::<Result<Vec<String>, CmdError>>()?;
+        Ok(RedisCommand::Subscribe(channels))
     }
 }
 
@@ -625,15 +2684,70 @@ fn publish(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
     }
 }
 
+fn pubsub(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let subcommand = extract_string_arg(
+        args.get(1).ok_or(CmdError::InvalidArgumentNum)?,
+        "subcommand",
+    )?;
+    match subcommand.to_uppercase().as_str() {
+        "CHANNELS" if args.len() <= 3 => {
+            let pattern = match args.get(2) {
+                Some(pattern) => Some(extract_string_arg(pattern, "pattern")?),
+                None => None,
+            };
+            Ok(RedisCommand::PubSubChannels(pattern))
+        }
+        "CHANNELS" => Err(CmdError::InvalidArgumentNum),
+        _ => Err(CmdError::InvalidArgument(subcommand)),
+    }
+}
+
 fn zadd(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
-    if args.len() != 4 {
-        Err(CmdError::InvalidArgumentNum)
-    } else {
-        let set = extract_string_arg(&args[1], "set")?;
-        let score: f64 = extract_parse_arg(&args[2], "score")?;
-        let member = extract_string_arg(&args[3], "member")?;
-        Ok(RedisCommand::ZAdd(set, score, member))
+    if args.len() < 4 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let set = extract_string_arg(&args[1], "set")?;
+    let mut opts = ZAddOptions::default();
+    let mut i = 2;
+    while i < args.len() {
+        let flag = extract_string_arg(&args[i], "flag")?.to_uppercase();
+        match flag.as_str() {
+            "NX" => opts.nx = true,
+            "XX" => opts.xx = true,
+            "GT" => opts.gt = true,
+            "LT" => opts.lt = true,
+            "CH" => opts.ch = true,
+            "INCR" => opts.incr = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    if opts.nx && opts.xx {
+        return Err(CmdError::InvalidArgument("xx".to_string()));
+    }
+    if (opts.gt || opts.lt) && opts.nx || opts.gt && opts.lt {
+        return Err(CmdError::InvalidArgument("gt".to_string()));
     }
+    let pairs = &args[i..];
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    if opts.incr && pairs.len() != 2 {
+        return Err(CmdError::InvalidArgument("incr".to_string()));
+    }
+    let members = pairs
+        .chunks_exact(2)
+        .map(|pair| {
+            let score: f64 = extract_parse_arg(&pair[0], "score")?;
+            // Rust parses "nan", but NaN can't be ordered so isn't a score
+            if score.is_nan() {
+                return Err(CmdError::InvalidFloat);
+            }
+            let member = extract_string_arg(&pair[1], "member")?;
+            Ok((score, member))
+        })
+        .collect::<Result<Vec<(f64, String)>, CmdError>>()?;
+    Ok(RedisCommand::ZAdd(set, members, opts))
 }
 
 fn zrank(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
@@ -686,6 +2800,115 @@ fn zrem(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
     }
 }
 
+fn zremrangebyrank(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 4 {
+        Err(CmdError::InvalidArgumentNum)
+    } else {
+        let set = extract_string_arg(&args[1], "set")?;
+        let start: i64 = extract_parse_arg(&args[2], "start")?;
+        let stop: i64 = extract_parse_arg(&args[3], "stop")?;
+        Ok(RedisCommand::ZRemRangeByRank(set, start, stop))
+    }
+}
+
+/// A score endpoint such as `1.5`, `(1.5` or `-inf`
+fn parse_score_bound(arg: &RedisValueRef, field_name: &str) -> Result<ScoreBound, CmdError> {
+    let bound = extract_string_arg(arg, field_name)?;
+    let (score, exclusive) = match bound.strip_prefix('(') {
+        Some(score) => (score, true),
+        None => (bound.as_str(), false),
+    };
+    match score.parse::<f64>() {
+        Ok(score) if !score.is_nan() => Ok((score, exclusive)),
+        _ => Err(CmdError::ParseError {
+            field: field_name.to_string(),
+        }),
+    }
+}
+
+fn zremrangebyscore(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 4 {
+        Err(CmdError::InvalidArgumentNum)
+    } else {
+        let set = extract_string_arg(&args[1], "set")?;
+        let min = parse_score_bound(&args[2], "min")?;
+        let max = parse_score_bound(&args[3], "max")?;
+        Ok(RedisCommand::ZRemRangeByScore(set, min, max))
+    }
+}
+
+/// A lex endpoint: `[member`, `(member`, `-` or `+`
+fn parse_lex_bound(arg: &RedisValueRef, field_name: &str) -> Result<LexBound, CmdError> {
+    let bound = extract_string_arg(arg, field_name)?;
+    if bound == "-" {
+        Ok(LexBound::Min)
+    } else if bound == "+" {
+        Ok(LexBound::Max)
+    } else if let Some(member) = bound.strip_prefix('[') {
+        Ok(LexBound::Inclusive(member.to_string()))
+    } else if let Some(member) = bound.strip_prefix('(') {
+        Ok(LexBound::Exclusive(member.to_string()))
+    } else {
+        Err(CmdError::InvalidArgument(field_name.to_string()))
+    }
+}
+
+fn zremrangebylex(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 4 {
+        Err(CmdError::InvalidArgumentNum)
+    } else {
+        let set = extract_string_arg(&args[1], "set")?;
+        let min = parse_lex_bound(&args[2], "min")?;
+        let max = parse_lex_bound(&args[3], "max")?;
+        Ok(RedisCommand::ZRemRangeByLex(set, min, max))
+    }
+}
+
+/// `numkeys key...` as taken by ZDIFF and ZDIFFSTORE, returning the keys and
+/// whatever arguments follow them
+fn numkeys_and_keys(args: &[RedisValueRef]) -> Result<(Vec<String>, &[RedisValueRef]), CmdError> {
+    let numkeys: usize = extract_parse_arg(&args[0], "numkeys")?;
+    if numkeys == 0 || args.len() < 1 + numkeys {
+        return Err(CmdError::InvalidArgument("numkeys".to_string()));
+    }
+    let keys = args[1..1 + numkeys]
+        .iter()
+        .map(|arg| extract_string_arg(arg, "key"))
+        .collect::<Result<_, _>>()?;
+    Ok((keys, &args[1 + numkeys..]))
+}
+
+fn zdiff(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 3 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let (keys, rest) = numkeys_and_keys(&args[1..])?;
+    let withscores = match rest {
+        [] => false,
+        [option] => {
+            let option = extract_string_arg(option, "option")?;
+            if !option.eq_ignore_ascii_case("WITHSCORES") {
+                return Err(CmdError::InvalidArgument(option));
+            }
+            true
+        }
+        _ => return Err(CmdError::InvalidArgumentNum),
+    };
+    Ok(RedisCommand::ZDiff(keys, withscores))
+}
+
+fn zdiffstore(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 4 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let dest = extract_string_arg(&args[1], "destination")?;
+    let (keys, rest) = numkeys_and_keys(&args[2..])?;
+    if !rest.is_empty() {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    Ok(RedisCommand::ZDiffStore(dest, keys))
+}
+
 fn geoadd(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
     if args.len() != 5 {
         Err(CmdError::InvalidArgumentNum)
@@ -739,6 +2962,62 @@ fn geosearch(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
     }
 }
 
+/// The WITHCOORD, WITHDIST, COUNT and ASC/DESC options of GEORADIUS
+fn parse_georadius_options(args: &[RedisValueRef]) -> Result<GeoRadiusOptions, CmdError> {
+    let mut opts = GeoRadiusOptions::default();
+    let mut i = 0;
+    while i < args.len() {
+        let option = extract_string_arg(&args[i], "option")?;
+        match option.to_uppercase().as_str() {
+            "WITHCOORD" => opts.withcoord = true,
+            "WITHDIST" => opts.withdist = true,
+            "ASC" => opts.desc = Some(false),
+            "DESC" => opts.desc = Some(true),
+            "COUNT" => {
+                let count: usize = extract_parse_arg(
+                    args.get(i + 1).ok_or(CmdError::InvalidArgumentNum)?,
+                    "count",
+                )?;
+                if count == 0 {
+                    return Err(CmdError::InvalidArgument("count".to_string()));
+                }
+                opts.count = Some(count);
+                i += 1;
+            }
+            _ => return Err(CmdError::InvalidArgument(option)),
+        }
+        i += 1;
+    }
+    Ok(opts)
+}
+
+fn georadius(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 6 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let lng: f64 = extract_parse_arg(&args[2], "longitude")?;
+    let lat: f64 = extract_parse_arg(&args[3], "latitude")?;
+    let radius: f64 = extract_parse_arg(&args[4], "radius")?;
+    let unit = extract_string_arg(&args[5], "unit")?;
+    let opts = parse_georadius_options(&args[6..])?;
+    Ok(RedisCommand::GeoRadius(key, lng, lat, radius, unit, opts))
+}
+
+fn georadiusbymember(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 5 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let member = extract_string_arg(&args[2], "member")?;
+    let radius: f64 = extract_parse_arg(&args[3], "radius")?;
+    let unit = extract_string_arg(&args[4], "unit")?;
+    let opts = parse_georadius_options(&args[5..])?;
+    Ok(RedisCommand::GeoRadiusByMember(
+        key, member, radius, unit, opts,
+    ))
+}
+
 fn acl(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
     let command = extract_string_arg(&args[1], "command")?;
     match command.as_str() {
@@ -766,16 +3045,847 @@ fn auth(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
     Ok(RedisCommand::Auth(username, password))
 }
 
+fn hello(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let protover = args
+        .get(1)
+        .map(|protover| extract_parse_arg::<u64>(protover, "protover"))
+        .transpose()?;
+    let mut auth = None;
+    let mut name = None;
+    let mut i = 2;
+    while i < args.len() {
+        let option = extract_string_arg(&args[i], "option")?.to_uppercase();
+        match option.as_str() {
+            "AUTH" if i + 2 < args.len() => {
+                let username = extract_string_arg(&args[i + 1], "username")?;
+                let password = extract_string_arg(&args[i + 2], "password")?;
+                auth = Some((username, password));
+                i += 3;
+            }
+            "SETNAME" if i + 1 < args.len() => {
+                name = Some(extract_string_arg(&args[i + 1], "clientname")?);
+                i += 2;
+            }
+            _ => return Err(CmdError::InvalidArgument(option)),
+        }
+    }
+    Ok(RedisCommand::Hello(protover, auth, name))
+}
+
+fn client(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let subcommand = extract_string_arg(
+        args.get(1).ok_or(CmdError::InvalidArgumentNum)?,
+        "subcommand",
+    )?;
+    match subcommand.to_uppercase().as_str() {
+        "SETNAME" if args.len() == 3 => {
+            let name = extract_string_arg(&args[2], "name")?;
+            if name.contains(' ') {
+                Err(CmdError::InvalidArgument("name".to_string()))
+            } else {
+                Ok(RedisCommand::ClientSetName(name))
+            }
+        }
+        "GETNAME" if args.len() == 2 => Ok(RedisCommand::ClientGetName()),
+        "TRACKING" | "NO-EVICT" | "NO-TOUCH" if args.len() == 3 => {
+            let status = extract_string_arg(&args[2], "status")?;
+            let on = match status.to_uppercase().as_str() {
+                "ON" => true,
+                "OFF" => false,
+                _ => return Err(CmdError::InvalidArgument(status)),
+            };
+            Ok(match subcommand.to_uppercase().as_str() {
+                "TRACKING" => RedisCommand::ClientTracking(on),
+                "NO-EVICT" => RedisCommand::ClientNoEvict(on),
+                _ => RedisCommand::ClientNoTouch(on),
+            })
+        }
+        "SETNAME" | "GETNAME" | "TRACKING" | "NO-EVICT" | "NO-TOUCH" => {
+            Err(CmdError::InvalidArgumentNum)
+        }
+        _ => Err(CmdError::InvalidArgument(subcommand)),
+    }
+}
+
+fn _command(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    // A bare COMMAND describes every command, same as COMMAND INFO without names
+    let Some(subcommand) = args.get(1) else {
+        return Ok(RedisCommand::CommandInfo(vec![]));
+    };
+    let subcommand = extract_string_arg(subcommand, "subcommand")?;
+    match subcommand.to_uppercase().as_str() {
+        "INFO" => {
+            let names = args[2..]
+                .iter()
+                .map(|name| extract_string_arg(name, "command-name"))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(RedisCommand::CommandInfo(names))
+        }
+        "COUNT" if args.len() == 2 => Ok(RedisCommand::CommandCount),
+        "COUNT" => Err(CmdError::InvalidArgumentNum),
+        "LIST" if args.len() == 2 => Ok(RedisCommand::CommandList(None)),
+        "LIST" if args.len() == 5 => {
+            let filterby = extract_string_arg(&args[2], "filterby")?;
+            if !filterby.eq_ignore_ascii_case("FILTERBY") {
+                return Err(CmdError::InvalidArgument(filterby));
+            }
+            let kind = extract_string_arg(&args[3], "filter")?;
+            let value = extract_string_arg(&args[4], "value")?;
+            let filter = match kind.to_uppercase().as_str() {
+                "MODULE" => CommandFilter::Module(value),
+                "ACLCAT" => CommandFilter::AclCat(value),
+                "PATTERN" => CommandFilter::Pattern(value),
+                _ => return Err(CmdError::InvalidArgument(kind)),
+            };
+            Ok(RedisCommand::CommandList(Some(filter)))
+        }
+        "LIST" => Err(CmdError::InvalidArgumentNum),
+        _ => Err(CmdError::InvalidArgument(subcommand)),
+    }
+}
+
+fn debug(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let subcommand = extract_string_arg(
+        args.get(1).ok_or(CmdError::InvalidArgumentNum)?,
+        "subcommand",
+    )?;
+    match subcommand.to_uppercase().as_str() {
+        "SET-ACTIVE-EXPIRE" if args.len() == 3 => {
+            let enabled: u8 = extract_parse_arg(&args[2], "enabled")?;
+            Ok(RedisCommand::DebugSetActiveExpire(enabled != 0))
+        }
+        "SET-ACTIVE-EXPIRE" => Err(CmdError::InvalidArgumentNum),
+        "QUICKLIST-PACKED-THRESHOLD" if args.len() == 3 => {
+            let threshold = extract_string_arg(&args[2], "threshold")?;
+            let threshold = parse_memory_size(&threshold).map_err(|_| CmdError::ParseError {
+                field: "threshold".to_string(),
+            })? as usize;
+            if threshold == 0 {
+                return Err(CmdError::InvalidArgument("threshold".to_string()));
+            }
+            Ok(RedisCommand::DebugQuicklistPackedThreshold(threshold))
+        }
+        "QUICKLIST-PACKED-THRESHOLD" => Err(CmdError::InvalidArgumentNum),
+        "STRINGMATCH-LEN" if args.len() == 4 => {
+            let pattern = extract_string_arg(&args[2], "pattern")?;
+            let string = extract_string_arg(&args[3], "string")?;
+            Ok(RedisCommand::DebugStringMatchLen(pattern, string))
+        }
+        "STRINGMATCH-LEN" => Err(CmdError::InvalidArgumentNum),
+        "SLEEP" if args.len() == 3 => {
+            let seconds: f64 = extract_parse_arg(&args[2], "seconds")?;
+            // Negative, non-finite and too long for a Duration are all refused
+            if std::time::Duration::try_from_secs_f64(seconds).is_err() {
+                return Err(CmdError::InvalidArgument("seconds".to_string()));
+            }
+            Ok(RedisCommand::DebugSleep(seconds))
+        }
+        "SLEEP" => Err(CmdError::InvalidArgumentNum),
+        "CHANGE-REPL-ID" if args.len() == 2 => Ok(RedisCommand::DebugChangeReplId),
+        "CHANGE-REPL-ID" => Err(CmdError::InvalidArgumentNum),
+        "OBJECT" if args.len() == 3 => {
+            let key = extract_string_arg(&args[2], "key")?;
+            Ok(RedisCommand::DebugObject(key))
+        }
+        "OBJECT" => Err(CmdError::InvalidArgumentNum),
+        "RELOAD" if args.len() == 2 => Ok(RedisCommand::DebugReload),
+        "RELOAD" => Err(CmdError::InvalidArgumentNum),
+        _ => Err(CmdError::InvalidArgument(subcommand)),
+    }
+}
+
+fn slowlog(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let subcommand = extract_string_arg(
+        args.get(1).ok_or(CmdError::InvalidArgumentNum)?,
+        "subcommand",
+    )?;
+    match subcommand.to_uppercase().as_str() {
+        "GET" if args.len() <= 3 => {
+            let count = match args.get(2) {
+                Some(arg) => {
+                    let count: i64 = extract_parse_arg(arg, "count")?;
+                    if count < -1 {
+                        return Err(CmdError::InvalidArgument("count".to_string()));
+                    }
+                    Some(count)
+                }
+                None => None,
+            };
+            Ok(RedisCommand::SlowlogGet(count))
+        }
+        "LEN" if args.len() == 2 => Ok(RedisCommand::SlowlogLen),
+        "RESET" if args.len() == 2 => Ok(RedisCommand::SlowlogReset),
+        "GET" | "LEN" | "RESET" => Err(CmdError::InvalidArgumentNum),
+        _ => Err(CmdError::InvalidArgument(subcommand)),
+    }
+}
+
+fn memory(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let subcommand = extract_string_arg(
+        args.get(1).ok_or(CmdError::InvalidArgumentNum)?,
+        "subcommand",
+    )?;
+    match subcommand.to_uppercase().as_str() {
+        "USAGE" if args.len() == 3 || args.len() == 5 => {
+            let key = extract_string_arg(&args[2], "key")?;
+            let samples = match args.get(3) {
+                Some(option) => {
+                    let option = extract_string_arg(option, "option")?;
+                    if !option.eq_ignore_ascii_case("SAMPLES") {
+                        return Err(CmdError::InvalidArgument(option));
+                    }
+                    Some(extract_parse_arg(&args[4], "count")?)
+                }
+                None => None,
+            };
+            Ok(RedisCommand::MemoryUsage(key, samples))
+        }
+        "USAGE" => Err(CmdError::InvalidArgumentNum),
+        "DOCTOR" if args.len() == 2 => Ok(RedisCommand::MemoryDoctor),
+        "DOCTOR" => Err(CmdError::InvalidArgumentNum),
+        _ => Err(CmdError::InvalidArgument(subcommand)),
+    }
+}
+
+fn cluster(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let subcommand = extract_string_arg(
+        args.get(1).ok_or(CmdError::InvalidArgumentNum)?,
+        "subcommand",
+    )?;
+    if args.len() != 2 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    match subcommand.to_uppercase().as_str() {
+        "INFO" => Ok(RedisCommand::ClusterInfo),
+        "MYID" => Ok(RedisCommand::ClusterMyId),
+        "SLOTS" => Ok(RedisCommand::ClusterSlots),
+        "SHARDS" => Ok(RedisCommand::ClusterShards),
+        _ => Err(CmdError::InvalidArgument(subcommand)),
+    }
+}
+
+/// FUNCTION: LIST and STATS describe an empty function registry so that
+/// clients probing for support don't fail. LIBRARYNAME/WITHCODE filters on
+/// LIST and the REPLACE flag on LOAD are accepted and ignored.
+fn function(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let subcommand = extract_string_arg(
+        args.get(1).ok_or(CmdError::InvalidArgumentNum)?,
+        "subcommand",
+    )?;
+    match subcommand.to_uppercase().as_str() {
+        "LIST" => Ok(RedisCommand::FunctionList),
+        "STATS" if args.len() == 2 => Ok(RedisCommand::FunctionStats),
+        "LOAD" if args.len() >= 3 => {
+            let code = extract_string_arg(&args[args.len() - 1], "function code")?;
+            Ok(RedisCommand::FunctionLoad(code))
+        }
+        "STATS" | "LOAD" => Err(CmdError::InvalidArgumentNum),
+        _ => Err(CmdError::InvalidArgument(subcommand)),
+    }
+}
+
+fn fcall(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 3 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let function = extract_string_arg(&args[1], "function")?;
+    let numkeys: usize = extract_parse_arg(&args[2], "numkeys")?;
+    if args.len() < 3 + numkeys {
+        return Err(CmdError::InvalidArgument("numkeys".to_string()));
+    }
+    let keys = args[3..3 + numkeys]
+        .iter()
+        .map(|arg| extract_string_arg(arg, "key"))
+        .collect::<Result<_, _>>()?;
+    let rest = args[3 + numkeys..]
+        .iter()
+        .map(|arg| extract_string_arg(arg, "argument"))
+        .collect::<Result<_, _>>()?;
+    Ok(RedisCommand::Fcall(function, keys, rest))
+}
+
+fn lcs(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 3 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key1 = extract_string_arg(&args[1], "key1")?;
+    let key2 = extract_string_arg(&args[2], "key2")?;
+    let mut opts = LcsOptions::default();
+    let mut i = 3;
+    while i < args.len() {
+        let option = extract_string_arg(&args[i], "option")?.to_uppercase();
+        match option.as_str() {
+            "LEN" => opts.len = true,
+            "IDX" => opts.idx = true,
+            "WITHMATCHLEN" => opts.with_match_len = true,
+            "MINMATCHLEN" => {
+                i += 1;
+                let min_match_len = args.get(i).ok_or(CmdError::InvalidArgumentNum)?;
+                // Like Redis, a negative length just means no minimum
+                let min_match_len: i64 = extract_parse_arg(min_match_len, "minmatchlen")?;
+                opts.min_match_len = min_match_len.max(0) as u64;
+            }
+            _ => return Err(CmdError::InvalidArgument(option)),
+        }
+        i += 1;
+    }
+    if opts.len && opts.idx {
+        return Err(CmdError::InvalidArgument("LEN and IDX".to_string()));
+    }
+    Ok(RedisCommand::Lcs(key1, key2, opts))
+}
+
+fn setbit(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 4 {
+        Err(CmdError::InvalidArgumentNum)
+    } else {
+        let key = extract_string_arg(&args[1], "key")?;
+        let offset: u64 = extract_parse_arg(&args[2], "offset")?;
+        let value: u8 = extract_parse_arg(&args[3], "value")?;
+        if value > 1 {
+            return Err(CmdError::InvalidArgument("value".to_string()));
+        }
+        Ok(RedisCommand::SetBit(key, offset, value))
+    }
+}
+
+fn getbit(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() != 3 {
+        Err(CmdError::InvalidArgumentNum)
+    } else {
+        let key = extract_string_arg(&args[1], "key")?;
+        let offset: u64 = extract_parse_arg(&args[2], "offset")?;
+        Ok(RedisCommand::GetBit(key, offset))
+    }
+}
+
+fn parse_bit_unit(arg: &RedisValueRef) -> Result<BitUnit, CmdError> {
+    let unit = extract_string_arg(arg, "unit")?.to_uppercase();
+    match unit.as_str() {
+        "BYTE" => Ok(BitUnit::Byte),
+        "BIT" => Ok(BitUnit::Bit),
+        _ => Err(CmdError::InvalidArgument(unit)),
+    }
+}
+
+fn bitcount(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let key = extract_string_arg(args.get(1).ok_or(CmdError::InvalidArgumentNum)?, "key")?;
+    match args.len() {
+        2 => Ok(RedisCommand::BitCount(key, None, BitUnit::Byte)),
+        4 | 5 => {
+            let start: i64 = extract_parse_arg(&args[2], "start")?;
+            let end: i64 = extract_parse_arg(&args[3], "end")?;
+            let unit = args
+                .get(4)
+                .map(parse_bit_unit)
+                .transpose()?
+                .unwrap_or_default();
+            Ok(RedisCommand::BitCount(key, Some((start, end)), unit))
+        }
+        _ => Err(CmdError::InvalidArgumentNum),
+    }
+}
+
+fn bitpos(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 3 || args.len() > 6 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let key = extract_string_arg(&args[1], "key")?;
+    let bit: u8 = extract_parse_arg(&args[2], "bit")?;
+    if bit > 1 {
+        return Err(CmdError::InvalidArgument("bit".to_string()));
+    }
+    let start = args
+        .get(3)
+        .map(|start| extract_parse_arg::<i64>(start, "start"))
+        .transpose()?;
+    let end = args
+        .get(4)
+        .map(|end| extract_parse_arg::<i64>(end, "end"))
+        .transpose()?;
+    let unit = args
+        .get(5)
+        .map(parse_bit_unit)
+        .transpose()?
+        .unwrap_or_default();
+    Ok(RedisCommand::BitPos(key, bit, start, end, unit))
+}
+
+fn bitop(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    if args.len() < 4 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    let operation = extract_string_arg(&args[1], "operation")?.to_uppercase();
+    let operation = match operation.as_str() {
+        "AND" => BitOperation::And,
+        "OR" => BitOperation::Or,
+        "XOR" => BitOperation::Xor,
+        "NOT" => BitOperation::Not,
+        _ => return Err(CmdError::InvalidArgument(operation)),
+    };
+    let dest = extract_string_arg(&args[2], "destkey")?;
+    let keys = args[3..]
+        .iter()
+        .map(|arg| extract_string_arg(arg, "key"))
+        .collect::<Result<Vec<String>, CmdError>>()?;
+    if operation == BitOperation::Not && keys.len() != 1 {
+        return Err(CmdError::InvalidArgumentNum);
+    }
+    Ok(RedisCommand::BitOp(operation, dest, keys))
+}
+
+/// A BITFIELD offset: a bit offset, or `#n` for the nth field of this type
+fn parse_bitfield_offset(arg: &RedisValueRef, ty: BitFieldType) -> Result<u64, CmdError> {
+    let offset = extract_string_arg(arg, "offset")?;
+    let invalid = || CmdError::InvalidArgument("offset".to_string());
+    let offset = match offset.strip_prefix('#') {
+        Some(index) => index
+            .parse::<u64>()
+            .ok()
+            .and_then(|index| index.checked_mul(ty.bits as u64)),
+        None => offset.parse::<u64>().ok(),
+    }
+    .ok_or_else(invalid)?;
+    let last_bit = offset.checked_add(ty.bits as u64 - 1).ok_or_else(invalid)?;
+    if last_bit > MAX_BIT_OFFSET {
+        return Err(invalid());
+    }
+    Ok(offset)
+}
+
+fn bitfield(args: &[RedisValueRef]) -> Result<RedisCommand, CmdError> {
+    let key = extract_string_arg(args.get(1).ok_or(CmdError::InvalidArgumentNum)?, "key")?;
+    let mut ops = Vec::new();
+    let mut rest = &args[2..];
+    while let Some(op) = rest.first() {
+        let op = extract_string_arg(op, "subcommand")?.to_uppercase();
+        let argc = match op.as_str() {
+            "OVERFLOW" => 2,
+            "GET" => 3,
+            "SET" | "INCRBY" => 4,
+            _ => return Err(CmdError::InvalidArgument(op)),
+        };
+        let Some(op_args) = rest.get(1..argc) else {
+            return Err(CmdError::InvalidArgumentNum);
+        };
+        ops.push(match op.as_str() {
+            "OVERFLOW" => {
+                let mode = extract_string_arg(&op_args[0], "overflow")?;
+                BitFieldOp::Overflow(match mode.to_uppercase().as_str() {
+                    "WRAP" => Overflow::Wrap,
+                    "SAT" => Overflow::Sat,
+                    "FAIL" => Overflow::Fail,
+                    _ => return Err(CmdError::InvalidArgument(mode)),
+                })
+            }
+            _ => {
+                let ty: BitFieldType = extract_parse_arg(&op_args[0], "type")?;
+                let offset = parse_bitfield_offset(&op_args[1], ty)?;
+                match op.as_str() {
+                    "GET" => BitFieldOp::Get(ty, offset),
+                    "SET" => BitFieldOp::Set(ty, offset, extract_parse_arg(&op_args[2], "value")?),
+                    _ => {
+                        BitFieldOp::IncrBy(ty, offset, extract_parse_arg(&op_args[2], "increment")?)
+                    }
+                }
+            }
+        });
+        rest = &rest[argc..];
+    }
+    Ok(RedisCommand::BitField(key, ops))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_command_round_trip() {
+        let s = |v: &str| v.to_string();
+        let commands = vec![
+            RedisCommand::Ping(None),
+            RedisCommand::Lolwut(None),
+            RedisCommand::Lolwut(Some(5)),
+            RedisCommand::Ping(Some(s("hi"))),
+            RedisCommand::Echo(Bytes::from("hello")),
+            RedisCommand::Set(s("k"), s("v")),
+            RedisCommand::SetEx(s("k"), s("v"), 100),
+            RedisCommand::SetPxAt(s("k"), s("v"), 1_700_000_000_000),
+            RedisCommand::Get(s("k")),
+            RedisCommand::Rpush(s("l"), vec![s("a"), s("b")]),
+            RedisCommand::Lpush(s("l"), vec![s("a")]),
+            RedisCommand::Rpushx(s("l"), vec![s("a"), s("b")]),
+            RedisCommand::Lpushx(s("l"), vec![s("a")]),
+            RedisCommand::Lrange(s("l"), 0, -1),
+            RedisCommand::LLen(s("l")),
+            RedisCommand::LPop(s("l"), None),
+            RedisCommand::LPop(s("l"), Some(2)),
+            RedisCommand::BLPop(s("l"), Some(0.5)),
+            RedisCommand::Type(s("k")),
+            RedisCommand::XAdd(s("st"), (Some(1), None), vec![(s("f"), s("v"))], false),
+            RedisCommand::XAdd(s("st"), (None, None), vec![(s("f"), s("v"))], false),
+            RedisCommand::XAdd(s("st"), (None, None), vec![(s("f"), s("v"))], true),
+            RedisCommand::XRange(s("st"), ((None, None), false), ((None, None), false)),
+            RedisCommand::XSetId(s("st"), 5, 5),
+            RedisCommand::XInfoStream(s("st"), None),
+            RedisCommand::XInfoStream(s("st"), Some(0)),
+            RedisCommand::HSet(s("h"), vec![(s("f1"), s("v1")), (s("f2"), s("v2"))]),
+            RedisCommand::HGet(s("h"), s("f1")),
+            RedisCommand::HGetAll(s("h")),
+            RedisCommand::HLen(s("h")),
+            RedisCommand::HDel(s("h"), vec![s("f1"), s("f2")]),
+            RedisCommand::HExpire(s("h"), 10, vec![s("f1"), s("f2")]),
+            RedisCommand::HPExpireAt(s("h"), 1_700_000_000_000, vec![s("f1")]),
+            RedisCommand::HTtl(s("h"), vec![s("f1")]),
+            RedisCommand::HPersist(s("h"), vec![s("f1")]),
+            RedisCommand::HGetDel(s("h"), vec![s("f1"), s("f2")]),
+            RedisCommand::HGetEx(s("h"), None, vec![s("f1")]),
+            RedisCommand::HGetEx(s("h"), Some(GetExOption::Expire(5000)), vec![s("f1")]),
+            RedisCommand::HGetEx(s("h"), Some(GetExOption::Persist), vec![s("f1")]),
+            RedisCommand::HSetEx(
+                s("h"),
+                HSetExOptions::default(),
+                vec![(s("f1"), s("v1")), (s("f2"), s("v2"))],
+            ),
+            RedisCommand::HSetEx(
+                s("h"),
+                HSetExOptions {
+                    condition: Some(FieldCondition::Fnx),
+                    expiry: Some(GetExOption::ExpireAt(5000)),
+                    keep_ttl: false,
+                },
+                vec![(s("f1"), s("v1"))],
+            ),
+            RedisCommand::HSetEx(
+                s("h"),
+                HSetExOptions {
+                    condition: Some(FieldCondition::Fxx),
+                    expiry: None,
+                    keep_ttl: true,
+                },
+                vec![(s("f1"), s("v1"))],
+            ),
+            RedisCommand::SAdd(s("set"), vec![s("a"), s("b")]),
+            RedisCommand::SRem(s("set"), vec![s("a")]),
+            RedisCommand::SMembers(s("set")),
+            RedisCommand::SetOp(SetOperation::Inter, vec![s("a"), s("b")]),
+            RedisCommand::SetOp(SetOperation::Diff, vec![s("a")]),
+            RedisCommand::SetOpStore(SetOperation::Union, s("dest"), vec![s("a"), s("b")]),
+            RedisCommand::SIsMember(s("set"), s("a")),
+            RedisCommand::SCard(s("set")),
+            RedisCommand::PfAdd(s("hll"), vec![s("a"), s("b")]),
+            RedisCommand::PfCount(vec![s("hll"), s("other")]),
+            RedisCommand::PfMerge(s("dest"), vec![s("hll")]),
+            RedisCommand::XRange(
+                s("st"),
+                ((Some(1), Some(1)), true),
+                ((Some(2), Some(0)), true),
+            ),
+            RedisCommand::XRead(
+                vec![(s("a"), (Some(0), Some(0))), (s("b"), (Some(1), Some(2)))],
+                None,
+            ),
+            RedisCommand::XRead(vec![(s("a"), (Some(u64::MAX), Some(u64::MAX)))], Some(100)),
+            RedisCommand::Incr(s("k")),
+            RedisCommand::Multi,
+            RedisCommand::Exec,
+            RedisCommand::Discard,
+            RedisCommand::Info(s("replication")),
+            RedisCommand::ReplConf(s("GETACK"), s("*")),
+            RedisCommand::Psync(s("?"), -1),
+            RedisCommand::RdbPayload(Bytes::from("REDIS0011")),
+            RedisCommand::Wait(1, 500),
+            RedisCommand::WaitAof(1, 0, 100),
+            RedisCommand::Config(s("GET"), s("dir")),
+            RedisCommand::Keys(s("*")),
+            RedisCommand::Del(vec![s("a"), s("b")]),
+            RedisCommand::Unlink(vec![s("a")]),
+            RedisCommand::Scan(0, ScanOptions::default()),
+            RedisCommand::Scan(
+                7,
+                ScanOptions {
+                    pattern: Some(s("user:*")),
+                    count: Some(100),
+                    key_type: Some(s("zset")),
+                },
+            ),
+            RedisCommand::Subscribe(vec![s("ch"), s("other")]),
+            RedisCommand::Unsubscribe(s("ch")),
+            RedisCommand::PSubscribe(s("c*")),
+            RedisCommand::PUnsubscribe(s("c*")),
+            RedisCommand::Publish(s("ch"), s("msg")),
+            RedisCommand::PubSubChannels(None),
+            RedisCommand::PubSubChannels(Some(s("c*"))),
+            RedisCommand::ZAdd(s("z"), vec![(1.5, s("m"))], ZAddOptions::default()),
+            RedisCommand::ZAdd(
+                s("z"),
+                vec![(1.0, s("a")), (2.0, s("b"))],
+                ZAddOptions::default(),
+            ),
+            RedisCommand::ZAdd(
+                s("z"),
+                vec![(2.0, s("m"))],
+                ZAddOptions {
+                    xx: true,
+                    gt: true,
+                    ch: true,
+                    incr: true,
+                    ..Default::default()
+                },
+            ),
+            RedisCommand::ZRank(s("z"), s("m")),
+            RedisCommand::ZRange(s("z"), 0, -1),
+            RedisCommand::ZCard(s("z")),
+            RedisCommand::ZScore(s("z"), s("m")),
+            RedisCommand::ZRem(s("z"), s("m")),
+            RedisCommand::ZRemRangeByRank(s("z"), 1, -2),
+            RedisCommand::ZRemRangeByScore(s("z"), (f64::NEG_INFINITY, false), (2.5, true)),
+            RedisCommand::ZRemRangeByLex(s("z"), LexBound::Min, LexBound::Inclusive(s("m"))),
+            RedisCommand::ZRemRangeByLex(s("z"), LexBound::Exclusive(s("a")), LexBound::Max),
+            RedisCommand::ZRemRangeByLex(s("z"), LexBound::Max, LexBound::Min),
+            RedisCommand::ZDiff(vec![s("a"), s("b")], false),
+            RedisCommand::ZDiff(vec![s("a")], true),
+            RedisCommand::ZDiffStore(s("dest"), vec![s("a"), s("b")]),
+            RedisCommand::GeoAdd(s("g"), 13.361389, 38.115556, s("Palermo")),
+            RedisCommand::GeoPos(s("g"), vec![s("Palermo")]),
+            RedisCommand::GeoDist(s("g"), s("a"), s("b")),
+            RedisCommand::GeoSearch(s("g"), 15.0, 37.0, 200.0, s("km")),
+            RedisCommand::GeoRadius(
+                s("g"),
+                15.0,
+                37.0,
+                200.0,
+                s("km"),
+                GeoRadiusOptions::default(),
+            ),
+            RedisCommand::GeoRadiusByMember(
+                s("g"),
+                s("Palermo"),
+                100.5,
+                s("mi"),
+                GeoRadiusOptions {
+                    withcoord: true,
+                    withdist: true,
+                    count: Some(3),
+                    desc: Some(true),
+                },
+            ),
+            RedisCommand::AclWhoami(),
+            RedisCommand::AclGetUser(s("default")),
+            RedisCommand::AclSetUser(s("default"), s("pw")),
+            RedisCommand::Auth(s("default"), s("pw")),
+            RedisCommand::Hello(None, None, None),
+            RedisCommand::Hello(Some(3), Some((s("default"), s("pw"))), Some(s("app"))),
+            RedisCommand::Reset,
+            RedisCommand::Quit,
+            RedisCommand::FlushDb(false),
+            RedisCommand::FlushDb(true),
+            RedisCommand::FlushAll(true),
+            RedisCommand::Lcs(
+                s("a"),
+                s("b"),
+                LcsOptions {
+                    idx: true,
+                    min_match_len: 4,
+                    with_match_len: true,
+                    ..Default::default()
+                },
+            ),
+            RedisCommand::SetBit(s("k"), 7, 1),
+            RedisCommand::GetBit(s("k"), 7),
+            RedisCommand::BitCount(s("k"), None, BitUnit::Byte),
+            RedisCommand::BitCount(s("k"), Some((1, -1)), BitUnit::Bit),
+            RedisCommand::BitPos(s("k"), 1, None, None, BitUnit::Byte),
+            RedisCommand::BitPos(s("k"), 0, Some(2), Some(-1), BitUnit::Bit),
+            RedisCommand::BitOp(BitOperation::Xor, s("dest"), vec![s("a"), s("b")]),
+            RedisCommand::BitField(s("bf"), vec![]),
+            RedisCommand::BitField(
+                s("bf"),
+                vec![
+                    BitFieldOp::Set(
+                        BitFieldType {
+                            signed: false,
+                            bits: 8,
+                        },
+                        0,
+                        200,
+                    ),
+                    BitFieldOp::Overflow(Overflow::Fail),
+                    BitFieldOp::IncrBy(
+                        BitFieldType {
+                            signed: true,
+                            bits: 64,
+                        },
+                        13,
+                        -5,
+                    ),
+                    BitFieldOp::Overflow(Overflow::Sat),
+                    BitFieldOp::Get(
+                        BitFieldType {
+                            signed: true,
+                            bits: 3,
+                        },
+                        100,
+                    ),
+                ],
+            ),
+            RedisCommand::ClientSetName(s("app")),
+            RedisCommand::ClientGetName(),
+            RedisCommand::ClientTracking(true),
+            RedisCommand::ClientTracking(false),
+            RedisCommand::ClientNoEvict(true),
+            RedisCommand::ClientNoTouch(false),
+            RedisCommand::CommandInfo(vec![s("get"), s("set")]),
+            RedisCommand::CommandCount,
+            RedisCommand::CommandList(None),
+            RedisCommand::CommandList(Some(CommandFilter::Pattern(s("z*")))),
+            RedisCommand::CommandList(Some(CommandFilter::AclCat(s("read")))),
+            RedisCommand::CommandList(Some(CommandFilter::Module(s("json")))),
+            RedisCommand::DebugSetActiveExpire(false),
+            RedisCommand::DebugQuicklistPackedThreshold(1024),
+            RedisCommand::DebugStringMatchLen(s("h*o"), s("hello")),
+            RedisCommand::GetEx(s("k"), None),
+            RedisCommand::GetEx(s("k"), Some(GetExOption::Expire(1500))),
+            RedisCommand::GetEx(s("k"), Some(GetExOption::ExpireAt(1700000000000))),
+            RedisCommand::GetEx(s("k"), Some(GetExOption::Persist)),
+            RedisCommand::Append(s("k"), s("abc")),
+            RedisCommand::StrLen(s("k")),
+            RedisCommand::Expire(s("k"), 10),
+            RedisCommand::Expire(s("k"), -1),
+            RedisCommand::PExpireAt(s("k"), 1_700_000_000_000),
+            RedisCommand::Sort(s("l"), SortOptions::default()),
+            RedisCommand::ObjectEncoding(s("k")),
+            RedisCommand::ObjectIdleTime(s("k")),
+            RedisCommand::ObjectFreq(s("k")),
+            RedisCommand::Touch(vec![s("a"), s("b")]),
+            RedisCommand::BgSave,
+            RedisCommand::Save,
+            RedisCommand::LastSave,
+            RedisCommand::Role,
+            RedisCommand::Shutdown(None),
+            RedisCommand::Shutdown(Some(true)),
+            RedisCommand::Shutdown(Some(false)),
+            RedisCommand::ConfigResetStat,
+            RedisCommand::SlowlogGet(None),
+            RedisCommand::SlowlogGet(Some(-1)),
+            RedisCommand::SlowlogLen,
+            RedisCommand::SlowlogReset,
+            RedisCommand::MemoryUsage(s("k"), None),
+            RedisCommand::MemoryUsage(s("k"), Some(0)),
+            RedisCommand::MemoryDoctor,
+            RedisCommand::ClusterInfo,
+            RedisCommand::ClusterMyId,
+            RedisCommand::ClusterSlots,
+            RedisCommand::ClusterShards,
+            RedisCommand::FunctionList,
+            RedisCommand::FunctionStats,
+            RedisCommand::FunctionLoad(s("#!lua name=lib")),
+            RedisCommand::Fcall(s("f"), vec![s("k1"), s("k2")], vec![s("a")]),
+            RedisCommand::Fcall(s("f"), vec![], vec![]),
+            RedisCommand::DebugSleep(0.5),
+            RedisCommand::DebugChangeReplId,
+            RedisCommand::DebugObject(s("k")),
+            RedisCommand::DebugReload,
+            RedisCommand::Sort(
+                s("l"),
+                SortOptions {
+                    by: Some(s("weight_*")),
+                    limit: Some((0, 10)),
+                    get: vec![s("#"), s("name_*")],
+                    desc: true,
+                    alpha: true,
+                    store: Some(s("dest")),
+                },
+            ),
+            RedisCommand::SetRange(s("k"), 6, s("Redis")),
+            RedisCommand::GetRange(s("k"), 0, -1),
+        ];
+        for command in commands {
+            let value: RedisValueRef = command.clone().try_into().unwrap();
+            let parsed: RedisCommand = value.try_into().unwrap();
+            assert_eq!(parsed, command);
+        }
+        // SET ... GET replicates as a plain SET
+        let value: RedisValueRef = RedisCommand::SetGet(s("k"), s("v"), Some(SetExpiry::In(10)))
+            .try_into()
+            .unwrap();
+        let parsed: RedisCommand = value.try_into().unwrap();
+        assert_eq!(parsed, RedisCommand::SetEx(s("k"), s("v"), 10));
+    }
+
+    #[test]
+    fn test_getex_options() {
+        let value = RArray(vec![
+            RString("GETEX"),
+            RString("k"),
+            RString("ex"),
+            RString("2"),
+        ]);
+        let command: RedisCommand = value.try_into().unwrap();
+        assert_eq!(
+            command,
+            RedisCommand::GetEx("k".to_string(), Some(GetExOption::Expire(2000)))
+        );
+
+        let value = RArray(vec![RString("GETEX"), RString("k"), RString("PERSIST")]);
+        let command: RedisCommand = value.try_into().unwrap();
+        assert_eq!(
+            command,
+            RedisCommand::GetEx("k".to_string(), Some(GetExOption::Persist))
+        );
+
+        let value = RArray(vec![
+            RString("GETEX"),
+            RString("k"),
+            RString("PX"),
+            RString("0"),
+        ]);
+        let error: Result<RedisCommand, CmdError> = value.try_into();
+        assert!(error.is_err());
+    }
+
+    #[test]
+    fn test_sort_ro_rejects_store() {
+        let value = RArray(vec![RString("SORT_RO"), RString("l"), RString("ALPHA")]);
+        let command: RedisCommand = value.try_into().unwrap();
+        assert_eq!(
+            command,
+            RedisCommand::Sort(
+                "l".to_string(),
+                SortOptions {
+                    alpha: true,
+                    ..Default::default()
+                }
+            )
+        );
+
+        let value = RArray(vec![
+            RString("SORT_RO"),
+            RString("l"),
+            RString("STORE"),
+            RString("dest"),
+        ]);
+        let error: Result<RedisCommand, CmdError> = value.try_into();
+        assert_eq!(
+            error.unwrap_err(),
+            CmdError::InvalidArgument("STORE".to_string())
+        );
+    }
+
     #[test]
     fn test_ping() {
         let value = RArray(vec![RString("PING")]);
         let command: RedisCommand = value.try_into().unwrap();
 
-        assert_eq!(command, RedisCommand::Ping);
+        assert_eq!(command, RedisCommand::Ping(None));
+
+        let value = RArray(vec![RString("PING"), RString("hi")]);
+        let command: RedisCommand = value.try_into().unwrap();
+        assert_eq!(command, RedisCommand::Ping(Some("hi".to_string())));
+
+        let value = RArray(vec![RString("PING"), RString("a"), RString("b")]);
+        let error: Result<RedisCommand, CmdError> = value.try_into();
+        assert_eq!(error.unwrap_err(), CmdError::InvalidArgumentNum);
     }
 
     #[test]
@@ -783,7 +3893,242 @@ mod tests {
         let value = RArray(vec![RString("ECHO"), RString("Hello")]);
         let command: RedisCommand = value.try_into().unwrap();
 
-        assert_eq!(command, RedisCommand::Echo("Hello".to_string()));
+        assert_eq!(command, RedisCommand::Echo(Bytes::from("Hello")));
+    }
+
+    #[test]
+    fn test_echo_binary() {
+        let message = Bytes::from_static(b"\xffbinary\x00\xfe");
+        let value = RArray(vec![
+            RString("ECHO"),
+            RedisValueRef::String(message.clone()),
+        ]);
+        let command: RedisCommand = value.try_into().unwrap();
+        assert_eq!(command, RedisCommand::Echo(message.clone()));
+
+        let RedisCommand::Echo(parsed) = command else {
+            unreachable!()
+        };
+        assert_eq!(crate::echo(parsed), RedisValueRef::String(message));
+    }
+
+    #[test]
+    fn test_set_get_option() {
+        let value = RArray(vec![
+            RString("SET"),
+            RString("key"),
+            RString("value"),
+            RString("px"),
+            RString("100"),
+            RString("GET"),
+        ]);
+        let command: RedisCommand = value.try_into().unwrap();
+
+        assert_eq!(
+            command,
+            RedisCommand::SetGet(
+                "key".to_string(),
+                "value".to_string(),
+                Some(SetExpiry::In(100))
+            )
+        );
+
+        let value = RArray(vec![
+            RString("SET"),
+            RString("key"),
+            RString("value"),
+            RString("PXAT"),
+            RString("1700000000000"),
+            RString("GET"),
+        ]);
+        let command: RedisCommand = value.try_into().unwrap();
+        let expiry = Some(SetExpiry::At(1_700_000_000_000));
+        assert_eq!(
+            command,
+            RedisCommand::SetGet("key".to_string(), "value".to_string(), expiry)
+        );
+    }
+
+    #[test]
+    fn test_non_positive_expire_time() {
+        let parse = |args: &[&str]| -> Result<RedisCommand, CmdError> {
+            RArray(args.iter().map(|a| RString(*a)).collect()).try_into()
+        };
+        let invalid = |command: &str| Err(CmdError::InvalidExpireTime(command.to_string()));
+        assert_eq!(parse(&["SETEX", "k", "0", "v"]), invalid("setex"));
+        assert_eq!(parse(&["PSETEX", "k", "-5", "v"]), invalid("psetex"));
+        assert_eq!(parse(&["SET", "k", "v", "EX", "-1"]), invalid("set"));
+        assert_eq!(parse(&["SET", "k", "v", "PX", "0"]), invalid("set"));
+        // Too big to count in milliseconds
+        let huge = i64::MAX.to_string();
+        assert_eq!(parse(&["SETEX", "k", &huge, "v"]), invalid("setex"));
+        assert_eq!(
+            parse(&["SETEX", "k", "10", "v"]),
+            Ok(RedisCommand::SetEx(
+                "k".to_string(),
+                "v".to_string(),
+                10_000
+            ))
+        );
+        assert_eq!(
+            CmdError::InvalidExpireTime("set".to_string()).to_string(),
+            "invalid expire time in 'set' command"
+        );
+
+        // EXPIRE takes any time, a past one deletes the key
+        assert_eq!(
+            parse(&["EXPIRE", "k", "-1"]),
+            Ok(RedisCommand::Expire("k".to_string(), -1))
+        );
+    }
+
+    #[test]
+    fn test_bitcount_args() {
+        let value = RArray(vec![
+            RString("BITCOUNT"),
+            RString("key"),
+            RString("1"),
+            RString("-1"),
+            RString("bit"),
+        ]);
+        let command: RedisCommand = value.try_into().unwrap();
+        assert_eq!(
+            command,
+            RedisCommand::BitCount("key".to_string(), Some((1, -1)), BitUnit::Bit)
+        );
+
+        let value = RArray(vec![RString("BITCOUNT"), RString("key"), RString("1")]);
+        let error: Result<RedisCommand, CmdError> = value.try_into();
+        assert_eq!(error.unwrap_err(), CmdError::InvalidArgumentNum);
+    }
+
+    #[test]
+    fn test_debug_sleep_out_of_range() {
+        for seconds in ["-1", "inf", "1e20"] {
+            let value = RArray(vec![RString("DEBUG"), RString("SLEEP"), RString(seconds)]);
+            let error: Result<RedisCommand, CmdError> = value.try_into();
+            assert_eq!(
+                error.unwrap_err(),
+                CmdError::InvalidArgument("seconds".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_lcs_negative_min_match_len() {
+        let value = RArray(vec![
+            RString("LCS"),
+            RString("a"),
+            RString("b"),
+            RString("IDX"),
+            RString("MINMATCHLEN"),
+            RString("-1"),
+        ]);
+        let command: RedisCommand = value.try_into().unwrap();
+        let opts = LcsOptions {
+            idx: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            command,
+            RedisCommand::Lcs("a".to_string(), "b".to_string(), opts)
+        );
+    }
+
+    #[test]
+    fn test_bitfield_offset_overflow() {
+        let value = RArray(vec![
+            RString("BITFIELD"),
+            RString("key"),
+            RString("GET"),
+            RString("u8"),
+            RString("18446744073709551615"),
+        ]);
+        let error: Result<RedisCommand, CmdError> = value.try_into();
+        assert_eq!(
+            error.unwrap_err(),
+            CmdError::InvalidArgument("offset".to_string())
+        );
+    }
+
+    #[test]
+    fn test_hsetex_numfields_overflow() {
+        let value = RArray(vec![
+            RString("HSETEX"),
+            RString("h"),
+            RString("FIELDS"),
+            RString("9223372036854775809"),
+            RString("f"),
+            RString("v"),
+        ]);
+        let error: Result<RedisCommand, CmdError> = value.try_into();
+        assert_eq!(
+            error.unwrap_err(),
+            CmdError::InvalidArgument("numfields".to_string())
+        );
+    }
+
+    #[test]
+    fn test_bitop_not_single_source() {
+        let value = RArray(vec![
+            RString("BITOP"),
+            RString("not"),
+            RString("dest"),
+            RString("a"),
+            RString("b"),
+        ]);
+        let error: Result<RedisCommand, CmdError> = value.try_into();
+        assert_eq!(error.unwrap_err(), CmdError::InvalidArgumentNum);
+    }
+
+    #[test]
+    fn test_debug_packed_threshold_size() {
+        let parse = |threshold: &str| -> Result<RedisCommand, CmdError> {
+            RArray(vec![
+                RString("DEBUG"),
+                RString("QUICKLIST-PACKED-THRESHOLD"),
+                RString(threshold),
+            ])
+            .try_into()
+        };
+        assert_eq!(
+            parse("1K").unwrap(),
+            RedisCommand::DebugQuicklistPackedThreshold(1000)
+        );
+        assert_eq!(
+            parse("2kb").unwrap(),
+            RedisCommand::DebugQuicklistPackedThreshold(2048)
+        );
+        assert!(parse("1xb").is_err());
+    }
+
+    #[test]
+    fn test_hello_auth_setname() {
+        let value = RArray(vec![
+            RString("HELLO"),
+            RString("3"),
+            RString("AUTH"),
+            RString("default"),
+            RString("pw"),
+            RString("SETNAME"),
+            RString("app"),
+        ]);
+        let command: RedisCommand = value.try_into().unwrap();
+        assert_eq!(
+            command,
+            RedisCommand::Hello(
+                Some(3),
+                Some(("default".to_string(), "pw".to_string())),
+                Some("app".to_string())
+            )
+        );
+
+        let value = RArray(vec![RString("HELLO"), RString("3"), RString("AUTH")]);
+        let error: Result<RedisCommand, CmdError> = value.try_into();
+        assert_eq!(
+            error.unwrap_err(),
+            CmdError::InvalidArgument("AUTH".to_string())
+        );
     }
 
     #[test]
@@ -808,7 +4153,8 @@ mod tests {
                 vec![
                     ("field1".to_string(), "value1".to_string()),
                     ("field2".to_string(), "value2".to_string())
-                ]
+                ],
+                false
             )
         );
     }
@@ -826,6 +4172,75 @@ mod tests {
         assert_eq!(error.unwrap_err(), CmdError::InvalidArgumentNum);
     }
 
+    #[test]
+    fn test_for_replication_absolute_expiry() {
+        let ok = RString("OK");
+        let before = now_ms();
+        let absolute = |command: RedisCommand| match command.for_replication(&ok) {
+            RedisCommand::SetPxAt(_, _, at) | RedisCommand::HPExpireAt(_, at, _) => at,
+            RedisCommand::PExpireAt(_, at) => at as u64,
+            RedisCommand::GetEx(_, Some(GetExOption::ExpireAt(at))) => at,
+            RedisCommand::HSetEx(_, opts, _) => match opts.expiry {
+                Some(GetExOption::ExpireAt(at)) => at,
+                other => panic!("expected an absolute field expiry, got {:?}", other),
+            },
+            RedisCommand::HGetEx(_, Some(GetExOption::ExpireAt(at)), _) => at,
+            other => panic!("expected an absolute expiry, got {:?}", other),
+        };
+        let k = || "k".to_string();
+        let commands = [
+            RedisCommand::SetEx(k(), k(), 10_000),
+            RedisCommand::SetGet(k(), k(), Some(SetExpiry::In(10_000))),
+            RedisCommand::Expire(k(), 10),
+            RedisCommand::GetEx(k(), Some(GetExOption::Expire(10_000))),
+            RedisCommand::HExpire(k(), 10, vec![k()]),
+            RedisCommand::HGetEx(k(), Some(GetExOption::Expire(10_000)), vec![k()]),
+            RedisCommand::HSetEx(
+                k(),
+                HSetExOptions {
+                    expiry: Some(GetExOption::Expire(10_000)),
+                    ..Default::default()
+                },
+                vec![(k(), k())],
+            ),
+        ];
+        for command in commands {
+            let at = absolute(command);
+            assert!(at >= before + 10_000 && at <= now_ms() + 10_000);
+        }
+    }
+
+    #[test]
+    fn test_for_replication_blpop() {
+        // The pop a served BLPOP did is passed on by itself, and a timed out
+        // one changed nothing
+        let served = RArray(vec![RString("l"), RString("x")]);
+        let blpop = RedisCommand::BLPop("l".to_string(), Some(1.0));
+        assert!(!blpop.clone().for_replication(&served).can_replicate());
+        assert!(
+            !blpop
+                .for_replication(&RedisValueRef::NullArray)
+                .can_replicate()
+        );
+        assert!(RedisCommand::Publish("ch".to_string(), "m".to_string()).can_replicate());
+    }
+
+    #[test]
+    fn test_zadd_nan_score() {
+        let value = RArray(vec![
+            RString("ZADD"),
+            RString("key"),
+            RString("nan"),
+            RString("m"),
+        ]);
+        let error: Result<RedisCommand, CmdError> = value.try_into();
+        assert_eq!(error.unwrap_err(), CmdError::InvalidFloat);
+        assert_eq!(
+            CmdError::InvalidFloat.to_string(),
+            "value is not a valid float"
+        );
+    }
+
     #[test]
     fn test_xadd_bad_id() {
         let value = RArray(vec![
@@ -855,7 +4270,27 @@ mod tests {
 
         assert_eq!(
             command,
-            RedisCommand::XRange("key".to_string(), (Some(1), Some(0)), (Some(2), Some(0)),)
+            RedisCommand::XRange(
+                "key".to_string(),
+                ((Some(1), Some(0)), false),
+                ((Some(2), Some(0)), false)
+            )
+        );
+
+        let value = RArray(vec![
+            RString("XRANGE"),
+            RString("key"),
+            RString("(1-0"),
+            RString("+"),
+        ]);
+        let command: RedisCommand = value.try_into().unwrap();
+        assert_eq!(
+            command,
+            RedisCommand::XRange(
+                "key".to_string(),
+                ((Some(1), Some(0)), true),
+                ((None, None), false)
+            )
         );
     }
 
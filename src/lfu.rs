@@ -0,0 +1,166 @@
+/// Access frequency tracking for the LFU maxmemory policies. Like Redis, each
+/// key keeps an 8 bit logarithmic counter: every access bumps it with a
+/// probability that shrinks as it grows, and it loses one point for every
+/// minute the key goes unused.
+use crate::{
+    Db, now_ms,
+    parser::{RError, RInt, RNull, RedisValueRef},
+    type_of,
+};
+
+/// Counter a key starts with, so new keys aren't the first to go
+pub const LFU_INIT_VAL: u8 = 5;
+/// How much harder each increment gets (lfu-log-factor)
+const LFU_LOG_FACTOR: f64 = 10.0;
+/// Minutes of disuse it takes to lose one point (lfu-decay-time)
+const LFU_DECAY_TIME: u64 = 1;
+
+/// Every value maxmemory-policy accepts
+pub const MAXMEMORY_POLICIES: [&str; 8] = [
+    "noeviction",
+    "allkeys-lru",
+    "allkeys-lfu",
+    "allkeys-random",
+    "volatile-lru",
+    "volatile-lfu",
+    "volatile-random",
+    "volatile-ttl",
+];
+
+pub fn is_lfu_policy(policy: &str) -> bool {
+    policy.ends_with("-lfu")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frequency {
+    counter: u8,
+    /// Minutes since the epoch when the counter was last decayed
+    decayed_at: u64,
+}
+
+impl Frequency {
+    pub fn new(now_min: u64) -> Self {
+        Self {
+            counter: LFU_INIT_VAL,
+            decayed_at: now_min,
+        }
+    }
+
+    /// The counter once the minutes since it was last decayed are taken off
+    pub fn counter(&self, now_min: u64) -> u8 {
+        let periods = now_min.saturating_sub(self.decayed_at) / LFU_DECAY_TIME;
+        self.counter
+            .saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+
+    /// Count an access. `roll` is a random number in [0, 1) deciding whether
+    /// the counter goes up.
+    pub fn access(&mut self, now_min: u64, roll: f64) {
+        self.counter = self.counter(now_min);
+        self.decayed_at = now_min;
+        let base = self.counter.saturating_sub(LFU_INIT_VAL) as f64;
+        if self.counter < u8::MAX && roll < 1.0 / (base * LFU_LOG_FACTOR + 1.0) {
+            self.counter += 1;
+        }
+    }
+}
+
+fn now_minutes() -> u64 {
+    now_ms() / 60_000
+}
+
+/// A random number in [0, 1), from the leading 48 bits of a v4 UUID. The
+/// version and variant bits come after those, so all of these are random.
+pub(crate) fn random_roll() -> f64 {
+    (uuid::Uuid::new_v4().as_u128() >> 80) as f64 / (1u64 << 48) as f64
+}
+
+/// Count an access to `key`, starting its counter if it has none yet
+pub fn record(db: &Db, key: &str) {
+    let now = now_minutes();
+    match db.lfu.get_mut(key) {
+        Some(mut frequency) => frequency.access(now, random_roll()),
+        None => {
+            db.lfu.insert(key.to_string(), Frequency::new(now));
+        }
+    }
+}
+
+/// The key's counter as of now. A key with none yet counts as new.
+pub fn frequency(db: &Db, key: &str) -> u8 {
+    db.lfu
+        .get(key)
+        .map_or(LFU_INIT_VAL, |frequency| frequency.counter(now_minutes()))
+}
+
+/// OBJECT FREQ: the key's access frequency counter. Only tracked while an
+/// LFU policy is selected.
+pub fn object_freq(db: &Db, key: String) -> RedisValueRef {
+    if !is_lfu_policy(&db.maxmemory_policy) {
+        return RError(
+            "ERR An LFU maxmemory policy is not selected, access frequency not tracked. \
+             Please note that when switching between policies at runtime LRU and LFU data \
+             will take some time to adjust.",
+        );
+    }
+    if type_of(db, &key) == "none" {
+        return RNull();
+    }
+    RInt(frequency(db, &key) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{RedisDb, handle_command, interpreter::RedisCommand};
+
+    fn setup() -> Arc<RedisDb> {
+        let mut db = RedisDb::new(None, "/tmp/redis-files", "dump.rdb");
+        db.maxmemory_policy = "allkeys-lfu".to_string();
+        Arc::new(db)
+    }
+
+    #[test]
+    fn test_frequency_counter() {
+        let mut frequency = Frequency::new(0);
+        // At the initial value every access counts
+        frequency.access(0, 0.99);
+        assert_eq!(frequency.counter(0), LFU_INIT_VAL + 1);
+        // After that a high roll isn't enough
+        frequency.access(0, 0.99);
+        assert_eq!(frequency.counter(0), LFU_INIT_VAL + 1);
+        frequency.access(0, 0.0);
+        assert_eq!(frequency.counter(0), LFU_INIT_VAL + 2);
+        // One point is lost per idle minute
+        assert_eq!(frequency.counter(3), LFU_INIT_VAL - 1);
+        assert_eq!(frequency.counter(1000), 0);
+    }
+
+    #[tokio::test]
+    async fn test_object_freq() {
+        let db = setup();
+        for key in ["hot", "cold"] {
+            let set = RedisCommand::Set(key.to_string(), "v".to_string());
+            handle_command(&db, set).await;
+        }
+        for _ in 0..1000 {
+            handle_command(&db, RedisCommand::Get("hot".to_string())).await;
+        }
+        handle_command(&db, RedisCommand::Get("cold".to_string())).await;
+
+        let freq = |key: &str| match object_freq(&db, key.to_string()) {
+            RedisValueRef::Int(freq) => freq,
+            other => panic!("OBJECT FREQ should return an integer, got {:?}", other),
+        };
+        assert!(freq("hot") > freq("cold"));
+        assert_eq!(object_freq(&db, "missing".to_string()), RNull());
+
+        let lru = Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"));
+        assert!(matches!(
+            object_freq(&lru, "hot".to_string()),
+            RedisValueRef::Error(_)
+        ));
+    }
+}
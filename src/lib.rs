@@ -1,38 +1,63 @@
-use std::collections::{HashMap, VecDeque};
+use std::cell::RefCell;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
 use std::error::Error;
 use std::fs::{File, create_dir_all};
+use std::hash::{DefaultHasher, Hash as _, Hasher};
 use std::io::Read;
-use std::path::Path;
-use std::sync::atomic::AtomicI64;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::auth::User;
+use crate::hashes::Hash;
 use crate::interpreter::RedisCommand;
-use crate::parser::{RArray, RError, RInt, RNull, RSimpleString, RString, RedisValueRef};
-use crate::rdb::parse_rdb;
+use crate::lists::List;
+use crate::parser::{RArray, RError, RInt, RMap, RNull, RSimpleString, RString, RedisValueRef};
+use crate::rdb::{RdbValue, SnapshotEntry, parse_rdb, write_rdb};
+use crate::sets::Set;
 use crate::streams::StreamCollection;
 use crate::zset::ZSet;
 use bytes::Bytes;
 use dashmap::DashMap;
 
+pub mod aof;
 pub mod auth;
+pub mod bits;
+pub mod cluster;
+pub mod commands;
+pub mod debug;
+pub mod evict;
+pub mod functions;
 pub mod geo;
+pub mod glob;
+pub mod hashes;
+pub mod hll;
 pub mod interpreter;
+pub mod lfu;
 pub mod lists;
+pub mod lzf;
+pub mod memory;
 pub mod parser;
 pub mod pubsub;
 pub mod rdb;
 pub mod replication;
+pub mod sets;
+pub mod slowlog;
+pub mod sort;
+pub mod stats;
 pub mod streams;
+pub mod tracking;
 pub mod zset;
 
 // Storage Type
 #[derive(Debug, Clone, PartialEq)]
 pub enum RedisValue {
     String(Bytes),
-    List(VecDeque<Bytes>),
+    List(List),
     Stream(StreamCollection),
+    Hash(Hash),
+    Set(Set),
 }
 
 /// Convert from storage format to wire protocol format
@@ -53,17 +78,73 @@ impl From<&RedisValue> for RedisValueRef {
                     .map(|e| e.into())
                     .collect(),
             ),
+            RedisValue::Hash(hash) => RArray(
+                hash.iter()
+                    .flat_map(|(field, value)| {
+                        [
+                            RedisValueRef::String(field.clone()),
+                            RedisValueRef::String(value.clone()),
+                        ]
+                    })
+                    .collect(),
+            ),
+            RedisValue::Set(set) => {
+                RArray(set.iter().cloned().map(RedisValueRef::String).collect())
+            }
         }
     }
 }
 
+impl RedisValue {
+    /// The value as it is written to a dump. Streams aren't persisted.
+    pub fn to_rdb(&self) -> Option<RdbValue> {
+        let value = match self {
+            RedisValue::String(value) => RdbValue::String(value.to_vec()),
+            RedisValue::List(list) => {
+                RdbValue::List(list.iter().map(|item| item.to_vec()).collect())
+            }
+            RedisValue::Set(set) => {
+                RdbValue::Set(set.iter().map(|member| member.to_vec()).collect())
+            }
+            RedisValue::Hash(hash) if hash.has_field_expiries() => RdbValue::HashMetadata(
+                hash.iter_with_expiry()
+                    .map(|(field, value, expire)| (field.to_vec(), value.to_vec(), expire))
+                    .collect(),
+            ),
+            RedisValue::Hash(hash) => RdbValue::Hash(
+                hash.iter()
+                    .map(|(field, value)| (field.to_vec(), value.to_vec()))
+                    .collect(),
+            ),
+            RedisValue::Stream(_) => return None,
+        };
+        Some(value)
+    }
+}
+
 pub fn ref_error(msg: &str) -> RedisValueRef {
     RError(msg)
 }
 
+/// A random 40 character hex ID, the format Redis uses for run and
+/// replication IDs
+pub fn random_hex_id() -> String {
+    let mut id = format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    id.truncate(40);
+    id
+}
+
 #[derive(Debug)]
 pub struct Replica {
     pub id: String,
+    /// Where the replica can be reached: the address it connected from and the
+    /// port it said it listens on
+    pub ip: String,
+    pub port: u16,
     pub offset: i64,
     pub tx: tokio::sync::mpsc::Sender<RedisCommand>,
 }
@@ -72,18 +153,94 @@ pub struct Replica {
 pub struct RedisDb {
     pub dict: DashMap<String, RedisValue>,
     pub ttl: DashMap<String, u64>,
+    /// Unix ms each key was last accessed, for OBJECT IDLETIME
+    pub last_access: DashMap<String, u64>,
+    /// Access frequency of each key, kept only under an LFU maxmemory policy
+    pub lfu: DashMap<String, lfu::Frequency>,
     pub waiters: Arc<Mutex<HashMap<String, VecDeque<tokio::sync::oneshot::Sender<Bytes>>>>>,
+    /// How many senders are queued in `waiters`, so pushes can skip the lock
+    /// when nobody is blocked
+    pub queued_waiters: Arc<AtomicUsize>,
     pub stream_waiters:
         Arc<Mutex<HashMap<String, VecDeque<tokio::sync::oneshot::Sender<RedisValueRef>>>>>,
     pub replica_of: Option<(String, u16)>,
     pub replicating_to: Arc<Mutex<Vec<Replica>>>,
-    pub replication_id: String,
+    /// Changed by DEBUG CHANGE-REPL-ID, so shared behind a lock
+    pub replication_id: Arc<Mutex<String>>,
+    /// Identifies this server process; generated at startup and never changed
+    pub run_id: String,
+    /// On a replica, whether the connection to the master is synced and live
+    pub master_link_up: Arc<AtomicBool>,
+    /// On a replica, unix ms when data last arrived from the master (0 if never)
+    pub master_last_io: Arc<AtomicU64>,
     pub replication_offset: Arc<AtomicI64>,
     pub cfg_dir: String,
     pub db_file: String,
     pub pubsub: Arc<Mutex<HashMap<String, tokio::sync::broadcast::Sender<RedisValueRef>>>>,
     pub zsets: Arc<Mutex<HashMap<String, ZSet>>>,
     pub users: Arc<Mutex<HashMap<String, User>>>,
+    pub proto_max_bulk_len: usize,
+    pub active_expire: Arc<AtomicBool>,
+    pub list_max_listpack_entries: Arc<AtomicUsize>,
+    pub list_max_listpack_value: Arc<AtomicUsize>,
+    /// Elements bigger than this are stored as their own quicklist node
+    pub quicklist_packed_threshold: Arc<AtomicUsize>,
+    pub tracking: Arc<Mutex<HashMap<String, Vec<tokio::sync::mpsc::UnboundedSender<String>>>>>,
+    pub aof_file: String,
+    /// The open AOF while appendonly is on
+    pub aof: Arc<Mutex<Option<File>>>,
+    pub rdb_bgsave_in_progress: Arc<AtomicBool>,
+    /// Unix seconds of the last successful save, starting from server start
+    pub rdb_last_save_time: Arc<AtomicU64>,
+    /// Count of changes made to the dataset since startup
+    pub dirty: Arc<AtomicU64>,
+    /// What `dirty` was when the last successful save took its snapshot
+    pub dirty_at_last_save: Arc<AtomicU64>,
+    pub command_stats: Arc<Mutex<HashMap<&'static str, stats::CmdStat>>>,
+    pub slowlog: Arc<Mutex<slowlog::Slowlog>>,
+    /// Microseconds; negative turns the slowlog off
+    pub slowlog_log_slower_than: Arc<AtomicI64>,
+    pub slowlog_max_len: usize,
+    /// Connections beyond this many are turned away
+    pub maxclients: usize,
+    /// Memory limit in bytes, 0 for none. Keys are evicted to stay under it.
+    pub maxmemory: u64,
+    /// How keys are chosen for eviction. LFU policies turn on access
+    /// frequency tracking for OBJECT FREQ.
+    pub maxmemory_policy: String,
+    pub connected_clients: Arc<AtomicUsize>,
+    /// Signalled once SHUTDOWN has done its saving, to stop accepting connections
+    pub shutdown: Arc<tokio::sync::Notify>,
+}
+
+/// Default limit on simultaneous client connections
+pub const DEFAULT_MAX_CLIENTS: usize = 10000;
+
+/// A connection's place in the client count, given back when dropped
+pub struct ClientSlot(Db);
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.connected_clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Per-connection switches set with CLIENT NO-EVICT and CLIENT NO-TOUCH
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClientFlags {
+    /// Exempt from client eviction. Clients are never evicted here, so this
+    /// is only recorded.
+    pub no_evict: bool,
+    /// Commands leave the access time of their keys alone. TOUCH still
+    /// counts as an access.
+    pub no_touch: bool,
+}
+
+/// Count a new connection, or None if maxclients connections are already open
+pub fn register_client(db: &Db) -> Option<ClientSlot> {
+    let count = db.connected_clients.fetch_add(1, Ordering::Relaxed);
+    let slot = ClientSlot(db.clone());
+    (count < db.maxclients).then_some(slot)
 }
 
 impl RedisDb {
@@ -91,20 +248,93 @@ impl RedisDb {
         RedisDb {
             dict: DashMap::new(),
             ttl: DashMap::new(),
+            last_access: DashMap::new(),
+            lfu: DashMap::new(),
             waiters: Arc::new(Mutex::new(HashMap::new())),
+            queued_waiters: Arc::new(AtomicUsize::new(0)),
             stream_waiters: Arc::new(Mutex::new(HashMap::new())),
             replica_of,
             replicating_to: Arc::new(Mutex::new(Vec::new())),
-            replication_id: "8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb".to_string(),
+            replication_id: Arc::new(Mutex::new(random_hex_id())),
+            run_id: random_hex_id(),
+            master_link_up: Arc::new(AtomicBool::new(false)),
+            master_last_io: Arc::new(AtomicU64::new(0)),
             replication_offset: Arc::new(AtomicI64::new(0)),
             cfg_dir: cfg_dir.to_string(),
             db_file: db_file.to_string(),
             pubsub: Arc::new(Mutex::new(HashMap::new())),
             zsets: Arc::new(Mutex::new(HashMap::new())),
             users: Arc::new(Mutex::new(HashMap::new())),
+            proto_max_bulk_len: parser::DEFAULT_MAX_BULK_LEN,
+            active_expire: Arc::new(AtomicBool::new(true)),
+            list_max_listpack_entries: Arc::new(AtomicUsize::new(lists::LIST_MAX_LISTPACK_ENTRIES)),
+            list_max_listpack_value: Arc::new(AtomicUsize::new(lists::LIST_MAX_LISTPACK_VALUE)),
+            quicklist_packed_threshold: Arc::new(AtomicUsize::new(
+                lists::QUICKLIST_PACKED_THRESHOLD,
+            )),
+            tracking: Arc::new(Mutex::new(HashMap::new())),
+            aof_file: aof::DEFAULT_AOF_FILE.to_string(),
+            aof: Arc::new(Mutex::new(None)),
+            rdb_bgsave_in_progress: Arc::new(AtomicBool::new(false)),
+            rdb_last_save_time: Arc::new(AtomicU64::new(now_ms() / 1000)),
+            dirty: Arc::new(AtomicU64::new(0)),
+            dirty_at_last_save: Arc::new(AtomicU64::new(0)),
+            command_stats: Arc::new(Mutex::new(HashMap::new())),
+            slowlog: Arc::new(Mutex::new(slowlog::Slowlog::default())),
+            slowlog_log_slower_than: Arc::new(AtomicI64::new(slowlog::DEFAULT_LOG_SLOWER_THAN)),
+            slowlog_max_len: slowlog::DEFAULT_MAX_LEN,
+            maxclients: DEFAULT_MAX_CLIENTS,
+            maxmemory: 0,
+            maxmemory_policy: "noeviction".to_string(),
+            connected_clients: Arc::new(AtomicUsize::new(0)),
+            shutdown: Arc::new(tokio::sync::Notify::new()),
         }
     }
 
+    /// Record changes to the dataset. Call this only for writes that actually
+    /// modified something.
+    pub fn add_dirty(&self, changes: u64) {
+        self.dirty.fetch_add(changes, Ordering::Relaxed);
+    }
+
+    pub fn changes_since_last_save(&self) -> u64 {
+        self.dirty.load(Ordering::Relaxed) - self.dirty_at_last_save.load(Ordering::Relaxed)
+    }
+
+    pub fn rdb_path(&self) -> PathBuf {
+        Path::new(&self.cfg_dir).join(&self.db_file)
+    }
+
+    /// Copy out everything a dump needs. Streams can't be written to the RDB
+    /// yet so they are left out, and hash fields are written without TTLs.
+    pub fn snapshot(&self) -> Vec<SnapshotEntry> {
+        let expire = |key: &str| self.ttl.get(key).map(|ttl| *ttl);
+        let mut entries: Vec<SnapshotEntry> = self
+            .dict
+            .iter()
+            .filter(|entry| !self.is_expired(entry.key()))
+            .filter_map(|entry| {
+                Some(SnapshotEntry {
+                    key: entry.key().clone(),
+                    value: entry.value().to_rdb()?,
+                    expire: expire(entry.key()),
+                })
+            })
+            .collect();
+        let zsets = self.zsets.lock().unwrap();
+        entries.extend(
+            zsets
+                .iter()
+                .filter(|(key, _)| !self.is_expired(key))
+                .map(|(key, zset)| SnapshotEntry {
+                    key: key.clone(),
+                    value: zset.to_rdb(),
+                    expire: expire(key),
+                }),
+        );
+        entries
+    }
+
     pub fn try_load_rdb(&self) -> Result<usize, Box<dyn Error>> {
         let path = Path::new(&self.cfg_dir);
         if !path.exists() {
@@ -116,26 +346,66 @@ impl RedisDb {
             let mut file = File::open(rdb_file)?;
             let mut buffer: Vec<u8> = Vec::new();
             file.read_to_end(&mut buffer)?;
-            let parsed = match parse_rdb(&buffer) {
-                Ok((_, rdb)) => rdb,
-                Err(e) => return Err(format!("Failed to parse RDB: {:?}", e).into()),
-            };
-            let cnt = parsed.entries.len();
-            for entry in &parsed.entries {
-                self.dict.insert(
-                    entry.kv.key.clone(),
-                    RedisValue::String(Bytes::from(entry.kv.value.clone())),
-                );
-                if let Some(ttl) = entry.expire {
-                    self.ttl.insert(entry.kv.key.clone(), ttl);
-                }
-            }
-            return Ok(cnt);
+            return self.load_rdb(&buffer);
         }
 
         Ok(0)
     }
 
+    /// Load the keys from an RDB dump, returning how many there were
+    pub fn load_rdb(&self, buffer: &[u8]) -> Result<usize, Box<dyn Error>> {
+        let parsed = match parse_rdb(buffer) {
+            Ok((_, rdb)) => rdb,
+            Err(e) => return Err(format!("Failed to parse RDB: {:?}", e).into()),
+        };
+        let cnt = parsed.entries.len();
+        for entry in parsed.entries {
+            let key = entry.key;
+            if let Some(ttl) = entry.expire {
+                self.ttl.insert(key.clone(), ttl);
+            }
+            let value = match entry.value {
+                RdbValue::String(value) => RedisValue::String(Bytes::from(value)),
+                RdbValue::List(items) => {
+                    let mut list: List = items
+                        .into_iter()
+                        .map(Bytes::from)
+                        .collect::<Vec<_>>()
+                        .into();
+                    self.update_list_encoding(&mut list);
+                    RedisValue::List(list)
+                }
+                RdbValue::Set(members) => {
+                    RedisValue::Set(members.into_iter().map(Bytes::from).collect())
+                }
+                RdbValue::Hash(fields) => RedisValue::Hash(
+                    fields
+                        .into_iter()
+                        .map(|(field, value)| (Bytes::from(field), Bytes::from(value), None))
+                        .collect(),
+                ),
+                RdbValue::HashMetadata(fields) => RedisValue::Hash(
+                    fields
+                        .into_iter()
+                        .map(|(field, value, expire)| {
+                            (Bytes::from(field), Bytes::from(value), expire)
+                        })
+                        .collect(),
+                ),
+                RdbValue::ZSet(members) => {
+                    let zset = members
+                        .into_iter()
+                        .map(|(member, score)| (Bytes::from(member), score))
+                        .collect();
+                    self.zsets.lock().unwrap().insert(key, zset);
+                    continue;
+                }
+            };
+            self.dict.insert(key, value);
+        }
+        Ok(cnt)
+    }
+
     fn is_expired(&self, key: &str) -> bool {
         if let Some(expiry) = self.ttl.get(key) {
             let now = SystemTime::now()
@@ -151,13 +421,22 @@ impl RedisDb {
     fn remove_if_expired(&self, key: &str) -> bool {
         if self.is_expired(key) {
             self.dict.remove(key);
+            self.zsets.lock().unwrap().remove(key);
             self.ttl.remove(key);
+            self.forget_access(key);
+            self.add_dirty(1);
             true
         } else {
             false
         }
     }
 
+    /// Drop the access time and LFU counter of a key that's gone
+    fn forget_access(&self, key: &str) {
+        self.last_access.remove(key);
+        self.lfu.remove(key);
+    }
+
     pub fn get_if_valid(
         &self,
         key: &str,
@@ -180,6 +459,32 @@ impl RedisDb {
         }
     }
 
+    /// Remove every key whose TTL has passed, returning how many were evicted.
+    /// Does nothing while active expiry is switched off with DEBUG SET-ACTIVE-EXPIRE,
+    /// leaving expired keys to be cleaned up lazily on access.
+    pub fn active_expire_cycle(&self) -> usize {
+        if !self.active_expire.load(Ordering::Relaxed) {
+            return 0;
+        }
+        let keys: Vec<String> = self.ttl.iter().map(|entry| entry.key().clone()).collect();
+        keys.iter()
+            .filter(|key| self.remove_if_expired(key))
+            .count()
+    }
+
+    /// Check a list that just grew against the configured listpack limits
+    pub fn update_list_encoding(&self, list: &mut List) {
+        // An element that needs a plain node can never live in a listpack
+        let max_value = self
+            .list_max_listpack_value
+            .load(Ordering::Relaxed)
+            .min(self.quicklist_packed_threshold.load(Ordering::Relaxed));
+        list.update_encoding(
+            self.list_max_listpack_entries.load(Ordering::Relaxed),
+            max_value,
+        );
+    }
+
     pub fn connected_replicas(&self) -> usize {
         self.replicating_to
             .lock()
@@ -192,47 +497,211 @@ impl RedisDb {
 
 pub type Db = Arc<RedisDb>;
 
+/// Background task running the active expiry cycle ten times a second
+pub fn run_active_expire_loop(db: &Db) {
+    let db = db.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_millis(100));
+        loop {
+            interval.tick().await;
+            db.active_expire_cycle();
+        }
+    });
+}
+
 pub async fn handle_command(db: &Db, command: RedisCommand) -> RedisValueRef {
+    handle_client_command(db, &command, ClientFlags::default()).await
+}
+
+/// Run a command on behalf of a client connection, honouring its flags
+pub async fn handle_client_command(
+    db: &Db,
+    command: &RedisCommand,
+    flags: ClientFlags,
+) -> RedisValueRef {
+    let name = command.name();
+    let accessed: Vec<String> = match command.touches_keys() {
+        true => command.keys().into_iter().cloned().collect(),
+        false => vec![],
+    };
+    let start = std::time::Instant::now();
+    let result = execute(db, command.clone()).await;
+    let elapsed = start.elapsed();
+    record_access(db, &accessed, !flags.no_touch);
+    stats::record_call(db, name, elapsed);
+    slowlog::record(db, command, elapsed);
+    result
+}
+
+/// Run a client's command and pass its effects on: update client tracking,
+/// then log the write to the AOF and send it to replicas. `tracker` is the
+/// connection's invalidation channel while it has tracking on. A command
+/// that failed changed nothing, so it isn't passed on. Keys are evicted
+/// first if used memory is over maxmemory, and passed on as a DEL.
+pub async fn run_client_command(
+    db: &Db,
+    command: RedisCommand,
+    flags: ClientFlags,
+    tracker: Option<&tracking::Invalidations>,
+) -> RedisValueRef {
+    let evictions = evict::perform_evictions(db);
+    if !evictions.keys.is_empty() {
+        let del = RedisCommand::Del(evictions.keys);
+        tracking::record(db, &del, None);
+        propagate(db, del).await;
+    }
+    if evictions.out_of_memory && commands::is_deny_oom(command.name()) {
+        return RError("OOM command not allowed when used memory > 'maxmemory'.");
+    }
+    let (result, also) = ALSO_PROPAGATE
+        .scope(RefCell::default(), async {
+            let result = handle_client_command(db, &command, flags).await;
+            (result, ALSO_PROPAGATE.with(RefCell::take))
+        })
+        .await;
+    let command = command.for_replication(&result);
+    tracking::record(db, &command, tracker);
+    if matches!(result, RedisValueRef::Error(_)) {
+        return result;
+    }
+    propagate(db, command).await;
+    for command in also {
+        propagate(db, command).await;
+    }
+    result
+}
+
+tokio::task_local! {
+    /// Writes a client command made on behalf of others, like the pops of the
+    /// BLPOP clients a push served. They're passed on right after it.
+    static ALSO_PROPAGATE: RefCell<Vec<RedisCommand>>;
+}
+
+/// Pass `command` on after the client command that's running. Outside of one,
+/// while loading the AOF or applying the master's stream, nothing is passed
+/// on anyway.
+pub(crate) fn also_propagate(command: RedisCommand) {
+    let _ = ALSO_PROPAGATE.try_with(|also| also.borrow_mut().push(command));
+}
+
+/// Log a write to the AOF and send it to replicas
+async fn propagate(db: &Db, command: RedisCommand) {
+    aof::append(db, &command);
+    if command.can_replicate() {
+        let command_bytes = replication::command_bytes(command.clone()) as i64;
+        db.replication_offset
+            .fetch_add(command_bytes, Ordering::Relaxed);
+    }
+    replication::broadcast_to_replicas(db, command).await;
+}
+
+async fn execute(db: &Db, command: RedisCommand) -> RedisValueRef {
     match command {
-        RedisCommand::Ping => ping(),
+        RedisCommand::Ping(message) => ping(message),
         RedisCommand::Echo(arg) => echo(arg),
+        RedisCommand::Lolwut(version) => lolwut(version),
         RedisCommand::Set(key, value) => set(db, key, value).await,
         RedisCommand::SetEx(key, value, ttl) => set_ex(db, key, value, ttl).await,
+        RedisCommand::SetPxAt(key, value, at) => set_at(db, key, value, at),
+        RedisCommand::SetGet(key, value, ttl) => set_get(db, key, value, ttl).await,
         RedisCommand::Get(key) => get(db, key).await,
         RedisCommand::Rpush(key, value) => lists::rpush(db, key, value).await,
         RedisCommand::Lpush(key, value) => lists::lpush(db, key, value).await,
+        RedisCommand::Rpushx(key, value) => lists::rpushx(db, key, value).await,
+        RedisCommand::Lpushx(key, value) => lists::lpushx(db, key, value).await,
         RedisCommand::Lrange(key, start, stop) => lists::lrange(db, key, start, stop).await,
         RedisCommand::LLen(key) => lists::llen(db, key).await,
         RedisCommand::LPop(key, num_elements) => lists::lpop(db, key, num_elements).await,
         RedisCommand::BLPop(key, timeout) => lists::blpop(db, key, timeout).await,
         RedisCommand::Type(key) => _type(db, key).await,
-        RedisCommand::XAdd(key, id_tuple, fields) => streams::xadd(db, key, id_tuple, fields).await,
+        RedisCommand::XAdd(key, id_tuple, fields, nomkstream) => {
+            streams::xadd(db, key, id_tuple, fields, nomkstream).await
+        }
         RedisCommand::XRange(key, start, stop) => streams::xrange(db, key, start, stop).await,
+        RedisCommand::XSetId(key, ms, seq) => streams::xsetid(db, key, ms, seq),
+        RedisCommand::XInfoStream(key, full) => streams::xinfo_stream(db, key, full),
+        RedisCommand::HSet(key, pairs) => hashes::hset(db, key, pairs),
+        RedisCommand::HGet(key, field) => hashes::hget(db, key, field),
+        RedisCommand::HGetAll(key) => hashes::hgetall(db, key),
+        RedisCommand::HLen(key) => hashes::hlen(db, key),
+        RedisCommand::HDel(key, fields) => hashes::hdel(db, key, fields),
+        RedisCommand::HExpire(key, seconds, fields) => hashes::hexpire(db, key, seconds, fields),
+        RedisCommand::HPExpireAt(key, at, fields) => hashes::hpexpireat(db, key, at, fields),
+        RedisCommand::HTtl(key, fields) => hashes::httl(db, key, fields),
+        RedisCommand::HPersist(key, fields) => hashes::hpersist(db, key, fields),
+        RedisCommand::HGetDel(key, fields) => hashes::hgetdel(db, key, fields),
+        RedisCommand::HGetEx(key, option, fields) => hashes::hgetex(db, key, option, fields),
+        RedisCommand::HSetEx(key, opts, pairs) => hashes::hsetex(db, key, opts, pairs),
+        RedisCommand::SAdd(key, members) => sets::sadd(db, key, members),
+        RedisCommand::SRem(key, members) => sets::srem(db, key, members),
+        RedisCommand::SMembers(key) => sets::smembers(db, key),
+        RedisCommand::SetOp(op, keys) => sets::set_op(db, op, keys),
+        RedisCommand::SetOpStore(op, dest, keys) => sets::set_op_store(db, op, dest, keys),
+        RedisCommand::SIsMember(key, member) => sets::sismember(db, key, member),
+        RedisCommand::SCard(key) => sets::scard(db, key),
+        RedisCommand::PfAdd(key, elements) => hll::pfadd(db, key, elements),
+        RedisCommand::PfCount(keys) => hll::pfcount(db, keys),
+        RedisCommand::PfMerge(dest, sources) => hll::pfmerge(db, dest, sources),
+        RedisCommand::MemoryUsage(key, samples) => memory::usage(db, key, samples),
+        RedisCommand::MemoryDoctor => memory::doctor(),
+        RedisCommand::ClusterInfo => cluster::info(),
+        RedisCommand::ClusterMyId => cluster::myid(db),
+        RedisCommand::ClusterSlots => cluster::slots(),
+        RedisCommand::ClusterShards => cluster::shards(),
+        RedisCommand::FunctionList => functions::list(),
+        RedisCommand::FunctionStats => functions::stats(),
+        RedisCommand::FunctionLoad(_) | RedisCommand::Fcall(..) => functions::unsupported(),
         RedisCommand::XRead(streams, timeout) => match timeout {
             Some(timeout) => streams::xread_block(db, streams, timeout).await,
             None => streams::xread(db, streams).await,
         },
         RedisCommand::Incr(key) => incr(db, key).await,
-        RedisCommand::Multi => unreachable!(),
-        RedisCommand::Exec => unreachable!(),
-        RedisCommand::Discard => unreachable!(),
+        // Connection state commands are dealt with by the connection loop and
+        // never reach here from a client, but MULTI queues and scripts can
+        // still route one this way
+        command @ (RedisCommand::Multi
+        | RedisCommand::Exec
+        | RedisCommand::Discard
+        | RedisCommand::ReplConf(..)
+        | RedisCommand::Psync(..)
+        | RedisCommand::Wait(..)
+        | RedisCommand::WaitAof(..)
+        | RedisCommand::Subscribe(..)
+        | RedisCommand::Unsubscribe(..)
+        | RedisCommand::PSubscribe(..)
+        | RedisCommand::PUnsubscribe(..)
+        | RedisCommand::Hello(..)
+        | RedisCommand::Reset
+        | RedisCommand::Shutdown(..)
+        | RedisCommand::ClientSetName(..)
+        | RedisCommand::ClientGetName(..)
+        | RedisCommand::ClientTracking(..)
+        | RedisCommand::ClientNoEvict(..)
+        | RedisCommand::ClientNoTouch(..)) => RError(format!(
+            "ERR {} is not allowed in this context",
+            command.name().to_uppercase()
+        )),
         RedisCommand::Info(section) => info(db, section).await,
-        RedisCommand::ReplConf(_key, _value) => unreachable!(),
-        RedisCommand::Psync(_id, _offset) => unreachable!(),
         RedisCommand::RdbPayload(payload) => replication::set_rdb_payload(db, payload).await,
-        RedisCommand::Wait(_replicas, _timeout) => unreachable!(),
         RedisCommand::Config(operation, key) => config(db, operation, key),
         RedisCommand::Keys(pattern) => keys(db, pattern),
-        RedisCommand::Subscribe(_channel) => unreachable!(),
-        RedisCommand::Unsubscribe(_channel) => unreachable!(),
-        RedisCommand::PSubscribe(_pattern) => unreachable!(),
-        RedisCommand::PUnsubscribe(_pattern) => unreachable!(),
+        RedisCommand::Del(keys) => del(db, keys),
+        RedisCommand::Unlink(keys) => unlink(db, keys),
+        RedisCommand::Scan(cursor, opts) => scan(db, cursor, opts),
         RedisCommand::Publish(channel, message) => pubsub::publish(db, channel, message).await,
-        RedisCommand::ZAdd(set, score, member) => zset::zadd(db, set, score, member),
+        RedisCommand::PubSubChannels(pattern) => pubsub::channels(db, pattern),
+        RedisCommand::ZAdd(set, members, opts) => zset::zadd_with_options(db, set, members, &opts),
         RedisCommand::ZRank(set, member) => zset::zrank(db, set, member),
         RedisCommand::ZRange(set, start, stop) => zset::zrange(db, set, start, stop),
         RedisCommand::ZCard(set) => zset::zcard(db, set),
         RedisCommand::ZScore(set, member) => zset::zscore(db, set, member),
+        RedisCommand::ZDiff(keys, withscores) => zset::zdiff(db, keys, withscores),
+        RedisCommand::ZDiffStore(dest, keys) => zset::zdiffstore(db, dest, keys),
+        RedisCommand::ZRemRangeByRank(set, start, stop) => {
+            zset::zremrangebyrank(db, set, start, stop)
+        }
+        RedisCommand::ZRemRangeByScore(set, min, max) => zset::zremrangebyscore(db, set, min, max),
+        RedisCommand::ZRemRangeByLex(set, min, max) => zset::zremrangebylex(db, set, min, max),
         RedisCommand::ZRem(set, member) => zset::zrem(db, set, member),
         RedisCommand::GeoAdd(set, lng, lat, member) => geo::geoadd(db, set, lng, lat, member),
         RedisCommand::GeoPos(set, members) => geo::geopos(db, set, members),
@@ -240,133 +709,1413 @@ pub async fn handle_command(db: &Db, command: RedisCommand) -> RedisValueRef {
         RedisCommand::GeoSearch(set, lng, lat, radius, unit) => {
             geo::geosearch(db, set, lng, lat, radius, unit)
         }
+        RedisCommand::GeoRadius(key, lng, lat, radius, unit, opts) => {
+            geo::georadius(db, key, lng, lat, radius, unit, &opts)
+        }
+        RedisCommand::GeoRadiusByMember(key, member, radius, unit, opts) => {
+            geo::georadiusbymember(db, key, member, radius, unit, &opts)
+        }
         RedisCommand::AclWhoami() => auth::aclwhoami(db),
         RedisCommand::AclGetUser(user) => auth::aclgetuser(db, user),
         RedisCommand::AclSetUser(user, password) => auth::aclsetuser(db, user, password),
         RedisCommand::Auth(user, password) => auth::auth(db, user, password),
+        RedisCommand::Quit => RSimpleString("OK"),
+        RedisCommand::FlushDb(asynchronous) | RedisCommand::FlushAll(asynchronous) => {
+            flushdb(db, asynchronous)
+        }
+        RedisCommand::BgSave => bgsave(db),
+        RedisCommand::Save => save(db),
+        RedisCommand::LastSave => lastsave(db),
+        RedisCommand::Role => role(db),
+        RedisCommand::ConfigResetStat => stats::reset(db),
+        RedisCommand::SlowlogGet(count) => slowlog::get(db, count),
+        RedisCommand::SlowlogLen => slowlog::len(db),
+        RedisCommand::SlowlogReset => slowlog::reset(db),
+        RedisCommand::DebugSleep(seconds) => debug::sleep(seconds).await,
+        RedisCommand::DebugChangeReplId => debug::change_repl_id(db),
+        RedisCommand::DebugObject(key) => debug::object(db, key),
+        RedisCommand::DebugReload => debug::reload(db),
+        RedisCommand::Lcs(key1, key2, opts) => lcs(db, key1, key2, opts),
+        RedisCommand::SetBit(key, offset, value) => bits::setbit(db, key, offset, value),
+        RedisCommand::GetBit(key, offset) => bits::getbit(db, key, offset),
+        RedisCommand::BitCount(key, range, unit) => bits::bitcount(db, key, range, unit),
+        RedisCommand::BitPos(key, bit, start, end, unit) => {
+            bits::bitpos(db, key, bit, start, end, unit)
+        }
+        RedisCommand::BitOp(op, dest, keys) => bits::bitop(db, op, dest, keys),
+        RedisCommand::BitField(key, ops) => bits::bitfield(db, key, ops),
+        RedisCommand::CommandInfo(names) => commands::command_info(names),
+        RedisCommand::CommandCount => commands::command_count(),
+        RedisCommand::CommandList(filter) => commands::command_list(filter),
+        RedisCommand::DebugSetActiveExpire(enabled) => debug::set_active_expire(db, enabled),
+        RedisCommand::DebugQuicklistPackedThreshold(threshold) => {
+            debug::quicklist_packed_threshold(db, threshold)
+        }
+        RedisCommand::DebugStringMatchLen(pattern, string) => {
+            debug::stringmatch_len(pattern, string)
+        }
+        RedisCommand::GetEx(key, option) => getex(db, key, option).await,
+        RedisCommand::Append(key, value) => append(db, key, value).await,
+        RedisCommand::StrLen(key) => strlen(db, key).await,
+        RedisCommand::SetRange(key, offset, value) => setrange(db, key, offset, value).await,
+        RedisCommand::GetRange(key, start, end) => getrange(db, key, start, end).await,
+        RedisCommand::Expire(key, seconds) => expire(db, key, seconds).await,
+        RedisCommand::PExpireAt(key, at) => pexpireat(db, key, at),
+        RedisCommand::Sort(key, opts) => sort::sort(db, key, opts).await,
+        RedisCommand::ObjectEncoding(key) => object_encoding(db, key),
+        RedisCommand::ObjectIdleTime(key) => object_idletime(db, key),
+        RedisCommand::ObjectFreq(key) => lfu::object_freq(db, key),
+        RedisCommand::Touch(keys) => touch(db, keys),
+    }
+}
+
+pub fn ping(message: Option<String>) -> RedisValueRef {
+    match message {
+        Some(message) => RString(message),
+        None => RSimpleString("PONG"),
+    }
+}
+
+/// The Redis version this server reports itself as
+pub const REDIS_VERSION: &str = "7.4.0";
+
+/// Server properties returned by HELLO. RESP3 connections get a map,
+/// RESP2 connections get the same pairs flattened into an array.
+pub fn hello(db: &Db, protocol: u8) -> RedisValueRef {
+    let role = if db.replica_of.is_some() {
+        "replica"
+    } else {
+        "master"
+    };
+    let pairs = vec![
+        (RString("server"), RString("redis")),
+        (RString("version"), RString(REDIS_VERSION)),
+        (RString("proto"), RInt(protocol as i64)),
+        (RString("mode"), RString("standalone")),
+        (RString("role"), RString(role)),
+        (RString("modules"), RArray(vec![])),
+    ];
+    if protocol == 3 {
+        RMap(pairs)
+    } else {
+        RArray(pairs.into_iter().flat_map(|(k, v)| [k, v]).collect())
     }
 }
 
-pub fn ping() -> RedisValueRef {
-    RSimpleString("PONG")
+pub fn echo(arg: Bytes) -> RedisValueRef {
+    RedisValueRef::String(arg)
 }
 
-pub fn echo(arg: String) -> RedisValueRef {
-    RString(arg)
+const LOLWUT_ART: &str = concat!(
+    "  .-------.\n",
+    " (  o   o  )\n",
+    "  |   ^   |\n",
+    "  |  '-'  |\n",
+    "  '-------'\n",
+);
+
+/// LOLWUT [VERSION version]: a piece of ASCII art and the server version.
+/// Every version gets the same picture.
+pub fn lolwut(_version: Option<u64>) -> RedisValueRef {
+    RString(format!("{}\nRedis ver. {}\n", LOLWUT_ART, REDIS_VERSION))
 }
 
+/// SET: replace the value at `key`. Like Redis, any TTL the old value had is
+/// discarded along with it.
 pub async fn set(db: &Db, key: String, value: String) -> RedisValueRef {
+    take_keys(db, std::slice::from_ref(&key));
     db.dict.insert(key, RedisValue::String(Bytes::from(value)));
+    db.add_dirty(1);
     RSimpleString("OK")
 }
 
 pub async fn set_ex(db: &Db, key: String, value: String, ttl: u64) -> RedisValueRef {
-    let expiry = (SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as u64)
-        .saturating_add(ttl);
+    set_at(db, key, value, now_ms().saturating_add(ttl))
+}
 
+/// SET with PXAT or EXAT: a time that has already passed leaves no key behind
+pub fn set_at(db: &Db, key: String, value: String, at: u64) -> RedisValueRef {
+    if at <= now_ms() {
+        remove_keys(db, &[key]);
+        return RSimpleString("OK");
+    }
+    take_keys(db, std::slice::from_ref(&key));
     db.dict
         .insert(key.clone(), RedisValue::String(Bytes::from(value)));
-    db.ttl.insert(key, expiry);
+    db.ttl.insert(key, at);
+    db.add_dirty(1);
     RSimpleString("OK")
 }
 
+/// SET with the GET option: returns the previous string value (or null) and
+/// refuses to overwrite a key holding a non-string value.
+pub async fn set_get(
+    db: &Db,
+    key: String,
+    value: String,
+    expiry: Option<SetExpiry>,
+) -> RedisValueRef {
+    let old_value = match string_value(db, &key) {
+        Ok(Some(s)) => RString(String::from_utf8_lossy(&s).to_string()),
+        Ok(None) => RNull(),
+        Err(e) => return e,
+    };
+    match expiry {
+        Some(SetExpiry::In(ttl)) => set_ex(db, key, value, ttl).await,
+        Some(SetExpiry::At(at)) => set_at(db, key, value, at),
+        None => set(db, key, value).await,
+    };
+    old_value
+}
+
+/// When SET ... GET expires the key: after a number of milliseconds, or at a
+/// unix time in milliseconds
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetExpiry {
+    In(u64),
+    At(u64),
+}
+
+/// GET: the string at `key`, or null. Any other kind of value is an error.
 pub async fn get(db: &Db, key: String) -> RedisValueRef {
     match db.get_if_valid(&key) {
-        Some(value) => RedisValueRef::from(&*value),
+        Some(entry) => match &*entry {
+            RedisValue::String(_) => RedisValueRef::from(&*entry),
+            _ => RError("WRONGTYPE Operation against a key holding the wrong kind of value"),
+        },
+        None if db.zsets.lock().unwrap().contains_key(&key) => {
+            RError("WRONGTYPE Operation against a key holding the wrong kind of value")
+        }
         None => RNull(),
     }
 }
 
-pub async fn _type(db: &Db, key: String) -> RedisValueRef {
-    let result = match db.get_if_valid(&key) {
+/// Set a TTL in seconds on an existing key. Returns 1 if the TTL was set, 0 if
+/// the key doesn't exist.
+pub async fn expire(db: &Db, key: String, seconds: i64) -> RedisValueRef {
+    let at = (now_ms() as i64).saturating_add(seconds.saturating_mul(1000));
+    pexpireat(db, key, at)
+}
+
+/// EXPIREAT and PEXPIREAT: expire the key at a unix time in milliseconds. A
+/// time that isn't in the future deletes the key straight away rather than
+/// leaving it for expiry to find.
+pub fn pexpireat(db: &Db, key: String, at: i64) -> RedisValueRef {
+    if db.get_if_valid(&key).is_none() && !db.zsets.lock().unwrap().contains_key(&key) {
+        return RInt(0);
+    }
+    if at <= now_ms() as i64 {
+        remove_keys(db, &[key]);
+        return RInt(1);
+    }
+    db.ttl.insert(key, at as u64);
+    db.add_dirty(1);
+    RInt(1)
+}
+
+/// How GETEX should change the key's expiry. Times are in milliseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GetExOption {
+    Expire(u64),
+    ExpireAt(u64),
+    Persist,
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// Drop any TTL on the key, returning whether it had one
+pub fn persist(db: &Db, key: &str) -> bool {
+    db.ttl.remove(key).is_some()
+}
+
+/// GET that can also set or clear the key's TTL. With no option the TTL is left alone.
+pub async fn getex(db: &Db, key: String, option: Option<GetExOption>) -> RedisValueRef {
+    let value = match string_value(db, &key) {
+        Ok(Some(s)) => RString(String::from_utf8_lossy(&s).to_string()),
+        Ok(None) => return RNull(),
+        Err(e) => return e,
+    };
+    match option {
+        Some(GetExOption::Expire(ttl)) => {
+            db.ttl.insert(key, now_ms().saturating_add(ttl));
+            db.add_dirty(1);
+        }
+        Some(GetExOption::ExpireAt(at)) => {
+            db.ttl.insert(key, at);
+            db.add_dirty(1);
+        }
+        Some(GetExOption::Persist) if persist(db, &key) => db.add_dirty(1),
+        Some(GetExOption::Persist) | None => {}
+    }
+    value
+}
+
+/// The type name TYPE reports for a key, or "none" if it doesn't exist
+pub fn type_of(db: &Db, key: &str) -> &'static str {
+    match db.get_if_valid(key) {
         Some(entry) => match *entry {
             RedisValue::String(_) => "string",
             RedisValue::List(_) => "list",
             RedisValue::Stream(_) => "stream",
+            RedisValue::Hash(_) => "hash",
+            RedisValue::Set(_) => "set",
         },
+        None if db.zsets.lock().unwrap().contains_key(key) => "zset",
         None => "none",
+    }
+}
+
+pub async fn _type(db: &Db, key: String) -> RedisValueRef {
+    RSimpleString(type_of(db, &key))
+}
+
+/// Resolve LRANGE/ZRANGE style inclusive indexes against a collection of
+/// `len` elements. Negative indexes count from the end; a start before the
+/// beginning clamps to 0 and a stop past the end clamps to the last element.
+/// None means the range selects nothing.
+pub fn normalize_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (start + len).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        stop + len
+    } else {
+        stop.min(len - 1)
     };
-    RSimpleString(result)
+    if start >= len || stop < 0 || start > stop {
+        return None;
+    }
+    Some((start as usize, stop as usize))
 }
 
-pub async fn incr(db: &Db, key: String) -> RedisValueRef {
-    let result = match db.get_if_valid(&key) {
-        Some(entry) => match &*entry {
-            RedisValue::String(value) => {
-                let new_value = String::from_utf8_lossy(value).to_string();
-                let new_value = match new_value.parse::<i64>() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        return RError("ERR value is not an integer or out of range");
-                    }
-                };
-                new_value + 1
-            }
-            _ => {
-                return RError("WRONGTYPE");
+/// Whether a string value would be stored with the int encoding: a 64 bit
+/// integer in canonical form, so no leading zeros, `+` sign or whitespace
+fn is_canonical_int(value: &[u8]) -> bool {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok().map(|n| n.to_string() == value))
+        .unwrap_or(false)
+}
+
+/// Longest string Redis stores as embstr, in the same allocation as its header
+pub const EMBSTR_SIZE_LIMIT: usize = 44;
+
+/// Internal encoding of the value at `key`, if there is one
+pub fn encoding_of(db: &Db, key: &str) -> Option<&'static str> {
+    if let Some(entry) = db.get_if_valid(key) {
+        return Some(match &*entry {
+            RedisValue::String(value) if is_canonical_int(value) => "int",
+            RedisValue::String(value) if value.len() <= EMBSTR_SIZE_LIMIT => "embstr",
+            RedisValue::String(_) => "raw",
+            RedisValue::List(list) => list.encoding(),
+            RedisValue::Stream(_) => "stream",
+            RedisValue::Hash(hash) => hash.encoding(),
+            RedisValue::Set(set) => set.encoding(),
+        });
+    }
+    match db.zsets.lock().unwrap().get(key) {
+        Some(zset) if zset.members().count() <= 128 && zset.members().all(|m| m.len() <= 64) => {
+            Some("listpack")
+        }
+        Some(_) => Some("skiplist"),
+        None => None,
+    }
+}
+
+/// OBJECT ENCODING: the internal representation Redis would use for the value
+pub fn object_encoding(db: &Db, key: String) -> RedisValueRef {
+    match encoding_of(db, &key) {
+        Some(encoding) => RString(encoding),
+        None => RNull(),
+    }
+}
+
+/// Stamp `keys` as accessed now, and count the access under an LFU policy,
+/// unless `touch` is off (CLIENT NO-TOUCH). Either way keys that no longer
+/// exist lose their stamp, so a key created later under the same name starts
+/// out fresh. Returns how many of the keys exist.
+pub fn record_access(db: &Db, keys: &[String], touch: bool) -> usize {
+    let now = now_ms();
+    let track_frequency = lfu::is_lfu_policy(&db.maxmemory_policy);
+    let mut existing = 0;
+    for key in keys {
+        if type_of(db, key) == "none" {
+            db.forget_access(key);
+            continue;
+        }
+        existing += 1;
+        if touch {
+            db.last_access.insert(key.clone(), now);
+            if track_frequency {
+                lfu::record(db, key);
             }
+        }
+    }
+    existing
+}
+
+/// TOUCH: how many of the keys exist, refreshing their access time
+pub fn touch(db: &Db, keys: Vec<String>) -> RedisValueRef {
+    RInt(record_access(db, &keys, true) as i64)
+}
+
+/// OBJECT IDLETIME: seconds since the key was last accessed
+pub fn object_idletime(db: &Db, key: String) -> RedisValueRef {
+    if type_of(db, &key) == "none" {
+        return RNull();
+    }
+    let now = now_ms();
+    let last_access = db.last_access.get(&key).map_or(now, |at| *at);
+    RInt((now.saturating_sub(last_access) / 1000) as i64)
+}
+
+/// The bytes of a string value, or the WRONGTYPE reply string commands give
+/// for any other kind of value
+pub(crate) fn expect_string_value(entry: &mut RedisValue) -> Result<&mut Bytes, RedisValueRef> {
+    match entry {
+        RedisValue::String(value) => Ok(value),
+        _ => Err(wrong_type()),
+    }
+}
+
+/// The reply for a command run against a key of another type
+pub(crate) fn wrong_type() -> RedisValueRef {
+    RError("WRONGTYPE Operation against a key holding the wrong kind of value")
+}
+
+/// Zsets live outside the dict, so a key the dict doesn't have may still be
+/// taken. String commands check this before treating the key as missing.
+pub(crate) fn expect_no_zset(db: &Db, key: &str) -> Result<(), RedisValueRef> {
+    if db.zsets.lock().unwrap().contains_key(key) {
+        return Err(wrong_type());
+    }
+    Ok(())
+}
+
+/// The string at `key` for commands that only read it, or None if the key
+/// doesn't exist. Any other type, zsets included, is a WRONGTYPE error.
+pub(crate) fn string_value(db: &Db, key: &str) -> Result<Option<Bytes>, RedisValueRef> {
+    match db.get_if_valid(key).as_deref() {
+        Some(RedisValue::String(value)) => Ok(Some(value.clone())),
+        Some(_) => Err(wrong_type()),
+        None => expect_no_zset(db, key).map(|()| None),
+    }
+}
+
+pub async fn incr(db: &Db, key: String) -> RedisValueRef {
+    let result = match db
+        .get_mut_if_valid(&key)
+        .as_deref_mut()
+        .map(expect_string_value)
+    {
+        Some(Ok(value)) => {
+            let new_value = String::from_utf8_lossy(value).to_string();
+            let new_value = match new_value.parse::<i64>() {
+                Ok(num) => num,
+                Err(_) => {
+                    return RError("ERR value is not an integer or out of range");
+                }
+            };
+            new_value + 1
+        }
+        Some(Err(e)) => return e,
+        None => match expect_no_zset(db, &key) {
+            Ok(()) => 1,
+            Err(e) => return e,
         },
-        None => 1,
     };
     db.dict
         .insert(key, RedisValue::String(Bytes::from(result.to_string())));
+    db.add_dirty(1);
     RInt(result)
 }
 
-pub async fn info(db: &Db, _section: String) -> RedisValueRef {
-    let role = if db.replica_of.is_some() {
-        "slave"
+/// APPEND works on the stored bytes, so values written by INCR (kept in their
+/// ASCII form) are extended like any other string.
+pub async fn append(db: &Db, key: String, value: String) -> RedisValueRef {
+    match db
+        .get_mut_if_valid(&key)
+        .as_deref_mut()
+        .map(expect_string_value)
+    {
+        Some(Ok(existing)) => {
+            let mut appended = existing.to_vec();
+            appended.extend_from_slice(value.as_bytes());
+            let len = appended.len();
+            *existing = Bytes::from(appended);
+            db.add_dirty(1);
+            RInt(len as i64)
+        }
+        Some(Err(e)) => e,
+        None => {
+            if let Err(e) = expect_no_zset(db, &key) {
+                return e;
+            }
+            let len = value.len();
+            db.dict.insert(key, RedisValue::String(Bytes::from(value)));
+            db.add_dirty(1);
+            RInt(len as i64)
+        }
+    }
+}
+
+pub async fn strlen(db: &Db, key: String) -> RedisValueRef {
+    match db
+        .get_mut_if_valid(&key)
+        .as_deref_mut()
+        .map(expect_string_value)
+    {
+        Some(Ok(value)) => RInt(value.len() as i64),
+        Some(Err(e)) => e,
+        None => match expect_no_zset(db, &key) {
+            Ok(()) => RInt(0),
+            Err(e) => e,
+        },
+    }
+}
+
+/// Strings are capped at 512MB, the same limit as proto-max-bulk-len
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+/// Overwrite part of a string starting at `offset`, zero-padding if the string
+/// is shorter. Returns the new length.
+pub async fn setrange(db: &Db, key: String, offset: usize, value: String) -> RedisValueRef {
+    if offset.saturating_add(value.len()) > MAX_STRING_LEN {
+        return RError("ERR string exceeds maximum allowed size (proto-max-bulk-len)");
+    }
+    let mut bytes = match db
+        .get_mut_if_valid(&key)
+        .as_deref_mut()
+        .map(expect_string_value)
+    {
+        Some(Ok(existing)) => existing.to_vec(),
+        Some(Err(e)) => return e,
+        None => match expect_no_zset(db, &key) {
+            Ok(()) => Vec::new(),
+            Err(e) => return e,
+        },
+    };
+    // An empty write leaves the value (or lack of one) untouched
+    if value.is_empty() {
+        return RInt(bytes.len() as i64);
+    }
+    let end = offset + value.len();
+    if bytes.len() < end {
+        bytes.resize(end, 0);
+    }
+    bytes[offset..end].copy_from_slice(value.as_bytes());
+    let len = bytes.len();
+    db.dict.insert(key, RedisValue::String(Bytes::from(bytes)));
+    db.add_dirty(1);
+    RInt(len as i64)
+}
+
+/// GETRANGE: the bytes from `start` to `end` inclusive. Negative offsets
+/// count back from the end; the range is clamped to the string, and an empty
+/// string comes back when nothing is left of it.
+pub async fn getrange(db: &Db, key: String, start: i64, end: i64) -> RedisValueRef {
+    let value = match db
+        .get_mut_if_valid(&key)
+        .as_deref_mut()
+        .map(expect_string_value)
+    {
+        Some(Ok(value)) => value.clone(),
+        Some(Err(e)) => return e,
+        None => match expect_no_zset(db, &key) {
+            Ok(()) => Bytes::new(),
+            Err(e) => return e,
+        },
+    };
+    let len = value.len() as i64;
+    // Both ends before the start of the string, in the wrong order
+    if start < 0 && end < 0 && start > end {
+        return RString("");
+    }
+    let start = if start < 0 {
+        (start + len).max(0)
     } else {
-        "master"
+        start
+    };
+    let end = if end < 0 {
+        (end + len).max(0)
+    } else {
+        end.min(len - 1)
+    };
+    if len == 0 || start > end {
+        return RString("");
+    }
+    RedisValueRef::String(value.slice(start as usize..=end as usize))
+}
+
+pub async fn info(db: &Db, section: String) -> RedisValueRef {
+    let section = section.to_lowercase();
+    let all = matches!(section.as_str(), "all" | "default" | "everything");
+    let mut sections = Vec::new();
+    if all || section == "server" {
+        sections.push(info_server(db));
+    }
+    if all || section == "clients" {
+        sections.push(info_clients(db));
+    }
+    // Like Redis, the per-command section is only in the full output
+    if matches!(section.as_str(), "all" | "everything" | "commandstats") {
+        sections.push(stats::info_commandstats(db));
+    }
+    if all || section == "persistence" {
+        sections.push(info_persistence(db));
+    }
+    if all || section == "replication" {
+        sections.push(info_replication(db));
+    }
+    RString(sections.join("\n"))
+}
+
+fn info_server(db: &Db) -> String {
+    format!(
+        "# Server\n\
+        redis_version:{}\n\
+        redis_mode:standalone\n\
+        process_id:{}\n\
+        run_id:{}\n",
+        REDIS_VERSION,
+        std::process::id(),
+        db.run_id,
+    )
+}
+
+fn info_clients(db: &Db) -> String {
+    format!(
+        "# Clients\n\
+        connected_clients:{}\n\
+        maxclients:{}\n",
+        db.connected_clients.load(Ordering::Relaxed),
+        db.maxclients,
+    )
+}
+
+fn info_persistence(db: &Db) -> String {
+    format!(
+        "# Persistence\n\
+        rdb_changes_since_last_save:{}\n\
+        rdb_bgsave_in_progress:{}\n\
+        rdb_last_save_time:{}\n\
+        aof_enabled:{}\n",
+        db.changes_since_last_save(),
+        db.rdb_bgsave_in_progress.load(Ordering::Relaxed) as u8,
+        db.rdb_last_save_time.load(Ordering::Relaxed),
+        aof::is_enabled(db) as u8,
+    )
+}
+
+fn info_replication(db: &Db) -> String {
+    let role = match &db.replica_of {
+        Some((host, port)) => {
+            let link_status = if db.master_link_up.load(Ordering::Relaxed) {
+                "up"
+            } else {
+                "down"
+            };
+            let last_io = match db.master_last_io.load(Ordering::Relaxed) {
+                0 => -1,
+                last_io => (now_ms().saturating_sub(last_io) / 1000) as i64,
+            };
+            format!(
+                "slave\n\
+                master_host:{}\n\
+                master_port:{}\n\
+                master_link_status:{}\n\
+                master_last_io_seconds_ago:{}",
+                host, port, link_status, last_io
+            )
+        }
+        None => "master".to_string(),
     };
-    let info = format!(
+    format!(
         "# Replication\n\
         role:{}\n\
         master_replid:{}\n\
         master_repl_offset:{}\n",
         role,
-        db.replication_id,
+        db.replication_id.lock().unwrap(),
         db.replication_offset
             .load(std::sync::atomic::Ordering::Relaxed)
-    );
-    RString(info)
+    )
 }
 
-pub fn config(db: &Db, operation: String, value: String) -> RedisValueRef {
-    match operation.as_str() {
-        "GET" => match value.to_lowercase().as_str() {
-            "dir" => RArray(vec![RString("dir"), RString(db.cfg_dir.clone())]),
-            "dbfilename" => RArray(vec![RString("dbfilename"), RString(db.db_file.clone())]),
-            _ => RError("Unknown config key"),
-        },
-        _ => RError("Config operation must be GET"),
+/// ROLE: "master" with the replication offset and each replica's address and
+/// acknowledged offset, or "slave" with the master's address, the link state
+/// and the offset. Like Redis, there's no place in the reply for the run ID,
+/// which INFO server reports instead.
+pub fn role(db: &Db) -> RedisValueRef {
+    let offset = db.replication_offset.load(Ordering::Relaxed);
+    match &db.replica_of {
+        Some((host, port)) => {
+            let state = if db.master_link_up.load(Ordering::Relaxed) {
+                "connected"
+            } else {
+                "connect"
+            };
+            RArray(vec![
+                RString("slave"),
+                RString(host.clone()),
+                RInt(*port as i64),
+                RString(state),
+                RInt(offset),
+            ])
+        }
+        None => {
+            let replicas = db
+                .replicating_to
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|replica| {
+                    RArray(vec![
+                        RString(replica.ip.clone()),
+                        RString(replica.port.to_string()),
+                        RString(replica.offset.to_string()),
+                    ])
+                })
+                .collect();
+            RArray(vec![RString("master"), RInt(offset), RArray(replicas)])
+        }
     }
 }
 
-pub fn keys(db: &Db, _pattern: String) -> RedisValueRef {
-    let db_keys = db
-        .dict
-        .iter()
-        .map(|entry| RString(entry.key().clone()))
-        .collect::<Vec<_>>();
+/// Write a dump to a temporary file first and rename it into place, so a
+/// crash mid-save never leaves a truncated RDB behind
+fn write_rdb_file(path: &Path, entries: &[SnapshotEntry]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension(format!("tmp-{}", std::process::id()));
+    std::fs::write(&tmp, write_rdb(entries))?;
+    std::fs::rename(tmp, path)
+}
 
-    RArray(db_keys)
+/// SAVE: write the dump in the foreground, blocking until it's on disk
+pub fn save(db: &Db) -> RedisValueRef {
+    if db.rdb_bgsave_in_progress.load(Ordering::SeqCst) {
+        return RError("ERR Background save already in progress");
+    }
+    let dirty = db.dirty.load(Ordering::Relaxed);
+    match write_rdb_file(&db.rdb_path(), &db.snapshot()) {
+        Ok(()) => {
+            db.rdb_last_save_time
+                .store(now_ms() / 1000, Ordering::Relaxed);
+            db.dirty_at_last_save.store(dirty, Ordering::Relaxed);
+            RSimpleString("OK")
+        }
+        Err(e) => RError(format!("ERR {}", e)),
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::time::Duration;
+/// LASTSAVE: unix time of the last successful SAVE or BGSAVE
+pub fn lastsave(db: &Db) -> RedisValueRef {
+    RInt(db.rdb_last_save_time.load(Ordering::Relaxed) as i64)
+}
 
-    fn setup() -> Arc<RedisDb> {
-        Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
+/// SHUTDOWN: flush the AOF, save unless NOSAVE was given, and signal the
+/// server to stop. There are no save points to consult, so with neither
+/// option it saves, as Redis does with its default ones. Fails with the reply
+/// to send if the save didn't work, in which case the server stays up.
+pub fn shutdown(db: &Db, save_option: Option<bool>) -> Result<(), RedisValueRef> {
+    if aof::is_enabled(db) {
+        aof::fsync(db);
     }
+    if save_option.unwrap_or(true)
+        && let RedisValueRef::Error(e) = save(db)
+    {
+        eprintln!("Error trying to save the DB before shutdown: {:?}", e);
+        return Err(RError("ERR Errors trying to SHUTDOWN. Check logs."));
+    }
+    println!("Ready to exit, bye bye...");
+    db.shutdown.notify_one();
+    Ok(())
+}
 
-    #[tokio::test]
-    async fn test_set_get() {
+/// BGSAVE: snapshot the dataset and write it out on a blocking thread, so the
+/// server keeps serving while the file is written
+pub fn bgsave(db: &Db) -> RedisValueRef {
+    if db.rdb_bgsave_in_progress.swap(true, Ordering::SeqCst) {
+        return RError("ERR Background save already in progress");
+    }
+    // Changes made while the file is written still count against the next save
+    let dirty = db.dirty.load(Ordering::Relaxed);
+    let entries = db.snapshot();
+    let path = db.rdb_path();
+    let db = db.clone();
+    tokio::task::spawn_blocking(move || {
+        match write_rdb_file(&path, &entries) {
+            Ok(()) => {
+                db.rdb_last_save_time
+                    .store(now_ms() / 1000, Ordering::Relaxed);
+                db.dirty_at_last_save.store(dirty, Ordering::Relaxed);
+            }
+            Err(e) => eprintln!("Background save failed: {}", e),
+        }
+        db.rdb_bgsave_in_progress.store(false, Ordering::SeqCst);
+    });
+    RSimpleString("Background saving started")
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ScanOptions {
+    pub pattern: Option<String>,
+    pub count: Option<usize>,
+    pub key_type: Option<String>,
+}
+
+/// Where a key sits in SCAN order. The slot only depends on the key itself,
+/// so it stays put however the rest of the keyspace changes. Cursor 0 is
+/// reserved for the start of an iteration.
+fn scan_slot(key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish().max(1)
+}
+
+/// SCAN: page through the keyspace. Keys are visited in order of their
+/// `scan_slot`, and the cursor is the slot to carry on from. Because slots
+/// don't move, a key that exists for the whole of a full iteration is
+/// returned at least once, whatever is added or removed in between. Keys
+/// added or removed during the iteration may or may not show up, and a key
+/// can occasionally come back twice. COUNT sets how many keys each call
+/// looks at, and the MATCH and TYPE filters are applied to those afterwards,
+/// so a page can come back empty with a non-zero cursor.
+pub fn scan(db: &Db, cursor: u64, opts: ScanOptions) -> RedisValueRef {
+    let count = opts.count.unwrap_or(10);
+    // The lowest count + 1 (slot, key) pairs from the cursor on: the page, and
+    // the slot the next call starts from
+    let mut lowest: BinaryHeap<(u64, String)> = BinaryHeap::new();
+    let mut offer = |key: &str| {
+        let slot = scan_slot(key);
+        if slot < cursor {
+            return;
+        }
+        if lowest.len() <= count {
+            lowest.push((slot, key.to_string()));
+        } else if let Some(mut highest) = lowest.peek_mut()
+            && (slot, key) < (highest.0, highest.1.as_str())
+        {
+            *highest = (slot, key.to_string());
+        }
+    };
+    for entry in db.dict.iter() {
+        if !db.is_expired(entry.key()) {
+            offer(entry.key());
+        }
+    }
+    // Taken after the dict is done with; a key in both maps is listed once
+    for key in db.zsets.lock().unwrap().keys() {
+        if !db.dict.contains_key(key) {
+            offer(key);
+        }
+    }
+    let keys = lowest.into_sorted_vec();
+
+    let next = keys.get(count).map_or(0, |(slot, _)| *slot);
+    let page = keys
+        .into_iter()
+        .take(count)
+        .map(|(_, key)| key)
+        .filter(|key| {
+            opts.pattern
+                .as_ref()
+                .is_none_or(|pattern| glob::glob_match(pattern.as_bytes(), key.as_bytes(), false))
+        })
+        .filter(|key| {
+            opts.key_type
+                .as_ref()
+                .is_none_or(|key_type| type_of(db, key).eq_ignore_ascii_case(key_type))
+        })
+        .map(RString)
+        .collect();
+    RArray(vec![RString(next.to_string()), RArray(page)])
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LcsOptions {
+    pub len: bool,
+    pub idx: bool,
+    pub min_match_len: u64,
+    pub with_match_len: bool,
+}
+
+/// Longest common subsequence of two string values. Missing keys are
+/// treated as empty strings.
+pub fn lcs(db: &Db, key1: String, key2: String, opts: LcsOptions) -> RedisValueRef {
+    let mut values = Vec::with_capacity(2);
+    for key in [&key1, &key2] {
+        match string_value(db, key) {
+            Ok(value) => values.push(value.unwrap_or_default()),
+            Err(e) => return e,
+        }
+    }
+    let (a, b) = (&values[0], &values[1]);
+    if opts.len {
+        return RInt(lcs_row(a, b)[b.len()] as i64);
+    }
+    if !opts.idx {
+        let mut result = Vec::new();
+        lcs_string(a, b, &mut result);
+        return RString(String::from_utf8_lossy(&result).to_string());
+    }
+
+    // Match ranges need the whole table to walk back through, so like Redis
+    // refuse one that would take more memory than a bulk string may
+    let table_bytes = (a.len() + 1)
+        .checked_mul(b.len() + 1)
+        .and_then(|cells| cells.checked_mul(size_of::<u32>()));
+    if table_bytes.is_none_or(|bytes| bytes > db.proto_max_bulk_len) {
+        return RError(
+            "ERR Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len",
+        );
+    }
+
+    // table[i][j] is the LCS length of a[..i] and b[..j]
+    let width = b.len() + 1;
+    let mut table = vec![0u32; (a.len() + 1) * width];
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            table[i * width + j] = if a[i - 1] == b[j - 1] {
+                table[(i - 1) * width + j - 1] + 1
+            } else {
+                table[(i - 1) * width + j].max(table[i * width + j - 1])
+            };
+        }
+    }
+    let lcs_len = table[a.len() * width + b.len()] as usize;
+
+    // Walk the table backwards collecting the ranges of contiguous matches
+    // (reported last match first like Redis)
+    let mut matches = Vec::new();
+    let mut current: Option<(usize, usize, usize, usize)> = None; // a_start, a_end, b_start, b_end
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 && j > 0 {
+        let mut emit = false;
+        if a[i - 1] == b[j - 1] {
+            current = match current {
+                None => Some((i - 1, i - 1, j - 1, j - 1)),
+                Some((a_start, a_end, b_start, b_end)) if a_start == i && b_start == j => {
+                    Some((a_start - 1, a_end, b_start - 1, b_end))
+                }
+                range => {
+                    emit = true;
+                    range
+                }
+            };
+            if let Some((a_start, _, b_start, _)) = current
+                && (a_start == 0 || b_start == 0)
+            {
+                emit = true;
+            }
+            i -= 1;
+            j -= 1;
+        } else {
+            if table[(i - 1) * width + j] > table[i * width + j - 1] {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+            emit = current.is_some();
+        }
+
+        if emit && let Some((a_start, a_end, b_start, b_end)) = current.take() {
+            let match_len = a_end - a_start + 1;
+            if match_len as u64 >= opts.min_match_len {
+                let mut entry = vec![
+                    RArray(vec![RInt(a_start as i64), RInt(a_end as i64)]),
+                    RArray(vec![RInt(b_start as i64), RInt(b_end as i64)]),
+                ];
+                if opts.with_match_len {
+                    entry.push(RInt(match_len as i64));
+                }
+                matches.push(RArray(entry));
+            }
+        }
+    }
+
+    RArray(vec![
+        RString("matches"),
+        RArray(matches),
+        RString("len"),
+        RInt(lcs_len as i64),
+    ])
+}
+
+/// The last row of the LCS table of `a` against `b`, computed two rows at a
+/// time: row[j] is the LCS length of `a` and b[..j]
+fn lcs_row<'a>(a: impl IntoIterator<Item = &'a u8>, b: &[u8]) -> Vec<u32> {
+    let mut prev = vec![0u32; b.len() + 1];
+    let mut row = vec![0u32; b.len() + 1];
+    for &x in a {
+        for j in 1..=b.len() {
+            row[j] = if x == b[j - 1] {
+                prev[j - 1] + 1
+            } else {
+                prev[j].max(row[j - 1])
+            };
+        }
+        std::mem::swap(&mut prev, &mut row);
+    }
+    prev
+}
+
+/// Hirschberg's algorithm: append an LCS of `a` and `b` to `out` in linear
+/// space, by splitting `a` in half and finding the split of `b` where the
+/// LCS of the front halves plus that of the back halves is longest
+fn lcs_string(a: &[u8], b: &[u8], out: &mut Vec<u8>) {
+    if a.is_empty() || b.is_empty() {
+        return;
+    }
+    if let [x] = a {
+        if b.contains(x) {
+            out.push(*x);
+        }
+        return;
+    }
+    let (front, back) = a.split_at(a.len() / 2);
+    let forward = lcs_row(front, b);
+    let reversed_b: Vec<u8> = b.iter().rev().copied().collect();
+    let backward = lcs_row(back.iter().rev(), &reversed_b);
+    let split = (0..=b.len())
+        .max_by_key(|&j| forward[j] + backward[b.len() - j])
+        .unwrap_or(0);
+    lcs_string(front, &b[..split], out);
+    lcs_string(back, &b[split..], out);
+}
+
+/// A size such as `512mb` or `1g`, as Redis takes them in its config. k, m
+/// and g are powers of 1000, while kb, mb and gb are powers of 1024.
+pub fn parse_memory_size(size: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid memory size '{}'", size);
+    let lower = size.to_ascii_lowercase();
+    let (digits, unit) = lower.split_at(
+        lower
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(lower.len()),
+    );
+    let multiplier: u64 = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1 << 10,
+        "m" => 1000 * 1000,
+        "mb" => 1 << 20,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1 << 30,
+        _ => return Err(invalid()),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(invalid)
+}
+
+/// Every parameter CONFIG GET knows about, with its current value
+fn config_params(db: &Db) -> Vec<(&'static str, String)> {
+    let appendonly = if aof::is_enabled(db) { "yes" } else { "no" };
+    vec![
+        ("dir", db.cfg_dir.clone()),
+        ("dbfilename", db.db_file.clone()),
+        ("appendonly", appendonly.to_string()),
+        ("appendfilename", db.aof_file.clone()),
+        ("maxclients", db.maxclients.to_string()),
+        ("maxmemory", db.maxmemory.to_string()),
+        ("maxmemory-policy", db.maxmemory_policy.clone()),
+    ]
+}
+
+/// CONFIG GET <pattern>: name/value pairs for every parameter whose name
+/// matches the glob pattern
+pub fn config(db: &Db, operation: String, pattern: String) -> RedisValueRef {
+    if !operation.eq_ignore_ascii_case("GET") {
+        return RError("Config operation must be GET");
+    }
+    RArray(
+        config_params(db)
+            .into_iter()
+            .filter(|(name, _)| glob::glob_match(pattern.as_bytes(), name.as_bytes(), true))
+            .flat_map(|(name, value)| [RString(name), RString(value)])
+            .collect(),
+    )
+}
+
+/// Values removed from the keyspace. Sorted sets are kept apart from the
+/// other types, so they come back separately.
+#[derive(Default)]
+struct Removed {
+    values: Vec<RedisValue>,
+    zsets: Vec<ZSet>,
+}
+
+impl Removed {
+    fn count(&self) -> usize {
+        self.values.len() + self.zsets.len()
+    }
+
+    /// Roughly how much work freeing everything is: one unit per element
+    fn free_effort(&self) -> usize {
+        let values: usize = self
+            .values
+            .iter()
+            .map(|value| match value {
+                RedisValue::String(_) => 1,
+                RedisValue::List(list) => list.len(),
+                RedisValue::Stream(stream) => stream.len(),
+                RedisValue::Hash(hash) => hash.len(),
+                RedisValue::Set(set) => set.len(),
+            })
+            .sum();
+        values
+            + self
+                .zsets
+                .iter()
+                .map(|zset| zset.members().count())
+                .sum::<usize>()
+    }
+}
+
+/// Take `keys` out of the keyspace, skipping any that don't exist or have expired
+fn remove_keys(db: &Db, keys: &[String]) -> Removed {
+    let removed = take_keys(db, keys);
+    db.add_dirty(removed.count() as u64);
+    removed
+}
+
+/// remove_keys without counting a change, for writers that replace the value
+/// straight away and count that instead
+fn take_keys(db: &Db, keys: &[String]) -> Removed {
+    let mut removed = Removed::default();
+    for key in keys {
+        if db.remove_if_expired(key) {
+            continue;
+        }
+        let value = db.dict.remove(key).map(|(_, value)| value);
+        let zset = db.zsets.lock().unwrap().remove(key);
+        if value.is_none() && zset.is_none() {
+            continue;
+        }
+        db.ttl.remove(key);
+        db.forget_access(key);
+        removed.values.extend(value);
+        removed.zsets.extend(zset);
+    }
+    removed
+}
+
+/// Above this much free effort UNLINK hands the values to a background task,
+/// the same threshold Redis uses for lazy freeing
+const LAZYFREE_THRESHOLD: usize = 64;
+
+/// DEL: remove the keys, replying with how many existed
+pub fn del(db: &Db, keys: Vec<String>) -> RedisValueRef {
+    RInt(remove_keys(db, &keys).count() as i64)
+}
+
+/// UNLINK: DEL, except that big values are freed on a blocking task instead
+/// of holding up the reply
+pub fn unlink(db: &Db, keys: Vec<String>) -> RedisValueRef {
+    let removed = remove_keys(db, &keys);
+    let count = removed.count();
+    if removed.free_effort() > LAZYFREE_THRESHOLD {
+        tokio::task::spawn_blocking(move || drop(removed));
+    }
+    RInt(count as i64)
+}
+
+/// FLUSHDB and FLUSHALL: remove every key. With ASYNC the values are freed on
+/// a blocking task, so a big keyspace doesn't hold up the reply.
+pub fn flushdb(db: &Db, asynchronous: bool) -> RedisValueRef {
+    let mut keys: Vec<String> = db.dict.iter().map(|entry| entry.key().clone()).collect();
+    keys.extend(db.zsets.lock().unwrap().keys().cloned());
+    let removed = remove_keys(db, &keys);
+    db.last_access.clear();
+    db.lfu.clear();
+    if asynchronous {
+        tokio::task::spawn_blocking(move || drop(removed));
+    }
+    RSimpleString("OK")
+}
+
+pub fn keys(db: &Db, _pattern: String) -> RedisValueRef {
+    let db_keys = db
+        .dict
+        .iter()
+        .map(|entry| RString(entry.key().clone()))
+        .collect::<Vec<_>>();
+
+    RArray(db_keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn setup() -> Arc<RedisDb> {
+        Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
+    }
+
+    #[tokio::test]
+    async fn test_bgsave() {
+        let db_file = format!("{}.rdb", uuid::Uuid::new_v4());
+        let db = Arc::new(RedisDb::new(None, "/tmp/redis-files", &db_file));
+        set(&db, "key".to_string(), "value".to_string()).await;
+        set_ex(&db, "ttl".to_string(), "value".to_string(), 100_000).await;
+        lists::rpush(&db, "list".to_string(), vec!["a".to_string()]).await;
+        db.rdb_last_save_time.store(0, Ordering::Relaxed);
+
+        assert_eq!(bgsave(&db), RSimpleString("Background saving started"));
+        while db.rdb_bgsave_in_progress.load(Ordering::SeqCst) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(db.rdb_last_save_time.load(Ordering::Relaxed) > 0);
+
+        let loaded = Arc::new(RedisDb::new(None, "/tmp/redis-files", &db_file));
+        assert_eq!(loaded.try_load_rdb().unwrap(), 3);
+        assert_eq!(get(&loaded, "key".to_string()).await, RString("value"));
+        assert_eq!(
+            lists::lrange(&loaded, "list".to_string(), 0, -1).await,
+            RArray(vec![RString("a")])
+        );
+        assert_eq!(get(&loaded, "ttl".to_string()).await, RString("value"));
+        assert!(loaded.ttl.contains_key("ttl"));
+        std::fs::remove_file(db.rdb_path()).unwrap();
+    }
+
+    #[test]
+    fn test_load_rdb_keeps_binary_members() {
+        let entry = |key: &str, value| SnapshotEntry {
+            key: key.to_string(),
+            value,
+            expire: None,
+        };
+        let entries = vec![
+            entry("set", RdbValue::Set(vec![vec![0xFF, 0x00]])),
+            entry("hash", RdbValue::Hash(vec![(vec![0xFE], b"v".to_vec())])),
+            entry("zset", RdbValue::ZSet(vec![(vec![0xC3, 0x28], 1.0)])),
+        ];
+        let db = setup();
+        assert_eq!(db.load_rdb(&write_rdb(&entries)).unwrap(), 3);
+
+        let mut snapshot = db.snapshot();
+        snapshot.sort_by(|a, b| a.key.cmp(&b.key));
+        let mut expected = entries;
+        expected.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(snapshot, expected);
+    }
+
+    /// Collect every key a full SCAN iteration returns
+    fn scan_all(db: &Db, opts: ScanOptions) -> Vec<String> {
+        let mut cursor = 0;
+        let mut keys = Vec::new();
+        loop {
+            let RedisValueRef::Array(reply) = scan(db, cursor, opts.clone()) else {
+                panic!("SCAN should return an array");
+            };
+            let [RedisValueRef::String(next), RedisValueRef::Array(page)] = &reply[..] else {
+                panic!("SCAN should return a cursor and a page of keys");
+            };
+            keys.extend(page.iter().map(|key| key.to_string()));
+            cursor = String::from_utf8_lossy(next).parse().unwrap();
+            if cursor == 0 {
+                break;
+            }
+        }
+        keys.sort();
+        keys
+    }
+
+    #[tokio::test]
+    async fn test_scan_type() {
+        let db = setup();
+        set(&db, "str".to_string(), "v".to_string()).await;
+        lists::rpush(&db, "list".to_string(), vec!["a".to_string()]).await;
+        zset::zadd(&db, "zset:1".to_string(), 1.0, "m".to_string());
+        zset::zadd(&db, "zset:2".to_string(), 1.0, "m".to_string());
+        for i in 0..20 {
+            set(&db, format!("key:{}", i), "v".to_string()).await;
+        }
+
+        let opts = ScanOptions {
+            key_type: Some("zset".to_string()),
+            count: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(scan_all(&db, opts), vec!["zset:1", "zset:2"]);
+        let opts = ScanOptions {
+            key_type: Some("LIST".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(scan_all(&db, opts), vec!["list"]);
+        let opts = ScanOptions {
+            pattern: Some("key:1*".to_string()),
+            key_type: Some("string".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(scan_all(&db, opts).len(), 11);
+        assert_eq!(scan_all(&db, ScanOptions::default()).len(), 24);
+
+        // A key held in both maps is still listed once
+        db.dict
+            .insert("zset:1".to_string(), RedisValue::String(Bytes::from("v")));
+        assert_eq!(scan_all(&db, ScanOptions::default()).len(), 24);
+    }
+
+    #[tokio::test]
+    async fn test_scan_while_inserting() {
+        let db = setup();
+        let original: Vec<String> = (0..50).map(|i| format!("key:{}", i)).collect();
+        for key in &original {
+            set(&db, key.clone(), "v".to_string()).await;
+        }
+
+        let opts = ScanOptions {
+            count: Some(5),
+            ..Default::default()
+        };
+        let mut seen = Vec::new();
+        let mut cursor = 0;
+        let mut added = 0;
+        loop {
+            let RedisValueRef::Array(reply) = scan(&db, cursor, opts.clone()) else {
+                panic!("SCAN should return an array");
+            };
+            let [RedisValueRef::String(next), RedisValueRef::Array(page)] = &reply[..] else {
+                panic!("SCAN should return a cursor and a page of keys");
+            };
+            seen.extend(page.iter().map(|key| key.to_string()));
+            cursor = String::from_utf8_lossy(next).parse().unwrap();
+            if cursor == 0 {
+                break;
+            }
+            // Grow the keyspace between every call
+            for _ in 0..10 {
+                set(&db, format!("new:{}", added), "v".to_string()).await;
+                added += 1;
+            }
+        }
+        for key in &original {
+            assert!(seen.contains(key), "{} was skipped", key);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dirty_counter() {
+        let db_file = format!("{}.rdb", uuid::Uuid::new_v4());
+        let db = Arc::new(RedisDb::new(None, "/tmp/redis-files", &db_file));
+        for i in 0..3 {
+            set(&db, "key".to_string(), i.to_string()).await;
+        }
+        assert_eq!(db.changes_since_last_save(), 3);
+
+        // Writes that change nothing don't count
+        expire(&db, "missing".to_string(), 10).await;
+        getex(&db, "key".to_string(), Some(GetExOption::Persist)).await;
+        incr(&db, "key".to_string()).await;
+        lists::lpop(&db, "missing".to_string(), None).await;
+        assert_eq!(db.changes_since_last_save(), 4);
+
+        save(&db);
+        assert_eq!(db.changes_since_last_save(), 0);
+        lists::rpush(
+            &db,
+            "list".to_string(),
+            vec!["a".to_string(), "b".to_string()],
+        )
+        .await;
+        assert_eq!(db.changes_since_last_save(), 2);
+        assert_eq!(db.dirty.load(Ordering::Relaxed), 6);
+        std::fs::remove_file(db.rdb_path()).unwrap();
+    }
+
+    #[test]
+    fn test_role() {
+        let db = setup();
+        assert_eq!(
+            role(&db),
+            RArray(vec![RString("master"), RInt(0), RArray(vec![])])
+        );
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        replication::register_replica(&db, tx, "10.0.0.2".to_string(), 6380);
+        db.replication_offset.store(42, Ordering::Relaxed);
+        let replica = RArray(vec![RString("10.0.0.2"), RString("6380"), RString("0")]);
+        assert_eq!(
+            role(&db),
+            RArray(vec![RString("master"), RInt(42), RArray(vec![replica])])
+        );
+
+        let replica = RedisDb::new(
+            Some(("master".to_string(), 6379)),
+            "/tmp/redis-files",
+            "dump.rdb",
+        );
+        let expected = vec![
+            RString("slave"),
+            RString("master"),
+            RInt(6379),
+            RString("connect"),
+            RInt(0),
+        ];
+        assert_eq!(role(&Arc::new(replica)), RArray(expected));
+    }
+
+    #[tokio::test]
+    async fn test_lastsave() {
+        let db_file = format!("{}.rdb", uuid::Uuid::new_v4());
+        let db = Arc::new(RedisDb::new(None, "/tmp/redis-files", &db_file));
+        db.rdb_last_save_time.store(0, Ordering::Relaxed);
+        assert_eq!(lastsave(&db), RInt(0));
+
+        set(&db, "key".to_string(), "value".to_string()).await;
+        assert_eq!(save(&db), RSimpleString("OK"));
+        let RedisValueRef::Int(saved) = lastsave(&db) else {
+            panic!("LASTSAVE should return an integer");
+        };
+        assert!(saved as u64 >= now_ms() / 1000 - 1);
+        assert!(db.rdb_path().exists());
+        std::fs::remove_file(db.rdb_path()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_info_sections() {
+        let db = setup();
+        let RedisValueRef::String(all) = info(&db, "all".to_string()).await else {
+            panic!("INFO should return a bulk string");
+        };
+        let all = String::from_utf8_lossy(&all);
+        assert!(all.contains("# Persistence\n"));
+        assert!(all.contains("aof_enabled:0\n"));
+        assert!(all.contains("# Replication\n"));
+        assert!(all.starts_with("# Server\n"));
+        assert!(all.contains(&format!("run_id:{}\n", db.run_id)));
+        assert!(all.contains("# Clients\nconnected_clients:0\nmaxclients:10000\n"));
+
+        let RedisValueRef::String(replication) = info(&db, "replication".to_string()).await else {
+            panic!("INFO should return a bulk string");
+        };
+        assert!(!String::from_utf8_lossy(&replication).contains("# Persistence"));
+    }
+
+    #[test]
+    fn test_ping() {
+        assert_eq!(ping(None), RSimpleString("PONG"));
+        assert_eq!(ping(Some("hello".to_string())), RString("hello"));
+    }
+
+    #[tokio::test]
+    async fn test_set_get() {
         let db = setup();
         let key = "key".to_string();
         let value = "value".to_string();
@@ -378,6 +2127,118 @@ mod tests {
         assert_eq!(result, RString("value"));
     }
 
+    #[test]
+    fn test_parse_memory_size() {
+        assert_eq!(parse_memory_size("100"), Ok(100));
+        assert_eq!(parse_memory_size("1k"), Ok(1000));
+        assert_eq!(parse_memory_size("1kb"), Ok(1024));
+        assert_eq!(parse_memory_size("512MB"), Ok(512 << 20));
+        assert_eq!(parse_memory_size("1gb"), Ok(1 << 30));
+        assert!(parse_memory_size("1tb").is_err());
+        assert!(parse_memory_size("kb").is_err());
+        assert!(parse_memory_size("-1k").is_err());
+    }
+
+    #[test]
+    fn test_config_get_pattern() {
+        let db = setup();
+        let get = |pattern: &str| config(&db, "get".to_string(), pattern.to_string());
+        assert_eq!(
+            get("max*"),
+            RArray(vec![
+                RString("maxclients"),
+                RString("10000"),
+                RString("maxmemory"),
+                RString("0"),
+                RString("maxmemory-policy"),
+                RString("noeviction"),
+            ])
+        );
+        assert_eq!(
+            get("DIR"),
+            RArray(vec![RString("dir"), RString("/tmp/redis-files")])
+        );
+        assert_eq!(get("nothing*"), RArray(vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_get_wrong_type() {
+        let db = setup();
+        let wrong_type =
+            RError("WRONGTYPE Operation against a key holding the wrong kind of value");
+        lists::rpush(&db, "list".to_string(), vec!["a".to_string()]).await;
+        assert_eq!(get(&db, "list".to_string()).await, wrong_type);
+        zset::zadd(&db, "zset".to_string(), 1.0, "m".to_string());
+        assert_eq!(get(&db, "zset".to_string()).await, wrong_type);
+    }
+
+    #[tokio::test]
+    async fn test_string_commands_wrong_type() {
+        let db = setup();
+        let wrong_type =
+            RError("WRONGTYPE Operation against a key holding the wrong kind of value");
+        lists::rpush(&db, "list".to_string(), vec!["a".to_string()]).await;
+        zset::zadd(&db, "zset".to_string(), 1.0, "m".to_string());
+        for key in ["list", "zset"] {
+            let key = key.to_string();
+            assert_eq!(incr(&db, key.clone()).await, wrong_type);
+            assert_eq!(append(&db, key.clone(), "b".to_string()).await, wrong_type);
+            assert_eq!(strlen(&db, key.clone()).await, wrong_type);
+            assert_eq!(getrange(&db, key.clone(), 0, -1).await, wrong_type);
+            assert_eq!(
+                setrange(&db, key.clone(), 0, "b".to_string()).await,
+                wrong_type
+            );
+            assert_eq!(getex(&db, key.clone(), None).await, wrong_type);
+            let persist = Some(GetExOption::Persist);
+            assert_eq!(getex(&db, key.clone(), persist).await, wrong_type);
+            assert_eq!(
+                set_get(&db, key.clone(), "b".to_string(), None).await,
+                wrong_type
+            );
+            let opts = LcsOptions::default();
+            assert_eq!(
+                lcs(&db, key.clone(), "missing".to_string(), opts),
+                wrong_type
+            );
+        }
+        // Neither key was replaced by a string
+        assert_eq!(type_of(&db, "list"), "list");
+        assert_eq!(type_of(&db, "zset"), "zset");
+        let result = lists::lrange(&db, "list".to_string(), 0, -1).await;
+        assert_eq!(result, RArray(vec![RString("a")]));
+    }
+
+    #[tokio::test]
+    async fn test_zset_key_is_not_shadowed() {
+        let db = setup();
+        let wrong_type =
+            RError("WRONGTYPE Operation against a key holding the wrong kind of value");
+        let key = || "zset".to_string();
+        let items = || vec!["a".to_string()];
+        zset::zadd(&db, key(), 1.0, "m".to_string());
+        let pairs = vec![("f".to_string(), "v".to_string())];
+        assert_eq!(hashes::hset(&db, key(), pairs.clone()), wrong_type);
+        assert_eq!(sets::sadd(&db, key(), items()), wrong_type);
+        assert_eq!(hll::pfadd(&db, key(), items()), wrong_type);
+        assert_eq!(hll::pfmerge(&db, key(), vec![]), wrong_type);
+        assert_eq!(lists::rpush(&db, key(), items()).await, wrong_type);
+        assert_eq!(lists::lpushx(&db, key(), items()).await, wrong_type);
+        let result = streams::xadd(&db, key(), (None, None), pairs, true).await;
+        assert_eq!(result, wrong_type);
+        assert!(db.dict.get("zset").is_none());
+
+        // ZADD doesn't shadow a value in the dict either
+        set(&db, "str".to_string(), "v".to_string()).await;
+        let result = zset::zadd(&db, "str".to_string(), 1.0, "m".to_string());
+        assert_eq!(result, wrong_type);
+
+        // SET replaces the zset rather than sitting beside it
+        set(&db, key(), "v".to_string()).await;
+        assert_eq!(type_of(&db, "zset"), "string");
+        assert_eq!(del(&db, vec![key()]), RInt(1));
+    }
+
     #[tokio::test]
     async fn test_get_set_expired() {
         let db = setup();
@@ -402,6 +2263,509 @@ mod tests {
         assert_eq!(result, RString("value"));
     }
 
+    #[tokio::test]
+    async fn test_expire() {
+        let db = setup();
+        let key = "key".to_string();
+        assert_eq!(expire(&db, key.clone(), 10).await, RInt(0));
+        assert!(!db.ttl.contains_key(&key));
+
+        set(&db, key.clone(), "value".to_string()).await;
+        assert_eq!(expire(&db, key.clone(), 10).await, RInt(1));
+        assert!(db.ttl.contains_key(&key));
+        assert_eq!(get(&db, key.clone()).await, RString("value"));
+
+        assert_eq!(expire(&db, key.clone(), -1).await, RInt(1));
+        assert!(db.dict.is_empty());
+        assert!(db.ttl.is_empty());
+        assert_eq!(expire(&db, key, -1).await, RInt(0));
+    }
+
+    #[tokio::test]
+    async fn test_set_clears_ttl() {
+        let db = setup();
+        let key = "key".to_string();
+        set_ex(&db, key.clone(), "v1".to_string(), 100_000).await;
+        assert!(db.ttl.contains_key(&key));
+
+        set(&db, key.clone(), "v2".to_string()).await;
+        assert!(!db.ttl.contains_key(&key));
+        assert_eq!(get(&db, key).await, RString("v2"));
+    }
+
+    #[tokio::test]
+    async fn test_expire_zset() {
+        let db = setup();
+        let key = "zset".to_string();
+        zset::zadd(&db, key.clone(), 1.0, "m".to_string());
+        assert_eq!(expire(&db, key.clone(), 10).await, RInt(1));
+        assert!(db.ttl.contains_key(&key));
+
+        // Once the TTL passes, zset commands see the key as gone
+        db.ttl.insert(key.clone(), now_ms() - 1);
+        assert_eq!(zset::zcard(&db, key.clone()), RInt(0));
+        assert!(db.zsets.lock().unwrap().is_empty());
+        assert!(db.ttl.is_empty());
+
+        zset::zadd(&db, key.clone(), 1.0, "m".to_string());
+        db.ttl.insert(key.clone(), now_ms() - 1);
+        assert_eq!(type_of(&db, &key), "none");
+        assert_eq!(expire(&db, key, 10).await, RInt(0));
+    }
+
+    #[tokio::test]
+    async fn test_expire_in_the_past() {
+        let db = setup();
+        let key = "key".to_string();
+        set(&db, key.clone(), "value".to_string()).await;
+        let reply = handle_command(&db, RedisCommand::Expire(key.clone(), -1)).await;
+        assert_eq!(reply, RInt(1));
+        assert!(!db.dict.contains_key(&key));
+        assert_eq!(get(&db, key.clone()).await, RNull());
+
+        set_ex(&db, key.clone(), "value".to_string(), 100_000).await;
+        let past = now_ms() as i64 - 1000;
+        assert_eq!(pexpireat(&db, key.clone(), past), RInt(1));
+        assert!(db.ttl.is_empty());
+        assert_eq!(get(&db, key.clone()).await, RNull());
+        assert_eq!(pexpireat(&db, key.clone(), past), RInt(0));
+
+        set(&db, key.clone(), "value".to_string()).await;
+        let future = now_ms() as i64 + 100_000;
+        assert_eq!(pexpireat(&db, key.clone(), future), RInt(1));
+        assert_eq!(db.ttl.get(&key).map(|at| *at), Some(future as u64));
+    }
+
+    #[tokio::test]
+    async fn test_getex() {
+        let db = setup();
+        let key = "key".to_string();
+        set_ex(&db, key.clone(), "value".to_string(), 100000).await;
+
+        let result = getex(&db, key.clone(), None).await;
+        assert_eq!(result, RString("value"));
+        assert!(db.ttl.contains_key(&key));
+
+        let result = getex(&db, key.clone(), Some(GetExOption::Persist)).await;
+        assert_eq!(result, RString("value"));
+        assert!(!db.ttl.contains_key(&key));
+
+        getex(&db, key.clone(), Some(GetExOption::Expire(1))).await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(get(&db, key.clone()).await, RNull());
+
+        let result = getex(&db, key.clone(), Some(GetExOption::Persist)).await;
+        assert_eq!(result, RNull());
+        assert!(!db.ttl.contains_key(&key));
+    }
+
+    #[tokio::test]
+    async fn test_append_to_integer() {
+        let db = setup();
+        let key = "k".to_string();
+        set(&db, key.clone(), "100".to_string()).await;
+        let result = append(&db, key.clone(), "abc".to_string()).await;
+        assert_eq!(result, RInt(6));
+        assert_eq!(get(&db, key.clone()).await, RString("100abc"));
+        assert_eq!(strlen(&db, key.clone()).await, RInt(6));
+
+        // Values written by INCR are stored as ASCII too
+        let key = "counter".to_string();
+        incr(&db, key.clone()).await;
+        incr(&db, key.clone()).await;
+        assert_eq!(append(&db, key.clone(), "0".to_string()).await, RInt(2));
+        assert_eq!(get(&db, key.clone()).await, RString("20"));
+        assert_eq!(incr(&db, key).await, RInt(21));
+    }
+
+    #[tokio::test]
+    async fn test_object_encoding_int() {
+        let db = setup();
+        let cases = [
+            ("12345", "int"),
+            ("-42", "int"),
+            ("007", "embstr"),
+            ("+5", "embstr"),
+            ("-0", "embstr"),
+            // Too big for 64 bits
+            ("99999999999999999999", "embstr"),
+        ];
+        for (value, expected) in cases {
+            set(&db, "k".to_string(), value.to_string()).await;
+            let encoding = object_encoding(&db, "k".to_string());
+            assert_eq!(encoding, RString(expected), "SET k {}", value);
+        }
+    }
+
+    #[test]
+    fn test_normalize_range() {
+        assert_eq!(normalize_range(0, -1, 5), Some((0, 4)));
+        assert_eq!(normalize_range(-2, 100, 5), Some((3, 4)));
+        assert_eq!(normalize_range(-100, 0, 5), Some((0, 0)));
+        assert_eq!(normalize_range(-100, -50, 5), None);
+        assert_eq!(normalize_range(5, 10, 5), None);
+        assert_eq!(normalize_range(3, 2, 5), None);
+        assert_eq!(normalize_range(0, -1, 0), None);
+    }
+
+    #[tokio::test]
+    async fn test_object_encoding_embstr_raw() {
+        let db = setup();
+        set(&db, "short".to_string(), "a".repeat(EMBSTR_SIZE_LIMIT)).await;
+        assert_eq!(object_encoding(&db, "short".to_string()), RString("embstr"));
+        set(&db, "long".to_string(), "a".repeat(EMBSTR_SIZE_LIMIT + 1)).await;
+        assert_eq!(object_encoding(&db, "long".to_string()), RString("raw"));
+    }
+
+    #[tokio::test]
+    async fn test_del_unlink() {
+        let db = setup();
+        let keys = || ["a", "b", "missing", "a"].map(String::from).to_vec();
+        for remove in [del, unlink] {
+            set(&db, "a".to_string(), "1".to_string()).await;
+            lists::rpush(&db, "b".to_string(), vec!["x".to_string(); 1000]).await;
+            // A repeated key only counts the first time
+            assert_eq!(remove(&db, keys()), RInt(2));
+            assert!(db.dict.is_empty());
+            assert_eq!(remove(&db, keys()), RInt(0));
+        }
+
+        zset::zadd(&db, "z".to_string(), 1.0, "m".to_string());
+        set_ex(&db, "expiring".to_string(), "v".to_string(), 100_000).await;
+        let keys = vec!["z".to_string(), "expiring".to_string()];
+        assert_eq!(unlink(&db, keys), RInt(2));
+        assert_eq!(type_of(&db, "z"), "none");
+        assert!(db.ttl.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_writes_not_propagated() {
+        let db = setup();
+        let offset = || db.replication_offset.load(Ordering::Relaxed);
+        let flags = ClientFlags::default();
+        lists::rpush(&db, "list".to_string(), vec!["a".to_string()]).await;
+        let reply = run_client_command(&db, RedisCommand::Incr("list".to_string()), flags, None);
+        assert!(matches!(reply.await, RedisValueRef::Error(_)));
+        assert_eq!(offset(), 0);
+
+        let set = RedisCommand::Set("k".to_string(), "v".to_string());
+        run_client_command(&db, set, flags, None).await;
+        assert!(offset() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_connection_commands_refused() {
+        let db = setup();
+        let reply = handle_command(&db, RedisCommand::Shutdown(Some(false))).await;
+        assert_eq!(reply, RError("ERR SHUTDOWN is not allowed in this context"));
+        let reply = handle_command(&db, RedisCommand::Reset).await;
+        assert_eq!(reply, RError("ERR RESET is not allowed in this context"));
+    }
+
+    #[tokio::test]
+    async fn test_flushdb() {
+        let db = setup();
+        for asynchronous in [true, false] {
+            set(&db, "a".to_string(), "1".to_string()).await;
+            set_ex(&db, "expiring".to_string(), "v".to_string(), 100_000).await;
+            lists::rpush(&db, "b".to_string(), vec!["x".to_string(); 1000]).await;
+            zset::zadd(&db, "z".to_string(), 1.0, "m".to_string());
+            assert_eq!(flushdb(&db, asynchronous), RSimpleString("OK"));
+            assert!(db.dict.is_empty());
+            assert!(db.ttl.is_empty());
+            assert_eq!(type_of(&db, "z"), "none");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lolwut() {
+        let db = setup();
+        for version in [None, Some(5)] {
+            let reply = handle_command(&db, RedisCommand::Lolwut(version)).await;
+            assert!(reply.to_string().contains("Redis ver. 7."));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_touch_resets_idletime() {
+        let db = setup();
+        let key = "k".to_string();
+        handle_command(&db, RedisCommand::Set(key.clone(), "v".to_string())).await;
+        assert_eq!(object_idletime(&db, key.clone()), RInt(0));
+
+        db.last_access.insert(key.clone(), now_ms() - 10_000);
+        // Looking at the idle time doesn't count as an access
+        let idletime = RedisCommand::ObjectIdleTime(key.clone());
+        assert_eq!(handle_command(&db, idletime.clone()).await, RInt(10));
+        assert_eq!(handle_command(&db, idletime.clone()).await, RInt(10));
+
+        let touch = RedisCommand::Touch(vec![key.clone(), "missing".to_string(), key.clone()]);
+        assert_eq!(handle_command(&db, touch).await, RInt(2));
+        assert_eq!(handle_command(&db, idletime).await, RInt(0));
+        assert!(!db.last_access.contains_key("missing"));
+        assert_eq!(object_idletime(&db, "missing".to_string()), RNull());
+    }
+
+    #[tokio::test]
+    async fn test_no_touch_keeps_idletime() {
+        let db = setup();
+        let key = "k".to_string();
+        let no_touch = ClientFlags {
+            no_touch: true,
+            ..Default::default()
+        };
+        set(&db, key.clone(), "v".to_string()).await;
+        db.last_access.insert(key.clone(), now_ms() - 10_000);
+
+        for _ in 0..2 {
+            let get = RedisCommand::Get(key.clone());
+            assert_eq!(
+                handle_client_command(&db, &get, no_touch).await,
+                RString("v")
+            );
+            assert_eq!(object_idletime(&db, key.clone()), RInt(10));
+        }
+
+        // TOUCH is still an access, as are commands from other clients
+        let touch = RedisCommand::Touch(vec![key.clone()]);
+        handle_client_command(&db, &touch, no_touch).await;
+        assert_eq!(object_idletime(&db, key.clone()), RInt(0));
+        db.last_access.insert(key.clone(), now_ms() - 10_000);
+        handle_command(&db, RedisCommand::Get(key.clone())).await;
+        assert_eq!(object_idletime(&db, key), RInt(0));
+    }
+
+    #[tokio::test]
+    async fn test_removed_keys_forget_access() {
+        let db = setup();
+        let keys = ["deleted", "expired", "replaced"].map(String::from);
+        for key in &keys {
+            set(&db, key.clone(), "v".to_string()).await;
+            db.last_access.insert(key.clone(), now_ms());
+            lfu::record(&db, key);
+        }
+        del(&db, vec![keys[0].clone()]);
+        db.ttl.insert(keys[1].clone(), now_ms() - 1);
+        db.active_expire_cycle();
+        set(&db, keys[2].clone(), "w".to_string()).await;
+        for key in &keys {
+            assert!(!db.last_access.contains_key(key), "{key}");
+            assert!(!db.lfu.contains_key(key), "{key}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_getrange() {
+        let db = setup();
+        let key = "k".to_string();
+        set(&db, key.clone(), "This is a string".to_string()).await;
+        let range = |start, end| getrange(&db, key.clone(), start, end);
+        assert_eq!(range(0, 3).await, RString("This"));
+        assert_eq!(range(0, -1).await, RString("This is a string"));
+        assert_eq!(range(-3, -1).await, RString("ing"));
+        assert_eq!(range(10, 100).await, RString("string"));
+        assert_eq!(range(-100, 3).await, RString("This"));
+    }
+
+    #[tokio::test]
+    async fn test_getrange_empty() {
+        let db = setup();
+        let key = "k".to_string();
+        set(&db, key.clone(), "hello".to_string()).await;
+        let range = |start, end| getrange(&db, key.clone(), start, end);
+        // Start past the end, start after end, both ends reversed before 0
+        assert_eq!(range(5, 10).await, RString(""));
+        assert_eq!(range(3, 1).await, RString(""));
+        assert_eq!(range(-1, -3).await, RString(""));
+        assert_eq!(range(-100, -50).await, RString("h"));
+        assert_eq!(
+            getrange(&db, "missing".to_string(), 0, -1).await,
+            RString("")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_setrange_missing_key() {
+        let db = setup();
+        let key = "new".to_string();
+        assert_eq!(
+            setrange(&db, key.clone(), 5, "x".to_string()).await,
+            RInt(6)
+        );
+        assert_eq!(get(&db, key).await, RString("\0\0\0\0\0x"));
+    }
+
+    #[tokio::test]
+    async fn test_setrange() {
+        let db = setup();
+        let key = "k".to_string();
+        set(&db, key.clone(), "Hello World".to_string()).await;
+        let result = setrange(&db, key.clone(), 6, "Redis".to_string()).await;
+        assert_eq!(result, RInt(11));
+        assert_eq!(get(&db, key).await, RString("Hello Redis"));
+
+        let key = "padded".to_string();
+        let result = setrange(&db, key.clone(), 3, "x".to_string()).await;
+        assert_eq!(result, RInt(4));
+        assert_eq!(get(&db, key).await, RString("\0\0\0x"));
+
+        let result = setrange(&db, "missing".to_string(), 5, "".to_string()).await;
+        assert_eq!(result, RInt(0));
+        assert!(!db.dict.contains_key("missing"));
+    }
+
+    #[tokio::test]
+    async fn test_set_get_option() {
+        let db = setup();
+        let key = "key".to_string();
+
+        let result = set_get(&db, key.clone(), "v1".to_string(), None).await;
+        assert_eq!(result, RNull());
+
+        let result = set_get(&db, key.clone(), "v2".to_string(), None).await;
+        assert_eq!(result, RString("v1"));
+
+        let result = get(&db, key.clone()).await;
+        assert_eq!(result, RString("v2"));
+
+        let at = now_ms() + 100_000;
+        let result = set_get(&db, key.clone(), "v3".to_string(), Some(SetExpiry::At(at))).await;
+        assert_eq!(result, RString("v2"));
+        assert_eq!(db.ttl.get(&key).map(|ttl| *ttl), Some(at));
+    }
+
+    #[tokio::test]
+    async fn test_set_get_option_wrong_type() {
+        let db = setup();
+        let key = "k".to_string();
+        lists::rpush(&db, key.clone(), vec!["a".to_string()]).await;
+
+        let result = set_get(&db, key.clone(), "v".to_string(), None).await;
+        assert!(matches!(result, RedisValueRef::Error(_)));
+
+        // The list must be left untouched
+        let result = lists::lrange(&db, key, 0, -1).await;
+        assert_eq!(result, RArray(vec![RString("a")]));
+    }
+
+    #[tokio::test]
+    async fn test_lcs() {
+        let db = setup();
+        set(&db, "key1".to_string(), "ohmytext".to_string()).await;
+        set(&db, "key2".to_string(), "mynewtext".to_string()).await;
+
+        let result = lcs(
+            &db,
+            "key1".to_string(),
+            "key2".to_string(),
+            LcsOptions::default(),
+        );
+        assert_eq!(result, RString("mytext"));
+
+        let opts = LcsOptions {
+            len: true,
+            ..Default::default()
+        };
+        let result = lcs(&db, "key1".to_string(), "key2".to_string(), opts);
+        assert_eq!(result, RInt(6));
+
+        let opts = LcsOptions {
+            idx: true,
+            ..Default::default()
+        };
+        let result = lcs(&db, "key1".to_string(), "key2".to_string(), opts);
+        let expected = RArray(vec![
+            RString("matches"),
+            RArray(vec![
+                RArray(vec![
+                    RArray(vec![RInt(4), RInt(7)]),
+                    RArray(vec![RInt(5), RInt(8)]),
+                ]),
+                RArray(vec![
+                    RArray(vec![RInt(2), RInt(3)]),
+                    RArray(vec![RInt(0), RInt(1)]),
+                ]),
+            ]),
+            RString("len"),
+            RInt(6),
+        ]);
+        assert_eq!(result, expected);
+
+        let opts = LcsOptions {
+            idx: true,
+            min_match_len: 4,
+            with_match_len: true,
+            ..Default::default()
+        };
+        let result = lcs(&db, "key1".to_string(), "key2".to_string(), opts);
+        let expected = RArray(vec![
+            RString("matches"),
+            RArray(vec![RArray(vec![
+                RArray(vec![RInt(4), RInt(7)]),
+                RArray(vec![RInt(5), RInt(8)]),
+                RInt(4),
+            ])]),
+            RString("len"),
+            RInt(6),
+        ]);
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    async fn test_lcs_long_strings() {
+        let mut db = RedisDb::new(None, "/tmp/redis-files", "dump.rdb");
+        db.proto_max_bulk_len = 1024;
+        let db = Arc::new(db);
+        let a: String = (0..300)
+            .map(|i| (b'a' + (i * 7 % 5) as u8) as char)
+            .collect();
+        let b: String = (0..200)
+            .map(|i| (b'a' + (i * 3 % 4) as u8) as char)
+            .collect();
+        set(&db, "a".to_string(), a.clone()).await;
+        set(&db, "b".to_string(), b.clone()).await;
+
+        let opts = LcsOptions {
+            len: true,
+            ..Default::default()
+        };
+        let RedisValueRef::Int(len) = lcs(&db, "a".to_string(), "b".to_string(), opts) else {
+            panic!("LEN should reply with an integer");
+        };
+        let RedisValueRef::String(result) =
+            lcs(&db, "a".to_string(), "b".to_string(), Default::default())
+        else {
+            panic!("LCS should reply with a string");
+        };
+        assert_eq!(result.len() as i64, len);
+        for value in [&a, &b] {
+            let mut rest = value.bytes();
+            assert!(result.iter().all(|&c| rest.any(|x| x == c)));
+        }
+
+        // The table IDX needs would be bigger than a bulk string may be
+        let opts = LcsOptions {
+            idx: true,
+            ..Default::default()
+        };
+        let result = lcs(&db, "a".to_string(), "b".to_string(), opts);
+        assert!(matches!(result, RedisValueRef::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_lcs_missing_key() {
+        let db = setup();
+        set(&db, "key1".to_string(), "ohmytext".to_string()).await;
+
+        let result = lcs(
+            &db,
+            "key1".to_string(),
+            "missing".to_string(),
+            LcsOptions::default(),
+        );
+        assert_eq!(result, RString(""));
+    }
+
     #[tokio::test]
     async fn test_type() {
         let db = setup();
@@ -467,7 +2831,7 @@ mod tests {
 
         // Test with list
         let stored_list =
-            RedisValue::List(VecDeque::from(vec![Bytes::from("a"), Bytes::from("b")]));
+            RedisValue::List(VecDeque::from(vec![Bytes::from("a"), Bytes::from("b")]).into());
         let protocol: RedisValueRef = (&stored_list).into();
         match protocol {
             RedisValueRef::Array(items) => assert_eq!(items.len(), 2),
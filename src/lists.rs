@@ -1,14 +1,113 @@
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 
+use crate::interpreter::RedisCommand;
 use crate::parser::{RArray, RError, RInt, RNull, RNullArray, RString, RedisValueRef};
-use crate::{Db, RedisValue, get};
+use crate::{Db, RedisValue, also_propagate, expect_no_zset, normalize_range};
 use bytes::Bytes;
 
+/// Lists default to the compact listpack encoding up to this many entries...
+pub const LIST_MAX_LISTPACK_ENTRIES: usize = 128;
+/// ...as long as every element is at most this many bytes
+pub const LIST_MAX_LISTPACK_VALUE: usize = 64;
+/// Default size above which an element gets a plain quicklist node (1GB)
+pub const QUICKLIST_PACKED_THRESHOLD: usize = 1 << 30;
+
+/// Size in bytes at which a quicklist node is full, matching the default
+/// `list-max-listpack-size -2`
+pub const QUICKLIST_NODE_MAX_BYTES: usize = 8192;
+
+/// List storage. Alongside the items it remembers whether the list has ever
+/// outgrown the listpack encoding, since a list doesn't convert back once it
+/// has become a quicklist.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct List {
+    items: VecDeque<Bytes>,
+    quicklist: bool,
+}
+
+impl List {
+    /// Record the switch to quicklist if the list is now too long or has an
+    /// element that is too large
+    pub fn update_encoding(&mut self, max_entries: usize, max_value: usize) {
+        if !self.quicklist {
+            self.quicklist = self.items.len() > max_entries
+                || self.items.iter().any(|item| item.len() > max_value);
+        }
+    }
+
+    /// How many quicklist nodes the items would be split across. A node
+    /// takes up to `max_entries` items or `QUICKLIST_NODE_MAX_BYTES`, and an
+    /// item of at least `packed_threshold` bytes gets a plain node of its own.
+    pub fn quicklist_nodes(&self, max_entries: usize, packed_threshold: usize) -> usize {
+        let mut nodes = 0;
+        let (mut entries, mut bytes) = (0, 0);
+        for item in &self.items {
+            let plain = item.len() >= packed_threshold;
+            let full = entries == max_entries || bytes + item.len() > QUICKLIST_NODE_MAX_BYTES;
+            if entries > 0 && (plain || full) {
+                nodes += 1;
+                (entries, bytes) = (0, 0);
+            }
+            if plain {
+                nodes += 1;
+            } else {
+                entries += 1;
+                bytes += item.len();
+            }
+        }
+        nodes + (entries > 0) as usize
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        if self.quicklist {
+            "quicklist"
+        } else {
+            "listpack"
+        }
+    }
+}
+
+impl Deref for List {
+    type Target = VecDeque<Bytes>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.items
+    }
+}
+
+impl DerefMut for List {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.items
+    }
+}
+
+impl From<VecDeque<Bytes>> for List {
+    fn from(items: VecDeque<Bytes>) -> Self {
+        List {
+            items,
+            quicklist: false,
+        }
+    }
+}
+
+impl From<Vec<Bytes>> for List {
+    fn from(items: Vec<Bytes>) -> Self {
+        VecDeque::from(items).into()
+    }
+}
+
 /// Pops n values where n is the number of waiters waiting
 /// and then notifies them with the value. Redis requires
 /// the ordering of waiters be left intact so this needs to
 /// be atomic.
-async fn notify_waiters(db: &Db, key: &str) {
+pub(crate) async fn notify_waiters(db: &Db, key: &str) {
+    // Fast path: with no client blocked anywhere there's nothing to hand out
+    if db.queued_waiters.load(Ordering::SeqCst) == 0 {
+        return;
+    }
     let (assignments, is_now_empty) = {
         let mut assignments = Vec::new();
         let mut waiters_guard = db.waiters.lock().unwrap();
@@ -22,6 +121,7 @@ async fn notify_waiters(db: &Db, key: &str) {
             while !list.is_empty() && !waiter_queue.is_empty() {
                 let value = list.pop_front().unwrap();
                 let tx = waiter_queue.pop_front().unwrap();
+                db.queued_waiters.fetch_sub(1, Ordering::SeqCst);
 
                 if !tx.is_closed() {
                     // Waiter is still alive, pair them
@@ -37,8 +137,10 @@ async fn notify_waiters(db: &Db, key: &str) {
         (assignments, is_now_empty)
     };
 
-    // Send each waiter their value
+    // Send each waiter their value. Replicas don't block, they just need the
+    // element gone, straight after the push that brought it.
     for (tx, value) in assignments {
+        also_propagate(RedisCommand::LPop(key.to_string(), None));
         let _ = tx.send(value);
     }
 
@@ -47,19 +149,51 @@ async fn notify_waiters(db: &Db, key: &str) {
     }
 }
 
-pub async fn rpush(db: &Db, key: String, value: Vec<String>) -> RedisValueRef {
-    let value: Vec<Bytes> = value.into_iter().map(Bytes::from).collect();
-    let result = match db.dict.get_mut(&key) {
+/// Push `values` onto the tail of the list at `key`, or onto its head with
+/// `front`. Without `create` a missing list stays missing. The type check and
+/// the push happen under the same entry guard.
+async fn push(
+    db: &Db,
+    key: String,
+    values: Vec<String>,
+    front: bool,
+    create: bool,
+) -> RedisValueRef {
+    let values = values.into_iter().map(Bytes::from);
+    // An expired list is dropped here so the push starts a fresh one
+    let result = match db.get_mut_if_valid(&key) {
         Some(mut entry) => match &mut *entry {
             RedisValue::List(list) => {
-                list.extend(value.clone());
+                let len = list.len();
+                if front {
+                    values.for_each(|value| list.push_front(value));
+                } else {
+                    list.extend(values);
+                }
+                db.add_dirty((list.len() - len) as u64);
+                db.update_list_encoding(list);
                 RInt(list.len() as i64)
             }
             _ => RError("Attempted to push to an array of the wrong type"),
         },
+        // RPUSHX/LPUSHX: nothing to push onto, and no waiters to wake
+        None if !create => {
+            return expect_no_zset(db, &key).map_or_else(|err| err, |()| RInt(0));
+        }
         None => {
-            let num_items = value.len() as i64;
-            db.dict.insert(key.clone(), RedisValue::List(value.into()));
+            if let Err(err) = expect_no_zset(db, &key) {
+                return err;
+            }
+            let mut list = List::default();
+            if front {
+                values.for_each(|value| list.push_front(value));
+            } else {
+                list.extend(values);
+            }
+            db.add_dirty(list.len() as u64);
+            db.update_list_encoding(&mut list);
+            let num_items = list.len() as i64;
+            db.dict.insert(key.clone(), RedisValue::List(list));
             RInt(num_items)
         }
     };
@@ -67,57 +201,31 @@ pub async fn rpush(db: &Db, key: String, value: Vec<String>) -> RedisValueRef {
     result
 }
 
+pub async fn rpush(db: &Db, key: String, value: Vec<String>) -> RedisValueRef {
+    push(db, key, value, false, true).await
+}
+
 pub async fn lpush(db: &Db, key: String, value: Vec<String>) -> RedisValueRef {
-    let value: Vec<Bytes> = value.into_iter().map(Bytes::from).collect();
-    let result = match db.dict.get_mut(&key) {
-        Some(mut entry) => match &mut *entry {
-            RedisValue::List(list) => {
-                for item in value.into_iter() {
-                    list.push_front(item);
-                }
-                RInt(list.len() as i64)
-            }
-            _ => RError("Attempted to push to an array of the wrong type"),
-        },
-        None => {
-            let num_items = value.len() as i64;
-            db.dict.insert(key.clone(), RedisValue::List(value.into()));
-            RInt(num_items)
-        }
-    };
-    notify_waiters(db, &key).await;
-    result
+    push(db, key, value, true, true).await
+}
+
+/// RPUSHX: RPUSH, but only onto a list that already exists
+pub async fn rpushx(db: &Db, key: String, value: Vec<String>) -> RedisValueRef {
+    push(db, key, value, false, false).await
+}
+
+/// LPUSHX: LPUSH, but only onto a list that already exists
+pub async fn lpushx(db: &Db, key: String, value: Vec<String>) -> RedisValueRef {
+    push(db, key, value, true, false).await
 }
 
 pub async fn lrange(db: &Db, key: String, start: i64, stop: i64) -> RedisValueRef {
     let bytes: Vec<Bytes> = match db.get_if_valid(&key) {
         Some(entry) => match &*entry {
-            RedisValue::List(list) => {
-                let list_len = list.len() as i64;
-                let start = if start < 0 && start.abs() >= list_len {
-                    0
-                } else if start < 0 {
-                    start + list_len
-                } else {
-                    start.min(list_len - 1)
-                };
-
-                let stop = if stop < 0 && stop.abs() >= list_len {
-                    0
-                } else if stop < 0 {
-                    stop + list_len
-                } else {
-                    stop.min(list_len - 1)
-                };
-
-                if start >= list_len || start > stop {
-                    vec![]
-                } else {
-                    list.range(start as usize..=stop as usize)
-                        .cloned()
-                        .collect()
-                }
-            }
+            RedisValue::List(list) => match normalize_range(start, stop, list.len()) {
+                Some((start, stop)) => list.range(start..=stop).cloned().collect(),
+                None => vec![],
+            },
             _ => vec![],
         },
         None => vec![],
@@ -141,17 +249,21 @@ pub async fn llen(db: &Db, key: String) -> RedisValueRef {
     }
 }
 
+/// LPOP key [count]. With no count the reply is the bare element; once a
+/// count is given it's always an array, even when that count is 1.
 pub async fn lpop(db: &Db, key: String, num_elements: Option<u64>) -> RedisValueRef {
     let key_string = key;
+    let count_was_explicit = num_elements.is_some();
     let result = {
         match db.get_mut_if_valid(&key_string) {
             Some(mut entry) => match &mut *entry {
                 RedisValue::List(list) if !list.is_empty() => {
                     let num_elements = (num_elements.unwrap_or(1) as usize).min(list.len());
                     let ret: Vec<Bytes> = list.drain(0..num_elements).collect();
+                    db.add_dirty(ret.len() as u64);
                     let is_now_empty = list.is_empty();
 
-                    let response = if ret.len() == 1 {
+                    let response = if !count_was_explicit {
                         RString(String::from_utf8_lossy(&ret[0]).to_string())
                     } else {
                         RArray(
@@ -176,41 +288,90 @@ pub async fn lpop(db: &Db, key: String, num_elements: Option<u64>) -> RedisValue
             response
         }
         Some((response, false)) => response,
+        None if count_was_explicit => RNullArray(),
         None => RNull(),
     }
 }
 
-// blocking lpop
+/// BLPOP timeouts are seconds with millisecond precision. Rounding rather
+/// than truncating absorbs float error (1.1 * 1000 isn't exactly 1100), and
+/// any positive timeout waits at least 1ms.
+fn timeout_duration(seconds: f64) -> Duration {
+    Duration::from_millis(((seconds * 1000.0).round() as u64).max(1))
+}
+
+/// A BLPOP's place in the queue. However the wait ends, timed out or with
+/// the client gone, its sender is taken back out when this is dropped so it
+/// doesn't stay counted in `queued_waiters`.
+struct Waiting<'a> {
+    db: &'a Db,
+    key: &'a str,
+    rx: tokio::sync::oneshot::Receiver<Bytes>,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.rx.close();
+        let mut waiters = self.db.waiters.lock().unwrap();
+        if let Some(queue) = waiters.get_mut(self.key) {
+            // Any other waiter that has given up goes too
+            let queued = queue.len();
+            queue.retain(|tx| !tx.is_closed());
+            self.db
+                .queued_waiters
+                .fetch_sub(queued - queue.len(), Ordering::SeqCst);
+            if queue.is_empty() {
+                waiters.remove(self.key);
+            }
+        }
+    }
+}
+
 pub async fn blpop(db: &Db, key: String, timeout: Option<f64>) -> RedisValueRef {
     let timeout = timeout.unwrap_or(0.0);
-    let exists = get(db, key.clone()).await;
-    match exists {
-        RedisValueRef::NullBulkString => {
-            let (tx, rx) = tokio::sync::oneshot::channel();
-            {
-                let mut waiters = db.waiters.lock().unwrap();
-                waiters.entry(key.clone()).or_default().push_back(tx);
-            }
-            let res = if timeout > 0.0 {
-                tokio::time::timeout(Duration::from_millis((timeout * 1000.0) as u64), rx)
-                    .await
-                    .ok()
-                    .and_then(Result::ok)
-            } else {
-                rx.await.ok()
-            };
-            match res {
-                Some(val) => RArray(vec![
-                    RString(key),
-                    RString(String::from_utf8_lossy(&val).to_string()),
-                ]),
-                None => RNullArray(),
+    if timeout.is_nan() || timeout.is_infinite() {
+        return RError("ERR timeout is not a float or out of range");
+    }
+    if timeout < 0.0 {
+        return RError("ERR timeout is negative");
+    }
+    // Only look at the type here: the guard mustn't be held across the pop
+    let is_list = match db.get_if_valid(&key) {
+        Some(entry) => match &*entry {
+            RedisValue::List(_) => true,
+            _ => {
+                return RError("WRONGTYPE Operation against a key holding the wrong kind of value");
             }
-        }
-        _ => {
-            let val = lpop(db, key.clone(), Some(1)).await;
-            RArray(vec![RString(key), val])
-        }
+        },
+        None => false,
+    };
+    if is_list {
+        let val = lpop(db, key.clone(), None).await;
+        also_propagate(RedisCommand::LPop(key.clone(), None));
+        return RArray(vec![RString(key), val]);
+    }
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    {
+        let mut waiters = db.waiters.lock().unwrap();
+        waiters.entry(key.clone()).or_default().push_back(tx);
+        db.queued_waiters.fetch_add(1, Ordering::SeqCst);
+    }
+    let mut waiting = Waiting { db, key: &key, rx };
+    let res = if timeout > 0.0 {
+        tokio::time::timeout(timeout_duration(timeout), &mut waiting.rx)
+            .await
+            .ok()
+            .and_then(Result::ok)
+    } else {
+        (&mut waiting.rx).await.ok()
+    };
+    drop(waiting);
+    match res {
+        Some(val) => RArray(vec![
+            RString(key),
+            RString(String::from_utf8_lossy(&val).to_string()),
+        ]),
+        None => RNullArray(),
     }
 }
 
@@ -226,6 +387,137 @@ mod tests {
         Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
     }
 
+    #[tokio::test]
+    async fn test_pushx_missing_key() {
+        let db = setup();
+        let key = "missing".to_string();
+        assert_eq!(
+            rpushx(&db, key.clone(), vec!["a".to_string()]).await,
+            RInt(0)
+        );
+        assert_eq!(
+            lpushx(&db, key.clone(), vec!["a".to_string()]).await,
+            RInt(0)
+        );
+        assert!(!db.dict.contains_key(&key));
+        assert_eq!(db.dirty.load(std::sync::atomic::Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_pushx_existing_list() {
+        let db = setup();
+        let key = "list".to_string();
+        rpush(&db, key.clone(), vec!["b".to_string()]).await;
+        assert_eq!(
+            rpushx(&db, key.clone(), vec!["c".to_string()]).await,
+            RInt(2)
+        );
+        assert_eq!(
+            lpushx(&db, key.clone(), vec!["a".to_string()]).await,
+            RInt(3)
+        );
+        assert_eq!(
+            lrange(&db, key, 0, -1).await,
+            RArray(vec![RString("a"), RString("b"), RString("c")])
+        );
+    }
+
+    #[test]
+    fn test_timeout_duration() {
+        assert_eq!(timeout_duration(0.1), Duration::from_millis(100));
+        assert_eq!(timeout_duration(1.1), Duration::from_millis(1100));
+        assert_eq!(timeout_duration(0.0001), Duration::from_millis(1));
+    }
+
+    #[tokio::test]
+    async fn test_blpop_timeout() {
+        let db = setup();
+        let start = tokio::time::Instant::now();
+        let result = blpop(&db, "empty".to_string(), Some(0.5)).await;
+        assert_eq!(result, RNullArray());
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(500), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(1000), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_blpop_available() {
+        let db = setup();
+        rpush(
+            &db,
+            "list".to_string(),
+            vec!["a".to_string(), "b".to_string()],
+        )
+        .await;
+        let start = tokio::time::Instant::now();
+        let result = blpop(&db, "list".to_string(), Some(1.0)).await;
+        assert_eq!(result, RArray(vec![RString("list"), RString("a")]));
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        set(&db, "str".to_string(), "value".to_string()).await;
+        assert_eq!(
+            blpop(&db, "str".to_string(), Some(1.0)).await,
+            RError("WRONGTYPE Operation against a key holding the wrong kind of value")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blpop_negative_timeout() {
+        let db = setup();
+        let result = blpop(&db, "empty".to_string(), Some(-1.0)).await;
+        assert_eq!(result, RError("ERR timeout is negative"));
+        assert!(db.waiters.lock().unwrap().get("empty").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_encoding() {
+        let db = setup();
+        let key = "key".to_string();
+        rpush(&db, key.clone(), vec!["a".to_string(); 128]).await;
+        assert_eq!(
+            crate::object_encoding(&db, key.clone()),
+            RString("listpack")
+        );
+
+        rpush(&db, key.clone(), vec!["a".to_string()]).await;
+        assert_eq!(
+            crate::object_encoding(&db, key.clone()),
+            RString("quicklist")
+        );
+        // Shrinking back under the threshold keeps the quicklist encoding
+        lpop(&db, key.clone(), Some(100)).await;
+        assert_eq!(
+            crate::object_encoding(&db, key.clone()),
+            RString("quicklist")
+        );
+
+        // A single oversized element is enough to switch
+        let key = "big".to_string();
+        lpush(&db, key.clone(), vec!["x".repeat(65)]).await;
+        assert_eq!(crate::object_encoding(&db, key), RString("quicklist"));
+    }
+
+    #[tokio::test]
+    async fn test_push_to_expired_list() {
+        let db = setup();
+        let key = "key".to_string();
+        rpush(&db, key.clone(), vec!["old".to_string()]).await;
+        // Backdate the TTL rather than waiting on a one second EXPIRE
+        crate::expire(&db, key.clone(), 1).await;
+        db.ttl.insert(key.clone(), 1);
+
+        let result = rpush(&db, key.clone(), vec!["new".to_string()]).await;
+        assert_eq!(result, RInt(1));
+        assert!(!db.ttl.contains_key(&key));
+        let result = lrange(&db, key.clone(), 0, -1).await;
+        assert_eq!(result, RArray(vec![RString("new")]));
+
+        crate::expire(&db, key.clone(), 1).await;
+        db.ttl.insert(key.clone(), 1);
+        let result = lpush(&db, key.clone(), vec!["newer".to_string()]).await;
+        assert_eq!(result, RInt(1));
+    }
+
     #[tokio::test]
     async fn test_rpush_new_list() {
         let db = setup();
@@ -240,8 +532,7 @@ mod tests {
         let result = rpush(&db, key.clone(), value2).await;
         assert_eq!(result, RInt(2));
 
-        // Get should return the list as an array
-        let result = get(&db, key).await;
+        let result = lrange(&db, key, 0, -1).await;
         let expected = RArray(vec![RString("value1"), RString("value2")]);
         assert_eq!(result, expected);
     }
@@ -274,8 +565,7 @@ mod tests {
         let result = rpush(&db, key.clone(), value).await;
         assert_eq!(result, RInt(2));
 
-        // Get should return the list as an array
-        let result = get(&db, key).await;
+        let result = lrange(&db, key, 0, -1).await;
         let expected = RArray(vec![RString("value1"), RString("value2")]);
         assert_eq!(result, expected);
     }
@@ -296,6 +586,12 @@ mod tests {
         let result = lrange(&db, key, 0, -1).await;
         let expected = RArray(vec![RString("a"), RString("b"), RString("c")]);
         assert_eq!(result, expected);
+
+        // A new list gets the values one at a time too
+        let value = vec!["a".to_string(), "b".to_string()];
+        lpush(&db, "new".to_string(), value).await;
+        let result = lrange(&db, "new".to_string(), 0, -1).await;
+        assert_eq!(result, RArray(vec![RString("b"), RString("a")]));
     }
 
     #[tokio::test]
@@ -353,9 +649,15 @@ mod tests {
         assert_eq!(result, RInt(5));
 
         // Matches example test on #RI1
-        let result = lrange(&db, key, 2, -1).await;
+        let result = lrange(&db, key.clone(), 2, -1).await;
         let expected = RArray(vec![RString("c"), RString("d"), RString("e")]);
         assert_eq!(result, expected);
+
+        // A start before the list clamps to 0, a stop before it leaves nothing
+        let result = lrange(&db, key.clone(), -100, 1).await;
+        assert_eq!(result, RArray(vec![RString("a"), RString("b")]));
+        let result = lrange(&db, key, -100, -50).await;
+        assert_eq!(result, RArray(vec![]));
     }
 
     #[tokio::test]
@@ -430,6 +732,24 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[tokio::test]
+    async fn test_lpop_explicit_count_one() {
+        let db = setup();
+        let key = "key".to_string();
+        rpush(&db, key.clone(), vec!["a".to_string(), "b".to_string()]).await;
+
+        // No count pops a bare element, an explicit count of 1 an array
+        assert_eq!(lpop(&db, key.clone(), None).await, RString("a"));
+        assert_eq!(
+            lpop(&db, key.clone(), Some(1)).await,
+            RArray(vec![RString("b")])
+        );
+
+        // A missing key is a null of the matching shape
+        assert_eq!(lpop(&db, key.clone(), None).await, RNull());
+        assert_eq!(lpop(&db, key, Some(1)).await, RNullArray());
+    }
+
     #[tokio::test]
     async fn test_blpop() {
         let db = setup();
@@ -459,4 +779,38 @@ mod tests {
             _ => panic!("Expected array result"),
         }
     }
+
+    #[tokio::test]
+    async fn test_given_up_waiters_leave_the_queue() {
+        let db = setup();
+        let key = "mylist".to_string();
+        assert_eq!(blpop(&db, key.clone(), Some(0.01)).await, RNullArray());
+        // The timed out waiter takes itself out of the queue
+        assert_eq!(db.queued_waiters.load(Ordering::SeqCst), 0);
+        assert!(db.waiters.lock().unwrap().is_empty());
+
+        // So does one whose client went away
+        let waiter = tokio::spawn({
+            let db = db.clone();
+            let key = key.clone();
+            async move { blpop(&db, key, None).await }
+        });
+        while db.queued_waiters.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        waiter.abort();
+        let _ = waiter.await;
+        assert_eq!(db.queued_waiters.load(Ordering::SeqCst), 0);
+
+        rpush(&db, key.clone(), vec!["value".to_string()]).await;
+        assert_eq!(db.queued_waiters.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            lrange(&db, key.clone(), 0, -1).await,
+            RArray(vec![RString("value")])
+        );
+        assert_eq!(
+            blpop(&db, key.clone(), Some(0.01)).await,
+            RArray(vec![RString("mylist"), RString("value")])
+        );
+    }
 }
@@ -0,0 +1,140 @@
+/// LZF, the compression Redis applies to long strings in RDB files. The
+/// stream is a series of chunks, each either a run of literal bytes or a
+/// back reference copying an earlier stretch of the output.
+const HASH_LOG: u32 = 14;
+/// Longest literal run one control byte can describe
+const MAX_LITERAL: usize = 1 << 5;
+/// Furthest back a reference can reach
+const MAX_OFFSET: usize = 1 << 13;
+/// Longest match a reference can describe
+const MAX_MATCH: usize = (1 << 8) + (1 << 3);
+/// Most output a byte of input can expand to: a three byte reference to the
+/// longest match
+const MAX_EXPANSION: usize = MAX_MATCH.div_ceil(3);
+
+fn hash(bytes: &[u8]) -> usize {
+    let v = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+    (v.wrapping_mul(2654435761) >> (32 - HASH_LOG)) as usize
+}
+
+fn flush_literals(out: &mut Vec<u8>, literals: &[u8]) {
+    for run in literals.chunks(MAX_LITERAL) {
+        out.push(run.len() as u8 - 1);
+        out.extend_from_slice(run);
+    }
+}
+
+/// Compress `input`, or None if that wouldn't make it any smaller
+pub fn compress(input: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut table = vec![usize::MAX; 1 << HASH_LOG];
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i + 2 < input.len() {
+        let slot = &mut table[hash(&input[i..])];
+        let candidate = std::mem::replace(slot, i);
+        let matches = candidate < i
+            && i - candidate <= MAX_OFFSET
+            && input[candidate..candidate + 3] == input[i..i + 3];
+        if !matches {
+            i += 1;
+            continue;
+        }
+
+        let max_len = MAX_MATCH.min(input.len() - i);
+        let mut len = 3;
+        while len < max_len && input[candidate + len] == input[i + len] {
+            len += 1;
+        }
+        flush_literals(&mut out, &input[literal_start..i]);
+
+        let offset = i - candidate - 1;
+        let encoded_len = len - 2;
+        if encoded_len < 7 {
+            out.push((encoded_len << 5 | offset >> 8) as u8);
+        } else {
+            out.push((7 << 5 | offset >> 8) as u8);
+            out.push((encoded_len - 7) as u8);
+        }
+        out.push(offset as u8);
+        i += len;
+        literal_start = i;
+    }
+    flush_literals(&mut out, &input[literal_start..]);
+    (out.len() < input.len()).then_some(out)
+}
+
+/// Expand `input` back to the `len` bytes it was compressed from. None if
+/// the data is malformed or doesn't come to that length.
+pub fn decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+    // `len` comes from the file, so only trust it as far as `input` could
+    // actually expand
+    let mut out = Vec::with_capacity(len.min(input.len().saturating_mul(MAX_EXPANSION)));
+    let mut i = 0;
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < MAX_LITERAL {
+            let run = input.get(i..i + ctrl + 1)?;
+            out.extend_from_slice(run);
+            i += ctrl + 1;
+            continue;
+        }
+
+        let mut match_len = ctrl >> 5;
+        if match_len == 7 {
+            match_len += *input.get(i)? as usize;
+            i += 1;
+        }
+        let offset = ((ctrl & 0x1f) << 8 | *input.get(i)? as usize) + 1;
+        i += 1;
+        let start = out.len().checked_sub(offset)?;
+        // References may overlap what they produce, so copy byte by byte
+        for j in 0..match_len + 2 {
+            out.push(out[start + j]);
+        }
+        if out.len() > len {
+            return None;
+        }
+    }
+    (out.len() == len).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let inputs: [&[u8]; 3] = [
+            &b"a".repeat(1000),
+            b"hello hello hello hello, said the parrot to the other parrot",
+            &(0..5000u32)
+                .flat_map(|i| (i % 251).to_le_bytes())
+                .collect::<Vec<_>>(),
+        ];
+        for input in inputs {
+            let compressed = compress(input).expect("compressible");
+            assert!(compressed.len() < input.len());
+            assert_eq!(decompress(&compressed, input.len()).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn test_incompressible() {
+        assert_eq!(compress(b"abcdefghijklmnopqrstuvwxyz"), None);
+        assert_eq!(compress(b""), None);
+    }
+
+    #[test]
+    fn test_decompress_malformed() {
+        // A reference before any output
+        assert_eq!(decompress(&[0x20, 0x00], 3), None);
+        // A literal run longer than the data
+        assert_eq!(decompress(&[0x05, b'a'], 6), None);
+        // Valid data but the wrong expected length
+        assert_eq!(decompress(&[0x00, b'a'], 2), None);
+        // A huge claimed length isn't allocated up front
+        assert_eq!(decompress(&[0x00, b'a'], usize::MAX), None);
+    }
+}
@@ -1,35 +1,88 @@
 use std::sync::Arc;
 
-use codecrafters_redis::auth;
 use codecrafters_redis::parser::RInt;
+use codecrafters_redis::pubsub::SubscriptionExit;
 use codecrafters_redis::replication::psync_preamble;
-use codecrafters_redis::{Db, RedisDb, Replica, handle_command, pubsub, replication};
+use codecrafters_redis::{
+    ClientFlags, Db, RedisDb, hello, lfu, parse_memory_size, pubsub, register_client, replication,
+    run_active_expire_loop, run_client_command, shutdown, tracking,
+};
+use codecrafters_redis::{aof, auth, commands};
 use codecrafters_redis::{
     interpreter::RedisCommand,
-    parser::{RArray, RError, RSimpleString, RespParser},
+    parser::{DEFAULT_MAX_ARRAY_LEN, RArray, RError, RNull, RSimpleString, RString, RespParser},
 };
 use futures::{SinkExt, StreamExt};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
 use tokio_util::codec::Decoder;
 
 async fn process(stream: TcpStream, db: Db) {
     tokio::spawn(async move {
-        let mut transport = RespParser.framed(stream);
+        let peer_ip = stream
+            .peer_addr()
+            .map_or_else(|_| "?".to_string(), |addr| addr.ip().to_string());
+        let parser = RespParser::new(db.proto_max_bulk_len, DEFAULT_MAX_ARRAY_LEN);
+        let mut transport = parser.framed(stream);
+        let Some(_client) = register_client(&db) else {
+            let resp = RError("ERR max number of clients reached");
+            let _ = transport.send(resp).await;
+            return;
+        };
         let mut in_transaction = false;
         let mut queued_commands: Vec<RedisCommand> = Vec::new();
         let mut authenticated = false;
-        while let Some(redis_value) = transport.next().await {
+        let mut protocol: u8 = 2;
+        let mut client_name: Option<String> = None;
+        let mut tracking = false;
+        // The port a replica says it listens on, reported by ROLE
+        let mut listening_port = 0;
+        let mut flags = ClientFlags::default();
+        let (mut invalidations_tx, mut invalidations_rx) = mpsc::unbounded_channel();
+        loop {
+            let redis_value = tokio::select! {
+                redis_value = transport.next() => match redis_value {
+                    Some(redis_value) => redis_value,
+                    None => break,
+                },
+                Some(key) = invalidations_rx.recv() => {
+                    // Pushes need RESP3, there's nowhere to deliver them on RESP2
+                    if protocol == 3 {
+                        let push = tracking::invalidation_message(key);
+                        transport.send(push).await.unwrap();
+                    }
+                    continue;
+                }
+            };
             match redis_value {
                 Ok(value) => match value.try_into() {
                     Ok(command) => {
+                        // QUIT needs no auth and isn't queued by MULTI
+                        if command == RedisCommand::Quit {
+                            let _ = transport.send(RSimpleString("OK")).await;
+                            break;
+                        }
                         if !authenticated && !auth::check_auth(&db, &command) {
-                            let resp = RError("NOAUTH Authentication required");
+                            let resp = if auth::credentials(&command).is_some() {
+                                RError(
+                                    "WRONGPASS invalid username-password pair or user is disabled.",
+                                )
+                            } else {
+                                RError("NOAUTH Authentication required")
+                            };
                             transport.send(resp).await.unwrap();
                             continue;
                         } else {
                             authenticated = true;
                         }
                         match command {
+                            _ if in_transaction && commands::is_no_multi(command.name()) => {
+                                let resp = RError(format!(
+                                    "ERR {} is not allowed in transactions",
+                                    command.name().to_uppercase()
+                                ));
+                                transport.send(resp).await.unwrap();
+                            }
                             RedisCommand::Multi => {
                                 if in_transaction {
                                     let resp = RError("ERR MULTI calls can not be nested");
@@ -46,7 +99,9 @@ async fn process(stream: TcpStream, db: Db) {
                                     in_transaction = false;
                                     let mut results = Vec::new();
                                     for cmd in queued_commands.drain(..) {
-                                        let result = handle_command(&db, cmd).await;
+                                        let tracker = tracking.then_some(&invalidations_tx);
+                                        let result =
+                                            run_client_command(&db, cmd, flags, tracker).await;
                                         results.push(result);
                                     }
 
@@ -68,7 +123,10 @@ async fn process(stream: TcpStream, db: Db) {
                                         .unwrap();
                                 }
                             }
-                            RedisCommand::ReplConf(_key, _value) => {
+                            RedisCommand::ReplConf(key, value) => {
+                                if key.eq_ignore_ascii_case("listening-port") {
+                                    listening_port = value.parse().unwrap_or(0);
+                                }
                                 let command = RSimpleString("OK");
                                 transport.send(command).await.unwrap();
                             }
@@ -77,16 +135,12 @@ async fn process(stream: TcpStream, db: Db) {
                                 let response = psync_preamble(&db, id_in, offset_in).await;
                                 transport.send(response).await.unwrap();
                                 let (tx, mut rx) = tokio::sync::mpsc::channel::<RedisCommand>(1024);
-                                let replica_id = uuid::Uuid::new_v4().to_string();
-                                let replica = Replica {
-                                    id: replica_id.clone(),
-                                    offset: 0,
+                                let replica_id = replication::register_replica(
+                                    &db,
                                     tx,
-                                };
-                                {
-                                    let mut replicas = db.replicating_to.lock().unwrap();
-                                    replicas.push(replica);
-                                }
+                                    peer_ip.clone(),
+                                    listening_port,
+                                );
                                 replication::run_psync_loop(
                                     &mut rx,
                                     &mut transport,
@@ -96,34 +150,118 @@ async fn process(stream: TcpStream, db: Db) {
                                 .await;
                             }
                             RedisCommand::Wait(replicas, timeout) => {
-                                let last_wait_offset = db
-                                    .replication_offset
-                                    .load(std::sync::atomic::Ordering::Relaxed);
-                                let command =
-                                    RedisCommand::ReplConf("GETACK".to_string(), "*".to_string());
-                                replication::broadcast_to_replicas(&db, command).await;
-
-                                let mut timeout_limit = 0;
-                                loop {
-                                    let cnt = db
-                                        .replicating_to
-                                        .lock()
-                                        .unwrap()
-                                        .iter()
-                                        .filter(|r| {
-                                            !r.tx.is_closed() && r.offset >= last_wait_offset
-                                        })
-                                        .count();
-                                    if cnt as u64 >= replicas || timeout_limit >= timeout {
-                                        transport.send(RInt(cnt as i64)).await.unwrap();
-                                        break;
+                                let cnt =
+                                    replication::wait_for_replicas(&db, replicas, timeout).await;
+                                transport.send(RInt(cnt as i64)).await.unwrap();
+                            }
+                            RedisCommand::WaitAof(numlocal, replicas, timeout) => {
+                                let resp = if db.replica_of.is_some() {
+                                    RError(
+                                        "ERR WAITAOF cannot be used with replica instances. Please also note that writes to replicas are just local and are not propagated.",
+                                    )
+                                } else if numlocal > 0 && !aof::is_enabled(&db) {
+                                    RError(
+                                        "ERR WAITAOF cannot be used when numlocal is set but appendonly is disabled.",
+                                    )
+                                } else {
+                                    let local = aof::fsync(&db) as i64;
+                                    let cnt =
+                                        replication::wait_for_replicas(&db, replicas, timeout)
+                                            .await;
+                                    RArray(vec![RInt(local), RInt(cnt as i64)])
+                                };
+                                transport.send(resp).await.unwrap();
+                            }
+                            RedisCommand::Hello(protover, credentials, name) => match protover {
+                                None | Some(2) | Some(3) => {
+                                    if let Some((username, password)) = credentials {
+                                        let result = auth::auth(&db, username, password);
+                                        if result != RSimpleString("OK") {
+                                            transport.send(result).await.unwrap();
+                                            continue;
+                                        }
                                     }
-                                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                                    timeout_limit += 100;
+                                    if name.is_some() {
+                                        client_name = name;
+                                    }
+                                    protocol = protover.unwrap_or(protocol as u64) as u8;
+                                    transport.send(hello(&db, protocol)).await.unwrap();
+                                }
+                                Some(_) => {
+                                    let resp = RError("NOPROTO unsupported protocol version");
+                                    transport.send(resp).await.unwrap();
+                                }
+                            },
+                            RedisCommand::ClientSetName(name) => {
+                                client_name = if name.is_empty() { None } else { Some(name) };
+                                transport.send(RSimpleString("OK")).await.unwrap();
+                            }
+                            RedisCommand::ClientGetName() => {
+                                let resp = match &client_name {
+                                    Some(name) => RString(name.clone()),
+                                    None => RNull(),
+                                };
+                                transport.send(resp).await.unwrap();
+                            }
+                            RedisCommand::ClientTracking(on) => {
+                                if !on && tracking {
+                                    // A fresh channel keeps anything still queued on the
+                                    // old one from being delivered
+                                    tracking::forget(&db, &invalidations_tx);
+                                    (invalidations_tx, invalidations_rx) =
+                                        mpsc::unbounded_channel();
+                                }
+                                tracking = on;
+                                transport.send(RSimpleString("OK")).await.unwrap();
+                            }
+                            RedisCommand::ClientNoEvict(on) => {
+                                flags.no_evict = on;
+                                transport.send(RSimpleString("OK")).await.unwrap();
+                            }
+                            RedisCommand::ClientNoTouch(on) => {
+                                flags.no_touch = on;
+                                transport.send(RSimpleString("OK")).await.unwrap();
+                            }
+                            RedisCommand::Reset => {
+                                in_transaction = false;
+                                queued_commands.clear();
+                                authenticated = false;
+                                protocol = 2;
+                                flags = ClientFlags::default();
+                                if tracking {
+                                    tracking = false;
+                                    tracking::forget(&db, &invalidations_tx);
+                                    (invalidations_tx, invalidations_rx) =
+                                        mpsc::unbounded_channel();
                                 }
+                                transport.send(RSimpleString("RESET")).await.unwrap();
                             }
-                            RedisCommand::Subscribe(channel) => {
-                                pubsub::subscription_loop(&db, &mut transport, channel).await;
+                            RedisCommand::Shutdown(save) => match shutdown(&db, save) {
+                                // No reply: the connection just closes
+                                Ok(()) => break,
+                                Err(resp) => transport.send(resp).await.unwrap(),
+                            },
+                            RedisCommand::Subscribe(channels) => {
+                                let exit = pubsub::subscription_loop(
+                                    &db,
+                                    &mut transport,
+                                    channels,
+                                    protocol,
+                                    flags,
+                                    tracking.then_some(&invalidations_tx),
+                                )
+                                .await;
+                                match exit {
+                                    SubscriptionExit::Reset => {
+                                        in_transaction = false;
+                                        queued_commands.clear();
+                                        authenticated = false;
+                                        protocol = 2;
+                                        flags = ClientFlags::default();
+                                        transport.send(RSimpleString("RESET")).await.unwrap();
+                                    }
+                                    SubscriptionExit::Closed => break,
+                                }
                             }
                             _ => {
                                 if in_transaction {
@@ -131,17 +269,10 @@ async fn process(stream: TcpStream, db: Db) {
                                     transport.send(RSimpleString("QUEUED")).await.unwrap();
                                 } else {
                                     println!("Master - Received command: {:?}", command);
-                                    if command.can_replicate() {
-                                        let command_bytes =
-                                            replication::command_bytes(command.clone()) as i64;
-                                        db.replication_offset.fetch_add(
-                                            command_bytes,
-                                            std::sync::atomic::Ordering::Relaxed,
-                                        );
-                                    }
-                                    let result = handle_command(&db, command.clone()).await;
+                                    let tracker = tracking.then_some(&invalidations_tx);
+                                    let result =
+                                        run_client_command(&db, command, flags, tracker).await;
                                     transport.send(result).await.unwrap();
-                                    replication::broadcast_to_replicas(&db, command).await;
                                 }
                             }
                         }
@@ -153,10 +284,17 @@ async fn process(stream: TcpStream, db: Db) {
                     }
                 },
                 Err(err) => {
+                    // The stream can't be resynchronised after a framing error, so reply and hang up
                     eprintln!("Error processing Redis command: {:?}", err);
+                    let resp = RError(format!("ERR Protocol error: {}", err));
+                    let _ = transport.send(resp).await;
+                    break;
                 }
             }
         }
+        if tracking {
+            tracking::forget(&db, &invalidations_tx);
+        }
     });
 }
 
@@ -190,20 +328,105 @@ async fn main() {
     } else {
         "dump.rdb"
     };
+    let proto_max_bulk_len = args
+        .iter()
+        .position(|arg| arg == "--proto-max-bulk-len")
+        .and_then(|pos| args.get(pos + 1))
+        .and_then(|len| len.parse().ok());
+    let maxclients = args
+        .iter()
+        .position(|arg| arg == "--maxclients")
+        .and_then(|pos| args.get(pos + 1))
+        .and_then(|max| max.parse().ok());
+    let maxmemory = args
+        .iter()
+        .position(|arg| arg == "--maxmemory")
+        .and_then(|pos| args.get(pos + 1))
+        .and_then(|size| parse_memory_size(size).ok());
+    let maxmemory_policy = args
+        .iter()
+        .position(|arg| arg == "--maxmemory-policy")
+        .and_then(|pos| args.get(pos + 1));
+    if let Some(policy) = maxmemory_policy
+        && !lfu::MAXMEMORY_POLICIES.contains(&policy.as_str())
+    {
+        eprintln!(
+            "Invalid --maxmemory-policy '{}', must be one of: {}",
+            policy,
+            lfu::MAXMEMORY_POLICIES.join(", ")
+        );
+        std::process::exit(1);
+    }
+    let slowlog_log_slower_than = args
+        .iter()
+        .position(|arg| arg == "--slowlog-log-slower-than")
+        .and_then(|pos| args.get(pos + 1))
+        .and_then(|us| us.parse().ok());
+    let appendonly = args
+        .iter()
+        .position(|arg| arg == "--appendonly")
+        .and_then(|pos| args.get(pos + 1))
+        .is_some_and(|value| value == "yes");
+    let aof_file = args
+        .iter()
+        .position(|arg| arg == "--appendfilename")
+        .and_then(|pos| args.get(pos + 1));
     println!("Starting server on port {}", port);
     let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
         .await
         .unwrap();
-    let db = Arc::new(RedisDb::new(replica_of, cfg_dir, db_file));
-    db.try_load_rdb().unwrap();
+    let mut db = RedisDb::new(replica_of, cfg_dir, db_file);
+    if let Some(proto_max_bulk_len) = proto_max_bulk_len {
+        db.proto_max_bulk_len = proto_max_bulk_len;
+    }
+    if let Some(maxclients) = maxclients {
+        db.maxclients = maxclients;
+    }
+    if let Some(maxmemory) = maxmemory {
+        db.maxmemory = maxmemory;
+    }
+    if let Some(maxmemory_policy) = maxmemory_policy {
+        db.maxmemory_policy = maxmemory_policy.clone();
+    }
+    if let Some(slowlog_log_slower_than) = slowlog_log_slower_than {
+        db.slowlog_log_slower_than.store(
+            slowlog_log_slower_than,
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+    if let Some(aof_file) = aof_file {
+        db.aof_file = aof_file.clone();
+    }
+    let db = Arc::new(db);
+    // The AOF is the more complete record, so it wins over the RDB when enabled
+    let aof_path = std::path::Path::new(&db.cfg_dir).join(&db.aof_file);
+    if appendonly && aof_path.exists() {
+        let cnt = aof::load(&db, &aof_path).await.unwrap();
+        println!("Replayed {} commands from {}", cnt, aof_path.display());
+    } else {
+        db.try_load_rdb().unwrap();
+    }
+    if appendonly {
+        aof::enable(&db, &aof_path).unwrap();
+    }
 
-    // Replication
+    // Replication. Replicas leave expiry to the master rather than evicting keys themselves.
     if let Some((master_addr, master_port)) = db.replica_of.clone() {
         replication::run_replica_loop(&db, master_addr, master_port, port).await;
+    } else {
+        run_active_expire_loop(&db);
     }
 
+    serve(listener, db).await;
+}
+
+/// Accept connections until SHUTDOWN stops the server
+async fn serve(listener: TcpListener, db: Db) {
     loop {
-        let stream = listener.accept().await;
+        let stream = tokio::select! {
+            stream = listener.accept() => stream,
+            _ = db.shutdown.notified() => break,
+        };
 
         match stream {
             Ok((stream, _)) => {
@@ -217,3 +440,383 @@ async fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codecrafters_redis::parser::RedisValueRef;
+    use tokio::io::AsyncWriteExt;
+    use tokio_util::codec::Framed;
+
+    async fn start_server() -> std::net::SocketAddr {
+        start_server_with(RedisDb::new(None, "/tmp/redis-files", "dump.rdb")).await
+    }
+
+    async fn start_server_with(db: RedisDb) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let db = Arc::new(db);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                process(stream, db.clone()).await;
+            }
+        });
+        addr
+    }
+
+    async fn connect(addr: std::net::SocketAddr) -> Framed<TcpStream, RespParser> {
+        RespParser::default().framed(TcpStream::connect(addr).await.unwrap())
+    }
+
+    async fn request(
+        client: &mut Framed<TcpStream, RespParser>,
+        args: &[&str],
+    ) -> Option<RedisValueRef> {
+        let command = RArray(args.iter().map(|a| RString(*a)).collect());
+        client.send(command).await.unwrap();
+        client.next().await.map(|r| r.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_quit() {
+        let addr = start_server().await;
+        let mut client = connect(addr).await;
+        request(&mut client, &["MULTI"]).await.unwrap();
+
+        let resp = request(&mut client, &["QUIT"]).await;
+        assert_eq!(resp, Some(RString("OK")));
+        // Anything sent after QUIT is never read
+        let command = RArray(vec![RString("PING")]);
+        let _ = client.send(command).await;
+        assert!(client.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_exec_discard_without_multi() {
+        let addr = start_server().await;
+        let mut client = connect(addr).await;
+        let resp = request(&mut client, &["EXEC"]).await;
+        assert_eq!(resp, Some(RError("ERR EXEC without MULTI")));
+        let resp = request(&mut client, &["DISCARD"]).await;
+        assert_eq!(resp, Some(RError("ERR DISCARD without MULTI")));
+
+        // Once a transaction has been run or discarded there is none left
+        request(&mut client, &["MULTI"]).await.unwrap();
+        let resp = request(&mut client, &["DISCARD"]).await;
+        assert_eq!(resp, Some(RString("OK")));
+        let resp = request(&mut client, &["EXEC"]).await;
+        assert_eq!(resp, Some(RError("ERR EXEC without MULTI")));
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_in_multi() {
+        let addr = start_server().await;
+        let mut client = connect(addr).await;
+        request(&mut client, &["MULTI"]).await.unwrap();
+
+        let resp = request(&mut client, &["SUBSCRIBE", "ch"]).await;
+        assert_eq!(
+            resp,
+            Some(RError("ERR SUBSCRIBE is not allowed in transactions"))
+        );
+        let resp = request(&mut client, &["PSUBSCRIBE", "c*"]).await;
+        assert_eq!(
+            resp,
+            Some(RError("ERR PSUBSCRIBE is not allowed in transactions"))
+        );
+        // The command table decides, so SAVE is refused too
+        let resp = request(&mut client, &["SAVE"]).await;
+        assert_eq!(
+            resp,
+            Some(RError("ERR SAVE is not allowed in transactions"))
+        );
+        // None was queued, only the PING runs
+        let resp = request(&mut client, &["PING"]).await;
+        assert_eq!(resp, Some(RString("QUEUED")));
+        let resp = request(&mut client, &["EXEC"]).await;
+        assert_eq!(resp, Some(RArray(vec![RString("PONG")])));
+    }
+
+    #[tokio::test]
+    async fn test_quit_in_subscribe_mode() {
+        let addr = start_server().await;
+        let mut client = connect(addr).await;
+        request(&mut client, &["SUBSCRIBE", "ch"]).await.unwrap();
+
+        let resp = request(&mut client, &["QUIT"]).await;
+        assert_eq!(resp, Some(RString("OK")));
+        // Server hangs up after QUIT
+        assert!(client.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_maxclients() {
+        let mut db = RedisDb::new(None, "/tmp/redis-files", "dump.rdb");
+        db.maxclients = 1;
+        let addr = start_server_with(db).await;
+        let mut first = connect(addr).await;
+        assert_eq!(request(&mut first, &["PING"]).await, Some(RString("PONG")));
+
+        let mut second = connect(addr).await;
+        let resp = second.next().await.map(|r| r.unwrap());
+        assert_eq!(resp, Some(RError("ERR max number of clients reached")));
+        assert!(second.next().await.is_none());
+
+        // The slot frees up once the first client goes away
+        drop(first);
+        for _ in 0..50 {
+            let mut client = connect(addr).await;
+            if request(&mut client, &["PING"]).await == Some(RString("PONG")) {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("connections were still refused after the first client left");
+    }
+
+    #[tokio::test]
+    async fn test_reset_in_subscribe_mode() {
+        let addr = start_server().await;
+        let mut client = connect(addr).await;
+        request(&mut client, &["SUBSCRIBE", "ch"]).await.unwrap();
+
+        let resp = request(&mut client, &["RESET"]).await;
+        assert_eq!(resp, Some(RString("RESET")));
+        // Back in normal mode PING gets a plain PONG instead of the subscribed form
+        let resp = request(&mut client, &["PING"]).await;
+        assert_eq!(resp, Some(RString("PONG")));
+    }
+
+    #[tokio::test]
+    async fn test_resp3_allows_commands_in_subscribe_mode() {
+        let addr = start_server().await;
+        let mut client = connect(addr).await;
+        let resp = request(&mut client, &["HELLO", "3"]).await;
+        assert!(matches!(resp, Some(RedisValueRef::Map(_))));
+        let resp = request(&mut client, &["SUBSCRIBE", "ch"]).await;
+        assert!(matches!(resp, Some(RedisValueRef::Push(_))));
+
+        let resp = request(&mut client, &["SET", "k", "v"]).await;
+        assert_eq!(resp, Some(RString("OK")));
+    }
+
+    #[tokio::test]
+    async fn test_resp2_rejects_commands_in_subscribe_mode() {
+        let addr = start_server().await;
+        let mut client = connect(addr).await;
+        request(&mut client, &["SUBSCRIBE", "ch"]).await.unwrap();
+
+        let resp = request(&mut client, &["SET", "k", "v"]).await;
+        assert!(matches!(resp, Some(RedisValueRef::Error(_))));
+    }
+
+    #[tokio::test]
+    async fn test_hello_auth_setname() {
+        let addr = start_server().await;
+        let mut admin = connect(addr).await;
+        request(&mut admin, &["ACL", "SETUSER", "default", ">pw"])
+            .await
+            .unwrap();
+
+        let mut client = connect(addr).await;
+        let resp = request(&mut client, &["HELLO", "3", "AUTH", "default", "nope"]).await;
+        assert!(matches!(resp, Some(RedisValueRef::Error(e)) if e.starts_with(b"WRONGPASS")));
+
+        let args = ["HELLO", "3", "AUTH", "default", "pw", "SETNAME", "app"];
+        let resp = request(&mut client, &args).await;
+        assert!(matches!(resp, Some(RedisValueRef::Map(_))));
+        let resp = request(&mut client, &["CLIENT", "GETNAME"]).await;
+        assert_eq!(resp, Some(RString("app")));
+    }
+
+    #[tokio::test]
+    async fn test_hello_unsupported_protocol() {
+        let addr = start_server().await;
+        let mut client = connect(addr).await;
+        let resp = request(&mut client, &["HELLO", "4"]).await;
+        assert_eq!(resp, Some(RError("NOPROTO unsupported protocol version")));
+
+        // Still RESP2: HELLO without a version replies with a flat array
+        let resp = request(&mut client, &["HELLO"]).await;
+        let Some(RedisValueRef::Array(fields)) = resp else {
+            panic!("expected an array, got {:?}", resp);
+        };
+        let proto = fields.iter().position(|field| *field == RString("proto"));
+        assert_eq!(fields[proto.unwrap() + 1], RInt(2));
+    }
+
+    #[tokio::test]
+    async fn test_protocol_error_closes_connection() {
+        let addr = start_server().await;
+        let mut client = connect(addr).await;
+        client.get_mut().write_all(b"$abc\r\n").await.unwrap();
+
+        let resp = client.next().await.map(|r| r.unwrap());
+        assert_eq!(resp, Some(RError("ERR Protocol error: invalid integer")));
+        assert!(client.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ping_message() {
+        let addr = start_server().await;
+        let mut client = connect(addr).await;
+        let resp = request(&mut client, &["PING", "hi"]).await;
+        assert_eq!(resp, Some(RString("hi")));
+
+        request(&mut client, &["SUBSCRIBE", "ch"]).await.unwrap();
+        let resp = request(&mut client, &["PING", "hi"]).await;
+        assert_eq!(resp, Some(RArray(vec![RString("pong"), RString("hi")])));
+        let resp = request(&mut client, &["PING"]).await;
+        assert_eq!(resp, Some(RArray(vec![RString("pong"), RString("")])));
+    }
+
+    #[tokio::test]
+    async fn test_debug_subcommands() {
+        let addr = start_server().await;
+        let mut client = connect(addr).await;
+        let resp = request(
+            &mut client,
+            &["DEBUG", "STRINGMATCH-LEN", "a*[0-9]", "abc7"],
+        )
+        .await;
+        assert_eq!(resp, Some(RInt(1)));
+        let resp = request(&mut client, &["DEBUG", "STRINGMATCH-LEN", "a?", "abc"]).await;
+        assert_eq!(resp, Some(RInt(0)));
+
+        let resp = request(&mut client, &["DEBUG", "QUICKLIST-PACKED-THRESHOLD", "2"]).await;
+        assert_eq!(resp, Some(RString("OK")));
+        request(&mut client, &["RPUSH", "list", "abc"])
+            .await
+            .unwrap();
+        let resp = request(&mut client, &["OBJECT", "ENCODING", "list"]).await;
+        assert_eq!(resp, Some(RString("quicklist")));
+    }
+
+    #[tokio::test]
+    async fn test_debug_sleep_doesnt_block_others() {
+        let addr = start_server().await;
+        let mut sleeper = connect(addr).await;
+        let mut other = connect(addr).await;
+        let command = RArray(vec![RString("DEBUG"), RString("SLEEP"), RString("1")]);
+        sleeper.send(command).await.unwrap();
+
+        let start = std::time::Instant::now();
+        let resp = request(&mut other, &["PING"]).await;
+        assert_eq!(resp, Some(RString("PONG")));
+        assert!(start.elapsed() < std::time::Duration::from_millis(500));
+
+        let resp = sleeper.next().await.map(|r| r.unwrap());
+        assert_eq!(resp, Some(RString("OK")));
+        assert!(start.elapsed() >= std::time::Duration::from_millis(900));
+    }
+
+    #[tokio::test]
+    async fn test_client_tracking_invalidation() {
+        let addr = start_server().await;
+        let mut reader = connect(addr).await;
+        let mut writer = connect(addr).await;
+        request(&mut writer, &["SET", "key", "v1"]).await.unwrap();
+
+        request(&mut reader, &["HELLO", "3"]).await.unwrap();
+        let resp = request(&mut reader, &["CLIENT", "TRACKING", "ON"]).await;
+        assert_eq!(resp, Some(RString("OK")));
+        let resp = request(&mut reader, &["GET", "key"]).await;
+        assert_eq!(resp, Some(RString("v1")));
+
+        request(&mut writer, &["SET", "key", "v2"]).await.unwrap();
+        let push = tokio::time::timeout(std::time::Duration::from_secs(1), reader.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            push,
+            RedisValueRef::Push(vec![RString("invalidate"), RArray(vec![RString("key")])])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_while_subscribed_is_replicated() {
+        let addr = start_server().await;
+        let mut client = connect(addr).await;
+        let mut other = connect(addr).await;
+        let offset = |info: Option<RedisValueRef>| -> u64 {
+            let Some(RedisValueRef::String(info)) = info else {
+                panic!("INFO should reply with a bulk string");
+            };
+            let info = String::from_utf8_lossy(&info).to_string();
+            info.lines()
+                .find_map(|line| line.strip_prefix("master_repl_offset:"))
+                .unwrap()
+                .parse()
+                .unwrap()
+        };
+        let before = offset(request(&mut other, &["INFO", "replication"]).await);
+
+        request(&mut client, &["HELLO", "3"]).await.unwrap();
+        request(&mut client, &["SUBSCRIBE", "ch"]).await.unwrap();
+        let resp = request(&mut client, &["SET", "key", "value"]).await;
+        assert_eq!(resp, Some(RString("OK")));
+
+        let after = offset(request(&mut other, &["INFO", "replication"]).await);
+        assert!(after > before);
+    }
+
+    #[tokio::test]
+    async fn test_waitaof_without_aof() {
+        let addr = start_server().await;
+        let mut client = connect(addr).await;
+        let resp = request(&mut client, &["WAITAOF", "1", "0", "0"]).await;
+        assert!(matches!(resp, Some(RedisValueRef::Error(_))));
+        let resp = request(&mut client, &["WAITAOF", "0", "0", "0"]).await;
+        assert_eq!(resp, Some(RArray(vec![RInt(0), RInt(0)])));
+    }
+
+    /// A server for test_shutdown_nosave to stop, in a process of its own so
+    /// shutting it down doesn't take the other tests with it
+    #[tokio::test]
+    #[ignore = "started as a child process by test_shutdown_nosave"]
+    async fn shutdown_child() {
+        let Ok(addr) = std::env::var("SHUTDOWN_CHILD_ADDR") else {
+            return;
+        };
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let db = Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"));
+        serve(listener, db).await;
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_nosave() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "tests::shutdown_child", "--ignored"])
+            .env("SHUTDOWN_CHILD_ADDR", addr.to_string())
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let mut client = None;
+        for _ in 0..100 {
+            if let Ok(stream) = TcpStream::connect(addr).await {
+                client = Some(RespParser::default().framed(stream));
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        let mut client = client.expect("child server should start");
+        assert_eq!(request(&mut client, &["PING"]).await, Some(RString("PONG")));
+
+        // No reply, the connection is just closed
+        assert_eq!(request(&mut client, &["SHUTDOWN", "NOSAVE"]).await, None);
+        let status = tokio::task::spawn_blocking(move || child.wait())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(status.success());
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+}
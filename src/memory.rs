@@ -0,0 +1,133 @@
+/// MEMORY subcommands. Sizes are estimates built from the length of the
+/// stored data plus a fixed overhead per object and per element, roughly in
+/// line with what Redis reports for small values.
+use crate::{
+    Db, RedisValue,
+    parser::{RInt, RNull, RString, RedisValueRef},
+    zset::ZSet,
+};
+
+/// Object header every value carries
+const OBJECT_OVERHEAD: usize = 16;
+/// Dictionary entry holding the key
+const KEY_OVERHEAD: usize = 24;
+/// Bookkeeping for each element of an aggregate (list item, hash field, ...)
+const ELEMENT_OVERHEAD: usize = 8;
+/// Stream entry IDs are two 64 bit integers
+const STREAM_ID_SIZE: usize = 16;
+
+/// Approximate bytes used by a value, excluding its key
+pub fn memory_usage(value: &RedisValue) -> usize {
+    let data: usize = match value {
+        RedisValue::String(value) => value.len(),
+        RedisValue::List(list) => list.iter().map(|item| item.len() + ELEMENT_OVERHEAD).sum(),
+        RedisValue::Stream(stream) => stream
+            .all()
+            .iter()
+            .map(|(_, data)| {
+                let fields: usize = data
+                    .iter()
+                    .map(|(field, value)| field.len() + value.len() + ELEMENT_OVERHEAD)
+                    .sum();
+                STREAM_ID_SIZE + fields
+            })
+            .sum(),
+        RedisValue::Hash(hash) => hash
+            .iter()
+            .map(|(field, value)| field.len() + value.len() + ELEMENT_OVERHEAD)
+            .sum(),
+        RedisValue::Set(set) => set
+            .iter()
+            .map(|member| member.len() + ELEMENT_OVERHEAD)
+            .sum(),
+    };
+    OBJECT_OVERHEAD + data
+}
+
+fn zset_usage(zset: &ZSet) -> usize {
+    let members: usize = zset
+        .members()
+        .map(|member| member.len() + size_of::<f64>() + ELEMENT_OVERHEAD)
+        .sum();
+    OBJECT_OVERHEAD + members
+}
+
+/// What MEMORY USAGE would report for every key, zsets included
+pub fn key_usages(db: &Db) -> Vec<(String, usize)> {
+    let key_usage = |key: &String, value_size| (key.clone(), KEY_OVERHEAD + key.len() + value_size);
+    let mut usages: Vec<(String, usize)> = db
+        .zsets
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(key, zset)| key_usage(key, zset_usage(zset)))
+        .collect();
+    usages.extend(
+        db.dict
+            .iter()
+            .map(|entry| key_usage(entry.key(), memory_usage(entry.value()))),
+    );
+    usages
+}
+
+/// MEMORY USAGE <key> [SAMPLES <count>]: approximate bytes used by the key
+/// and its value, or null if there is no such key. Every element is counted,
+/// so the sample count is accepted but not needed.
+pub fn usage(db: &Db, key: String, _samples: Option<usize>) -> RedisValueRef {
+    let value_size = match db.get_if_valid(&key) {
+        Some(entry) => memory_usage(&entry),
+        None => match db.zsets.lock().unwrap().get(&key) {
+            Some(zset) => zset_usage(zset),
+            None => return RNull(),
+        },
+    };
+    RInt((KEY_OVERHEAD + key.len() + value_size) as i64)
+}
+
+/// MEMORY DOCTOR: there's no allocator to inspect, so nothing to report
+pub fn doctor() -> RedisValueRef {
+    RString(
+        "Hi Sam, I can't find any memory issue in your instance. \
+         I can only account for what occurs on this base.",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{RedisDb, lists::rpush, set};
+
+    fn setup() -> Arc<RedisDb> {
+        Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
+    }
+
+    fn usage_of(db: &Db, key: &str) -> i64 {
+        match usage(db, key.to_string(), None) {
+            RedisValueRef::Int(bytes) => bytes,
+            other => panic!("MEMORY USAGE should return an integer, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_usage_string() {
+        let db = setup();
+        set(&db, "small".to_string(), "a".to_string()).await;
+        set(&db, "large".to_string(), "a".repeat(1000)).await;
+        assert!(usage_of(&db, "large") > usage_of(&db, "small"));
+        assert_eq!(usage_of(&db, "large") - usage_of(&db, "small"), 999);
+        assert_eq!(usage(&db, "missing".to_string(), None), RNull());
+    }
+
+    #[tokio::test]
+    async fn test_memory_usage_list() {
+        let db = setup();
+        rpush(&db, "one".to_string(), vec!["abc".to_string()]).await;
+        rpush(&db, "two".to_string(), vec!["abc".to_string(); 2]).await;
+        assert_eq!(
+            usage_of(&db, "two") - usage_of(&db, "one"),
+            (3 + ELEMENT_OVERHEAD) as i64
+        );
+    }
+}
@@ -6,6 +6,7 @@
 use bytes::{Bytes, BytesMut};
 use memchr::memchr;
 use std::{
+    cell::Cell,
     fmt::{Display, Formatter},
     io,
 };
@@ -16,6 +17,11 @@ pub const NULL_BULK_STRING: &str = "$-1\r\n";
 pub const NULL_ARRAY: &str = "*-1\r\n";
 pub const EMPTY_ARRAY: &str = "*0\r\n";
 
+/// Default `proto-max-bulk-len`: the largest bulk string a client may declare.
+pub const DEFAULT_MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+/// Default limit on the number of elements a client may declare for an array or map.
+pub const DEFAULT_MAX_ARRAY_LEN: usize = 1024 * 1024;
+
 // /// These types are used by state and ops to actually perform useful work.
 // pub type Value = Bytes;
 // /// Key is the standard type to index our structures
@@ -35,6 +41,8 @@ pub enum RedisValueRef {
     NullBulkString,
     RDBFile(Bytes),
     MultiValue(Vec<RedisValueRef>),
+    Map(Vec<(RedisValueRef, RedisValueRef)>), // RESP3 only
+    Push(Vec<RedisValueRef>),                 // RESP3 only
     ErrorMsg(Vec<u8>), // This is not a RESP type. This is an redis-oxide internal error type.
 }
 
@@ -103,6 +111,16 @@ pub fn RInt(i: i64) -> RedisValueRef {
     RedisValueRef::Int(i)
 }
 
+#[allow(non_snake_case)]
+pub fn RMap(pairs: Vec<(RedisValueRef, RedisValueRef)>) -> RedisValueRef {
+    RedisValueRef::Map(pairs)
+}
+
+#[allow(non_snake_case)]
+pub fn RPush(values: Vec<RedisValueRef>) -> RedisValueRef {
+    RedisValueRef::Push(values)
+}
+
 #[allow(non_snake_case)]
 pub fn RNull() -> RedisValueRef {
     RedisValueRef::NullBulkString
@@ -140,6 +158,24 @@ impl Display for RedisValueRef {
                     .collect::<Vec<String>>()
                     .join(", ")
             ),
+            RedisValueRef::Push(values) => write!(
+                f,
+                ">[{}]",
+                values
+                    .iter()
+                    .map(|v| format!("{}", v))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            RedisValueRef::Map(pairs) => write!(
+                f,
+                "{{{}}}",
+                pairs
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k, v))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
         }
     }
 }
@@ -175,6 +211,8 @@ enum RedisBufSplit {
     Error(BufSplit),
     Int(i64),
     Array(Vec<RedisBufSplit>),
+    Map(Vec<(RedisBufSplit, RedisBufSplit)>),
+    Push(Vec<RedisBufSplit>),
     NullArray,
     NullBulkString,
 }
@@ -188,6 +226,15 @@ impl RedisBufSplit {
             RedisBufSplit::Array(arr) => {
                 RedisValueRef::Array(arr.into_iter().map(|bfs| bfs.redis_value(buf)).collect())
             }
+            RedisBufSplit::Push(values) => {
+                RedisValueRef::Push(values.into_iter().map(|bfs| bfs.redis_value(buf)).collect())
+            }
+            RedisBufSplit::Map(pairs) => RedisValueRef::Map(
+                pairs
+                    .into_iter()
+                    .map(|(k, v)| (k.redis_value(buf), v.redis_value(buf)))
+                    .collect(),
+            ),
             RedisBufSplit::NullArray => RedisValueRef::NullArray,
             RedisBufSplit::NullBulkString => RedisValueRef::NullBulkString,
             RedisBufSplit::Int(i) => RedisValueRef::Int(i),
@@ -205,6 +252,21 @@ pub enum RESPError {
     BadArraySize(i64),
 }
 
+impl Display for RESPError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RESPError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            RESPError::UnknownStartingByte => write!(f, "unknown starting byte"),
+            RESPError::IOError(e) => write!(f, "{}", e),
+            RESPError::IntParseFailure => write!(f, "invalid integer"),
+            RESPError::BadBulkStringSize(_) => write!(f, "invalid bulk length"),
+            RESPError::BadArraySize(_) => write!(f, "invalid multibulk length"),
+        }
+    }
+}
+
+impl std::error::Error for RESPError {}
+
 impl From<std::io::Error> for RESPError {
     fn from(e: std::io::Error) -> RESPError {
         RESPError::IOError(e)
@@ -213,7 +275,7 @@ impl From<std::io::Error> for RESPError {
 
 type RedisResult = Result<Option<(usize, RedisBufSplit)>, RESPError>;
 
-fn parse(buf: &BytesMut, pos: usize) -> RedisResult {
+fn parse(parser: &RespParser, buf: &BytesMut, pos: usize) -> RedisResult {
     if buf.is_empty() {
         return Ok(None);
     }
@@ -221,9 +283,11 @@ fn parse(buf: &BytesMut, pos: usize) -> RedisResult {
     match buf[pos] {
         b'+' => simple_string(buf, pos + 1),
         b'-' => error(buf, pos + 1),
-        b'$' => bulk_string(buf, pos + 1),
+        b'$' => bulk_string(parser, buf, pos + 1),
         b':' => resp_int(buf, pos + 1),
-        b'*' => array(buf, pos + 1),
+        b'*' => array(parser, buf, pos + 1),
+        b'%' => map(parser, buf, pos + 1),
+        b'>' => push(parser, buf, pos + 1),
         _ => Err(RESPError::UnknownStartingByte),
     }
 }
@@ -272,17 +336,22 @@ fn resp_int(buf: &BytesMut, pos: usize) -> RedisResult {
     Ok(int(buf, pos)?.map(|(pos, int)| (pos, RedisBufSplit::Int(int))))
 }
 
-fn bulk_string(buf: &BytesMut, pos: usize) -> RedisResult {
+fn bulk_string(parser: &RespParser, buf: &BytesMut, pos: usize) -> RedisResult {
     // recall that the `pos` returned by `int` is the first index of the string content.
     match int(buf, pos)? {
         // special case: redis defines a NullBulkString type, with length of -1.
         Some((pos, -1)) => Ok(Some((pos, RedisBufSplit::NullBulkString))),
         // We have a size >= 0
+        // Reject sizes over the limit before waiting on (or buffering) that many bytes
+        Some((_pos, size)) if size as u64 > parser.max_bulk_len as u64 => {
+            Err(RESPError::BadBulkStringSize(size))
+        }
         Some((pos, size)) if size >= 0 => {
             // We trust the client here, and directly calculate the end index of string (absolute w.r.t pos)
             let total_size = pos + size as usize;
             // The client hasn't sent us enough bytes
             if buf.len() < total_size + 2 {
+                parser.expect_len(total_size + 2);
                 Ok(None)
             } else {
                 // We have enough bytes, so we can generate the correct type.
@@ -303,12 +372,16 @@ fn bulk_string(buf: &BytesMut, pos: usize) -> RedisResult {
     }
 }
 
-fn array(buf: &BytesMut, pos: usize) -> RedisResult {
+fn array(parser: &RespParser, buf: &BytesMut, pos: usize) -> RedisResult {
     match int(buf, pos)? {
         // Not enough bytes to determine the array size
         None => Ok(None),
         // special value: NullArray. Has size -1.
         Some((pos, -1)) => Ok(Some((pos, RedisBufSplit::NullArray))),
+        // Too many elements to preallocate for
+        Some((_pos, num_elements)) if num_elements as u64 > parser.max_array_len as u64 => {
+            Err(RESPError::BadArraySize(num_elements))
+        }
         // Happy path. We have a valid size (num_elements > 0)
         Some((pos, num_elements)) if num_elements >= 0 => {
             // As we're recieving a dynamic number of elements, we need to heap allocate our BufSplits.
@@ -317,7 +390,7 @@ fn array(buf: &BytesMut, pos: usize) -> RedisResult {
             let mut curr_pos = pos;
             for _ in 0..num_elements {
                 // Mutual Recursion! We need to parse the value at `curr_pos`
-                match parse(buf, curr_pos)? {
+                match parse(parser, buf, curr_pos)? {
                     // We got a value, so add it to the `values` vector and
                     // update `curr_pos`.
                     Some((new_pos, value)) => {
@@ -336,26 +409,104 @@ fn array(buf: &BytesMut, pos: usize) -> RedisResult {
     }
 }
 
-/// The struct we're using. We don't need to store anything in the struct.
-/// Later on we can expand this struct for optimization purposes.
-pub struct RespParser;
+/// RESP3 push: framed like an array, but marks out-of-band data such as
+/// invalidation messages rather than a reply
+fn push(parser: &RespParser, buf: &BytesMut, pos: usize) -> RedisResult {
+    Ok(array(parser, buf, pos)?.map(|(pos, value)| match value {
+        RedisBufSplit::Array(values) => (pos, RedisBufSplit::Push(values)),
+        other => (pos, other),
+    }))
+}
+
+/// RESP3 map: `%<num pairs>\r\n` followed by alternating keys and values
+fn map(parser: &RespParser, buf: &BytesMut, pos: usize) -> RedisResult {
+    match int(buf, pos)? {
+        None => Ok(None),
+        Some((_pos, num_pairs)) if num_pairs as u64 > parser.max_array_len as u64 => {
+            Err(RESPError::BadArraySize(num_pairs))
+        }
+        Some((pos, num_pairs)) if num_pairs >= 0 => {
+            let mut pairs = Vec::with_capacity(num_pairs as usize);
+            let mut curr_pos = pos;
+            for _ in 0..num_pairs {
+                let (new_pos, key) = match parse(parser, buf, curr_pos)? {
+                    Some(parsed) => parsed,
+                    None => return Ok(None),
+                };
+                let (new_pos, value) = match parse(parser, buf, new_pos)? {
+                    Some(parsed) => parsed,
+                    None => return Ok(None),
+                };
+                curr_pos = new_pos;
+                pairs.push((key, value));
+            }
+            Ok(Some((curr_pos, RedisBufSplit::Map(pairs))))
+        }
+        Some((_pos, bad_num_pairs)) => Err(RESPError::BadArraySize(bad_num_pairs)),
+    }
+}
+
+/// The codec framing RESP values on a connection, along with the size limits
+/// it enforces on what a peer may declare.
+#[derive(Debug, Clone)]
+pub struct RespParser {
+    pub max_bulk_len: usize,
+    pub max_array_len: usize,
+    /// The buffer length the frame being decoded is known to need, from a bulk
+    /// string header seen on an earlier attempt. Until that much has arrived
+    /// there's no point parsing again.
+    expected_len: Cell<usize>,
+}
+
+impl RespParser {
+    pub fn new(max_bulk_len: usize, max_array_len: usize) -> Self {
+        RespParser {
+            max_bulk_len,
+            max_array_len,
+            expected_len: Cell::new(0),
+        }
+    }
+
+    fn expect_len(&self, len: usize) {
+        self.expected_len.set(self.expected_len.get().max(len));
+    }
+}
+
+impl Default for RespParser {
+    fn default() -> Self {
+        RespParser::new(DEFAULT_MAX_BULK_LEN, DEFAULT_MAX_ARRAY_LEN)
+    }
+}
+
+/// Most the decoder reserves ahead of a partial frame
+const MAX_RESERVE: usize = 64 * 1024;
 
 impl Decoder for RespParser {
     type Item = RedisValueRef;
     type Error = RESPError;
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if buf.is_empty() {
+        if buf.is_empty() || buf.len() < self.expected_len.get() {
             return Ok(None);
         }
 
-        match parse(buf, 0)? {
+        match parse(self, buf, 0)? {
             Some((pos, value)) => {
+                self.expected_len.set(0);
                 // We parsed a value! Shave off the bytes so tokio can continue filling the buffer.
                 let our_data = buf.split_to(pos);
                 // Use `redis_value` defined above to get the correct type
                 Ok(Some(value.redis_value(&our_data.freeze())))
             }
-            None => Ok(None),
+            None => {
+                // Make room for more of a large bulk string than a single read
+                // brings, but only a chunk at a time: the declared length is
+                // unchecked until the bytes actually arrive
+                let expected = self.expected_len.get();
+                if expected > buf.len() {
+                    buf.reserve((expected - buf.len()).min(MAX_RESERVE));
+                }
+                Ok(None)
+            }
         }
     }
 }
@@ -417,6 +568,23 @@ pub fn write_redis_value(item: RedisValueRef, dst: &mut BytesMut) {
         RedisValueRef::MultiValue(values) => {
             values.into_iter().for_each(|v| write_redis_value(v, dst))
         }
+        RedisValueRef::Push(values) => {
+            dst.extend_from_slice(b">");
+            dst.extend_from_slice(values.len().to_string().as_bytes());
+            dst.extend_from_slice(b"\r\n");
+            for redis_value in values {
+                write_redis_value(redis_value, dst);
+            }
+        }
+        RedisValueRef::Map(pairs) => {
+            dst.extend_from_slice(b"%");
+            dst.extend_from_slice(pairs.len().to_string().as_bytes());
+            dst.extend_from_slice(b"\r\n");
+            for (key, value) in pairs {
+                write_redis_value(key, dst);
+                write_redis_value(value, dst);
+            }
+        }
     }
 }
 
@@ -426,7 +594,7 @@ mod tests {
 
     #[test]
     fn test_decode_ping() {
-        let mut parser = RespParser;
+        let mut parser = RespParser::default();
         let decoded = RArray(vec![RString("PING")]);
         let mut out = BytesMut::new();
         parser.encode(decoded, &mut out).unwrap();
@@ -436,7 +604,7 @@ mod tests {
     #[test]
     fn test_decode_echo_hey() {
         let mut encoded = BytesMut::from("*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n");
-        let mut parser = RespParser;
+        let mut parser = RespParser::default();
         let result = parser.decode(&mut encoded).unwrap();
 
         let expected = Some(RArray(vec![RString("ECHO"), RString("hey")]));
@@ -446,10 +614,88 @@ mod tests {
 
     #[test]
     fn test_encode_echo_hey() {
-        let mut parser = RespParser;
+        let mut parser = RespParser::default();
         let decoded = RArray(vec![RString("ECHO"), RString("hey")]);
         let mut out = BytesMut::new();
         parser.encode(decoded, &mut out).unwrap();
         assert_eq!(out, BytesMut::from("*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n"));
     }
+
+    #[test]
+    fn test_map_round_trip() {
+        let mut parser = RespParser::default();
+        let map = RMap(vec![(RString("proto"), RInt(3))]);
+        let mut out = BytesMut::new();
+        parser.encode(map.clone(), &mut out).unwrap();
+        assert_eq!(out, BytesMut::from("%1\r\n$5\r\nproto\r\n:3\r\n"));
+
+        let result = parser.decode(&mut out).unwrap();
+        assert_eq!(result, Some(map));
+    }
+
+    #[test]
+    fn test_push_round_trip() {
+        let mut parser = RespParser::default();
+        let push = RPush(vec![RString("invalidate"), RArray(vec![RString("key")])]);
+        let mut out = BytesMut::new();
+        parser.encode(push.clone(), &mut out).unwrap();
+        assert_eq!(
+            out,
+            BytesMut::from(">2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nkey\r\n")
+        );
+
+        let result = parser.decode(&mut out).unwrap();
+        assert_eq!(result, Some(push));
+    }
+
+    #[test]
+    fn test_partial_bulk_string() {
+        let mut parser = RespParser::default();
+        let mut buf = BytesMut::from("*2\r\n$4\r\nECHO\r\n$11\r\nhel");
+        assert_eq!(parser.decode(&mut buf).unwrap(), None);
+        assert_eq!(parser.expected_len.get(), buf.len() - 3 + 13);
+
+        buf.extend_from_slice(b"lo wor");
+        assert_eq!(parser.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"ld\r\n+OK\r\n");
+        let result = parser.decode(&mut buf).unwrap();
+        assert_eq!(
+            result,
+            Some(RArray(vec![RString("ECHO"), RString("hello world")]))
+        );
+        // The hint is cleared once the frame completes
+        assert_eq!(parser.expected_len.get(), 0);
+        assert_eq!(parser.decode(&mut buf).unwrap(), Some(RString("OK")));
+    }
+
+    #[test]
+    fn test_declared_length_not_reserved() {
+        let mut parser = RespParser::default();
+        let mut buf = BytesMut::from("$536870911\r\n");
+        assert_eq!(parser.decode(&mut buf).unwrap(), None);
+        assert!(buf.capacity() <= 2 * MAX_RESERVE);
+    }
+
+    #[test]
+    fn test_oversized_bulk_string() {
+        let mut parser = RespParser::new(16, DEFAULT_MAX_ARRAY_LEN);
+        let mut encoded = BytesMut::from("$17\r\n");
+        let result = parser.decode(&mut encoded);
+        assert!(matches!(result, Err(RESPError::BadBulkStringSize(17))));
+
+        let mut encoded = BytesMut::from("$9223372036854775807\r\n");
+        let result = RespParser::default().decode(&mut encoded);
+        assert!(matches!(result, Err(RESPError::BadBulkStringSize(_))));
+    }
+
+    #[test]
+    fn test_oversized_array() {
+        let mut encoded = BytesMut::from("*1000000000\r\n");
+        let result = RespParser::default().decode(&mut encoded);
+        assert!(matches!(result, Err(RESPError::BadArraySize(1000000000))));
+
+        let mut encoded = BytesMut::from("%1000000000\r\n");
+        let result = RespParser::default().decode(&mut encoded);
+        assert!(matches!(result, Err(RESPError::BadArraySize(1000000000))));
+    }
 }
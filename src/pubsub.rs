@@ -2,60 +2,76 @@ use futures::{SinkExt, StreamExt};
 use tokio::net::TcpStream;
 use tokio_stream::StreamMap;
 use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
 use tokio_util::codec::Framed;
 
 use crate::{
-    Db,
+    ClientFlags, Db,
+    glob::glob_match,
     interpreter::RedisCommand,
-    parser::{RArray, RError, RInt, RString, RedisValueRef, RespParser},
+    parser::{RArray, RError, RInt, RPush, RSimpleString, RString, RedisValueRef, RespParser},
+    run_client_command,
+    tracking::Invalidations,
 };
 
 type Subscriptions = StreamMap<String, BroadcastStream<RedisValueRef>>;
 
+/// How a connection left subscribed mode
+#[derive(Debug, PartialEq)]
+pub enum SubscriptionExit {
+    /// RESET was received, the connection returns to normal mode
+    Reset,
+    /// QUIT was received or the client went away
+    Closed,
+}
+
 pub async fn subscription_loop(
     db: &Db,
     transport: &mut Framed<TcpStream, RespParser>,
-    channel: String,
-) {
+    channels: Vec<String>,
+    protocol: u8,
+    flags: ClientFlags,
+    tracker: Option<&Invalidations>,
+) -> SubscriptionExit {
     let mut subscriptions: Subscriptions = StreamMap::new();
-    let resp = subscribe(db, channel, &mut subscriptions).await;
+    let resp = subscribe_channels(db, channels, &mut subscriptions, protocol).await;
     transport.send(resp).await.unwrap();
 
-    loop {
+    let exit = loop {
         tokio::select! {
             Some((channel, result)) = subscriptions.next() => {
-                match result {
-                    Ok(message) => {
-                        transport.send(RArray(vec![
-                            RString("message"),
-                            RString(channel),
-                            message
-                        ])).await.unwrap();
-                    }
-                    Err(err) => {
-                        transport.send(RString(format!("Error: {}", err))).await.unwrap();
-                    }
+                if let Some(frame) = delivery(channel, result, protocol) {
+                    transport.send(frame).await.unwrap();
                 }
             }
             // gather all subscriptions and wait for the next message from any of them
-            Some(result) = transport.next() => {
+            result = transport.next() => {
                 match result {
-                    Ok(value) => {
+                    Some(Ok(value)) => {
                         let command: Result<RedisCommand, _> = value.try_into();
                         let resp = match command {
-                            Ok(RedisCommand::Subscribe(channel)) => {
-                                subscribe(db, channel, &mut subscriptions).await
+                            Ok(RedisCommand::Subscribe(channels)) => {
+                                subscribe_channels(db, channels, &mut subscriptions, protocol).await
                             }
                             Ok(RedisCommand::Unsubscribe(channel)) => {
-                                unsubscribe(db, channel, &mut subscriptions).await
+                                push_frame(unsubscribe(db, channel, &mut subscriptions).await, protocol)
                             }
                             Ok(RedisCommand::PSubscribe(pattern)) => {
-                                punsubscribe(db, pattern, &mut subscriptions).await
+                                push_frame(punsubscribe(db, pattern, &mut subscriptions).await, protocol)
                             }
                             Ok(RedisCommand::PUnsubscribe(pattern)) => {
-                                punsubscribe(db, pattern, &mut subscriptions).await
+                                push_frame(punsubscribe(db, pattern, &mut subscriptions).await, protocol)
+                            }
+                            Ok(RedisCommand::Ping(message)) => ping(message),
+                            Ok(RedisCommand::Reset) => break SubscriptionExit::Reset,
+                            Ok(RedisCommand::Quit) => {
+                                transport.send(RSimpleString("OK")).await.unwrap();
+                                break SubscriptionExit::Closed;
+                            }
+                            // RESP3 connections can interleave regular commands with pushes
+                            Ok(other_command) if protocol == 3 && allowed_in_resp3(&other_command) => {
+                                run_client_command(db, other_command, flags, tracker).await
                             }
-                            Ok(RedisCommand::Ping) => ping(),
                             Ok(other_command) => RError(format!(
                                 "ERR Can't execute {} in subscribed mode",
                                 other_command
@@ -67,17 +83,80 @@ pub async fn subscription_loop(
                         };
                         transport.send(resp).await.unwrap();
                     }
-                    Err(e) => {
+                    Some(Err(e)) => {
                         eprintln!("Error reading from transport: {:?}", e);
-                        break;
+                        break SubscriptionExit::Closed;
                     }
+                    None => break SubscriptionExit::Closed,
                 };
 
             }
         }
+    };
+    let channels: Vec<String> = subscriptions.keys().cloned().collect();
+    drop(subscriptions);
+    release_channels(db, channels);
+    exit
+}
+
+/// RESP3 clients get subscription events as push frames, so they can tell
+/// them apart from command replies. RESP2 gets plain arrays.
+fn push_frame(value: RedisValueRef, protocol: u8) -> RedisValueRef {
+    match value {
+        RedisValueRef::Array(values) if protocol == 3 => RPush(values),
+        other => other,
     }
 }
 
+fn message_frame(channel: String, message: RedisValueRef, protocol: u8) -> RedisValueRef {
+    push_frame(
+        RArray(vec![RString("message"), RString(channel), message]),
+        protocol,
+    )
+}
+
+/// The frame to send for an item from a channel. A subscriber that falls
+/// more than the channel's capacity behind loses the oldest messages, which
+/// the stream reports as a lag. Dropping pub/sub messages is allowed, so the
+/// lag is only logged and delivery carries on with the next message.
+fn delivery(
+    channel: String,
+    result: Result<RedisValueRef, BroadcastStreamRecvError>,
+    protocol: u8,
+) -> Option<RedisValueRef> {
+    match result {
+        Ok(message) => Some(message_frame(channel, message, protocol)),
+        Err(BroadcastStreamRecvError::Lagged(missed)) => {
+            eprintln!(
+                "Subscriber to {} lagged, {} messages dropped",
+                channel, missed
+            );
+            None
+        }
+    }
+}
+
+/// Commands that manage connection state can't be run from inside subscribed mode
+fn allowed_in_resp3(command: &RedisCommand) -> bool {
+    !matches!(
+        command,
+        RedisCommand::Multi
+            | RedisCommand::Exec
+            | RedisCommand::Discard
+            | RedisCommand::ReplConf(_, _)
+            | RedisCommand::Psync(_, _)
+            | RedisCommand::Wait(_, _)
+            | RedisCommand::WaitAof(_, _, _)
+            | RedisCommand::Hello(_, _, _)
+            | RedisCommand::ClientSetName(_)
+            | RedisCommand::ClientGetName()
+            | RedisCommand::ClientTracking(_)
+            | RedisCommand::ClientNoEvict(_)
+            | RedisCommand::ClientNoTouch(_)
+            | RedisCommand::Shutdown(_)
+    )
+}
+
 pub async fn subscribe(
     db: &Db,
     channel: String,
@@ -112,13 +191,44 @@ pub async fn subscribe(
     ])
 }
 
+/// SUBSCRIBE to several channels at once. Each channel gets its own
+/// confirmation, carrying the subscription count after that channel.
+pub async fn subscribe_channels(
+    db: &Db,
+    channels: Vec<String>,
+    subscriptions: &mut Subscriptions,
+    protocol: u8,
+) -> RedisValueRef {
+    let mut replies = Vec::with_capacity(channels.len());
+    for channel in channels {
+        let reply = subscribe(db, channel, subscriptions).await;
+        replies.push(push_frame(reply, protocol));
+    }
+    RedisValueRef::MultiValue(replies)
+}
+
+/// Drop the senders of channels nobody is listening on any more. Receivers
+/// are only created while holding the pubsub lock, so a count of zero here
+/// can't race with a subscriber picking up the sender we remove.
+fn release_channels(db: &Db, channels: impl IntoIterator<Item = String>) {
+    let mut pubsub = db.pubsub.lock().unwrap();
+    for channel in channels {
+        if pubsub
+            .get(&channel)
+            .is_some_and(|tx| tx.receiver_count() == 0)
+        {
+            pubsub.remove(&channel);
+        }
+    }
+}
+
 pub async fn unsubscribe(
-    _db: &Db,
+    db: &Db,
     channel: String,
     subscriptions: &mut Subscriptions,
 ) -> RedisValueRef {
-    if subscriptions.contains_key(&channel) {
-        subscriptions.remove(&channel);
+    if subscriptions.remove(&channel).is_some() {
+        release_channels(db, [channel.clone()]);
     }
     RArray(vec![
         RString("unsubscribe"),
@@ -143,8 +253,8 @@ pub async fn punsubscribe(
     RString("OK")
 }
 
-pub fn ping() -> RedisValueRef {
-    RArray(vec![RString("pong"), RString("")])
+pub fn ping(message: Option<String>) -> RedisValueRef {
+    RArray(vec![RString("pong"), RString(message.unwrap_or_default())])
 }
 
 pub async fn publish(db: &Db, channel: String, message: String) -> RedisValueRef {
@@ -157,3 +267,144 @@ pub async fn publish(db: &Db, channel: String, message: String) -> RedisValueRef
         RInt(0)
     }
 }
+
+/// PUBSUB CHANNELS [pattern]: channels with at least one subscriber
+pub fn channels(db: &Db, pattern: Option<String>) -> RedisValueRef {
+    let pubsub = db.pubsub.lock().unwrap();
+    let mut channels: Vec<&String> = pubsub
+        .iter()
+        .filter(|(_, tx)| tx.receiver_count() > 0)
+        .map(|(channel, _)| channel)
+        .filter(|channel| {
+            pattern
+                .as_ref()
+                .is_none_or(|pattern| glob_match(pattern.as_bytes(), channel.as_bytes(), false))
+        })
+        .collect();
+    channels.sort();
+    RArray(channels.into_iter().map(RString).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RedisDb;
+    use bytes::BytesMut;
+    use std::sync::Arc;
+    use tokio_util::codec::Encoder;
+
+    fn encode(value: RedisValueRef) -> BytesMut {
+        let mut out = BytesMut::new();
+        RespParser::default().encode(value, &mut out).unwrap();
+        out
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_multiple_channels() {
+        let db = Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"));
+        let mut subscriptions = StreamMap::new();
+        let channels = vec!["a".to_string(), "b".to_string()];
+        let resp = subscribe_channels(&db, channels, &mut subscriptions, 2).await;
+        assert_eq!(
+            resp,
+            RedisValueRef::MultiValue(vec![
+                RArray(vec![RString("subscribe"), RString("a"), RInt(1)]),
+                RArray(vec![RString("subscribe"), RString("b"), RInt(2)]),
+            ])
+        );
+        assert_eq!(
+            encode(resp),
+            BytesMut::from(
+                "*3\r\n$9\r\nsubscribe\r\n$1\r\na\r\n:1\r\n\
+                 *3\r\n$9\r\nsubscribe\r\n$1\r\nb\r\n:2\r\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_message_frame() {
+        let resp3 = message_frame("ch".to_string(), RString("hi"), 3);
+        assert_eq!(
+            encode(resp3),
+            BytesMut::from(">3\r\n$7\r\nmessage\r\n$2\r\nch\r\n$2\r\nhi\r\n")
+        );
+        let resp2 = message_frame("ch".to_string(), RString("hi"), 2);
+        assert_eq!(
+            encode(resp2),
+            BytesMut::from("*3\r\n$7\r\nmessage\r\n$2\r\nch\r\n$2\r\nhi\r\n")
+        );
+    }
+
+    #[test]
+    fn test_shutdown_not_allowed_in_resp3() {
+        assert!(!allowed_in_resp3(&RedisCommand::Shutdown(None)));
+        assert!(allowed_in_resp3(&RedisCommand::Get("k".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_lagged_subscriber_recovers() {
+        let db = Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"));
+        let mut subscriptions = StreamMap::new();
+        subscribe(&db, "ch".to_string(), &mut subscriptions).await;
+        // Twice the channel capacity, with nobody reading
+        for i in 0..2048 {
+            publish(&db, "ch".to_string(), i.to_string()).await;
+        }
+
+        let mut delivered = Vec::new();
+        while delivered.len() < 1024 {
+            let (channel, result) = subscriptions.next().await.unwrap();
+            delivered.extend(delivery(channel, result, 2));
+        }
+        // The oldest half was lost, the rest arrives in order
+        assert_eq!(
+            delivered.first(),
+            Some(&message_frame("ch".to_string(), RString("1024"), 2))
+        );
+        assert_eq!(
+            delivered.last(),
+            Some(&message_frame("ch".to_string(), RString("2047"), 2))
+        );
+
+        // Later messages keep coming
+        publish(&db, "ch".to_string(), "after".to_string()).await;
+        let (channel, result) = subscriptions.next().await.unwrap();
+        assert_eq!(
+            delivery(channel, result, 2),
+            Some(message_frame("ch".to_string(), RString("after"), 2))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe_releases_channel() {
+        let db = Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"));
+        let mut first = StreamMap::new();
+        let mut second = StreamMap::new();
+        let channels = vec!["news".to_string(), "sport".to_string()];
+        subscribe_channels(&db, channels.clone(), &mut first, 2).await;
+        subscribe_channels(&db, vec!["news".to_string()], &mut second, 2).await;
+        assert_eq!(
+            channels_of(&db, None),
+            RArray(vec![RString("news"), RString("sport")])
+        );
+        assert_eq!(channels_of(&db, Some("s*")), RArray(vec![RString("sport")]));
+
+        for channel in channels {
+            unsubscribe(&db, channel, &mut first).await;
+        }
+        assert_eq!(channels_of(&db, None), RArray(vec![RString("news")]));
+        assert!(!db.pubsub.lock().unwrap().contains_key("sport"));
+
+        unsubscribe(&db, "news".to_string(), &mut second).await;
+        assert_eq!(channels_of(&db, None), RArray(vec![]));
+        assert!(db.pubsub.lock().unwrap().is_empty());
+        assert_eq!(
+            publish(&db, "news".to_string(), "hi".to_string()).await,
+            RInt(0)
+        );
+    }
+
+    fn channels_of(db: &Db, pattern: Option<&str>) -> RedisValueRef {
+        channels(db, pattern.map(str::to_string))
+    }
+}
@@ -1,14 +1,17 @@
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take};
 use nom::combinator::{opt, peek};
-use nom::multi::{many_till, many0};
+use nom::error::{Error, ErrorKind};
+use nom::multi::{count, many_till, many0};
 use nom::{IResult, Parser};
 
+use crate::lzf;
+
 #[derive(Debug)]
 pub struct Rdb {
     pub header: Header,
     pub metadata: Vec<KeyValue>,
-    pub entries: Vec<DatabaseEntry>,
+    pub entries: Vec<SnapshotEntry>,
 }
 
 #[derive(Debug)]
@@ -23,12 +26,6 @@ pub struct KeyValue {
     pub value: String,
 }
 
-#[derive(Debug)]
-pub struct DatabaseEntry {
-    pub kv: KeyValue,
-    pub expire: Option<u64>,
-}
-
 #[allow(dead_code)]
 #[derive(Debug)]
 struct DatabaseHeader {
@@ -124,27 +121,110 @@ fn string_encoded(i: &[u8], encoding: u8) -> IResult<&[u8], String> {
             Ok((i, val.to_string()))
         }
         0b11 => {
-            // Compressed with LZF algo
-            panic!("Can't handle LZF strings")
+            let (i, bytes) = lzf_string(i)?;
+            Ok((i, String::from_utf8_lossy(&bytes).into_owned()))
         }
         _ => panic!("Unknown encoding"),
     }
 }
 
-fn encoded_value(i: &[u8]) -> IResult<&[u8], String> {
+/// An LZF compressed string: the compressed and original lengths, then the
+/// compressed data
+fn lzf_string(i: &[u8]) -> IResult<&[u8], Vec<u8>> {
+    let (i, compressed_len) = length(i)?;
+    let (i, len) = length(i)?;
+    let (rest, compressed) = take(compressed_len)(i)?;
+    match lzf::decompress(compressed, len as usize) {
+        Some(bytes) => Ok((rest, bytes)),
+        None => Err(nom::Err::Error(Error::new(i, ErrorKind::Verify))),
+    }
+}
+
+fn encoded_bytes(i: &[u8]) -> IResult<&[u8], Vec<u8>> {
     match length_or_encoding(i)? {
+        (i, LengthOrEncoding::Encoding(RDB_ENC_LZF)) => lzf_string(i),
         (i, LengthOrEncoding::Length(length)) => {
-            let (i, string) = take(length)(i)?;
-            let string = String::from_utf8_lossy(string).into_owned();
-            Ok((i, string))
+            let (i, bytes) = take(length)(i)?;
+            Ok((i, bytes.to_vec()))
         }
         (i, LengthOrEncoding::Encoding(encoding)) => {
             let (i, string) = string_encoded(i, encoding)?;
-            Ok((i, string))
+            Ok((i, string.into_bytes()))
+        }
+    }
+}
+
+fn encoded_value(i: &[u8]) -> IResult<&[u8], String> {
+    let (i, bytes) = encoded_bytes(i)?;
+    Ok((i, String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+/// A length followed by that many strings, as used by lists and sets
+fn encoded_list(i: &[u8]) -> IResult<&[u8], Vec<Vec<u8>>> {
+    let (i, len) = length(i)?;
+    count(encoded_bytes, len as usize).parse(i)
+}
+
+fn encoded_hash(i: &[u8]) -> IResult<&[u8], HashFields> {
+    let (i, len) = length(i)?;
+    count((encoded_bytes, encoded_bytes), len as usize).parse(i)
+}
+
+/// A length that may use the 64 bit form (0x81 and eight big-endian bytes)
+fn long_length(i: &[u8]) -> IResult<&[u8], u64> {
+    match i.first() {
+        Some(0x81) => {
+            let (i, len_bytes) = take(9usize)(i)?;
+            Ok((i, u64::from_be_bytes(len_bytes[1..].try_into().unwrap())))
         }
+        _ => length(i).map(|(i, len)| (i, len as u64)),
     }
 }
 
+/// A hash with field TTLs: the smallest expiry in unix milliseconds, then
+/// each field's TTL, name and value. A TTL of 0 means the field never
+/// expires, otherwise it is the expiry minus that smallest one, plus 1.
+fn encoded_hash_metadata(i: &[u8]) -> IResult<&[u8], HashFieldExpiries> {
+    let (i, min_expire) = take(8usize)(i)?;
+    let min_expire = u64::from_le_bytes(min_expire.try_into().unwrap());
+    let (i, len) = length(i)?;
+    let field = |i| {
+        let (i, ttl) = long_length(i)?;
+        let (i, (field, value)) = (encoded_bytes, encoded_bytes).parse(i)?;
+        let expire = (ttl != 0).then(|| (min_expire + ttl).saturating_sub(1));
+        Ok((i, (field, value, expire)))
+    };
+    count(field, len as usize).parse(i)
+}
+
+/// Sorted set score in the old text format: a one byte length, with 253,
+/// 254 and 255 standing for NaN, +inf and -inf
+fn text_score(i: &[u8]) -> IResult<&[u8], f64> {
+    let (i, len) = take(1usize)(i)?;
+    match len[0] {
+        253 => Ok((i, f64::NAN)),
+        254 => Ok((i, f64::INFINITY)),
+        255 => Ok((i, f64::NEG_INFINITY)),
+        len => {
+            let (rest, text) = take(len)(i)?;
+            match String::from_utf8_lossy(text).parse() {
+                Ok(score) => Ok((rest, score)),
+                Err(_) => Err(nom::Err::Error(Error::new(i, ErrorKind::Float))),
+            }
+        }
+    }
+}
+
+fn binary_score(i: &[u8]) -> IResult<&[u8], f64> {
+    let (i, bytes) = take(8usize)(i)?;
+    Ok((i, f64::from_le_bytes(bytes.try_into().unwrap())))
+}
+
+fn encoded_zset(i: &[u8], score: fn(&[u8]) -> IResult<&[u8], f64>) -> IResult<&[u8], ZSetMembers> {
+    let (i, len) = length(i)?;
+    count((encoded_bytes, score), len as usize).parse(i)
+}
+
 fn metadata_start(i: &[u8]) -> IResult<&[u8], &[u8]> {
     let delim: &[u8] = &[0xFA];
     tag(delim)(i)
@@ -187,50 +267,84 @@ fn database_header(i: &[u8]) -> IResult<&[u8], DatabaseHeader> {
     ))
 }
 
-fn database_value(i: &[u8]) -> IResult<&[u8], KeyValue> {
-    let delim: &[u8] = &[0x00];
-    let (i, _) = tag(delim)(i)?;
+/// A value type byte, the key, then the value in that type's format. Only
+/// the plain (non-listpack) encodings are understood.
+fn database_value(i: &[u8]) -> IResult<&[u8], (String, RdbValue)> {
+    let (i, value_type) = take(1usize)(i)?;
     let (i, key) = encoded_value(i)?;
-    let (i, value) = encoded_value(i)?;
-    Ok((i, KeyValue { key, value }))
+    let (i, value) = match value_type[0] {
+        RDB_TYPE_STRING => {
+            let (i, value) = encoded_bytes(i)?;
+            (i, RdbValue::String(value))
+        }
+        RDB_TYPE_LIST => {
+            let (i, items) = encoded_list(i)?;
+            (i, RdbValue::List(items))
+        }
+        RDB_TYPE_SET => {
+            let (i, members) = encoded_list(i)?;
+            (i, RdbValue::Set(members))
+        }
+        RDB_TYPE_ZSET => {
+            let (i, members) = encoded_zset(i, text_score)?;
+            (i, RdbValue::ZSet(members))
+        }
+        RDB_TYPE_HASH => {
+            let (i, fields) = encoded_hash(i)?;
+            (i, RdbValue::Hash(fields))
+        }
+        RDB_TYPE_ZSET_2 => {
+            let (i, members) = encoded_zset(i, binary_score)?;
+            (i, RdbValue::ZSet(members))
+        }
+        RDB_TYPE_HASH_METADATA => {
+            let (i, fields) = encoded_hash_metadata(i)?;
+            (i, RdbValue::HashMetadata(fields))
+        }
+        _ => return Err(nom::Err::Error(Error::new(i, ErrorKind::Switch))),
+    };
+    Ok((i, (key, value)))
 }
 
-fn entry_expire_ms(i: &[u8]) -> IResult<&[u8], DatabaseEntry> {
+fn entry_expire_ms(i: &[u8]) -> IResult<&[u8], SnapshotEntry> {
     let delim: &[u8] = &[0xFC];
     let (i, _) = tag(delim)(i)?;
     let (i, expire) = take(8usize)(i)?;
     let timestamp = u64::from_le_bytes(expire.try_into().unwrap());
-    let (i, database_value) = database_value(i)?;
+    let (i, (key, value)) = database_value(i)?;
     Ok((
         i,
-        DatabaseEntry {
-            kv: database_value,
+        SnapshotEntry {
+            key,
+            value,
             expire: Some(timestamp),
         },
     ))
 }
 
-fn entry_expire_sec(i: &[u8]) -> IResult<&[u8], DatabaseEntry> {
+fn entry_expire_sec(i: &[u8]) -> IResult<&[u8], SnapshotEntry> {
     let delim: &[u8] = &[0xFD];
     let (i, _) = tag(delim)(i)?;
     let (i, expire) = take(4usize)(i)?;
     let timestamp = u32::from_le_bytes(expire.try_into().unwrap());
-    let (i, database_value) = database_value(i)?;
+    let (i, (key, value)) = database_value(i)?;
     Ok((
         i,
-        DatabaseEntry {
-            kv: database_value,
+        SnapshotEntry {
+            key,
+            value,
             expire: Some(timestamp as u64),
         },
     ))
 }
 
-fn entry_no_expire(i: &[u8]) -> IResult<&[u8], DatabaseEntry> {
-    let (i, database_value) = database_value(i)?;
+fn entry_no_expire(i: &[u8]) -> IResult<&[u8], SnapshotEntry> {
+    let (i, (key, value)) = database_value(i)?;
     Ok((
         i,
-        DatabaseEntry {
-            kv: database_value,
+        SnapshotEntry {
+            key,
+            value,
             expire: None,
         },
     ))
@@ -263,6 +377,162 @@ pub fn parse_rdb(i: &[u8]) -> IResult<&[u8], Rdb> {
     ))
 }
 
+const RDB_TYPE_STRING: u8 = 0;
+const RDB_TYPE_LIST: u8 = 1;
+const RDB_TYPE_SET: u8 = 2;
+const RDB_TYPE_ZSET: u8 = 3;
+const RDB_TYPE_HASH: u8 = 4;
+const RDB_TYPE_ZSET_2: u8 = 5;
+/// A hash with at least one field TTL, in the format Redis 7.4 added
+const RDB_TYPE_HASH_METADATA: u8 = 24;
+/// String encoding marking LZF compressed data
+const RDB_ENC_LZF: u8 = 3;
+
+type HashFields = Vec<(Vec<u8>, Vec<u8>)>;
+/// Fields with their absolute expiry in unix milliseconds, if any
+type HashFieldExpiries = Vec<(Vec<u8>, Vec<u8>, Option<u64>)>;
+type ZSetMembers = Vec<(Vec<u8>, f64)>;
+
+/// A value as it is stored in a dump
+#[derive(Debug, Clone, PartialEq)]
+pub enum RdbValue {
+    String(Vec<u8>),
+    List(Vec<Vec<u8>>),
+    Set(Vec<Vec<u8>>),
+    Hash(HashFields),
+    HashMetadata(HashFieldExpiries),
+    ZSet(ZSetMembers),
+}
+
+/// A key as it is written to (or read from) a dump
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotEntry {
+    pub key: String,
+    pub value: RdbValue,
+    /// Absolute expiry in unix milliseconds
+    pub expire: Option<u64>,
+}
+
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    if len < 1 << 6 {
+        out.push(len as u8);
+    } else if len < 1 << 14 {
+        out.extend_from_slice(&(len as u16 | 0x4000).to_be_bytes());
+    } else if len <= u32::MAX as usize {
+        out.push(0x80);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        out.push(0x81);
+        out.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+}
+
+/// Strings longer than this are LZF compressed when that saves space, as
+/// Redis does
+const LZF_MIN_LEN: usize = 20;
+
+fn write_string(out: &mut Vec<u8>, s: &[u8]) {
+    if s.len() > LZF_MIN_LEN
+        && let Some(compressed) = lzf::compress(s)
+    {
+        out.push(0xC0 | RDB_ENC_LZF);
+        write_length(out, compressed.len());
+        write_length(out, s.len());
+        out.extend_from_slice(&compressed);
+        return;
+    }
+    write_length(out, s.len());
+    out.extend_from_slice(s);
+}
+
+/// Write a value's type byte and the key, followed by the value itself
+fn write_entry(out: &mut Vec<u8>, key: &str, value: &RdbValue) {
+    let value_type = match value {
+        RdbValue::String(_) => RDB_TYPE_STRING,
+        RdbValue::List(_) => RDB_TYPE_LIST,
+        RdbValue::Set(_) => RDB_TYPE_SET,
+        RdbValue::Hash(_) => RDB_TYPE_HASH,
+        RdbValue::HashMetadata(_) => RDB_TYPE_HASH_METADATA,
+        RdbValue::ZSet(_) => RDB_TYPE_ZSET_2,
+    };
+    out.push(value_type);
+    write_string(out, key.as_bytes());
+    write_value(out, value);
+}
+
+/// Bytes the value takes up in a dump, not counting its key or type
+pub fn serialized_length(value: &RdbValue) -> usize {
+    let mut out = Vec::new();
+    write_value(&mut out, value);
+    out.len()
+}
+
+/// Collections use the plain encodings and sorted sets the binary score one
+fn write_value(out: &mut Vec<u8>, value: &RdbValue) {
+    match value {
+        RdbValue::String(value) => write_string(out, value),
+        RdbValue::List(items) | RdbValue::Set(items) => {
+            write_length(out, items.len());
+            items.iter().for_each(|item| write_string(out, item));
+        }
+        RdbValue::Hash(fields) => {
+            write_length(out, fields.len());
+            for (field, value) in fields {
+                write_string(out, field);
+                write_string(out, value);
+            }
+        }
+        RdbValue::HashMetadata(fields) => {
+            let min_expire = fields.iter().filter_map(|(_, _, expire)| *expire).min();
+            let min_expire = min_expire.unwrap_or(0);
+            out.extend_from_slice(&min_expire.to_le_bytes());
+            write_length(out, fields.len());
+            for (field, value, expire) in fields {
+                let ttl = expire.map_or(0, |expire| expire - min_expire + 1);
+                write_length(out, ttl as usize);
+                write_string(out, field);
+                write_string(out, value);
+            }
+        }
+        RdbValue::ZSet(members) => {
+            write_length(out, members.len());
+            for (member, score) in members {
+                write_string(out, member);
+                out.extend_from_slice(&score.to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Serialize entries into an RDB file the parser above can load. The
+/// checksum is left zeroed, which Redis treats as "not computed".
+pub fn write_rdb(entries: &[SnapshotEntry]) -> Vec<u8> {
+    let mut out = b"REDIS0012".to_vec();
+    for (key, value) in [("redis-ver", "8.4.0"), ("redis-bits", "64")] {
+        out.push(0xFA);
+        write_string(&mut out, key.as_bytes());
+        write_string(&mut out, value.as_bytes());
+    }
+    if !entries.is_empty() {
+        out.extend_from_slice(&[0xFE, 0x00, 0xFB]);
+        write_length(&mut out, entries.len());
+        write_length(
+            &mut out,
+            entries.iter().filter(|e| e.expire.is_some()).count(),
+        );
+        for entry in entries {
+            if let Some(expire) = entry.expire {
+                out.push(0xFC);
+                out.extend_from_slice(&expire.to_le_bytes());
+            }
+            write_entry(&mut out, &entry.key, &entry.value);
+        }
+    }
+    out.push(0xFF);
+    out.extend_from_slice(&[0; 8]);
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -281,6 +551,90 @@ mod tests {
     626172fc89037fab9b010000000362617a046672617aff1ac69407fe5b14\
     fc";
 
+    #[test]
+    fn test_write_rdb_round_trip() {
+        let entries = vec![
+            SnapshotEntry {
+                key: "foo".to_string(),
+                value: RdbValue::String(b"bar".to_vec()),
+                expire: None,
+            },
+            SnapshotEntry {
+                key: "long".to_string(),
+                value: RdbValue::String(vec![b'x'; 20_000]),
+                expire: Some(1768108786569),
+            },
+        ];
+        let bytes = write_rdb(&entries);
+        let (rest, rdb) = parse_rdb(&bytes).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(rdb.metadata.len(), 2);
+        assert_eq!(rdb.entries.len(), 2);
+        assert_eq!(rdb.entries[0].key, "foo");
+        assert_eq!(rdb.entries[0].value, RdbValue::String(b"bar".to_vec()));
+        assert_eq!(rdb.entries[0].expire, None);
+        assert_eq!(rdb.entries[1].value, entries[1].value);
+        assert_eq!(rdb.entries[1].expire, Some(1768108786569));
+
+        let (_, rdb) = parse_rdb(&write_rdb(&[])).unwrap();
+        assert!(rdb.entries.is_empty());
+    }
+
+    #[test]
+    fn test_write_rdb_collections() {
+        let entries = vec![
+            SnapshotEntry {
+                key: "list".to_string(),
+                value: RdbValue::List(vec![b"a".to_vec(), b"b".to_vec()]),
+                expire: None,
+            },
+            SnapshotEntry {
+                key: "set".to_string(),
+                value: RdbValue::Set(vec![b"m".to_vec()]),
+                expire: None,
+            },
+            SnapshotEntry {
+                key: "hash".to_string(),
+                value: RdbValue::Hash(vec![(b"f".to_vec(), b"v".to_vec())]),
+                expire: Some(1768108786569),
+            },
+            SnapshotEntry {
+                key: "hash-ttl".to_string(),
+                value: RdbValue::HashMetadata(vec![
+                    (b"f".to_vec(), b"v".to_vec(), Some(1768108786569)),
+                    (b"g".to_vec(), b"w".to_vec(), None),
+                    (
+                        b"h".to_vec(),
+                        b"x".to_vec(),
+                        Some(1768108786569 + (1 << 33)),
+                    ),
+                ]),
+                expire: None,
+            },
+            SnapshotEntry {
+                key: "zset".to_string(),
+                value: RdbValue::ZSet(vec![(b"x".to_vec(), 1.5), (b"y".to_vec(), f64::INFINITY)]),
+                expire: None,
+            },
+        ];
+        let bytes = write_rdb(&entries);
+        let (rest, rdb) = parse_rdb(&bytes).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(rdb.entries, entries);
+    }
+
+    #[test]
+    fn test_text_zset_scores() {
+        // Old style ZSET: member "a" scored "2.5", member "b" scored -inf
+        let bytes = [0x02, 0x01, b'a', 0x03, b'2', b'.', b'5', 0x01, b'b', 0xFF];
+        let (rest, members) = encoded_zset(&bytes, text_score).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            members,
+            vec![(b"a".to_vec(), 2.5), (b"b".to_vec(), f64::NEG_INFINITY)]
+        );
+    }
+
     #[test]
     fn test_baz_ttl_db() {
         let b = db_bytes(BAZ_TTL_DB);
@@ -291,11 +645,11 @@ mod tests {
         assert_eq!(rdb.header.version, "0012");
         assert_eq!(rdb.metadata.len(), 5);
         assert_eq!(rdb.entries.len(), 2);
-        assert_eq!(rdb.entries[0].kv.key, "foo");
-        assert_eq!(rdb.entries[0].kv.value, "bar");
+        assert_eq!(rdb.entries[0].key, "foo");
+        assert_eq!(rdb.entries[0].value, RdbValue::String(b"bar".to_vec()));
         assert_eq!(rdb.entries[0].expire, None);
-        assert_eq!(rdb.entries[1].kv.key, "baz");
-        assert_eq!(rdb.entries[1].kv.value, "fraz");
+        assert_eq!(rdb.entries[1].key, "baz");
+        assert_eq!(rdb.entries[1].value, RdbValue::String(b"fraz".to_vec()));
         assert_eq!(rdb.entries[1].expire, Some(1768108786569));
     }
 
@@ -309,8 +663,8 @@ mod tests {
         assert_eq!(rdb.header.version, "0012");
         assert_eq!(rdb.metadata.len(), 5);
         assert_eq!(rdb.entries.len(), 1);
-        assert_eq!(rdb.entries[0].kv.key, "foo");
-        assert_eq!(rdb.entries[0].kv.value, "bar");
+        assert_eq!(rdb.entries[0].key, "foo");
+        assert_eq!(rdb.entries[0].value, RdbValue::String(b"bar".to_vec()));
         assert_eq!(rdb.entries[0].expire, None);
     }
 
@@ -350,17 +704,17 @@ mod tests {
         let entry_bytes = hex::decode(entry).unwrap();
         let (_, entry) = entry_expire_ms(&entry_bytes).unwrap();
         assert_eq!(entry.expire, Some(1713824559637));
-        assert_eq!(entry.kv.key, "foo");
-        assert_eq!(entry.kv.value, "bar");
+        assert_eq!(entry.key, "foo");
+        assert_eq!(entry.value, RdbValue::String(b"bar".to_vec()));
     }
 
     #[test]
     fn test_database_value() {
         let value = "0006666F6F6261720662617A717578";
         let value_bytes = hex::decode(value).unwrap();
-        let (_, kv) = database_value(&value_bytes).unwrap();
-        assert_eq!(kv.key, "foobar");
-        assert_eq!(kv.value, "bazqux");
+        let (_, (key, value)) = database_value(&value_bytes).unwrap();
+        assert_eq!(key, "foobar");
+        assert_eq!(value, RdbValue::String(b"bazqux".to_vec()));
     }
 
     #[test]
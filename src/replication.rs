@@ -1,11 +1,13 @@
 use crate::{
     Db,
     interpreter::RedisCommand,
+    now_ms,
     parser::{RSimpleString, RedisValueRef, RespParser},
 };
 use bytes::Bytes;
 use futures::{SinkExt, StreamExt};
-use tokio::{net::TcpStream, sync::mpsc::Receiver};
+use std::{sync::atomic::Ordering, time::Duration};
+use tokio::{io::AsyncReadExt, net::TcpStream, sync::mpsc::Receiver};
 use tokio_util::codec::Decoder;
 use tokio_util::codec::Framed;
 
@@ -35,7 +37,7 @@ pub async fn handshake(
 ) -> Result<(), ReplicationError> {
     // Start handshake - send PING and expect PONG
     transport
-        .send(RedisCommand::Ping.try_into().unwrap())
+        .send(RedisCommand::Ping(None).try_into().unwrap())
         .await?;
     let resp = get_next_response(transport).await?;
 
@@ -87,9 +89,81 @@ pub async fn handshake(
     Ok(())
 }
 
+/// Read the `$<len>\r\n<bytes>` RDB that follows FULLRESYNC. Unlike a bulk
+/// string it has no trailing CRLF, so it's taken straight off the read
+/// buffer rather than going through the RESP decoder.
+async fn read_rdb_payload(
+    transport: &mut Framed<TcpStream, RespParser>,
+) -> Result<Bytes, ReplicationError> {
+    loop {
+        let buf = transport.read_buffer_mut();
+        if let Some(header_end) = memchr::memmem::find(buf, b"\r\n") {
+            let len: usize = std::str::from_utf8(&buf[..header_end])
+                .ok()
+                .and_then(|header| header.strip_prefix('$'))
+                .and_then(|len| len.parse().ok())
+                .ok_or_else(|| {
+                    ReplicationError::HandshakeFailed("Expected an RDB payload from master".into())
+                })?;
+            if buf.len() >= header_end + 2 + len {
+                let _ = buf.split_to(header_end + 2);
+                return Ok(buf.split_to(len).freeze());
+            }
+        }
+        let mut chunk = [0u8; 4096];
+        let read = transport.get_mut().read(&mut chunk).await?;
+        if read == 0 {
+            return Err(ReplicationError::HandshakeFailed(
+                "Connection closed by master".into(),
+            ));
+        }
+        transport
+            .read_buffer_mut()
+            .extend_from_slice(&chunk[..read]);
+    }
+}
+
+/// Handle the master's answer to PSYNC: `+FULLRESYNC <replid> <offset>` and
+/// then the RDB snapshot, which replaces whatever the replica held.
+/// Returns the replication offset to continue from.
+pub async fn full_resync(
+    db: &Db,
+    transport: &mut Framed<TcpStream, RespParser>,
+) -> Result<usize, ReplicationError> {
+    let resp = get_next_response(transport).await?;
+    let line = String::from_utf8_lossy(&resp.as_string().unwrap_or_default()).to_string();
+    let (repl_id, offset) = match line.split(' ').collect::<Vec<_>>()[..] {
+        ["FULLRESYNC", repl_id, offset] => (
+            repl_id.to_string(),
+            offset.parse::<usize>().map_err(|_| {
+                ReplicationError::HandshakeFailed(format!("Bad FULLRESYNC offset: {}", offset))
+            })?,
+        ),
+        _ => {
+            return Err(ReplicationError::HandshakeFailed(format!(
+                "Expected FULLRESYNC, got {:?}",
+                line
+            )));
+        }
+    };
+
+    let payload = read_rdb_payload(transport).await?;
+    db.dict.clear();
+    db.ttl.clear();
+    db.zsets.lock().unwrap().clear();
+    let keys = db.load_rdb(&payload).map_err(|e| {
+        ReplicationError::HandshakeFailed(format!("Failed to load RDB from master: {}", e))
+    })?;
+    println!("Replica - Loaded {} keys from master", keys);
+    *db.replication_id.lock().unwrap() = repl_id;
+    db.replication_offset
+        .store(offset as i64, std::sync::atomic::Ordering::Relaxed);
+    Ok(offset)
+}
+
 pub async fn psync_preamble(db: &Db, _id: String, _offset: i64) -> RedisValueRef {
     // On handshake, id will be ? and offset will be -1
-    let repl_id = db.replication_id.clone();
+    let repl_id = db.replication_id.lock().unwrap().clone();
     let repl_offset = db
         .replication_offset
         .load(std::sync::atomic::Ordering::Relaxed);
@@ -101,6 +175,30 @@ pub async fn psync_preamble(db: &Db, _id: String, _offset: i64) -> RedisValueRef
     ])
 }
 
+/// Start replicating to a replica that has just been sent the RDB snapshot,
+/// returning its ID. The snapshot covers everything up to the current offset,
+/// so that is where the replica starts (not zero), or WAIT would treat it as
+/// behind until its first ACK.
+pub fn register_replica(
+    db: &Db,
+    tx: tokio::sync::mpsc::Sender<RedisCommand>,
+    ip: String,
+    port: u16,
+) -> String {
+    let id = uuid::Uuid::new_v4().to_string();
+    let offset = db
+        .replication_offset
+        .load(std::sync::atomic::Ordering::Relaxed);
+    db.replicating_to.lock().unwrap().push(crate::Replica {
+        id: id.clone(),
+        ip,
+        port,
+        offset,
+        tx,
+    });
+    id
+}
+
 pub async fn set_rdb_payload(_db: &Db, payload: Bytes) -> RedisValueRef {
     // Todo - actually parse this
     println!("Got request to set RDB payload with len {}", payload.len());
@@ -130,9 +228,9 @@ fn compute_redis_value_size(item: &RedisValueRef) -> usize {
         RedisValueRef::SimpleString(s) => {
             1 + s.len() + 2 // "+" + string + "\r\n"
         }
-        RedisValueRef::Array(array) => {
+        RedisValueRef::Array(array) | RedisValueRef::Push(array) => {
             let len_str = array.len().to_string();
-            let header_size = 1 + len_str.len() + 2; // "*" + len + "\r\n"
+            let header_size = 1 + len_str.len() + 2; // "*" or ">" + len + "\r\n"
             let elements_size: usize = array.iter().map(compute_redis_value_size).sum();
             header_size + elements_size
         }
@@ -147,10 +245,55 @@ fn compute_redis_value_size(item: &RedisValueRef) -> usize {
             1 + len_str.len() + 2 + file.len() // "$" + len + "\r\n" + data
         }
         RedisValueRef::MultiValue(values) => values.iter().map(compute_redis_value_size).sum(),
+        RedisValueRef::Map(pairs) => {
+            let len_str = pairs.len().to_string();
+            let header_size = 1 + len_str.len() + 2; // "%" + len + "\r\n"
+            let elements_size: usize = pairs
+                .iter()
+                .map(|(k, v)| compute_redis_value_size(k) + compute_redis_value_size(v))
+                .sum();
+            header_size + elements_size
+        }
         RedisValueRef::ErrorMsg(_) => 0,
     }
 }
 
+/// Connected replicas that have acknowledged at least `offset` bytes
+fn replicas_at_offset(db: &Db, offset: i64) -> usize {
+    db.replicating_to
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|r| !r.tx.is_closed() && r.offset >= offset)
+        .count()
+}
+
+/// Ask every replica for its offset and wait, polling every 100ms, until at
+/// least `replicas` have acknowledged everything written so far or `timeout`
+/// ms have passed. Returns how many replicas are caught up. If enough of them
+/// have already acknowledged the current offset there's no GETACK round trip.
+pub async fn wait_for_replicas(db: &Db, replicas: u64, timeout: u64) -> usize {
+    let last_wait_offset = db
+        .replication_offset
+        .load(std::sync::atomic::Ordering::Relaxed);
+    let cnt = replicas_at_offset(db, last_wait_offset);
+    if cnt as u64 >= replicas {
+        return cnt;
+    }
+    let command = RedisCommand::ReplConf("GETACK".to_string(), "*".to_string());
+    broadcast_to_replicas(db, command).await;
+
+    let mut timeout_limit = 0;
+    loop {
+        let cnt = replicas_at_offset(db, last_wait_offset);
+        if cnt as u64 >= replicas || timeout_limit >= timeout {
+            return cnt;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        timeout_limit += 100;
+    }
+}
+
 pub async fn broadcast_to_replicas(db: &Db, command: RedisCommand) {
     let replicas: Vec<_> = db
         .replicating_to
@@ -231,57 +374,92 @@ pub async fn run_psync_loop(
     }
 }
 
-pub async fn run_replica_loop(db: &Db, master_addr: String, master_port: u16, port: u16) {
-    let db = db.clone();
-    tokio::spawn(async move {
-        let stream = match TcpStream::connect((master_addr, master_port)).await {
-            Ok(stream) => stream,
-            Err(_) => {
-                eprintln!("Failed to connect to master");
-                std::process::exit(1);
-            }
-        };
-        let mut transport = RespParser.framed(stream);
-        if let Err(e) = handshake(&mut transport, port).await {
-            eprintln!("Replication handshake failed: {}", e);
-            std::process::exit(1);
-        }
-        let mut recieved_offset: usize = 0;
-        while let Some(redis_value) = transport.next().await {
-            match redis_value {
-                Ok(value) => {
-                    let result: Result<RedisCommand, _> = value.try_into();
-                    match result {
-                        Ok(command) => {
-                            println!("Replica - Received command: {:?}", command);
-                            let cmd_for_bytes = command.clone();
-                            match command {
-                                RedisCommand::ReplConf(key, _value) => {
-                                    let command = if key == "GETACK" {
-                                        // Value is bytes offset
-                                        RedisCommand::ReplConf(
-                                            "ACK".to_string(),
-                                            recieved_offset.to_string(),
-                                        )
-                                        .try_into()
-                                        .unwrap()
-                                    } else {
-                                        RSimpleString("OK")
-                                    };
-
-                                    transport.send(command).await.unwrap();
-                                }
-                                _ => {
-                                    crate::handle_command(&db, command).await;
+/// First delay before reconnecting to the master, doubled after each
+/// failed attempt...
+const RECONNECT_DELAY: Duration = Duration::from_millis(100);
+/// ...up to this much
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Connect to the master, sync, and apply the replication stream until the
+/// connection drops. Errors mean the link never came up.
+async fn replicate_from(
+    db: &Db,
+    master_addr: &str,
+    master_port: u16,
+    port: u16,
+) -> Result<(), ReplicationError> {
+    let stream = TcpStream::connect((master_addr, master_port)).await?;
+    let mut transport = RespParser::default().framed(stream);
+    handshake(&mut transport, port).await?;
+    let mut recieved_offset = full_resync(db, &mut transport).await?;
+    db.master_link_up.store(true, Ordering::Relaxed);
+    db.master_last_io.store(now_ms(), Ordering::Relaxed);
+    while let Some(redis_value) = transport.next().await {
+        db.master_last_io.store(now_ms(), Ordering::Relaxed);
+        match redis_value {
+            Ok(value) => {
+                // Count what the master actually sent, not a re-encoding of it
+                let frame_len = compute_redis_value_size(&value);
+                let result: Result<RedisCommand, _> = value.try_into();
+                match result {
+                    Ok(command) => {
+                        println!("Replica - Received command: {:?}", command);
+                        match command {
+                            RedisCommand::ReplConf(key, _value) => {
+                                let command = if key == "GETACK" {
+                                    // Like Redis, the offset covers everything
+                                    // before this GETACK but not the GETACK itself
+                                    RedisCommand::ReplConf(
+                                        "ACK".to_string(),
+                                        recieved_offset.to_string(),
+                                    )
+                                    .try_into()
+                                    .unwrap()
+                                } else {
+                                    RSimpleString("OK")
+                                };
+
+                                if let Err(e) = transport.send(command).await {
+                                    eprintln!("Replica - Failed to reply to master: {:?}", e);
+                                    break;
                                 }
                             }
-                            recieved_offset += command_bytes(cmd_for_bytes);
+                            _ => {
+                                crate::handle_command(db, command.clone()).await;
+                                crate::aof::append(db, &command);
+                            }
                         }
-                        Err(e) => eprintln!("Failed to parse command: {}", e),
                     }
+                    Err(e) => eprintln!("Failed to parse command: {}", e),
                 }
-                Err(e) => eprintln!("Failed to read command: {:?}", e),
+                recieved_offset += frame_len;
             }
+            Err(e) => eprintln!("Failed to read command: {:?}", e),
+        }
+    }
+    db.master_link_up.store(false, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Replicate from the master in the background, reconnecting with
+/// exponential backoff whenever the link can't be made or is lost
+pub async fn run_replica_loop(db: &Db, master_addr: String, master_port: u16, port: u16) {
+    let db = db.clone();
+    tokio::spawn(async move {
+        let mut delay = RECONNECT_DELAY;
+        loop {
+            match replicate_from(&db, &master_addr, master_port, port).await {
+                Ok(()) => {
+                    println!("Replica - Lost connection to master, reconnecting");
+                    delay = RECONNECT_DELAY;
+                }
+                Err(e) => eprintln!(
+                    "Replica - Couldn't sync with master: {}, retrying in {:?}",
+                    e, delay
+                ),
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
         }
     });
 }
@@ -289,6 +467,9 @@ pub async fn run_replica_loop(db: &Db, master_addr: String, master_port: u16, po
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::RedisDb;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
 
     #[test]
     fn test_compute_redis_value_size() {
@@ -312,4 +493,288 @@ mod tests {
         assert_eq!(compute_redis_value_size(&RedisValueRef::NullArray), 5);
         assert_eq!(compute_redis_value_size(&RedisValueRef::NullBulkString), 5);
     }
+
+    #[tokio::test]
+    async fn test_xadd_reaches_replica() {
+        let db = Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<RedisCommand>(16);
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut transport = RespParser::default().framed(stream);
+            run_psync_loop(&mut rx, &mut transport, &db, "replica").await;
+        });
+        let mut replica = RespParser::default().framed(TcpStream::connect(addr).await.unwrap());
+
+        let xadd = RedisCommand::XAdd(
+            "stream".to_string(),
+            (Some(1), Some(1)),
+            vec![("field".to_string(), "value".to_string())],
+            false,
+        );
+        tx.send(xadd.clone()).await.unwrap();
+
+        let received: RedisCommand = replica.next().await.unwrap().unwrap().try_into().unwrap();
+        assert_eq!(received, xadd);
+    }
+
+    /// Play back a master's side of the handshake: replies to PING and both
+    /// REPLCONFs, then FULLRESYNC with an RDB holding `from_master`, followed
+    /// directly by `then` in the same write
+    async fn fake_master(stream: &mut TcpStream, then: &[u8]) {
+        use tokio::io::AsyncWriteExt;
+        let rdb = crate::rdb::write_rdb(&[crate::rdb::SnapshotEntry {
+            key: "from_master".to_string(),
+            value: crate::rdb::RdbValue::String(b"yes".to_vec()),
+            expire: None,
+        }]);
+        let mut buf = [0u8; 1024];
+        for reply in ["+PONG\r\n", "+OK\r\n", "+OK\r\n"] {
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream.write_all(reply.as_bytes()).await.unwrap();
+        }
+        let _ = stream.read(&mut buf).await.unwrap();
+        // The RDB has no trailing CRLF before whatever comes next
+        let mut reply = format!(
+            "+FULLRESYNC 75cd7bc10c49047e0d163660f3b90625b1af31dc 7\r\n${}\r\n",
+            rdb.len()
+        )
+        .into_bytes();
+        reply.extend_from_slice(&rdb);
+        reply.extend_from_slice(then);
+        stream.write_all(&reply).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_full_resync() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            fake_master(
+                &mut stream,
+                b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n",
+            )
+            .await;
+        });
+
+        let db = Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"));
+        db.dict.insert(
+            "stale".to_string(),
+            crate::RedisValue::String(Bytes::from("old")),
+        );
+        let mut transport = RespParser::default().framed(TcpStream::connect(addr).await.unwrap());
+        handshake(&mut transport, 6380).await.unwrap();
+        assert_eq!(full_resync(&db, &mut transport).await.unwrap(), 7);
+
+        assert!(db.dict.contains_key("from_master"));
+        assert!(!db.dict.contains_key("stale"));
+        assert_eq!(
+            *db.replication_id.lock().unwrap(),
+            "75cd7bc10c49047e0d163660f3b90625b1af31dc"
+        );
+        let next: RedisCommand = transport.next().await.unwrap().unwrap().try_into().unwrap();
+        assert_eq!(
+            next,
+            RedisCommand::Set("foo".to_string(), "bar".to_string())
+        );
+    }
+
+    /// INFO replication, polled until it contains `expected` or a second passes
+    async fn wait_for_info(db: &Db, expected: &str) -> String {
+        for _ in 0..100 {
+            let RedisValueRef::String(info) = crate::info(db, "replication".to_string()).await
+            else {
+                panic!("INFO should return a bulk string");
+            };
+            let info = String::from_utf8_lossy(&info).to_string();
+            if info.contains(expected) {
+                return info;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("INFO replication never contained {:?}", expected);
+    }
+
+    #[tokio::test]
+    async fn test_master_link_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (close_tx, close_rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            fake_master(&mut stream, b"").await;
+            // Hold the connection open until the test is done checking
+            let _ = close_rx.await;
+        });
+
+        let db = Arc::new(RedisDb::new(
+            Some(("127.0.0.1".to_string(), addr.port())),
+            "/tmp/redis-files",
+            "dump.rdb",
+        ));
+        let info = wait_for_info(&db, "master_link_status:down").await;
+        assert!(info.contains("master_last_io_seconds_ago:-1\n"));
+
+        run_replica_loop(&db, "127.0.0.1".to_string(), addr.port(), 6380).await;
+        let info = wait_for_info(&db, "master_link_status:up").await;
+        assert!(info.contains("role:slave\n"));
+        assert!(info.contains(&format!("master_port:{}\n", addr.port())));
+        assert!(info.contains("master_last_io_seconds_ago:0\n"));
+
+        close_tx.send(()).unwrap();
+        wait_for_info(&db, "master_link_status:down").await;
+    }
+
+    #[tokio::test]
+    async fn test_getack_offset() {
+        const GETACK: &[u8] = b"*3\r\n$8\r\nREPLCONF\r\n$6\r\nGETACK\r\n$1\r\n*\r\n";
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (acks_tx, mut acks_rx) = tokio::sync::mpsc::unbounded_channel::<RedisCommand>();
+        tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            let (mut stream, _) = listener.accept().await.unwrap();
+            fake_master(&mut stream, GETACK).await;
+            let mut transport = RespParser::default().framed(stream);
+            let ack = transport.next().await.unwrap().unwrap();
+            acks_tx.send(ack.try_into().unwrap()).unwrap();
+
+            // 31 bytes of SET (lowercase, which re-encoding would change) and
+            // 14 bytes of PING, on top of the 37 byte GETACK already counted
+            let stream = transport.get_mut();
+            stream
+                .write_all(b"*3\r\n$3\r\nset\r\n$3\r\nfoo\r\n$3\r\nbar\r\n")
+                .await
+                .unwrap();
+            stream.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+            stream.write_all(GETACK).await.unwrap();
+            let ack = transport.next().await.unwrap().unwrap();
+            acks_tx.send(ack.try_into().unwrap()).unwrap();
+            let _ = transport.next().await;
+        });
+
+        let db = Arc::new(RedisDb::new(
+            Some(("127.0.0.1".to_string(), addr.port())),
+            "/tmp/redis-files",
+            "dump.rdb",
+        ));
+        run_replica_loop(&db, "127.0.0.1".to_string(), addr.port(), 6380).await;
+        let ack = |offset: usize| RedisCommand::ReplConf("ACK".to_string(), offset.to_string());
+        // FULLRESYNC started the stream at offset 7
+        assert_eq!(acks_rx.recv().await.unwrap(), ack(7));
+        assert_eq!(acks_rx.recv().await.unwrap(), ack(7 + 37 + 31 + 14));
+        assert!(db.dict.contains_key("foo"));
+    }
+
+    #[tokio::test]
+    async fn test_replica_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (close_tx, close_rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            // Hang up on the first attempt, before the handshake
+            let (stream, _) = listener.accept().await.unwrap();
+            drop(stream);
+            let (mut stream, _) = listener.accept().await.unwrap();
+            fake_master(&mut stream, b"").await;
+            let _ = close_rx.await;
+        });
+
+        let db = Arc::new(RedisDb::new(
+            Some(("127.0.0.1".to_string(), addr.port())),
+            "/tmp/redis-files",
+            "dump.rdb",
+        ));
+        run_replica_loop(&db, "127.0.0.1".to_string(), addr.port(), 6380).await;
+        wait_for_info(&db, "master_link_status:up").await;
+        assert!(db.dict.contains_key("from_master"));
+        close_tx.send(()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_wait_replica_already_caught_up() {
+        let db = Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"));
+        db.replication_offset
+            .store(42, std::sync::atomic::Ordering::Relaxed);
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<RedisCommand>(16);
+        db.replicating_to.lock().unwrap().push(crate::Replica {
+            id: "replica".to_string(),
+            ip: "127.0.0.1".to_string(),
+            port: 6380,
+            offset: 42,
+            tx,
+        });
+
+        let start = std::time::Instant::now();
+        assert_eq!(wait_for_replicas(&db, 1, 5000).await, 1);
+        assert!(start.elapsed() < std::time::Duration::from_millis(100));
+        // No GETACK was needed
+        assert!(rx.try_recv().is_err());
+
+        // Asking for more replicas than are caught up still polls until the timeout
+        assert_eq!(wait_for_replicas(&db, 2, 100).await, 1);
+        assert!(matches!(rx.try_recv(), Ok(RedisCommand::ReplConf(key, _)) if key == "GETACK"));
+    }
+
+    #[tokio::test]
+    async fn test_register_replica_offset() {
+        let db = Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"));
+        db.replication_offset
+            .store(1234, std::sync::atomic::Ordering::Relaxed);
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<RedisCommand>(16);
+        let id = register_replica(&db, tx, "127.0.0.1".to_string(), 6380);
+        {
+            let replicas = db.replicating_to.lock().unwrap();
+            assert_eq!(replicas.len(), 1);
+            assert_eq!(replicas[0].id, id);
+            assert_eq!(replicas[0].offset, 1234);
+        }
+        // The fresh replica already counts as caught up
+        assert_eq!(wait_for_replicas(&db, 1, 1000).await, 1);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_served_blpop_replicates_after_the_push() {
+        let db = Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"));
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<RedisCommand>(16);
+        register_replica(&db, tx, "127.0.0.1".to_string(), 6380);
+        let flags = crate::ClientFlags::default();
+        let blpop = RedisCommand::BLPop("l".to_string(), Some(2.0));
+        let waiter = tokio::spawn({
+            let db = db.clone();
+            async move { crate::run_client_command(&db, blpop, flags, None).await }
+        });
+        while db.queued_waiters.load(std::sync::atomic::Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        let rpush = RedisCommand::Rpush("l".to_string(), vec!["a".to_string(), "b".to_string()]);
+        crate::run_client_command(&db, rpush.clone(), flags, None).await;
+        waiter.await.unwrap();
+        // Everything is sent, the replica's loop drops what isn't a write
+        let mut writes = || {
+            std::iter::from_fn(|| rx.try_recv().ok())
+                .filter(RedisCommand::can_replicate)
+                .collect::<Vec<_>>()
+        };
+        let lpop = RedisCommand::LPop("l".to_string(), None);
+        assert_eq!(writes(), vec![rpush, lpop.clone()]);
+
+        // Served straight away, BLPOP is passed on as the pop it did
+        let blpop = RedisCommand::BLPop("l".to_string(), Some(2.0));
+        crate::run_client_command(&db, blpop, flags, None).await;
+        assert_eq!(writes(), vec![lpop]);
+    }
+
+    #[test]
+    fn test_xadd_replicates_generated_id() {
+        let xadd = RedisCommand::XAdd("stream".to_string(), (None, None), vec![], false);
+        let replicated = xadd.for_replication(&RedisValueRef::String(Bytes::from("5-3")));
+        assert_eq!(
+            replicated,
+            RedisCommand::XAdd("stream".to_string(), (Some(5), Some(3)), vec![], false)
+        );
+    }
 }
@@ -0,0 +1,409 @@
+use std::collections::HashSet;
+
+use bytes::Bytes;
+
+use crate::{
+    Db, RedisValue, expect_no_zset,
+    parser::{RArray, RError, RInt, RedisValueRef},
+    remove_keys,
+};
+
+/// Sets of integers use the intset encoding up to this many members
+pub const SET_MAX_INTSET_ENTRIES: usize = 512;
+/// Other small sets use listpack up to this many members...
+pub const SET_MAX_LISTPACK_ENTRIES: usize = 128;
+/// ...as long as every member is at most this many bytes
+pub const SET_MAX_LISTPACK_VALUE: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum SetEncoding {
+    #[default]
+    Intset,
+    Listpack,
+    Hashtable,
+}
+
+/// Set storage. Like lists, a set only ever moves to a bigger encoding
+/// (intset, then listpack, then hashtable) and never converts back.
+/// Members are kept as bytes, so ones loaded from a dump needn't be UTF-8.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Set {
+    members: HashSet<Bytes>,
+    encoding: SetEncoding,
+}
+
+/// Whether Redis would store `member` in an intset: a canonical 64 bit integer
+fn is_intset_member(member: &[u8]) -> bool {
+    std::str::from_utf8(member)
+        .is_ok_and(|member| member.parse::<i64>().is_ok_and(|n| n.to_string() == member))
+}
+
+impl Set {
+    fn update_encoding(&mut self) {
+        let len = self.members.len();
+        self.encoding = match self.encoding {
+            SetEncoding::Intset
+                if len <= SET_MAX_INTSET_ENTRIES
+                    && self.members.iter().all(|m| is_intset_member(m)) =>
+            {
+                SetEncoding::Intset
+            }
+            SetEncoding::Intset | SetEncoding::Listpack
+                if len <= SET_MAX_LISTPACK_ENTRIES
+                    && self
+                        .members
+                        .iter()
+                        .all(|m| m.len() <= SET_MAX_LISTPACK_VALUE) =>
+            {
+                SetEncoding::Listpack
+            }
+            _ => SetEncoding::Hashtable,
+        };
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match self.encoding {
+            SetEncoding::Intset => "intset",
+            SetEncoding::Listpack => "listpack",
+            SetEncoding::Hashtable => "hashtable",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        self.members.contains(member)
+    }
+
+    /// Members, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &Bytes> {
+        self.members.iter()
+    }
+}
+
+impl FromIterator<Bytes> for Set {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(iter: I) -> Self {
+        let mut set = Set {
+            members: iter.into_iter().collect(),
+            encoding: SetEncoding::default(),
+        };
+        set.update_encoding();
+        set
+    }
+}
+
+fn wrong_type() -> RedisValueRef {
+    RError("WRONGTYPE Operation against a key holding the wrong kind of value")
+}
+
+pub fn sadd(db: &Db, key: String, members: Vec<String>) -> RedisValueRef {
+    match db.get_mut_if_valid(&key) {
+        Some(mut entry) => match &mut *entry {
+            RedisValue::Set(set) => {
+                let added = members
+                    .into_iter()
+                    .filter(|member| set.members.insert(Bytes::from(member.clone())))
+                    .count();
+                set.update_encoding();
+                db.add_dirty(added as u64);
+                RInt(added as i64)
+            }
+            _ => wrong_type(),
+        },
+        None => {
+            if let Err(err) = expect_no_zset(db, &key) {
+                return err;
+            }
+            let set: Set = members.into_iter().map(Bytes::from).collect();
+            let added = set.len();
+            db.add_dirty(added as u64);
+            db.dict.insert(key, RedisValue::Set(set));
+            RInt(added as i64)
+        }
+    }
+}
+
+pub fn srem(db: &Db, key: String, members: Vec<String>) -> RedisValueRef {
+    let result = match db.get_mut_if_valid(&key) {
+        Some(mut entry) => match &mut *entry {
+            RedisValue::Set(set) => {
+                let removed = members
+                    .iter()
+                    .filter(|member| set.members.remove(member.as_bytes()))
+                    .count();
+                db.add_dirty(removed as u64);
+                Ok((removed, set.is_empty()))
+            }
+            _ => Err(wrong_type()),
+        },
+        None => Ok((0, false)),
+    }; // Dict get_mut guard dropped
+
+    match result {
+        Ok((removed, is_now_empty)) => {
+            if is_now_empty {
+                db.dict.remove(&key);
+                db.ttl.remove(&key);
+            }
+            RInt(removed as i64)
+        }
+        Err(err) => err,
+    }
+}
+
+pub fn smembers(db: &Db, key: String) -> RedisValueRef {
+    match db.get_if_valid(&key) {
+        Some(entry) => match &*entry {
+            RedisValue::Set(set) => {
+                RArray(set.iter().cloned().map(RedisValueRef::String).collect())
+            }
+            _ => wrong_type(),
+        },
+        None => RArray(vec![]),
+    }
+}
+
+pub fn sismember(db: &Db, key: String, member: String) -> RedisValueRef {
+    match db.get_if_valid(&key) {
+        Some(entry) => match &*entry {
+            RedisValue::Set(set) => RInt(set.contains(member.as_bytes()) as i64),
+            _ => wrong_type(),
+        },
+        None => RInt(0),
+    }
+}
+
+/// The set algebra behind SINTER, SUNION and SDIFF and their STORE forms
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOperation {
+    Inter,
+    Union,
+    Diff,
+}
+
+impl SetOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SetOperation::Inter => "sinter",
+            SetOperation::Union => "sunion",
+            SetOperation::Diff => "sdiff",
+        }
+    }
+
+    pub fn store_name(&self) -> &'static str {
+        match self {
+            SetOperation::Inter => "sinterstore",
+            SetOperation::Union => "sunionstore",
+            SetOperation::Diff => "sdiffstore",
+        }
+    }
+}
+
+/// Apply `op` across the sets at `keys`, in order. A missing key counts as
+/// an empty set, and any key holding another type is an error.
+fn combine(db: &Db, op: SetOperation, keys: &[String]) -> Result<HashSet<Bytes>, RedisValueRef> {
+    let mut sets = keys
+        .iter()
+        .map(|key| match db.get_if_valid(key) {
+            Some(entry) => match &*entry {
+                RedisValue::Set(set) => Ok(set.members.clone()),
+                _ => Err(wrong_type()),
+            },
+            None => Ok(HashSet::new()),
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter();
+    let mut result = sets.next().unwrap_or_default();
+    for set in sets {
+        match op {
+            SetOperation::Inter => result.retain(|member| set.contains(member)),
+            SetOperation::Union => result.extend(set),
+            SetOperation::Diff => result.retain(|member| !set.contains(member)),
+        }
+    }
+    Ok(result)
+}
+
+/// SINTER, SUNION, SDIFF: the members of the combined set
+pub fn set_op(db: &Db, op: SetOperation, keys: Vec<String>) -> RedisValueRef {
+    match combine(db, op, &keys) {
+        Ok(members) => RArray(members.into_iter().map(RedisValueRef::String).collect()),
+        Err(err) => err,
+    }
+}
+
+/// SINTERSTORE, SUNIONSTORE, SDIFFSTORE: replace `dest` with the combined set
+/// and reply with its size. An empty result just deletes `dest`.
+pub fn set_op_store(db: &Db, op: SetOperation, dest: String, keys: Vec<String>) -> RedisValueRef {
+    let members = match combine(db, op, &keys) {
+        Ok(members) => members,
+        Err(err) => return err,
+    };
+    remove_keys(db, std::slice::from_ref(&dest));
+    let len = members.len();
+    if len > 0 {
+        db.dict
+            .insert(dest, RedisValue::Set(members.into_iter().collect()));
+        db.add_dirty(1);
+    }
+    RInt(len as i64)
+}
+
+pub fn scard(db: &Db, key: String) -> RedisValueRef {
+    match db.get_if_valid(&key) {
+        Some(entry) => match &*entry {
+            RedisValue::Set(set) => RInt(set.len() as i64),
+            _ => wrong_type(),
+        },
+        None => RInt(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{RedisDb, object_encoding, parser::RString};
+
+    fn setup() -> Arc<RedisDb> {
+        Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
+    }
+
+    fn s(value: &str) -> String {
+        value.to_string()
+    }
+
+    #[test]
+    fn test_sadd_srem() {
+        let db = setup();
+        assert_eq!(sadd(&db, s("set"), vec![s("a"), s("b"), s("a")]), RInt(2));
+        assert_eq!(sadd(&db, s("set"), vec![s("b"), s("c")]), RInt(1));
+        assert_eq!(scard(&db, s("set")), RInt(3));
+        assert_eq!(sismember(&db, s("set"), s("c")), RInt(1));
+        assert_eq!(sismember(&db, s("set"), s("z")), RInt(0));
+
+        let RedisValueRef::Array(members) = smembers(&db, s("set")) else {
+            panic!("SMEMBERS should return an array");
+        };
+        let mut members: Vec<String> = members.iter().map(|m| m.to_string()).collect();
+        members.sort();
+        assert_eq!(members, vec!["a", "b", "c"]);
+
+        assert_eq!(srem(&db, s("set"), vec![s("a"), s("z")]), RInt(1));
+        assert_eq!(srem(&db, s("set"), vec![s("b"), s("c")]), RInt(2));
+        assert!(db.dict.get("set").is_none());
+    }
+
+    fn sorted_members(db: &Db, key: &str) -> Vec<String> {
+        let RedisValueRef::Array(members) = smembers(db, s(key)) else {
+            panic!("SMEMBERS should return an array");
+        };
+        let mut members: Vec<String> = members.iter().map(|m| m.to_string()).collect();
+        members.sort();
+        members
+    }
+
+    #[test]
+    fn test_set_op_store() {
+        let db = setup();
+        sadd(&db, s("a"), vec![s("1"), s("2"), s("3")]);
+        sadd(&db, s("b"), vec![s("2"), s("3"), s("4")]);
+        let keys = || vec![s("a"), s("b")];
+
+        assert_eq!(
+            set_op_store(&db, SetOperation::Inter, s("dest"), keys()),
+            RInt(2)
+        );
+        assert_eq!(sorted_members(&db, "dest"), vec!["2", "3"]);
+        // The destination is overwritten, not added to
+        assert_eq!(
+            set_op_store(&db, SetOperation::Diff, s("dest"), keys()),
+            RInt(1)
+        );
+        assert_eq!(sorted_members(&db, "dest"), vec!["1"]);
+        assert_eq!(
+            set_op_store(&db, SetOperation::Union, s("dest"), keys()),
+            RInt(4)
+        );
+        assert_eq!(sorted_members(&db, "dest"), vec!["1", "2", "3", "4"]);
+
+        // A missing key is an empty set, so the intersection is empty
+        let keys = vec![s("a"), s("missing")];
+        assert_eq!(
+            set_op_store(&db, SetOperation::Inter, s("dest"), keys),
+            RInt(0)
+        );
+        assert!(db.dict.get("dest").is_none());
+    }
+
+    #[test]
+    fn test_set_op() {
+        let db = setup();
+        sadd(&db, s("a"), vec![s("1"), s("2")]);
+        sadd(&db, s("b"), vec![s("2")]);
+        let RedisValueRef::Array(members) = set_op(&db, SetOperation::Diff, vec![s("a"), s("b")])
+        else {
+            panic!("SDIFF should return an array");
+        };
+        assert_eq!(members, vec![RString("1")]);
+
+        db.dict
+            .insert(s("str"), RedisValue::String(bytes::Bytes::from("v")));
+        let keys = vec![s("a"), s("str")];
+        assert_eq!(set_op(&db, SetOperation::Union, keys.clone()), wrong_type());
+        assert_eq!(
+            set_op_store(&db, SetOperation::Union, s("a"), keys),
+            wrong_type()
+        );
+        assert_eq!(sorted_members(&db, "a"), vec!["1", "2"]);
+    }
+
+    #[test]
+    fn test_set_wrong_type() {
+        let db = setup();
+        db.dict
+            .insert(s("str"), RedisValue::String(bytes::Bytes::from("v")));
+        assert_eq!(sadd(&db, s("str"), vec![s("a")]), wrong_type());
+        assert_eq!(scard(&db, s("str")), wrong_type());
+    }
+
+    #[test]
+    fn test_set_encoding() {
+        let db = setup();
+        sadd(&db, s("set"), vec![s("1"), s("2"), s("-3")]);
+        assert_eq!(object_encoding(&db, s("set")), RString("intset"));
+
+        sadd(&db, s("set"), vec![s("abc")]);
+        assert_eq!(object_encoding(&db, s("set")), RString("listpack"));
+        // Removing the non-integer member doesn't bring back the intset
+        srem(&db, s("set"), vec![s("abc")]);
+        assert_eq!(object_encoding(&db, s("set")), RString("listpack"));
+
+        let members = (0..SET_MAX_LISTPACK_ENTRIES)
+            .map(|i| format!("m{}", i))
+            .collect();
+        sadd(&db, s("set"), members);
+        assert_eq!(object_encoding(&db, s("set")), RString("hashtable"));
+    }
+
+    #[test]
+    fn test_intset_limits() {
+        let db = setup();
+        // Non-canonical integers aren't intset members
+        sadd(&db, s("padded"), vec![s("007")]);
+        assert_eq!(object_encoding(&db, s("padded")), RString("listpack"));
+
+        let members = (0..=SET_MAX_INTSET_ENTRIES)
+            .map(|i| i.to_string())
+            .collect();
+        sadd(&db, s("big"), members);
+        assert_eq!(object_encoding(&db, s("big")), RString("hashtable"));
+    }
+}
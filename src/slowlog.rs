@@ -0,0 +1,175 @@
+/// The slow query log. Commands that take longer than
+/// `slowlog-log-slower-than` microseconds to execute are kept, newest first,
+/// in a log capped at `slowlog-max-len` entries.
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::{
+    Db,
+    interpreter::RedisCommand,
+    now_ms,
+    parser::{RArray, RInt, RSimpleString, RString, RedisValueRef},
+};
+
+pub const DEFAULT_LOG_SLOWER_THAN: i64 = 10_000;
+pub const DEFAULT_MAX_LEN: usize = 128;
+/// Like Redis, only this many arguments are kept per entry...
+const MAX_ARGC: usize = 32;
+/// ...and each one is cut to this many bytes
+const MAX_ARG_LEN: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowlogEntry {
+    pub id: u64,
+    /// Unix seconds when the command ran
+    pub timestamp: u64,
+    pub duration_us: u64,
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Slowlog {
+    entries: VecDeque<SlowlogEntry>,
+    next_id: u64,
+}
+
+/// Log `command` if it took at least `slowlog-log-slower-than`. Only then are
+/// its arguments copied out.
+pub fn record(db: &Db, command: &RedisCommand, elapsed: Duration) {
+    let threshold = db.slowlog_log_slower_than.load(Ordering::Relaxed);
+    let duration_us = elapsed.as_micros() as u64;
+    if threshold < 0 || duration_us < threshold as u64 {
+        return;
+    }
+    let mut slowlog = db.slowlog.lock().unwrap();
+    let entry = SlowlogEntry {
+        id: slowlog.next_id,
+        timestamp: now_ms() / 1000,
+        duration_us,
+        args: command_args(command.clone()),
+    };
+    slowlog.next_id += 1;
+    slowlog.entries.push_front(entry);
+    slowlog.entries.truncate(db.slowlog_max_len);
+}
+
+fn command_args(command: RedisCommand) -> Vec<String> {
+    let Ok(RedisValueRef::Array(values)) = command.try_into() else {
+        return Vec::new();
+    };
+    let argc = values.len();
+    let mut args: Vec<String> = values
+        .into_iter()
+        .take(if argc > MAX_ARGC {
+            MAX_ARGC - 1
+        } else {
+            MAX_ARGC
+        })
+        .map(|value| match value {
+            RedisValueRef::String(s) if s.len() > MAX_ARG_LEN => format!(
+                "{}... ({} more bytes)",
+                String::from_utf8_lossy(&s[..MAX_ARG_LEN]),
+                s.len() - MAX_ARG_LEN
+            ),
+            RedisValueRef::String(s) => String::from_utf8_lossy(&s).to_string(),
+            other => other.to_string(),
+        })
+        .collect();
+    if argc > MAX_ARGC {
+        args.push(format!("... ({} more arguments)", argc - MAX_ARGC + 1));
+    }
+    args
+}
+
+/// SLOWLOG GET [count]: the newest `count` entries (10 by default, all for -1)
+pub fn get(db: &Db, count: Option<i64>) -> RedisValueRef {
+    let slowlog = db.slowlog.lock().unwrap();
+    let count = match count.unwrap_or(10) {
+        -1 => slowlog.entries.len(),
+        count => count as usize,
+    };
+    RArray(
+        slowlog
+            .entries
+            .iter()
+            .take(count)
+            .map(|entry| {
+                RArray(vec![
+                    RInt(entry.id as i64),
+                    RInt(entry.timestamp as i64),
+                    RInt(entry.duration_us as i64),
+                    RArray(entry.args.iter().map(RString).collect()),
+                    // Client address and name aren't known at this level
+                    RString(""),
+                    RString(""),
+                ])
+            })
+            .collect(),
+    )
+}
+
+pub fn len(db: &Db) -> RedisValueRef {
+    RInt(db.slowlog.lock().unwrap().entries.len() as i64)
+}
+
+pub fn reset(db: &Db) -> RedisValueRef {
+    db.slowlog.lock().unwrap().entries.clear();
+    RSimpleString("OK")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RedisDb, handle_command};
+    use std::sync::Arc;
+
+    fn setup() -> Arc<RedisDb> {
+        Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
+    }
+
+    #[tokio::test]
+    async fn test_slowlog_debug_sleep() {
+        let db = setup();
+        db.slowlog_log_slower_than.store(50_000, Ordering::Relaxed);
+        handle_command(&db, RedisCommand::Ping(None)).await;
+        handle_command(&db, RedisCommand::DebugSleep(0.1)).await;
+        assert_eq!(len(&db), RInt(1));
+
+        let RedisValueRef::Array(entries) = get(&db, None) else {
+            panic!("SLOWLOG GET should return an array");
+        };
+        let RedisValueRef::Array(entry) = &entries[0] else {
+            panic!("Each slowlog entry should be an array");
+        };
+        assert_eq!(entry[0], RInt(0));
+        assert!(matches!(entry[2], RedisValueRef::Int(us) if us >= 100_000));
+        assert_eq!(
+            entry[3],
+            RArray(vec![RString("DEBUG"), RString("SLEEP"), RString("0.1")])
+        );
+
+        assert_eq!(reset(&db), RSimpleString("OK"));
+        assert_eq!(len(&db), RInt(0));
+    }
+
+    #[test]
+    fn test_slowlog_caps_entries() {
+        let mut db = RedisDb::new(None, "/tmp/redis-files", "dump.rdb");
+        db.slowlog_max_len = 2;
+        let db = Arc::new(db);
+        db.slowlog_log_slower_than.store(0, Ordering::Relaxed);
+        for i in 0..3 {
+            record(&db, &RedisCommand::Get(i.to_string()), Duration::ZERO);
+        }
+        assert_eq!(len(&db), RInt(2));
+        let RedisValueRef::Array(entries) = get(&db, Some(1)) else {
+            panic!("SLOWLOG GET should return an array");
+        };
+        // Newest first
+        assert!(matches!(&entries[..], [RedisValueRef::Array(entry)] if entry[0] == RInt(2)));
+
+        let args = command_args(RedisCommand::Rpush("k".to_string(), vec!["x".repeat(200)]));
+        assert_eq!(args[2], format!("{}... (72 more bytes)", "x".repeat(128)));
+    }
+}
@@ -0,0 +1,417 @@
+use std::{cmp::Ordering, collections::VecDeque};
+
+use bytes::Bytes;
+
+use crate::{
+    Db, RedisValue,
+    lists::{List, notify_waiters},
+    parser::{RArray, RError, RInt, RNull, RString, RedisValueRef},
+    remove_keys, take_keys,
+};
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SortOptions {
+    pub by: Option<String>,
+    pub limit: Option<(i64, i64)>, // offset, count
+    pub get: Vec<String>,
+    pub desc: bool,
+    pub alpha: bool,
+    pub store: Option<String>,
+}
+
+/// Elements of the list, set or zset at `key`, or None if it holds another type
+fn sort_elements(db: &Db, key: &str) -> Option<Vec<Bytes>> {
+    if let Some(entry) = db.get_if_valid(key) {
+        return match &*entry {
+            RedisValue::List(list) => Some(list.iter().cloned().collect()),
+            RedisValue::Set(set) => Some(set.iter().cloned().collect()),
+            _ => None,
+        };
+    }
+    let zsets = db.zsets.lock().unwrap();
+    Some(match zsets.get(key) {
+        Some(zset) => zset.members().cloned().collect(),
+        None => Vec::new(),
+    })
+}
+
+/// Resolve a BY/GET pattern for an element: the first `*` is replaced with the
+/// element and the resulting key's string value is returned. `#` in GET stands
+/// for the element itself.
+fn lookup_pattern(db: &Db, pattern: &str, element: &Bytes) -> Option<Bytes> {
+    if pattern == "#" {
+        return Some(element.clone());
+    }
+    let key = pattern.replacen('*', &String::from_utf8_lossy(element), 1);
+    let entry = db.get_if_valid(&key)?;
+    match &*entry {
+        RedisValue::String(value) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+fn parse_score(element: &[u8]) -> Option<f64> {
+    std::str::from_utf8(element)
+        .ok()?
+        .parse::<f64>()
+        .ok()
+        .filter(|score| !score.is_nan())
+}
+
+/// Apply LIMIT offset count. A negative count means everything after the offset.
+fn apply_limit<T>(items: Vec<T>, limit: Option<(i64, i64)>) -> Vec<T> {
+    match limit {
+        Some((offset, count)) => {
+            let offset = offset.max(0) as usize;
+            let count = if count < 0 {
+                usize::MAX
+            } else {
+                count as usize
+            };
+            items.into_iter().skip(offset).take(count).collect()
+        }
+        None => items,
+    }
+}
+
+/// SORT key: numeric by default, lexicographic with ALPHA. Elements with equal
+/// weights are ordered by their bytes so the result is deterministic.
+/// BY sorts on the values of other keys (or skips sorting when the pattern has
+/// no `*`), GET projects other keys into the result and STORE saves the result
+/// as a list instead of returning it.
+pub async fn sort(db: &Db, key: String, opts: SortOptions) -> RedisValueRef {
+    let Some(elements) = sort_elements(db, &key) else {
+        return RError("WRONGTYPE Operation against a key holding the wrong kind of value");
+    };
+
+    let dont_sort = opts.by.as_ref().is_some_and(|by| !by.contains('*'));
+    let weight = |element: &Bytes| match &opts.by {
+        Some(by) => lookup_pattern(db, by, element),
+        None => Some(element.clone()),
+    };
+    let mut sorted = if dont_sort {
+        elements
+    } else if opts.alpha {
+        let mut weighted: Vec<(Bytes, Bytes)> = elements
+            .into_iter()
+            .map(|element| (weight(&element).unwrap_or_default(), element))
+            .collect();
+        weighted.sort();
+        weighted.into_iter().map(|(_, element)| element).collect()
+    } else {
+        // Missing BY keys weigh 0
+        let scored: Option<Vec<(f64, Bytes)>> = elements
+            .into_iter()
+            .map(|element| match weight(&element) {
+                Some(weight) => parse_score(&weight).map(|score| (score, element)),
+                None => Some((0.0, element)),
+            })
+            .collect();
+        let Some(mut scored) = scored else {
+            return RError("ERR One or more scores can't be converted into double");
+        };
+        scored.sort_by(|(a, a_bytes), (b, b_bytes)| {
+            a.partial_cmp(b)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a_bytes.cmp(b_bytes))
+        });
+        scored.into_iter().map(|(_, element)| element).collect()
+    };
+    if opts.desc && !dont_sort {
+        sorted.reverse();
+    }
+
+    let sorted = apply_limit(sorted, opts.limit);
+    let values: Vec<Option<Bytes>> = if opts.get.is_empty() {
+        sorted.into_iter().map(Some).collect()
+    } else {
+        sorted
+            .iter()
+            .flat_map(|element| {
+                opts.get
+                    .iter()
+                    .map(|pattern| lookup_pattern(db, pattern, element))
+            })
+            .collect()
+    };
+
+    match opts.store {
+        Some(dest) => {
+            let list: VecDeque<Bytes> = values.into_iter().map(Option::unwrap_or_default).collect();
+            let mut list = List::from(list);
+            db.update_list_encoding(&mut list);
+            let len = list.len();
+            if list.is_empty() {
+                remove_keys(db, std::slice::from_ref(&dest));
+            } else {
+                // Whatever was there goes, zsets included, TTL and all
+                take_keys(db, std::slice::from_ref(&dest));
+                db.dict.insert(dest.clone(), RedisValue::List(list));
+                db.add_dirty(len as u64);
+                notify_waiters(db, &dest).await;
+            }
+            RInt(len as i64)
+        }
+        None => RArray(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Some(value) => RString(String::from_utf8_lossy(&value).to_string()),
+                    None => RNull(),
+                })
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        RedisDb,
+        lists::{blpop, lrange, rpush},
+        set, type_of, zset,
+    };
+
+    fn setup() -> Arc<RedisDb> {
+        Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn expected(values: &[&str]) -> RedisValueRef {
+        RArray(values.iter().map(|v| RString(*v)).collect())
+    }
+
+    #[tokio::test]
+    async fn test_sort_numeric() {
+        let db = setup();
+        rpush(&db, "l".to_string(), strings(&["3", "10", "1", "2.5"])).await;
+
+        let result = sort(&db, "l".to_string(), SortOptions::default()).await;
+        assert_eq!(result, expected(&["1", "2.5", "3", "10"]));
+
+        let opts = SortOptions {
+            desc: true,
+            ..Default::default()
+        };
+        let result = sort(&db, "l".to_string(), opts).await;
+        assert_eq!(result, expected(&["10", "3", "2.5", "1"]));
+    }
+
+    #[tokio::test]
+    async fn test_sort_alpha() {
+        let db = setup();
+        rpush(
+            &db,
+            "l".to_string(),
+            strings(&["banana", "apple", "10", "cherry"]),
+        )
+        .await;
+
+        let result = sort(&db, "l".to_string(), SortOptions::default()).await;
+        assert_eq!(
+            result,
+            RError("ERR One or more scores can't be converted into double")
+        );
+
+        let opts = SortOptions {
+            alpha: true,
+            ..Default::default()
+        };
+        let result = sort(&db, "l".to_string(), opts).await;
+        assert_eq!(result, expected(&["10", "apple", "banana", "cherry"]));
+
+        let opts = SortOptions {
+            alpha: true,
+            desc: true,
+            ..Default::default()
+        };
+        let result = sort(&db, "l".to_string(), opts).await;
+        assert_eq!(result, expected(&["cherry", "banana", "apple", "10"]));
+    }
+
+    #[tokio::test]
+    async fn test_sort_limit() {
+        let db = setup();
+        rpush(&db, "l".to_string(), strings(&["5", "4", "3", "2", "1"])).await;
+
+        let opts = SortOptions {
+            limit: Some((1, 2)),
+            ..Default::default()
+        };
+        let result = sort(&db, "l".to_string(), opts).await;
+        assert_eq!(result, expected(&["2", "3"]));
+
+        let opts = SortOptions {
+            limit: Some((3, -1)),
+            desc: true,
+            ..Default::default()
+        };
+        let result = sort(&db, "l".to_string(), opts).await;
+        assert_eq!(result, expected(&["2", "1"]));
+
+        let opts = SortOptions {
+            limit: Some((10, 5)),
+            ..Default::default()
+        };
+        let result = sort(&db, "l".to_string(), opts).await;
+        assert_eq!(result, expected(&[]));
+    }
+
+    #[tokio::test]
+    async fn test_sort_set() {
+        let db = setup();
+        let members = strings(&["3", "10", "1"]);
+        crate::sets::sadd(&db, "s".to_string(), members);
+        let result = sort(&db, "s".to_string(), SortOptions::default()).await;
+        assert_eq!(result, expected(&["1", "3", "10"]));
+    }
+
+    #[tokio::test]
+    async fn test_sort_padded_number() {
+        let db = setup();
+        rpush(&db, "l".to_string(), strings(&["2", " 1"])).await;
+        let result = sort(&db, "l".to_string(), SortOptions::default()).await;
+        assert_eq!(
+            result,
+            RError("ERR One or more scores can't be converted into double")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sort_missing_and_wrong_type() {
+        let db = setup();
+        let result = sort(&db, "missing".to_string(), SortOptions::default()).await;
+        assert_eq!(result, expected(&[]));
+
+        set(&db, "s".to_string(), "value".to_string()).await;
+        let result = sort(&db, "s".to_string(), SortOptions::default()).await;
+        assert!(matches!(result, RedisValueRef::Error(_)));
+    }
+
+    /// Users 1-4 with weights and names to sort and project by
+    async fn setup_weights() -> Arc<RedisDb> {
+        let db = setup();
+        rpush(&db, "users".to_string(), strings(&["1", "2", "3", "4"])).await;
+        for (id, weight, name) in [(1, 30, "carol"), (2, 10, "alice"), (3, 20, "bob")] {
+            set(&db, format!("weight_{}", id), weight.to_string()).await;
+            set(&db, format!("name_{}", id), name.to_string()).await;
+        }
+        db
+    }
+
+    #[tokio::test]
+    async fn test_sort_by() {
+        let db = setup_weights().await;
+        let opts = SortOptions {
+            by: Some("weight_*".to_string()),
+            ..Default::default()
+        };
+        // User 4 has no weight key and sorts as 0
+        let result = sort(&db, "users".to_string(), opts).await;
+        assert_eq!(result, expected(&["4", "2", "3", "1"]));
+
+        let opts = SortOptions {
+            by: Some("name_*".to_string()),
+            alpha: true,
+            desc: true,
+            ..Default::default()
+        };
+        let result = sort(&db, "users".to_string(), opts).await;
+        assert_eq!(result, expected(&["1", "3", "2", "4"]));
+
+        // A pattern without * skips sorting but still applies LIMIT
+        let opts = SortOptions {
+            by: Some("nosort".to_string()),
+            limit: Some((1, 2)),
+            ..Default::default()
+        };
+        let result = sort(&db, "users".to_string(), opts).await;
+        assert_eq!(result, expected(&["2", "3"]));
+    }
+
+    #[tokio::test]
+    async fn test_sort_get() {
+        let db = setup_weights().await;
+        let opts = SortOptions {
+            by: Some("weight_*".to_string()),
+            get: vec!["#".to_string(), "name_*".to_string()],
+            ..Default::default()
+        };
+        let result = sort(&db, "users".to_string(), opts).await;
+        assert_eq!(
+            result,
+            RArray(vec![
+                RString("4"),
+                RNull(),
+                RString("2"),
+                RString("alice"),
+                RString("3"),
+                RString("bob"),
+                RString("1"),
+                RString("carol"),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sort_store() {
+        let db = setup_weights().await;
+        let opts = SortOptions {
+            by: Some("weight_*".to_string()),
+            get: vec!["name_*".to_string()],
+            limit: Some((1, 3)),
+            store: Some("sorted".to_string()),
+            ..Default::default()
+        };
+        let result = sort(&db, "users".to_string(), opts).await;
+        assert_eq!(result, RInt(3));
+        let result = lrange(&db, "sorted".to_string(), 0, -1).await;
+        assert_eq!(result, expected(&["alice", "bob", "carol"]));
+
+        // Storing an empty result removes the destination
+        let opts = SortOptions {
+            store: Some("sorted".to_string()),
+            ..Default::default()
+        };
+        let result = sort(&db, "missing".to_string(), opts).await;
+        assert_eq!(result, RInt(0));
+        assert!(!db.dict.contains_key("sorted"));
+    }
+
+    #[tokio::test]
+    async fn test_sort_store_replaces_and_wakes() {
+        let db = setup();
+        rpush(&db, "l".to_string(), strings(&["2", "1"])).await;
+        zset::zadd(&db, "dest".to_string(), 1.0, "m".to_string());
+        let opts = || SortOptions {
+            store: Some("dest".to_string()),
+            ..Default::default()
+        };
+        sort(&db, "l".to_string(), opts()).await;
+        assert_eq!(type_of(&db, "dest"), "list");
+
+        // A client blocked on the destination gets the first stored element
+        let waiter = tokio::spawn({
+            let db = db.clone();
+            async move { blpop(&db, "out".to_string(), Some(2.0)).await }
+        });
+        while db.queued_waiters.load(std::sync::atomic::Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        let opts = SortOptions {
+            store: Some("out".to_string()),
+            ..Default::default()
+        };
+        sort(&db, "l".to_string(), opts).await;
+        let result = waiter.await.unwrap();
+        assert_eq!(result, expected(&["out", "1"]));
+        let result = lrange(&db, "out".to_string(), 0, -1).await;
+        assert_eq!(result, expected(&["2"]));
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
-    Db, RedisValue,
-    parser::{RArray, RNullArray, RString, RedisValueRef},
+    Db, RedisValue, expect_no_zset,
+    parser::{RArray, RInt, RNull, RNullArray, RSimpleString, RString, RedisValueRef},
     ref_error,
 };
 use bytes::Bytes;
@@ -12,6 +12,8 @@ use std::{
 
 type StreamData = Vec<(Bytes, Bytes)>;
 pub type StreamIdIn = (Option<u64>, Option<u64>);
+/// An XRANGE endpoint and whether it was given as exclusive with a `(` prefix
+pub type StreamBoundIn = (StreamIdIn, bool);
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StreamId {
@@ -39,6 +41,20 @@ impl StreamId {
         }
     }
 
+    pub fn decrement(&self) -> Self {
+        if self.seq > 0 {
+            Self {
+                ms: self.ms,
+                seq: self.seq - 1,
+            }
+        } else {
+            Self {
+                ms: self.ms.saturating_sub(1),
+                seq: u64::MAX,
+            }
+        }
+    }
+
     pub fn new(ms: Option<u64>, seq: Option<u64>) -> Self {
         let ms = ms.unwrap_or(
             SystemTime::now()
@@ -83,23 +99,46 @@ impl Default for StreamId {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct StreamCollection(BTreeMap<StreamId, StreamData>);
+pub struct StreamCollection {
+    entries: BTreeMap<StreamId, StreamData>,
+    /// The highest ID ever handed out, which XSETID can raise past the top
+    /// entry. New IDs must be greater than this even if the stream is empty.
+    last_id: Option<StreamId>,
+}
 
 impl StreamCollection {
     pub fn new() -> Self {
-        Self(BTreeMap::new())
+        Self {
+            entries: BTreeMap::new(),
+            last_id: None,
+        }
     }
 
     pub fn insert(&mut self, id: StreamId, data: StreamData) {
-        self.0.insert(id, data);
+        if self.last_id.as_ref().is_none_or(|last_id| &id > last_id) {
+            self.last_id = Some(id.clone());
+        }
+        self.entries.insert(id, data);
     }
 
     pub fn get(&self, key: &StreamId) -> Option<&StreamData> {
-        self.0.get(key)
+        self.entries.get(key)
     }
 
     pub fn all(&self) -> Vec<(&StreamId, &StreamData)> {
-        self.0.iter().collect()
+        self.entries.iter().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn last_id(&self) -> Option<&StreamId> {
+        self.last_id.as_ref()
     }
 }
 
@@ -161,6 +200,7 @@ pub async fn xadd(
     key: String,
     id_tuple: StreamIdIn,
     fields: Vec<(String, String)>,
+    nomkstream: bool,
 ) -> RedisValueRef {
     let (ms, seq) = id_tuple;
     if ms == Some(0) && seq == Some(0) {
@@ -174,8 +214,12 @@ pub async fn xadd(
     match db.get_mut_if_valid(&key_string) {
         Some(mut entry) => match &mut *entry {
             RedisValue::Stream(existing_stream) => {
-                let stream_id = match existing_stream.0.last_key_value() {
-                    Some((last_id, _)) => {
+                let stream_id = match existing_stream.last_id() {
+                    // The clock is behind the last ID, so carry on from it
+                    Some(last_id) if ms.is_none() && StreamId::new(None, None) <= *last_id => {
+                        last_id.increment()
+                    }
+                    Some(last_id) => {
                         let new_id = compute_stream_id(ms, seq, last_id);
                         if &new_id <= last_id {
                             return ref_error(
@@ -189,37 +233,72 @@ pub async fn xadd(
                 };
                 notify_stream_waiters(db, &key_string, &stream_id, &fields);
                 existing_stream.insert(stream_id.clone(), fields);
+                db.add_dirty(1);
 
                 RString(String::from_utf8_lossy(&stream_id.to_bytes()).to_string())
             }
             _ => ref_error("Attempted add to non-stream value"),
         },
         None => {
+            if let Err(err) = expect_no_zset(db, &key_string) {
+                return err;
+            }
+            // NOMKSTREAM: a missing stream stays missing
+            if nomkstream {
+                return RNull();
+            }
             let mut new_map = StreamCollection::new();
             let new_id = StreamId::new(ms, seq);
             new_map.insert(new_id.clone(), fields);
             db.dict.insert(key_string, RedisValue::Stream(new_map));
+            db.add_dirty(1);
             RString(String::from_utf8_lossy(&new_id.to_bytes()).to_string())
         }
     }
 }
 
-pub async fn xrange(db: &Db, key: String, start: StreamIdIn, stop: StreamIdIn) -> RedisValueRef {
+pub async fn xrange(
+    db: &Db,
+    key: String,
+    start: StreamBoundIn,
+    stop: StreamBoundIn,
+) -> RedisValueRef {
     let key_string = key;
-    let (start_ms, start_seq) = start;
-    let (stop_ms, stop_seq) = stop;
+    let ((start_ms, start_seq), start_exclusive) = start;
+    let ((stop_ms, stop_seq), stop_exclusive) = stop;
     match db.get_if_valid(&key_string) {
         Some(entry) => match &*entry {
             RedisValue::Stream(stream) => {
-                let start = StreamId {
+                let mut start = StreamId {
                     ms: start_ms.unwrap_or(0),
                     seq: start_seq.unwrap_or(0),
                 };
-                let stop = StreamId {
+                let mut stop = StreamId {
                     ms: stop_ms.unwrap_or(u64::MAX),
                     seq: stop_seq.unwrap_or(u64::MAX),
                 };
-                RArray(stream.0.range(start..=stop).map(|e| e.into()).collect())
+                if start_exclusive {
+                    if start == StreamId::MAX {
+                        return RArray(vec![]);
+                    }
+                    start = start.increment();
+                }
+                if stop_exclusive {
+                    if stop == StreamId::default() {
+                        return RArray(vec![]);
+                    }
+                    stop = stop.decrement();
+                }
+                if start > stop {
+                    return RArray(vec![]);
+                }
+                RArray(
+                    stream
+                        .entries
+                        .range(start..=stop)
+                        .map(|e| e.into())
+                        .collect(),
+                )
             }
             _ => ref_error("Attempted range on non-stream value"),
         },
@@ -227,6 +306,102 @@ pub async fn xrange(db: &Db, key: String, start: StreamIdIn, stop: StreamIdIn) -
     }
 }
 
+/// XSETID: set the stream's last ID, which may not go below its top entry
+pub fn xsetid(db: &Db, key: String, ms: u64, seq: u64) -> RedisValueRef {
+    let id = StreamId { ms, seq };
+    match db.get_mut_if_valid(&key) {
+        Some(mut entry) => match &mut *entry {
+            RedisValue::Stream(stream) => {
+                if stream
+                    .entries
+                    .last_key_value()
+                    .is_some_and(|(top, _)| &id < top)
+                {
+                    return ref_error(
+                        "ERR The ID specified in XSETID is smaller than the target stream top item",
+                    );
+                }
+                stream.last_id = Some(id);
+                db.add_dirty(1);
+                RSimpleString("OK")
+            }
+            _ => ref_error("WRONGTYPE Operation against a key holding the wrong kind of value"),
+        },
+        None => ref_error("ERR no such key"),
+    }
+}
+
+/// Entries XINFO STREAM FULL shows when no COUNT is given
+pub const XINFO_FULL_COUNT: usize = 10;
+
+fn id_value(id: Option<&StreamId>) -> RedisValueRef {
+    let id = id.map_or(Bytes::from("0-0"), StreamId::to_bytes);
+    RString(String::from_utf8_lossy(&id).to_string())
+}
+
+/// XINFO STREAM key [FULL [COUNT count]]: the stream's metadata along with
+/// its first and last entries, or with FULL the first `count` entries (0 for
+/// all). Entries are never deleted, so nothing is reported as deleted.
+/// Consumer groups aren't implemented (there is no XGROUP), so the group list
+/// is always empty. There's no radix tree behind a stream and no count of
+/// entries ever added, so those fields are left out rather than made up.
+pub fn xinfo_stream(db: &Db, key: String, full: Option<usize>) -> RedisValueRef {
+    let entry = match db.get_if_valid(&key) {
+        Some(entry) => entry,
+        None => return ref_error("ERR no such key"),
+    };
+    let RedisValue::Stream(stream) = &*entry else {
+        return ref_error("WRONGTYPE Operation against a key holding the wrong kind of value");
+    };
+    let mut info = vec![
+        RString("length"),
+        RInt(stream.len() as i64),
+        RString("last-generated-id"),
+        id_value(stream.last_id()),
+        RString("max-deleted-entry-id"),
+        id_value(None),
+        RString("recorded-first-entry-id"),
+        id_value(stream.entries.keys().next()),
+    ];
+    match full {
+        Some(count) => {
+            let count = if count == 0 { usize::MAX } else { count };
+            info.extend([
+                RString("entries"),
+                RArray(
+                    stream
+                        .entries
+                        .iter()
+                        .take(count)
+                        .map(|e| e.into())
+                        .collect(),
+                ),
+                RString("groups"),
+                RArray(vec![]),
+            ]);
+        }
+        None => {
+            let first = stream
+                .entries
+                .first_key_value()
+                .map_or(RNull(), |e| e.into());
+            let last = stream
+                .entries
+                .last_key_value()
+                .map_or(RNull(), |e| e.into());
+            info.extend([
+                RString("groups"),
+                RInt(0),
+                RString("first-entry"),
+                first,
+                RString("last-entry"),
+                last,
+            ]);
+        }
+    }
+    RArray(info)
+}
+
 async fn xread_results(
     db: &Db,
     streams: &Vec<(String, StreamIdIn)>,
@@ -249,7 +424,7 @@ async fn xread_results(
                         start = start.increment();
                     }
                     let results: Vec<RedisValueRef> = stream
-                        .0
+                        .entries
                         .range(start..=StreamId::MAX)
                         .map(|e| e.into())
                         .collect();
@@ -272,6 +447,23 @@ pub async fn xread(db: &Db, streams: Vec<(String, StreamIdIn)>) -> RedisValueRef
     }
 }
 
+/// Where a blocked XREAD reads from once woken: `$` becomes the last ID the
+/// stream has now, so a later read only picks up entries added since
+fn wait_start(db: &Db, key: &str, id: StreamIdIn) -> StreamIdIn {
+    let start = StreamId {
+        ms: id.0.unwrap_or(0),
+        seq: id.1.unwrap_or(0),
+    };
+    if start != StreamId::MAX {
+        return id;
+    }
+    let last = match db.get_if_valid(key).as_deref() {
+        Some(RedisValue::Stream(stream)) => stream.last_id().cloned(),
+        _ => None,
+    };
+    last.map_or((Some(0), Some(0)), |last| (Some(last.ms), Some(last.seq)))
+}
+
 pub async fn xread_block(
     db: &Db,
     streams: Vec<(String, StreamIdIn)>,
@@ -282,33 +474,56 @@ pub async fn xread_block(
             if !result.is_empty() {
                 RArray(result)
             } else {
-                let mut receivers = Vec::new();
-                for (key, _) in streams {
-                    let key_string = key;
+                let register = |key: String, id: StreamIdIn| {
                     let (tx, rx) = tokio::sync::oneshot::channel();
                     {
                         let mut waiters = db.stream_waiters.lock().unwrap();
-                        waiters.entry(key_string).or_default().push_back(tx);
+                        waiters.entry(key.clone()).or_default().push_back(tx);
                     }
-                    receivers.push(rx);
-                }
+                    async move { (key, id, rx.await) }
+                };
                 // Race all receivers - return on first success or timeout
-                let mut futs = receivers.into_iter().collect::<FuturesUnordered<_>>();
-
-                if timeout > 0 {
-                    if let Ok(Some(Ok(val))) =
-                        tokio::time::timeout(Duration::from_millis(timeout), futs.next()).await
-                    {
-                        return RArray(vec![val]);
-                    }
-                } else {
-                    while let Some(result) = futs.next().await {
-                        if let Ok(val) = result {
-                            return RArray(vec![val]);
+                let mut futs = streams
+                    .into_iter()
+                    .map(|(key, id)| {
+                        let id = wait_start(db, &key, id);
+                        register(key, id)
+                    })
+                    .collect::<FuturesUnordered<_>>();
+                let wait = async {
+                    while let Some((key, id, result)) = futs.next().await {
+                        match result {
+                            Ok(val) => return Some(val),
+                            // The sender was dropped without an entry. One
+                            // may still have come in while it was going, so
+                            // look before waiting again.
+                            Err(_) => {
+                                let stream = vec![(key, id)];
+                                if let Ok(mut found) = xread_results(db, &stream, true).await
+                                    && !found.is_empty()
+                                {
+                                    return Some(found.remove(0));
+                                }
+                                let [(key, id)] = <[_; 1]>::try_from(stream).unwrap();
+                                futs.push(register(key, id));
+                            }
                         }
                     }
+                    None
+                };
+
+                let val = if timeout > 0 {
+                    tokio::time::timeout(Duration::from_millis(timeout), wait)
+                        .await
+                        .ok()
+                        .flatten()
+                } else {
+                    wait.await
+                };
+                match val {
+                    Some(val) => RArray(vec![val]),
+                    None => RNullArray(),
                 }
-                RNullArray()
             }
         }
 
@@ -343,6 +558,167 @@ mod tests {
         assert!(id1 == id2);
     }
 
+    #[tokio::test]
+    async fn test_xadd_nomkstream() {
+        let db = setup();
+        let fields = || vec![("f".to_string(), "v".to_string())];
+        let result = xadd(&db, "missing".to_string(), (None, None), fields(), true).await;
+        assert_eq!(result, RNull());
+        assert!(db.dict.get("missing").is_none());
+
+        // An existing stream is added to as usual
+        xadd(&db, "s".to_string(), (Some(1), Some(1)), fields(), false).await;
+        let result = xadd(&db, "s".to_string(), (None, None), fields(), true).await;
+        assert!(matches!(result, RedisValueRef::String(_)));
+        assert_eq!(
+            db.dict.get("s").map(|s| match &*s {
+                RedisValue::Stream(stream) => stream.len(),
+                _ => 0,
+            }),
+            Some(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_object_encoding_stream() {
+        let db = setup();
+        let fields = vec![("f".to_string(), "v".to_string())];
+        xadd(&db, "s".to_string(), (None, None), fields, false).await;
+        assert_eq!(
+            crate::object_encoding(&db, "s".to_string()),
+            RString("stream")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_xinfo_stream_full() {
+        let db = setup();
+        for seq in 1..=3 {
+            let fields = vec![("f".to_string(), format!("v{}", seq))];
+            xadd(&db, "s".to_string(), (Some(1), Some(seq)), fields, false).await;
+        }
+        let entry = |seq: u64| {
+            RArray(vec![
+                RString(format!("1-{}", seq)),
+                RArray(vec![RString("f"), RString(format!("v{}", seq))]),
+            ])
+        };
+        let header = vec![
+            RString("length"),
+            RInt(3),
+            RString("last-generated-id"),
+            RString("1-3"),
+            RString("max-deleted-entry-id"),
+            RString("0-0"),
+            RString("recorded-first-entry-id"),
+            RString("1-1"),
+        ];
+
+        let mut expected = header.clone();
+        expected.extend([
+            RString("entries"),
+            RArray(vec![entry(1), entry(2)]),
+            RString("groups"),
+            RArray(vec![]),
+        ]);
+        assert_eq!(
+            xinfo_stream(&db, "s".to_string(), Some(2)),
+            RArray(expected)
+        );
+
+        let mut expected = header;
+        expected.extend([
+            RString("groups"),
+            RInt(0),
+            RString("first-entry"),
+            entry(1),
+            RString("last-entry"),
+            entry(3),
+        ]);
+        assert_eq!(xinfo_stream(&db, "s".to_string(), None), RArray(expected));
+
+        let RedisValueRef::Array(full) = xinfo_stream(&db, "s".to_string(), Some(0)) else {
+            panic!("XINFO STREAM should return an array");
+        };
+        assert_eq!(full[9], RArray(vec![entry(1), entry(2), entry(3)]));
+        assert_eq!(
+            xinfo_stream(&db, "missing".to_string(), None),
+            ref_error("ERR no such key")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_xread_block_forever_survives_dropped_sender() {
+        let db = setup();
+        let key = "stream".to_string();
+        let fields = vec![("f".to_string(), "v".to_string())];
+        xadd(&db, key.clone(), (Some(1), Some(1)), fields.clone(), false).await;
+        let reader = tokio::spawn({
+            let db = db.clone();
+            let key = key.clone();
+            async move { xread_block(&db, vec![(key, (Some(1), Some(1)))], 0).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // Drop the waiter's sender without sending anything
+        db.stream_waiters.lock().unwrap().clear();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!reader.is_finished(), "XREAD BLOCK 0 gave up early");
+
+        xadd(&db, key.clone(), (Some(2), Some(1)), fields, false).await;
+        let result = tokio::time::timeout(Duration::from_secs(1), reader)
+            .await
+            .unwrap()
+            .unwrap();
+        let expected = RArray(vec![RArray(vec![
+            RString(key),
+            RArray(vec![RArray(vec![
+                RString("2-1"),
+                RArray(vec![RString("f"), RString("v")]),
+            ])]),
+        ])]);
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    async fn test_xread_block_rereads_after_dropped_sender() {
+        let db = setup();
+        let key = "stream".to_string();
+        let fields = vec![("f".to_string(), "v".to_string())];
+        xadd(&db, key.clone(), (Some(1), Some(1)), fields.clone(), false).await;
+        let reader = tokio::spawn({
+            let db = db.clone();
+            let key = key.clone();
+            let dollar = (Some(u64::MAX), Some(u64::MAX));
+            async move { xread_block(&db, vec![(key, dollar)], 0).await }
+        });
+        // Resolve the `$` above by waiting for the registration
+        while db.stream_waiters.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        // An entry arrives without the waiter hearing of it, then its sender goes
+        if let Some(mut entry) = db.dict.get_mut(&key)
+            && let RedisValue::Stream(stream) = &mut *entry
+        {
+            let fields = vec![(Bytes::from("f"), Bytes::from("v"))];
+            stream.insert(StreamId::new(Some(2), Some(1)), fields);
+        }
+        db.stream_waiters.lock().unwrap().clear();
+        let result = tokio::time::timeout(Duration::from_secs(1), reader)
+            .await
+            .expect("XREAD BLOCK missed the entry")
+            .unwrap();
+        let expected = RArray(vec![RArray(vec![
+            RString(key),
+            RArray(vec![RArray(vec![
+                RString("2-1"),
+                RArray(vec![RString("f"), RString("v")]),
+            ])]),
+        ])]);
+        assert_eq!(result, expected);
+    }
+
     #[test]
     fn test_stream_id_new() {
         let id1 = StreamId::new(None, None);
@@ -401,7 +777,7 @@ mod tests {
             ("field2".to_string(), "value2".to_string()),
         ];
 
-        let result = xadd(&db, key.clone(), (time, seq), fields.clone()).await;
+        let result = xadd(&db, key.clone(), (time, seq), fields.clone(), false).await;
         assert_eq!(result, RString("1-1"));
 
         let redis_val = db.get_if_valid(&key).unwrap().clone();
@@ -428,10 +804,10 @@ mod tests {
         let seq = Some(1);
         let fields = vec![];
 
-        let result = xadd(&db, key.clone(), (time, seq), fields.clone()).await;
+        let result = xadd(&db, key.clone(), (time, seq), fields.clone(), false).await;
         assert_eq!(result, RString("1-1"));
 
-        let result = xadd(&db, key.clone(), (Some(1), None), fields.clone()).await;
+        let result = xadd(&db, key.clone(), (Some(1), None), fields.clone(), false).await;
         assert_eq!(result, RString("1-2"));
     }
 
@@ -443,10 +819,10 @@ mod tests {
         let seq = Some(1);
         let fields = vec![];
 
-        let result = xadd(&db, key.clone(), (time, seq), fields.clone()).await;
+        let result = xadd(&db, key.clone(), (time, seq), fields.clone(), false).await;
         assert_eq!(result, RString("1-1"));
 
-        let result = xadd(&db, key.clone(), (time, seq), fields.clone()).await;
+        let result = xadd(&db, key.clone(), (time, seq), fields.clone(), false).await;
         assert_eq!(
             result,
             ref_error(
@@ -463,11 +839,11 @@ mod tests {
         let seq = Some(2);
         let fields = vec![];
 
-        let result = xadd(&db, key.clone(), (time, seq), fields.clone()).await;
+        let result = xadd(&db, key.clone(), (time, seq), fields.clone(), false).await;
         assert_eq!(result, RString("2-2"));
 
         // less ms
-        let result = xadd(&db, key.clone(), (Some(1), Some(3)), fields.clone()).await;
+        let result = xadd(&db, key.clone(), (Some(1), Some(3)), fields.clone(), false).await;
         assert_eq!(
             result,
             ref_error(
@@ -476,13 +852,109 @@ mod tests {
         );
 
         // less seq
-        let result = xadd(&db, key.clone(), (Some(2), Some(1)), fields.clone()).await;
+        let result = xadd(&db, key.clone(), (Some(2), Some(1)), fields.clone(), false).await;
+        assert_eq!(
+            result,
+            ref_error(
+                "ERR The ID specified in XADD is equal or smaller than the target stream top item"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_xsetid_empty_stream() {
+        let db = setup();
+        let key = "test_stream".to_string();
+        db.dict
+            .insert(key.clone(), RedisValue::Stream(StreamCollection::new()));
+        assert_eq!(xsetid(&db, key.clone(), 5, 5), RSimpleString("OK"));
+
+        let result = xadd(&db, key.clone(), (Some(5), Some(5)), vec![], false).await;
         assert_eq!(
             result,
             ref_error(
                 "ERR The ID specified in XADD is equal or smaller than the target stream top item"
             )
         );
+        let RedisValueRef::String(id) = xadd(&db, key.clone(), (None, None), vec![], false).await
+        else {
+            panic!("XADD should return the new ID");
+        };
+        let (ms, seq) = String::from_utf8_lossy(&id)
+            .split_once('-')
+            .map(|(ms, seq)| (ms.parse().unwrap(), seq.parse().unwrap()))
+            .unwrap();
+        assert!(StreamId { ms, seq } > StreamId { ms: 5, seq: 5 });
+    }
+
+    #[tokio::test]
+    async fn test_xsetid_below_top() {
+        let db = setup();
+        let key = "test_stream".to_string();
+        xadd(&db, key.clone(), (Some(3), Some(1)), vec![], false).await;
+        assert_eq!(
+            xsetid(&db, key.clone(), 2, 0),
+            ref_error("ERR The ID specified in XSETID is smaller than the target stream top item")
+        );
+        assert_eq!(xsetid(&db, key.clone(), 9, 0), RSimpleString("OK"));
+        let result = xadd(&db, key.clone(), (Some(4), Some(0)), vec![], false).await;
+        assert_eq!(
+            result,
+            ref_error(
+                "ERR The ID specified in XADD is equal or smaller than the target stream top item"
+            )
+        );
+        assert_eq!(
+            xsetid(&db, "missing".to_string(), 1, 1),
+            ref_error("ERR no such key")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_xrange_exclusive() {
+        let db = setup();
+        let key = "test_stream".to_string();
+        for seq in 1..=3 {
+            let fields = vec![("field".to_string(), "value".to_string())];
+            xadd(&db, key.clone(), (Some(1), Some(seq)), fields, false).await;
+        }
+        let ids = |result: RedisValueRef| match result {
+            RedisValueRef::Array(entries) => entries
+                .into_iter()
+                .map(|entry| match entry {
+                    RedisValueRef::Array(parts) => parts[0].clone(),
+                    other => other,
+                })
+                .collect::<Vec<_>>(),
+            other => vec![other],
+        };
+
+        let result = xrange(
+            &db,
+            key.clone(),
+            ((Some(1), Some(1)), true),
+            ((None, None), false),
+        )
+        .await;
+        assert_eq!(ids(result), vec![RString("1-2"), RString("1-3")]);
+
+        let result = xrange(
+            &db,
+            key.clone(),
+            ((None, None), false),
+            ((Some(1), Some(3)), true),
+        )
+        .await;
+        assert_eq!(ids(result), vec![RString("1-1"), RString("1-2")]);
+
+        let result = xrange(
+            &db,
+            key.clone(),
+            ((Some(1), Some(2)), true),
+            ((Some(1), Some(2)), true),
+        )
+        .await;
+        assert_eq!(ids(result), vec![]);
     }
 
     #[tokio::test]
@@ -513,10 +985,16 @@ mod tests {
             ),
         ];
         for entry in entries {
-            xadd(&db, key.clone(), entry.0, entry.1).await;
+            xadd(&db, key.clone(), entry.0, entry.1, false).await;
         }
 
-        let result = xrange(&db, key.clone(), (Some(0), Some(0)), (Some(2), Some(2))).await;
+        let result = xrange(
+            &db,
+            key.clone(),
+            ((Some(0), Some(0)), false),
+            ((Some(2), Some(2)), false),
+        )
+        .await;
         let expected = RArray(vec![
             RArray(vec![
                 RString("1-0"),
@@ -548,7 +1026,13 @@ mod tests {
         ]);
         assert_eq!(result, expected);
 
-        let result = xrange(&db, key.clone(), (Some(0), Some(0)), (Some(2), Some(1))).await;
+        let result = xrange(
+            &db,
+            key.clone(),
+            ((Some(0), Some(0)), false),
+            ((Some(2), Some(1)), false),
+        )
+        .await;
         let expected = RArray(vec![
             RArray(vec![
                 RString("1-0"),
@@ -571,7 +1055,13 @@ mod tests {
         ]);
         assert_eq!(result, expected);
 
-        let result = xrange(&db, key.clone(), (None, None), (None, None)).await;
+        let result = xrange(
+            &db,
+            key.clone(),
+            ((None, None), false),
+            ((None, None), false),
+        )
+        .await;
         let expected = RArray(vec![
             RArray(vec![
                 RString("1-0"),
@@ -635,7 +1125,7 @@ mod tests {
             ),
         ];
         for entry in entries {
-            xadd(&db, key1.clone(), (entry.0, entry.1), entry.2).await;
+            xadd(&db, key1.clone(), (entry.0, entry.1), entry.2, false).await;
         }
 
         let key2 = "test_stream2".to_string();
@@ -666,7 +1156,7 @@ mod tests {
             ),
         ];
         for entry in entries2 {
-            xadd(&db, key2.clone(), (entry.0, entry.1), entry.2).await;
+            xadd(&db, key2.clone(), (entry.0, entry.1), entry.2, false).await;
         }
 
         let result = xread(
@@ -757,13 +1247,20 @@ mod tests {
             ("field2".to_string(), "value2".to_string()),
         ];
         // less than what we query for
-        xadd(&db, key.clone(), (time, seq), fields.clone()).await;
+        xadd(&db, key.clone(), (time, seq), fields.clone(), false).await;
 
         let db_clone = db.clone();
         let key_clone = key.clone();
         tokio::spawn(async move {
             tokio::time::sleep(Duration::from_millis(50)).await;
-            xadd(&db_clone, key_clone, (Some(2), Some(1)), fields.clone()).await;
+            xadd(
+                &db_clone,
+                key_clone,
+                (Some(2), Some(1)),
+                fields.clone(),
+                false,
+            )
+            .await;
         });
 
         let start = std::time::Instant::now();
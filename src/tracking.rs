@@ -0,0 +1,134 @@
+/// Client-side caching. Connections with CLIENT TRACKING ON have the keys they
+/// read remembered here, and get an `invalidate` push the next time one of
+/// those keys is written. Like Redis, a key is forgotten once invalidated and
+/// has to be read again to be tracked.
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+    Db,
+    interpreter::RedisCommand,
+    parser::{RArray, RPush, RString, RedisValueRef},
+};
+
+pub type Invalidations = UnboundedSender<String>;
+
+/// Update the tracking table after a command has run. Writes invalidate the
+/// keys they touched, and FLUSHDB/FLUSHALL every tracked key. Reads register
+/// their keys for `tracker` if the connection is tracking.
+pub fn record(db: &Db, command: &RedisCommand, tracker: Option<&Invalidations>) {
+    if matches!(
+        command,
+        RedisCommand::FlushDb(_) | RedisCommand::FlushAll(_)
+    ) {
+        invalidate_all(db);
+    } else if command.can_replicate() {
+        invalidate(db, command.keys());
+    } else if let Some(tracker) = tracker {
+        track(db, command.keys(), tracker);
+    }
+}
+
+fn track(db: &Db, keys: Vec<&String>, tracker: &Invalidations) {
+    let mut tracking = db.tracking.lock().unwrap();
+    for key in keys {
+        let trackers = tracking.entry(key.clone()).or_default();
+        if !trackers.iter().any(|t| t.same_channel(tracker)) {
+            trackers.push(tracker.clone());
+        }
+    }
+}
+
+fn invalidate(db: &Db, keys: Vec<&String>) {
+    let mut tracking = db.tracking.lock().unwrap();
+    for key in keys {
+        // Connections that have gone away or turned tracking off just fail to send
+        for tracker in tracking.remove(key).unwrap_or_default() {
+            let _ = tracker.send(key.clone());
+        }
+    }
+}
+
+fn invalidate_all(db: &Db) {
+    let tracking = std::mem::take(&mut *db.tracking.lock().unwrap());
+    for (key, trackers) in tracking {
+        for tracker in trackers {
+            let _ = tracker.send(key.clone());
+        }
+    }
+}
+
+/// Stop tracking anything for `tracker`, when its connection turns tracking
+/// off or goes away. Channels of connections that went away without saying
+/// so are dropped along the way.
+pub fn forget(db: &Db, tracker: &Invalidations) {
+    db.tracking.lock().unwrap().retain(|_, trackers| {
+        trackers.retain(|t| !t.same_channel(tracker) && !t.is_closed());
+        !trackers.is_empty()
+    });
+}
+
+/// The RESP3 push frame telling a client to drop `key` from its cache
+pub fn invalidation_message(key: String) -> RedisValueRef {
+    RPush(vec![RString("invalidate"), RArray(vec![RString(key)])])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RedisDb;
+    use std::sync::Arc;
+
+    fn setup() -> Arc<RedisDb> {
+        Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
+    }
+
+    #[test]
+    fn test_invalidate_tracked_key() {
+        let db = setup();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        record(&db, &RedisCommand::Get("key".to_string()), Some(&tx));
+        record(&db, &RedisCommand::Get("key".to_string()), Some(&tx));
+        record(&db, &RedisCommand::Get("other".to_string()), None);
+        assert_eq!(db.tracking.lock().unwrap()["key"].len(), 1);
+        assert!(!db.tracking.lock().unwrap().contains_key("other"));
+
+        let write = RedisCommand::Set("key".to_string(), "value".to_string());
+        record(&db, &write, Some(&tx));
+        assert_eq!(rx.try_recv().unwrap(), "key");
+        // The key has to be read again before it's tracked
+        record(&db, &write, Some(&tx));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_flush_invalidates_everything() {
+        let db = setup();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        record(&db, &RedisCommand::Get("a".to_string()), Some(&tx));
+        record(&db, &RedisCommand::Get("b".to_string()), Some(&tx));
+        record(&db, &RedisCommand::FlushAll(false), None);
+        let mut invalidated = vec![rx.try_recv().unwrap(), rx.try_recv().unwrap()];
+        invalidated.sort();
+        assert_eq!(invalidated, ["a", "b"]);
+        assert!(db.tracking.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_forget_tracker() {
+        let db = setup();
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let (other, _other_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (gone, gone_rx) = tokio::sync::mpsc::unbounded_channel();
+        for tracker in [&tx, &other, &gone] {
+            record(&db, &RedisCommand::Get("key".to_string()), Some(tracker));
+        }
+        record(&db, &RedisCommand::Get("mine".to_string()), Some(&tx));
+        drop(gone_rx);
+
+        forget(&db, &tx);
+        let tracking = db.tracking.lock().unwrap();
+        assert!(!tracking.contains_key("mine"));
+        assert_eq!(tracking["key"].len(), 1);
+        assert!(tracking["key"][0].same_channel(&other));
+    }
+}
@@ -1,17 +1,33 @@
-use std::{collections::HashMap, ops::Bound};
+use std::{collections::HashMap, ops::Bound, sync::MutexGuard};
 
-use ordered_float::NotNan;
+use bytes::Bytes;
+use ordered_float::{FloatIsNan, NotNan};
 use skiplist::OrderedSkipList;
 
 use crate::{
-    Db,
+    Db, normalize_range,
     parser::{RArray, RInt, RNull, RString, RedisValueRef},
+    rdb::RdbValue,
+    ref_error, remove_keys, wrong_type,
 };
 
 type Score = NotNan<f64>;
+/// A score range endpoint and whether it was given as exclusive with a `(`
+/// prefix
+pub type ScoreBound = (f64, bool);
+/// A lex range endpoint: `-` and `+` for before the first and after the last
+/// member, otherwise a member given with `[` (inclusive) or `(` (exclusive).
+/// Either endpoint can be any of these; `+` as the minimum just selects nothing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    Min,
+    Max,
+    Inclusive(String),
+    Exclusive(String),
+}
 
 #[derive(Debug, PartialEq, Clone)]
-struct ListNode(Score, String);
+struct ListNode(Score, Bytes);
 
 impl PartialOrd for ListNode {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
@@ -22,9 +38,11 @@ impl PartialOrd for ListNode {
     }
 }
 
+/// Members are kept as bytes, so ones loaded from a dump needn't be UTF-8,
+/// and sort bytewise among equal scores as in Redis
 #[derive(Debug)]
 pub struct ZSet {
-    map: HashMap<String, Score>,
+    map: HashMap<Bytes, Score>,
     list: OrderedSkipList<ListNode>,
 }
 
@@ -36,9 +54,10 @@ impl ZSet {
         }
     }
 
-    /// Add a member to the zset, returning the number of elements added
-    fn add(&mut self, member: String, score: f64) -> usize {
-        let score = Score::new(score).unwrap();
+    /// Add a member to the zset, returning the number of elements added.
+    /// NaN can't be placed in score order, so it is refused.
+    fn add(&mut self, member: Bytes, score: f64) -> Result<usize, FloatIsNan> {
+        let score = Score::new(score)?;
         match self.map.get_mut(&member) {
             Some(existing) => {
                 // find and remove item from the skiplist
@@ -49,20 +68,79 @@ impl ZSet {
                 // insert a new value into the skiplist
                 let new_member = ListNode(score, member);
                 self.list.insert(new_member);
-                0
+                Ok(0)
             }
             None => {
                 self.map.insert(member.clone(), score);
                 self.list.insert(ListNode(score, member));
-                1
+                Ok(1)
             }
         }
     }
 
+    /// Members in score order
+    pub fn members(&self) -> impl Iterator<Item = &Bytes> {
+        self.list.iter().map(|node| &node.1)
+    }
+
+    /// Members and their scores, in score order
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, f64)> {
+        self.list.iter().map(|node| (&node.1, node.0.into_inner()))
+    }
+
+    /// Nodes ranked `start..=stop`, counting from the end for negative indexes
+    fn rank_range(&self, start: i64, stop: i64) -> Vec<&ListNode> {
+        match normalize_range(start, stop, self.list.len()) {
+            Some((start, stop)) => self.list.index_range(start..stop + 1).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Nodes whose score lies between `min` and `max`
+    fn score_range(&self, min: ScoreBound, max: ScoreBound) -> Vec<&ListNode> {
+        let ((min, min_exclusive), (max, max_exclusive)) = (min, max);
+        let start = ListNode(Score::new(min).unwrap(), Bytes::new());
+        self.list
+            .range(Bound::Included(&start), Bound::Unbounded)
+            .skip_while(|node| min_exclusive && *node.0 == min)
+            .take_while(|node| *node.0 < max || (!max_exclusive && *node.0 == max))
+            .collect()
+    }
+
+    /// Nodes whose member sorts between `min` and `max`. Lex ranges are only
+    /// meaningful when every member has the same score.
+    fn lex_range(&self, min: &LexBound, max: &LexBound) -> Vec<&ListNode> {
+        let above_min = |member: &[u8]| match min {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(min) => member >= min.as_bytes(),
+            LexBound::Exclusive(min) => member > min.as_bytes(),
+        };
+        let below_max = |member: &[u8]| match max {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(max) => member <= max.as_bytes(),
+            LexBound::Exclusive(max) => member < max.as_bytes(),
+        };
+        self.list
+            .iter()
+            .filter(|node| above_min(&node.1) && below_max(&node.1))
+            .collect()
+    }
+
+    /// The zset as it is written to a dump
+    pub fn to_rdb(&self) -> RdbValue {
+        RdbValue::ZSet(
+            self.iter()
+                .map(|(member, score)| (member.to_vec(), score))
+                .collect(),
+        )
+    }
+
     /// Remove a member from the zset, returning the number of elements removed
-    fn remove(&mut self, member: String) -> usize {
-        match self.map.remove(&member) {
-            Some(score) => {
+    fn remove(&mut self, member: &[u8]) -> usize {
+        match self.map.remove_entry(member) {
+            Some((member, score)) => {
                 let node = ListNode(score, member);
                 self.list.remove(&node);
                 1
@@ -72,34 +150,151 @@ impl ZSet {
     }
 }
 
+/// Build a zset from member/score pairs, as when loading a dump. NaN isn't a
+/// valid score, so such members are dropped.
+impl FromIterator<(Bytes, f64)> for ZSet {
+    fn from_iter<I: IntoIterator<Item = (Bytes, f64)>>(iter: I) -> Self {
+        let mut zset = ZSet::new();
+        for (member, score) in iter {
+            let _ = zset.add(member, score);
+        }
+        zset
+    }
+}
+
+/// ZADD flags. NX and XX decide whether new or existing members may be
+/// touched, GT and LT only let existing scores move in one direction.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ZAddOptions {
+    pub nx: bool,
+    pub xx: bool,
+    pub gt: bool,
+    pub lt: bool,
+    /// Reply with the number of members added or updated
+    pub ch: bool,
+    /// Add the score to the existing one and reply with the result, like ZINCRBY
+    pub incr: bool,
+}
+
+/// Lock the zsets once `set` has been dropped if its TTL has passed, so an
+/// expired zset reads as missing like any other key
+fn lock_zsets<'a>(db: &'a Db, set: &str) -> MutexGuard<'a, HashMap<String, ZSet>> {
+    db.remove_if_expired(set);
+    db.zsets.lock().unwrap()
+}
+
 pub fn zadd(db: &Db, set: String, score: f64, member: String) -> RedisValueRef {
-    let mut set_guard = db.zsets.lock().unwrap();
-    let cnt = match set_guard.get_mut(&set) {
-        Some(zset) => zset.add(member, score),
-        None => {
-            let mut zset = ZSet::new();
-            let cnt = zset.add(member, score);
-            set_guard.insert(set, zset);
-            cnt
+    zadd_with_options(db, set, vec![(score, member)], &ZAddOptions::default())
+}
+
+/// What adding a single member did: whether it was new, and its final score
+struct Added {
+    added: bool,
+    changed: bool,
+    score: f64,
+}
+
+/// Apply one score/member pair, or None if the flags ruled it out. Fails if
+/// the score is NaN, which INCR produces by adding inf to -inf.
+fn add_member(
+    db: &Db,
+    sets: &mut HashMap<String, ZSet>,
+    set: &str,
+    score: f64,
+    member: String,
+    opts: &ZAddOptions,
+) -> Result<Option<Added>, FloatIsNan> {
+    let existing = sets
+        .get(set)
+        .and_then(|zset| zset.map.get(member.as_bytes()))
+        .map(|score| score.into_inner());
+    let score = match existing {
+        Some(existing) if opts.incr => existing + score,
+        _ => score,
+    };
+    let skip = match existing {
+        None => opts.xx,
+        Some(existing) => {
+            opts.nx || (opts.gt && score <= existing) || (opts.lt && score >= existing)
         }
     };
+    if skip {
+        return Ok(None);
+    }
+    // Checked before the set is created, so a refused score leaves no trace
+    if score.is_nan() {
+        return Err(FloatIsNan);
+    }
+
+    let changed = existing != Some(score);
+    if changed {
+        db.add_dirty(1);
+    }
+    let added = sets
+        .entry(set.to_string())
+        .or_insert_with(ZSet::new)
+        .add(Bytes::from(member), score)?;
+    Ok(Some(Added {
+        added: added > 0,
+        changed,
+        score,
+    }))
+}
+
+/// ZADD: apply each pair in order, replying with the number of members added
+/// (or changed, with CH). INCR takes a single pair and replies with its score.
+pub fn zadd_with_options(
+    db: &Db,
+    set: String,
+    members: Vec<(f64, String)>,
+    opts: &ZAddOptions,
+) -> RedisValueRef {
+    // Nothing is added unless every score is valid
+    if members.iter().any(|(score, _)| score.is_nan()) {
+        return ref_error("ERR value is not a valid float");
+    }
+    let mut set_guard = lock_zsets(db, &set);
+    // Any other type lives in the dict
+    if db.dict.contains_key(&set) {
+        return wrong_type();
+    }
+    if opts.incr {
+        let Ok([(score, member)]) = <[_; 1]>::try_from(members) else {
+            return ref_error("ERR INCR option supports a single increment-element pair");
+        };
+        return match add_member(db, &mut set_guard, &set, score, member, opts) {
+            Ok(Some(added)) => RString(added.score.to_string()),
+            Ok(None) => RNull(),
+            Err(FloatIsNan) => ref_error("ERR resulting score is not a number (NaN)"),
+        };
+    }
+    let cnt = members
+        .into_iter()
+        .filter_map(|(score, member)| {
+            add_member(db, &mut set_guard, &set, score, member, opts)
+                .ok()
+                .flatten()
+        })
+        .filter(|added| if opts.ch { added.changed } else { added.added })
+        .count();
     RInt(cnt as i64)
 }
 
 pub fn zrem(db: &Db, set: String, member: String) -> RedisValueRef {
-    let mut set_guard = db.zsets.lock().unwrap();
+    let mut set_guard = lock_zsets(db, &set);
     let cnt = match set_guard.get_mut(&set) {
-        Some(zset) => zset.remove(member),
+        Some(zset) => zset.remove(member.as_bytes()),
         None => 0,
     };
+    db.add_dirty(cnt as u64);
     RInt(cnt as i64)
 }
 
 pub fn zrank(db: &Db, set: String, member: String) -> RedisValueRef {
-    let set_guard = db.zsets.lock().unwrap();
+    let set_guard = lock_zsets(db, &set);
     if let Some(zset) = set_guard.get(&set)
-        && let Some(score) = zset.map.get(&member)
-        && let Some(rank) = zset.list.index_of(&ListNode(*score, member))
+        && let Some((member, score)) = zset.map.get_key_value(member.as_bytes())
+        && let Some(rank) = zset.list.index_of(&ListNode(*score, member.clone()))
     {
         RInt(rank as i64)
     } else {
@@ -107,28 +302,15 @@ pub fn zrank(db: &Db, set: String, member: String) -> RedisValueRef {
     }
 }
 
-fn normalize_index(index: i64, len: usize) -> usize {
-    if index < 0 {
-        (len as i64 + index).max(0) as usize
-    } else {
-        index as usize
-    }
-}
-
 /// Range by index
 pub fn zrange(db: &Db, set: String, start: i64, stop: i64) -> RedisValueRef {
-    let set_guard = db.zsets.lock().unwrap();
+    let set_guard = lock_zsets(db, &set);
     match set_guard.get(&set) {
         Some(zset) => {
-            let len = zset.list.len();
-            let start = normalize_index(start, len);
-            let stop = normalize_index(stop, len);
-            let start = start.max(0);
-            let stop = stop.min(len - 1);
             let range = zset
-                .list
-                .index_range(start..stop + 1)
-                .map(|node| RString(node.1.clone()))
+                .rank_range(start, stop)
+                .into_iter()
+                .map(|node| RedisValueRef::String(node.1.clone()))
                 .collect();
             RArray(range)
         }
@@ -138,19 +320,13 @@ pub fn zrange(db: &Db, set: String, start: i64, stop: i64) -> RedisValueRef {
 
 /// Range by Score
 pub fn zrangebyscore(db: &Db, set: String, start: f64, stop: f64) -> RedisValueRef {
-    let set_guard = db.zsets.lock().unwrap();
+    let set_guard = lock_zsets(db, &set);
     match set_guard.get(&set) {
         Some(zset) => {
-            let start_score = Score::new(start).unwrap();
-            let stop_score = Score::new(stop).unwrap();
-
-            let start_node = ListNode(start_score, String::new());
-
-            let range: Vec<_> = zset
-                .list
-                .range(Bound::Included(&start_node), Bound::Unbounded)
-                .take_while(|node| node.0 <= stop_score)
-                .map(|node| RString(node.1.clone()))
+            let range = zset
+                .score_range((start, false), (stop, false))
+                .into_iter()
+                .map(|node| RedisValueRef::String(node.1.clone()))
                 .collect();
             RArray(range)
         }
@@ -158,8 +334,49 @@ pub fn zrangebyscore(db: &Db, set: String, start: f64, stop: f64) -> RedisValueR
     }
 }
 
+/// Remove the nodes `select` picks out of the zset at `set`, deleting the key
+/// if that empties it, and reply with how many were removed
+fn remove_range(
+    db: &Db,
+    set: String,
+    select: impl FnOnce(&ZSet) -> Vec<&ListNode>,
+) -> RedisValueRef {
+    let mut set_guard = lock_zsets(db, &set);
+    let Some(zset) = set_guard.get_mut(&set) else {
+        return RInt(0);
+    };
+    let members: Vec<Bytes> = select(zset)
+        .into_iter()
+        .map(|node| node.1.clone())
+        .collect();
+    for member in &members {
+        zset.remove(member);
+    }
+    if zset.map.is_empty() {
+        set_guard.remove(&set);
+        db.ttl.remove(&set);
+    }
+    db.add_dirty(members.len() as u64);
+    RInt(members.len() as i64)
+}
+
+/// ZREMRANGEBYRANK: remove the members ranked `start..=stop`
+pub fn zremrangebyrank(db: &Db, set: String, start: i64, stop: i64) -> RedisValueRef {
+    remove_range(db, set, |zset| zset.rank_range(start, stop))
+}
+
+/// ZREMRANGEBYSCORE: remove the members scored between `min` and `max`
+pub fn zremrangebyscore(db: &Db, set: String, min: ScoreBound, max: ScoreBound) -> RedisValueRef {
+    remove_range(db, set, |zset| zset.score_range(min, max))
+}
+
+/// ZREMRANGEBYLEX: remove the members sorting between `min` and `max`
+pub fn zremrangebylex(db: &Db, set: String, min: LexBound, max: LexBound) -> RedisValueRef {
+    remove_range(db, set, |zset| zset.lex_range(&min, &max))
+}
+
 pub fn zcard(db: &Db, set: String) -> RedisValueRef {
-    let set_guard = db.zsets.lock().unwrap();
+    let set_guard = lock_zsets(db, &set);
     match set_guard.get(&set) {
         Some(zset) => RInt(zset.list.len() as i64),
         None => RInt(0),
@@ -167,9 +384,9 @@ pub fn zcard(db: &Db, set: String) -> RedisValueRef {
 }
 
 pub fn zscore(db: &Db, set: String, member: String) -> RedisValueRef {
-    let set_guard = db.zsets.lock().unwrap();
+    let set_guard = lock_zsets(db, &set);
     if let Some(zset) = set_guard.get(&set)
-        && let Some(entry) = zset.map.get(&member)
+        && let Some(entry) = zset.map.get(member.as_bytes())
     {
         RString(entry.to_string())
     } else {
@@ -177,11 +394,63 @@ pub fn zscore(db: &Db, set: String, member: String) -> RedisValueRef {
     }
 }
 
+/// Members of the first zset that are in none of the others, whatever their
+/// score there, in score order
+fn diff(sets: &HashMap<String, ZSet>, keys: &[String]) -> Vec<(Bytes, f64)> {
+    let Some((first, others)) = keys.split_first() else {
+        return Vec::new();
+    };
+    let Some(zset) = sets.get(first) else {
+        return Vec::new();
+    };
+    let others: Vec<&ZSet> = others.iter().filter_map(|key| sets.get(key)).collect();
+    zset.iter()
+        .filter(|(member, _)| !others.iter().any(|other| other.map.contains_key(*member)))
+        .map(|(member, score)| (member.clone(), score))
+        .collect()
+}
+
+/// ZDIFF: the members of the first zset missing from all the others
+pub fn zdiff(db: &Db, keys: Vec<String>, withscores: bool) -> RedisValueRef {
+    for key in &keys {
+        db.remove_if_expired(key);
+    }
+    let members = diff(&db.zsets.lock().unwrap(), &keys);
+    RArray(
+        members
+            .into_iter()
+            .flat_map(|(member, score)| {
+                let score = withscores.then(|| RString(score.to_string()));
+                std::iter::once(RedisValueRef::String(member)).chain(score)
+            })
+            .collect(),
+    )
+}
+
+/// ZDIFFSTORE: replace `dest` with the ZDIFF of `keys`, replying with its
+/// size. An empty result just deletes `dest`.
+pub fn zdiffstore(db: &Db, dest: String, keys: Vec<String>) -> RedisValueRef {
+    for key in &keys {
+        db.remove_if_expired(key);
+    }
+    let members = diff(&db.zsets.lock().unwrap(), &keys);
+    remove_keys(db, std::slice::from_ref(&dest));
+    let len = members.len();
+    if len > 0 {
+        db.zsets
+            .lock()
+            .unwrap()
+            .insert(dest, members.into_iter().collect());
+        db.add_dirty(1);
+    }
+    RInt(len as i64)
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
-    use crate::RedisDb;
+    use crate::{RedisDb, parser::RError};
 
     use super::*;
 
@@ -189,6 +458,131 @@ mod tests {
         Arc::new(RedisDb::new(None, "/tmp/redis-files", "dump.rdb"))
     }
 
+    fn overlapping_sets(db: &Db) {
+        for (score, member) in [(1.0, "a"), (2.0, "b"), (3.0, "c")] {
+            zadd(db, "z1".to_string(), score, member.to_string());
+        }
+        // "b" is excluded whatever its score in the other set
+        zadd(db, "z2".to_string(), 10.0, "b".to_string());
+        zadd(db, "z2".to_string(), 4.0, "d".to_string());
+    }
+
+    fn letters(db: &Db, set: &str) {
+        for (score, member) in [(1.0, "a"), (2.0, "b"), (3.0, "c"), (4.0, "d"), (5.0, "e")] {
+            zadd(db, set.to_string(), score, member.to_string());
+        }
+    }
+
+    #[test]
+    fn test_zremrangebyrank() {
+        let db = setup();
+        letters(&db, "z");
+        assert_eq!(zremrangebyrank(&db, "z".to_string(), 1, -2), RInt(3));
+        assert_eq!(
+            zrange(&db, "z".to_string(), 0, -1),
+            RArray(vec![RString("a"), RString("e")])
+        );
+        assert_eq!(zscore(&db, "z".to_string(), "c".to_string()), RNull());
+        assert_eq!(zremrangebyrank(&db, "z".to_string(), 5, 10), RInt(0));
+
+        // Removing everything deletes the key
+        assert_eq!(zremrangebyrank(&db, "z".to_string(), 0, -1), RInt(2));
+        assert!(!db.zsets.lock().unwrap().contains_key("z"));
+        assert_eq!(zremrangebyrank(&db, "missing".to_string(), 0, -1), RInt(0));
+    }
+
+    #[test]
+    fn test_zremrangebyscore() {
+        let db = setup();
+        letters(&db, "z");
+        assert_eq!(
+            zremrangebyscore(&db, "z".to_string(), (2.0, true), (4.0, false)),
+            RInt(2)
+        );
+        assert_eq!(
+            zrange(&db, "z".to_string(), 0, -1),
+            RArray(vec![RString("a"), RString("b"), RString("e")])
+        );
+        assert_eq!(
+            zremrangebyscore(
+                &db,
+                "z".to_string(),
+                (f64::NEG_INFINITY, false),
+                (5.0, true)
+            ),
+            RInt(2)
+        );
+        assert_eq!(
+            zrange(&db, "z".to_string(), 0, -1),
+            RArray(vec![RString("e")])
+        );
+    }
+
+    #[test]
+    fn test_zremrangebylex() {
+        let db = setup();
+        for member in ["a", "b", "c", "d", "e"] {
+            zadd(&db, "z".to_string(), 0.0, member.to_string());
+        }
+        let min = LexBound::Inclusive("b".to_string());
+        let max = LexBound::Exclusive("d".to_string());
+        assert_eq!(zremrangebylex(&db, "z".to_string(), min, max), RInt(2));
+        assert_eq!(
+            zrange(&db, "z".to_string(), 0, -1),
+            RArray(vec![RString("a"), RString("d"), RString("e")])
+        );
+        // Reversed open ends are valid and select nothing
+        let result = zremrangebylex(&db, "z".to_string(), LexBound::Max, LexBound::Min);
+        assert_eq!(result, RInt(0));
+        let min = LexBound::Exclusive("d".to_string());
+        assert_eq!(
+            zremrangebylex(&db, "z".to_string(), min, LexBound::Max),
+            RInt(1)
+        );
+        let result = zremrangebylex(&db, "z".to_string(), LexBound::Min, LexBound::Max);
+        assert_eq!(result, RInt(2));
+        assert_eq!(zcard(&db, "z".to_string()), RInt(0));
+    }
+
+    #[test]
+    fn test_zdiff() {
+        let db = setup();
+        overlapping_sets(&db);
+        let keys = vec!["z1".to_string(), "z2".to_string()];
+        assert_eq!(
+            zdiff(&db, keys.clone(), false),
+            RArray(vec![RString("a"), RString("c")])
+        );
+        assert_eq!(
+            zdiff(&db, keys, true),
+            RArray(vec![RString("a"), RString("1"), RString("c"), RString("3")])
+        );
+        let keys = vec!["missing".to_string(), "z1".to_string()];
+        assert_eq!(zdiff(&db, keys, false), RArray(vec![]));
+    }
+
+    #[test]
+    fn test_zdiffstore() {
+        let db = setup();
+        overlapping_sets(&db);
+        let keys = vec!["z1".to_string(), "z2".to_string()];
+        assert_eq!(zdiffstore(&db, "dest".to_string(), keys), RInt(2));
+        assert_eq!(
+            zrange(&db, "dest".to_string(), 0, -1),
+            RArray(vec![RString("a"), RString("c")])
+        );
+        assert_eq!(
+            zscore(&db, "dest".to_string(), "c".to_string()),
+            RString("3")
+        );
+
+        // Nothing left over deletes the destination
+        let keys = vec!["z1".to_string(), "z1".to_string()];
+        assert_eq!(zdiffstore(&db, "dest".to_string(), keys), RInt(0));
+        assert_eq!(zcard(&db, "dest".to_string()), RInt(0));
+        assert!(!db.zsets.lock().unwrap().contains_key("dest"));
+    }
+
     #[test]
     fn test_zadd() {
         let db = setup();
@@ -213,6 +607,156 @@ mod tests {
         // Same
     }
 
+    #[test]
+    fn test_zadd_nx_xx() {
+        let db = setup();
+        let nx = ZAddOptions {
+            nx: true,
+            ..Default::default()
+        };
+        let xx = ZAddOptions {
+            xx: true,
+            ..Default::default()
+        };
+        let result = zadd_with_options(&db, "z".to_string(), vec![(1.0, "a".to_string())], &xx);
+        assert_eq!(result, RInt(0));
+        assert_eq!(zscore(&db, "z".to_string(), "a".to_string()), RNull());
+
+        let result = zadd_with_options(&db, "z".to_string(), vec![(1.0, "a".to_string())], &nx);
+        assert_eq!(result, RInt(1));
+        let result = zadd_with_options(&db, "z".to_string(), vec![(2.0, "a".to_string())], &nx);
+        assert_eq!(result, RInt(0));
+        assert_eq!(zscore(&db, "z".to_string(), "a".to_string()), RString("1"));
+
+        let result = zadd_with_options(&db, "z".to_string(), vec![(3.0, "a".to_string())], &xx);
+        assert_eq!(result, RInt(0));
+        assert_eq!(zscore(&db, "z".to_string(), "a".to_string()), RString("3"));
+    }
+
+    #[test]
+    fn test_zadd_gt_lt() {
+        let db = setup();
+        let gt = ZAddOptions {
+            gt: true,
+            ch: true,
+            ..Default::default()
+        };
+        let lt = ZAddOptions {
+            lt: true,
+            ch: true,
+            ..Default::default()
+        };
+        zadd(&db, "z".to_string(), 5.0, "a".to_string());
+
+        // GT never lowers a score
+        let result = zadd_with_options(&db, "z".to_string(), vec![(1.0, "a".to_string())], &gt);
+        assert_eq!(result, RInt(0));
+        assert_eq!(zscore(&db, "z".to_string(), "a".to_string()), RString("5"));
+        let result = zadd_with_options(&db, "z".to_string(), vec![(7.0, "a".to_string())], &gt);
+        assert_eq!(result, RInt(1));
+        assert_eq!(zscore(&db, "z".to_string(), "a".to_string()), RString("7"));
+
+        let result = zadd_with_options(&db, "z".to_string(), vec![(9.0, "a".to_string())], &lt);
+        assert_eq!(result, RInt(0));
+        let result = zadd_with_options(&db, "z".to_string(), vec![(2.0, "a".to_string())], &lt);
+        assert_eq!(result, RInt(1));
+        assert_eq!(zscore(&db, "z".to_string(), "a".to_string()), RString("2"));
+
+        // New members are still added
+        let result = zadd_with_options(&db, "z".to_string(), vec![(1.0, "b".to_string())], &gt);
+        assert_eq!(result, RInt(1));
+    }
+
+    #[test]
+    fn test_zadd_ch() {
+        let db = setup();
+        let ch = ZAddOptions {
+            ch: true,
+            ..Default::default()
+        };
+        zadd(&db, "z".to_string(), 1.0, "a".to_string());
+        assert_eq!(zadd(&db, "z".to_string(), 2.0, "a".to_string()), RInt(0));
+        let result = zadd_with_options(&db, "z".to_string(), vec![(3.0, "a".to_string())], &ch);
+        assert_eq!(result, RInt(1));
+        let result = zadd_with_options(&db, "z".to_string(), vec![(3.0, "a".to_string())], &ch);
+        assert_eq!(result, RInt(0));
+    }
+
+    #[test]
+    fn test_zadd_nan() {
+        let db = setup();
+        let members = vec![(1.0, "a".to_string()), (f64::NAN, "b".to_string())];
+        let result = zadd_with_options(&db, "z".to_string(), members, &ZAddOptions::default());
+        assert_eq!(result, RError("ERR value is not a valid float"));
+        assert!(db.zsets.lock().unwrap().get("z").is_none());
+
+        // Infinite scores are fine, but adding opposite ones gives NaN
+        let incr = ZAddOptions {
+            incr: true,
+            ..Default::default()
+        };
+        zadd(&db, "z".to_string(), f64::INFINITY, "a".to_string());
+        let members = vec![(f64::NEG_INFINITY, "a".to_string())];
+        let result = zadd_with_options(&db, "z".to_string(), members, &incr);
+        assert_eq!(result, RError("ERR resulting score is not a number (NaN)"));
+        assert_eq!(
+            zscore(&db, "z".to_string(), "a".to_string()),
+            RString("inf")
+        );
+    }
+
+    #[test]
+    fn test_zadd_incr() {
+        let db = setup();
+        let incr = ZAddOptions {
+            incr: true,
+            ..Default::default()
+        };
+        let result = zadd_with_options(&db, "z".to_string(), vec![(1.5, "a".to_string())], &incr);
+        assert_eq!(result, RString("1.5"));
+        let result = zadd_with_options(&db, "z".to_string(), vec![(2.0, "a".to_string())], &incr);
+        assert_eq!(result, RString("3.5"));
+
+        let incr_nx = ZAddOptions { nx: true, ..incr };
+        let result =
+            zadd_with_options(&db, "z".to_string(), vec![(2.0, "a".to_string())], &incr_nx);
+        assert_eq!(result, RNull());
+        assert_eq!(
+            zscore(&db, "z".to_string(), "a".to_string()),
+            RString("3.5")
+        );
+    }
+
+    #[test]
+    fn test_zadd_multiple() {
+        let db = setup();
+        let members = vec![
+            (1.0, "a".to_string()),
+            (2.0, "b".to_string()),
+            (3.0, "c".to_string()),
+        ];
+        let result = zadd_with_options(&db, "z".to_string(), members, &ZAddOptions::default());
+        assert_eq!(result, RInt(3));
+        assert_eq!(
+            zrange(&db, "z".to_string(), 0, -1),
+            RArray(vec![RString("a"), RString("b"), RString("c")])
+        );
+
+        // Pairs apply in order, so a repeated member keeps the last score
+        let ch = ZAddOptions {
+            ch: true,
+            ..Default::default()
+        };
+        let members = vec![
+            (5.0, "a".to_string()),
+            (0.0, "a".to_string()),
+            (4.0, "d".to_string()),
+        ];
+        let result = zadd_with_options(&db, "z".to_string(), members, &ch);
+        assert_eq!(result, RInt(3));
+        assert_eq!(zscore(&db, "z".to_string(), "a".to_string()), RString("0"));
+    }
+
     #[test]
     fn test_zrank() {
         let db = setup();
@@ -311,6 +855,11 @@ mod tests {
                 RString("member4")
             ])
         );
+
+        let range = zrange(&db, "test_set".to_string(), -100, -50);
+        assert_eq!(range, RArray(vec![]));
+        let range = zrange(&db, "test_set".to_string(), 3, 1);
+        assert_eq!(range, RArray(vec![]));
     }
 
     #[test]